@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use ceph_foundation::{
+    CephArgs, CephConf, CephConfError, Keyring, KeyringError, MonInfo,
+    crypto::Key,
+    entity::{EntityName, EntityType},
+};
+use msgr2::frames::{AuthMethod, AuthMethodCephX, AuthMethodNone, ConMode};
+
+use super::Credentials;
+
+/// The default of `auth_client_required`.
+const DEFAULT_AUTH_METHODS: &[&str] = &["cephx", "none"];
+
+/// The default of `ms_mon_client_mode`.
+const DEFAULT_MON_MODES: &[&str] = &["secure", "crc"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusterConfigError {
+    Conf(CephConfError),
+    /// An authentication method in `auth_client_required` is unknown.
+    UnknownAuthMethod(String),
+    /// A connection mode in `ms_mon_client_mode` is unknown.
+    UnknownConMode(String),
+    /// The keyring could not be read.
+    Io(PathBuf, std::io::ErrorKind),
+    /// The keyring could not be parsed.
+    Keyring(PathBuf, KeyringError),
+}
+
+impl From<CephConfError> for ClusterConfigError {
+    fn from(value: CephConfError) -> Self {
+        Self::Conf(value)
+    }
+}
+
+/// The monitors and authentication settings of a cluster
+/// for an entity, as configured in its `ceph.conf`.
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    pub name: EntityName,
+    /// The monitors in `mon_host`.
+    pub monitors: Vec<MonInfo>,
+    /// The keyrings to search for the key of `name`, in order
+    /// of preference.
+    pub keyrings: Vec<PathBuf>,
+    /// The authentication methods in `auth_client_required`,
+    /// in order of preference.
+    pub auth_methods: Vec<AuthMethod>,
+    /// The connection modes in `ms_mon_client_mode`, in
+    /// order of preference.
+    pub modes: Vec<ConMode>,
+}
+
+impl ClusterConfig {
+    /// Load the configuration the way Ceph tools do, using the options in
+    /// the `CEPH_ARGS` environment variable and the configuration file
+    /// they (or `CEPH_CONF`) point to.
+    ///
+    /// `name` takes precedence over the name in `CEPH_ARGS`,
+    /// which defaults to `client.admin`.
+    pub fn from_env(name: Option<EntityName>) -> Result<Self, ClusterConfigError> {
+        let args = CephArgs::from_env()?;
+        let conf = CephConf::load(&args)?;
+
+        let name = name.or(args.name).unwrap_or_else(|| EntityName {
+            ty: EntityType::Client,
+            name: "admin".to_string(),
+        });
+
+        Self::from_conf(&conf, name)
+    }
+
+    /// Get the configuration for `name` from `conf`.
+    pub fn from_conf(conf: &CephConf, name: EntityName) -> Result<Self, ClusterConfigError> {
+        let list = |key, default: &[&str]| {
+            conf.get_list(&name, key)
+                .unwrap_or_else(|| default.iter().map(|d| d.to_string()).collect())
+        };
+
+        let auth_methods = list("auth client required", DEFAULT_AUTH_METHODS)
+            .into_iter()
+            .map(|method| match method.as_str() {
+                "none" => Ok(AuthMethod::None),
+                "cephx" => Ok(AuthMethod::CephX),
+                "gss" => Ok(AuthMethod::Gss),
+                _ => Err(ClusterConfigError::UnknownAuthMethod(method)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let modes = list("ms mon client mode", DEFAULT_MON_MODES)
+            .into_iter()
+            .map(|mode| match mode.as_str() {
+                "crc" => Ok(ConMode::Crc),
+                "secure" => Ok(ConMode::Secure),
+                _ => Err(ClusterConfigError::UnknownConMode(mode)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            monitors: conf.mon_host(&name)?,
+            keyrings: conf.keyring_paths(&name),
+            auth_methods,
+            modes,
+            name,
+        })
+    }
+
+    /// Read the key of our entity from the first keyring that exists.
+    ///
+    /// Returns `None` if no keyring exists, or if it does not contain
+    /// a key for our entity.
+    pub fn key(&self) -> Result<Option<Key>, ClusterConfigError> {
+        let Some(path) = self.keyrings.iter().find(|path| path.exists()) else {
+            return Ok(None);
+        };
+
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ClusterConfigError::Io(path.clone(), e.kind()))?;
+
+        let keyring =
+            Keyring::parse(&text).map_err(|e| ClusterConfigError::Keyring(path.clone(), e))?;
+
+        Ok(keyring.key(&self.name).cloned())
+    }
+
+    /// The credentials for the first of our authentication methods that
+    /// can be used with `key` (CephX requires a key).
+    pub fn credentials(&self, key: Option<Key>) -> Option<Credentials> {
+        self.auth_methods.iter().find_map(|method| match method {
+            AuthMethod::CephX => {
+                let method = AuthMethodCephX {
+                    name: self.name.clone(),
+                    global_id: 0,
+                };
+
+                Some(Credentials::cephx(method, key.clone()?, self.modes.clone()))
+            }
+            AuthMethod::None => Some(Credentials::none(AuthMethodNone {
+                name: self.name.clone(),
+                global_id: 0,
+            })),
+            _ => None,
+        })
+    }
+}
+
+#[test]
+fn from_conf() {
+    let text = "\
+        [global]\n\
+        mon_host = [v2:10.0.0.1:3300,v1:10.0.0.1:6789] 10.0.0.2\n\
+        [client.admin]\n\
+        keyring = /etc/ceph/$cluster.admin.keyring\n\
+        ms mon client mode = crc\n";
+
+    let conf = CephConf::parse(text).unwrap();
+    let config = ClusterConfig::from_conf(&conf, "client.admin".parse().unwrap()).unwrap();
+
+    assert_eq!(config.monitors.len(), 2);
+    assert_eq!(
+        config.keyrings,
+        [PathBuf::from("/etc/ceph/ceph.admin.keyring")]
+    );
+    assert_eq!(config.auth_methods, [AuthMethod::CephX, AuthMethod::None]);
+    assert_eq!(config.modes, [ConMode::Crc]);
+
+    // Without a key, we fall back to no authentication.
+    let credentials = config.credentials(None).unwrap();
+    assert_eq!(credentials.modes(), [ConMode::Crc]);
+
+    let mut conf = CephConf::parse(text).unwrap();
+    conf.set("auth_client_required", "cephx");
+    let config = ClusterConfig::from_conf(&conf, "client.admin".parse().unwrap()).unwrap();
+    assert!(config.credentials(None).is_none());
+
+    conf.set("auth_client_required", "kerberos");
+    assert_eq!(
+        ClusterConfig::from_conf(&conf, "client.admin".parse().unwrap()).unwrap_err(),
+        ClusterConfigError::UnknownAuthMethod("kerberos".to_string())
+    );
+}
@@ -1,19 +1,64 @@
+use std::{sync::Arc, time::Duration};
+
 use ceph_foundation::entity::EntityType;
 use cephx::CephXTicketBlob;
+use msgr2::compression::{CompressionMethod, DEFAULT_MIN_SIZE};
+
+/// The default of [`Config::max_unacked`].
+///
+/// This matches the default of `objecter_inflight_ops`.
+pub const DEFAULT_MAX_UNACKED: usize = 1024;
+
+/// The default of [`Config::keepalive_interval`].
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A source of random numbers.
+///
+/// Challenges, nonces and cookies are drawn from the [`Rng`] of a
+/// [`Config`], so that they can be made deterministic in tests.
+pub trait Rng: core::fmt::Debug + Send + Sync {
+    /// Get the next random number.
+    fn next_u64(&self) -> u64;
+}
+
+/// Randomness provided by the operating system, which
+/// is the default [`Rng`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRng;
+
+impl Rng for OsRng {
+    fn next_u64(&self) -> u64 {
+        let mut data = [0u8; 8];
+        getrandom::fill(&mut data).expect("Failed to obtain random data");
+        u64::from_le_bytes(data)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     support_rev21: bool,
+    rng: Arc<dyn Rng>,
+    nonce: u32,
     request_tickets_for: Vec<EntityType>,
     old_ticket: Option<CephXTicketBlob>,
+    compression_methods: Vec<CompressionMethod>,
+    compression_min_size: usize,
+    max_unacked: usize,
+    keepalive_interval: Duration,
 }
 
 impl Config {
     pub fn new(support_rev21: bool) -> Self {
         Self {
             support_rev21,
+            rng: Arc::new(OsRng),
+            nonce: OsRng.next_u64() as u32,
             request_tickets_for: Vec::new(),
             old_ticket: None,
+            compression_methods: Vec::new(),
+            compression_min_size: DEFAULT_MIN_SIZE,
+            max_unacked: DEFAULT_MAX_UNACKED,
+            keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
         }
     }
 
@@ -29,6 +74,27 @@ impl Config {
         self.support_rev21
     }
 
+    /// Draw random numbers from `rng` instead of from [`OsRng`].
+    ///
+    /// The [nonce](Config::nonce) is drawn again from `rng`.
+    pub fn set_rng(&mut self, rng: impl Rng + 'static) {
+        self.nonce = rng.next_u64() as u32;
+        self.rng = Arc::new(rng);
+    }
+
+    pub fn rng(&self) -> &dyn Rng {
+        self.rng.as_ref()
+    }
+
+    /// The nonce of our addresses, which distinguishes us from
+    /// other clients with the same IP address.
+    ///
+    /// All connections made using (clones of) this configuration
+    /// use the same nonce.
+    pub fn nonce(&self) -> u32 {
+        self.nonce
+    }
+
     pub fn request_ticket_for(&mut self, entity: EntityType) {
         if !self.request_tickets_for.contains(&entity) {
             self.request_tickets_for.push(entity);
@@ -38,4 +104,49 @@ impl Config {
     pub fn tickets_for(&self) -> &[EntityType] {
         &self.request_tickets_for
     }
+
+    /// Request compression of frames of at least `min_size` bytes,
+    /// using one of `methods` (in order of preference).
+    ///
+    /// Methods that are not supported by this build are ignored.
+    pub fn request_compression(&mut self, methods: &[CompressionMethod], min_size: usize) {
+        self.compression_methods = methods
+            .iter()
+            .copied()
+            .filter(CompressionMethod::is_supported)
+            .collect();
+        self.compression_min_size = min_size;
+    }
+
+    pub fn compression_methods(&self) -> &[CompressionMethod] {
+        &self.compression_methods
+    }
+
+    pub fn compression_min_size(&self) -> usize {
+        self.compression_min_size
+    }
+
+    /// Refuse to send more messages while `max` sent messages
+    /// have not been acknowledged by the peer.
+    ///
+    /// Unacknowledged messages are kept around so that they can be
+    /// replayed after a reconnect.
+    pub fn set_max_unacked(&mut self, max: usize) {
+        self.max_unacked = max;
+    }
+
+    pub fn max_unacked(&self) -> usize {
+        self.max_unacked
+    }
+
+    /// Send a keepalive after nothing was received from a peer for
+    /// `interval`, and consider the session dead if the keepalive is
+    /// not acknowledged within `interval` either.
+    pub fn set_keepalive_interval(&mut self, interval: Duration) {
+        self.keepalive_interval = interval;
+    }
+
+    pub fn keepalive_interval(&self) -> Duration {
+        self.keepalive_interval
+    }
 }
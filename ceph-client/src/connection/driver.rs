@@ -0,0 +1,137 @@
+//! An asynchronous driver for [`ClientConnection`]s, based on
+//! `tokio`.
+
+use ceph_foundation::DecodeError;
+use msgr2::{
+    Frame,
+    frames::{Banner, ClientIdent},
+    wire::{RxError, TxError, TxFrame},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{
+    ClientConnection, Credentials, Handshake, HandshakeError, Message,
+    state::{Active, Established, Inactive},
+};
+
+/// An error that occurred while driving a connection.
+#[derive(Debug)]
+pub enum DriverError {
+    Io(std::io::Error),
+    Rx(RxError),
+    Tx(TxError),
+    Decode(DecodeError),
+    Handshake(HandshakeError),
+}
+
+impl From<std::io::Error> for DriverError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<RxError> for DriverError {
+    fn from(value: RxError) -> Self {
+        Self::Rx(value)
+    }
+}
+
+impl From<TxError> for DriverError {
+    fn from(value: TxError) -> Self {
+        Self::Tx(value)
+    }
+}
+
+impl From<DecodeError> for DriverError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+impl From<HandshakeError> for DriverError {
+    fn from(value: HandshakeError) -> Self {
+        Self::Handshake(value)
+    }
+}
+
+/// Send `frame` to `stream`.
+pub async fn send(
+    frame: TxFrame<'_>,
+    stream: impl AsyncWrite + Unpin,
+) -> Result<usize, DriverError> {
+    Ok(frame.write_async(stream).await?)
+}
+
+/// Receive a frame from `stream`.
+///
+/// Returns `None` if the frame contains a message that was already
+/// received, in which case it should be ignored.
+pub async fn recv_raw<'buf, S>(
+    buffer: &'buf mut Vec<u8>,
+    connection: &mut ClientConnection<S>,
+    mut stream: impl AsyncRead + Unpin,
+) -> Result<Option<Frame<'buf>>, DriverError>
+where
+    S: Established,
+{
+    let frame = connection.start_rx(buffer);
+    let frame = frame.read_preamble_async(&mut stream).await?;
+    let frame = frame.read_rest_async(&mut stream).await?;
+
+    Ok(connection.finish_rx_raw(frame)?)
+}
+
+/// Receive a non-message frame from `stream`.
+pub async fn recv<S>(
+    connection: &mut ClientConnection<S>,
+    mut stream: impl AsyncRead + Unpin,
+) -> Result<Message, DriverError>
+where
+    S: Established,
+{
+    let mut buffer = Vec::new();
+    let frame = connection.start_rx(&mut buffer);
+    let frame = frame.read_preamble_async(&mut stream).await?;
+    let frame = frame.read_rest_async(&mut stream).await?;
+
+    Ok(connection.finish_rx(frame)?)
+}
+
+/// Perform the handshake of `connection` over `stream`, authenticating
+/// using `credentials` and identifying with `ident`.
+pub async fn handshake<S>(
+    stream: &mut S,
+    connection: ClientConnection<Inactive>,
+    credentials: &Credentials,
+    ident: &ClientIdent,
+) -> Result<ClientConnection<Active>, DriverError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut handshake = Handshake::new(connection, credentials.clone(), ident.clone());
+
+    stream.write_all(&handshake.banner().to_bytes()).await?;
+
+    let mut banner = [0u8; Banner::SERIALIZED_SIZE];
+    stream.read_exact(&mut banner).await?;
+
+    let banner = Banner::parse(&banner).map_err(HandshakeError::Banner)?;
+    handshake.recv_banner(&banner)?;
+
+    loop {
+        while let Some(data) = handshake.poll_transmit() {
+            stream.write_all(&data).await?;
+        }
+
+        let mut buffer = Vec::new();
+        let Some(frame) = handshake.start_rx(&mut buffer) else {
+            break;
+        };
+
+        let frame = frame.read_preamble_async(&mut *stream).await?;
+        let frame = frame.read_rest_async(&mut *stream).await?;
+        handshake.recv_frame(frame)?;
+    }
+
+    Ok(handshake.finish().expect("Handshake is done"))
+}
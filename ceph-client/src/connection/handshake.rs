@@ -0,0 +1,436 @@
+use std::collections::VecDeque;
+
+use ceph_foundation::{DecodeError, crypto::Key, entity::EntityType};
+use cephx::{CephXAuthorizer, Ticket};
+use msgr2::{
+    Tag,
+    frames::{
+        AuthBadMethod, AuthMethod, AuthMethodCephX, AuthMethodNone, AuthRequest, Banner,
+        ClientIdent, ConMode, Hello, IdentMissingFeatures,
+    },
+    wire::{Completed, RxFrame, TxError, TxFrame, Unstarted},
+};
+
+use super::{
+    AuthError, ClientConnection, Message,
+    state::{Active, Authenticating, ExchangeHello, ExchangingSignatures, Identifying, Inactive},
+};
+
+/// The secret that proves our identity.
+#[derive(Debug, Clone)]
+enum Secret {
+    None,
+    Key(Key),
+    /// A service ticket, presented in an authorizer.
+    Ticket {
+        global_id: u64,
+        ticket: Ticket,
+    },
+}
+
+/// The credentials used to authenticate a connection.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    request: AuthRequest,
+    secret: Secret,
+}
+
+impl Credentials {
+    /// Connect without authentication.
+    pub fn none(method: AuthMethodNone) -> Self {
+        Self {
+            request: AuthRequest::new(method, vec![ConMode::Crc]),
+            secret: Secret::None,
+        }
+    }
+
+    /// Authenticate using CephX with `key`, and one of the
+    /// connection `modes` (in order of preference).
+    pub fn cephx(method: AuthMethodCephX, key: Key, modes: Vec<ConMode>) -> Self {
+        Self {
+            request: AuthRequest::new(method, modes),
+            secret: Secret::Key(key),
+        }
+    }
+
+    /// Authenticate to a service as `global_id` using a service
+    /// `ticket` for it, and one of the connection `modes` (in
+    /// order of preference).
+    ///
+    /// The ticket is obtained from the monitors, which are
+    /// authenticated to using [`Credentials::cephx`].
+    pub fn ticket(global_id: u64, ticket: Ticket, modes: Vec<ConMode>) -> Self {
+        Self {
+            request: AuthRequest::with_payload(AuthMethod::CephX, modes, Vec::new()),
+            secret: Secret::Ticket { global_id, ticket },
+        }
+    }
+
+    /// The key used to authenticate using CephX, if any.
+    pub(crate) fn key(&self) -> Option<&Key> {
+        match &self.secret {
+            Secret::Key(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// The preferred connection modes, in order of preference.
+    pub fn modes(&self) -> &[ConMode] {
+        self.request.preferred_modes()
+    }
+}
+
+/// An error that occurred during a [`Handshake`].
+#[derive(Debug)]
+pub enum HandshakeError {
+    Decode(DecodeError),
+    /// A frame could not be encrypted.
+    Tx(TxError),
+    /// The banner of the peer was incompatible.
+    Banner(String),
+    Auth(AuthError),
+    /// The peer rejected our authentication request.
+    AuthBadMethod(AuthBadMethod),
+    /// The signature of the peer was incorrect.
+    Signature(String),
+    Compression(String),
+    /// We do not support some features that the peer requires.
+    IdentMissingFeatures(IdentMissingFeatures),
+    Ident(String),
+    /// The peer sent a frame that was not expected at this
+    /// point of the handshake.
+    UnexpectedTag(msgr2::Tag),
+    /// The peer asked us to back off. The handshake can be
+    /// [retried](Handshake::retry) on a new connection later.
+    Wait,
+    /// The handshake already finished or failed.
+    Finished,
+}
+
+impl From<DecodeError> for HandshakeError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+impl From<TxError> for HandshakeError {
+    fn from(value: TxError) -> Self {
+        Self::Tx(value)
+    }
+}
+
+impl From<AuthError> for HandshakeError {
+    fn from(value: AuthError) -> Self {
+        Self::Auth(value)
+    }
+}
+
+#[derive(Debug)]
+enum Stage {
+    Banner(ClientConnection<Inactive>),
+    Hello(ClientConnection<ExchangeHello>),
+    Auth(ClientConnection<Authenticating>),
+    Signatures(ClientConnection<ExchangingSignatures>),
+    Compression(ClientConnection<Identifying>),
+    Ident(ClientConnection<Identifying>),
+    Done(ClientConnection<Active>),
+    /// The peer asked us to back off.
+    Wait(ClientConnection<Inactive>),
+    Failed,
+}
+
+/// A sans-IO driver for the handshake of a [`ClientConnection`], from
+/// the exchange of banners up to and including identification.
+///
+/// After sending [`Handshake::banner`] and receiving the banner of the peer,
+/// all frames returned by [`Handshake::poll_transmit`] should be sent, and
+/// received frames should be passed to [`Handshake::recv_frame`], until the
+/// handshake [is done](Handshake::is_done).
+#[derive(Debug)]
+pub struct Handshake {
+    stage: Stage,
+    banner: Banner,
+    credentials: Credentials,
+    /// The authorizer that was sent, if authenticating using a ticket.
+    authorizer: Option<CephXAuthorizer>,
+    ident: ClientIdent,
+    transmit: VecDeque<Vec<u8>>,
+}
+
+impl Handshake {
+    /// Create a new handshake for `connection` that authenticates using
+    /// `credentials`, and identifies using `ident`.
+    ///
+    /// If the addresses of `ident` are empty, the address that the peer
+    /// reports for us in its [`Hello`] is used, with the nonce of the
+    /// [`Config`](super::Config) of `connection`.
+    ///
+    /// If the session of `connection` is [established](super::Session::is_established),
+    /// it is resumed instead, after which unacknowledged messages are sent again.
+    pub fn new(
+        connection: ClientConnection<Inactive>,
+        credentials: Credentials,
+        ident: ClientIdent,
+    ) -> Self {
+        Self {
+            banner: connection.banner(),
+            stage: Stage::Banner(connection),
+            credentials,
+            authorizer: None,
+            ident,
+            transmit: VecDeque::new(),
+        }
+    }
+
+    /// The banner to send to the peer.
+    pub fn banner(&self) -> Banner {
+        self.banner
+    }
+
+    /// Whether the handshake completed successfully.
+    pub fn is_done(&self) -> bool {
+        matches!(self.stage, Stage::Done(_))
+    }
+
+    /// Get the next data to send to the peer, if any.
+    pub fn poll_transmit(&mut self) -> Option<Vec<u8>> {
+        self.transmit.pop_front()
+    }
+
+    /// Receive the banner of the peer.
+    pub fn recv_banner(&mut self, banner: &Banner) -> Result<(), HandshakeError> {
+        let Stage::Banner(connection) = core::mem::replace(&mut self.stage, Stage::Failed) else {
+            return Err(HandshakeError::Finished);
+        };
+
+        let mut connection = connection
+            .recv_banner(banner)
+            .map_err(HandshakeError::Banner)?;
+
+        let hello = Hello {
+            entity_type: EntityType::Client,
+            peer_address: self.ident.target.clone(),
+        };
+
+        Self::queue(&mut self.transmit, connection.send_hello(&hello))?;
+        self.stage = Stage::Hello(connection);
+        Ok(())
+    }
+
+    /// Start receiving the next frame.
+    ///
+    /// Returns `None` if no frame is expected, i.e. if the banner of the
+    /// peer was not received yet, or if the handshake finished.
+    pub fn start_rx<'me, 'buf>(
+        &'me mut self,
+        buffer: &'buf mut Vec<u8>,
+    ) -> Option<RxFrame<'buf, Unstarted<'me>>> {
+        let frame = match &mut self.stage {
+            Stage::Hello(c) => c.start_rx(buffer),
+            Stage::Auth(c) => c.start_rx(buffer),
+            Stage::Signatures(c) => c.start_rx(buffer),
+            Stage::Compression(c) | Stage::Ident(c) => c.start_rx(buffer),
+            Stage::Banner(_) | Stage::Done(_) | Stage::Wait(_) | Stage::Failed => return None,
+        };
+
+        Some(frame)
+    }
+
+    /// Receive `frame`, which was started using [`Handshake::start_rx`].
+    ///
+    /// If this returns an error, the handshake has failed.
+    pub fn recv_frame(&mut self, frame: RxFrame<'_, Completed>) -> Result<(), HandshakeError> {
+        let stage = core::mem::replace(&mut self.stage, Stage::Failed);
+
+        // Messages may only be sent once the handshake is done.
+        let tag = frame.preamble().tag;
+        if tag == Tag::Message {
+            return Err(HandshakeError::UnexpectedTag(tag));
+        }
+
+        self.stage = self.advance(stage, frame)?;
+
+        if matches!(self.stage, Stage::Wait(_)) {
+            return Err(HandshakeError::Wait);
+        }
+
+        Ok(())
+    }
+
+    /// Start a new handshake that resumes the session, after the peer
+    /// asked us to [wait](HandshakeError::Wait).
+    ///
+    /// The new handshake should be done on a new connection, after
+    /// backing off. Returns `None` if the peer did not ask us to wait.
+    pub fn retry(self) -> Option<Self> {
+        let Stage::Wait(connection) = self.stage else {
+            return None;
+        };
+
+        let mut ident = self.ident;
+        ident.global_seq = connection.session().global_seq;
+
+        Some(Self::new(connection, self.credentials, ident))
+    }
+
+    /// Finish the handshake, returning the established connection.
+    ///
+    /// Returns `None` if the handshake is not [done](Handshake::is_done).
+    pub fn finish(self) -> Option<ClientConnection<Active>> {
+        match self.stage {
+            Stage::Done(connection) => Some(connection),
+            _ => None,
+        }
+    }
+
+    fn advance(
+        &mut self,
+        stage: Stage,
+        frame: RxFrame<'_, Completed>,
+    ) -> Result<Stage, HandshakeError> {
+        let transmit = &mut self.transmit;
+        let key = match &self.credentials.secret {
+            Secret::Key(key) => Some(key),
+            _ => None,
+        };
+
+        let stage = match stage {
+            Stage::Hello(mut c) => match c.finish_rx(frame)? {
+                Message::Hello(hello) => {
+                    if self.ident.addresses.is_empty() {
+                        let mut address = hello.peer_address.clone();
+                        address.nonce = c.config.nonce();
+                        self.ident.addresses.push(address);
+                    }
+
+                    let mut c = c.recv_hello(&hello);
+
+                    if let Secret::Ticket { global_id, ticket } = &self.credentials.secret {
+                        let modes = self.credentials.modes().to_vec();
+                        let (authorizer, frame) = c.send_authorizer(*global_id, ticket, modes)?;
+                        Self::queue(transmit, frame)?;
+                        self.authorizer = Some(authorizer);
+                    } else {
+                        Self::queue(transmit, c.send_req(&self.credentials.request))?;
+                    }
+
+                    Stage::Auth(c)
+                }
+                m => return Err(HandshakeError::UnexpectedTag(m.tag())),
+            },
+            Stage::Auth(mut c) => match (c.finish_rx(frame)?, key) {
+                (Message::AuthReplyMore(more), _) if self.authorizer.is_some() => {
+                    let authorizer = self.authorizer.as_mut().expect("Authorizer was sent");
+                    Self::queue(transmit, c.recv_authorizer_challenge(authorizer, &more)?)?;
+                    Stage::Auth(c)
+                }
+                (Message::AuthReplyMore(more), Some(key)) => {
+                    Self::queue(transmit, c.recv_cephx_server_challenge(key, &more)?)?;
+                    Stage::Auth(c)
+                }
+                (Message::AuthDone(done), key) => {
+                    let mut c = if let Some(authorizer) = &self.authorizer {
+                        c.recv_authorizer_done(authorizer, &done)?
+                    } else if let Some(key) = key {
+                        c.recv_cephx_done(key, &done)?
+                    } else {
+                        c.recv_none_done(&done)?
+                    };
+
+                    Self::queue(transmit, c.send_signature())?;
+                    Stage::Signatures(c)
+                }
+                (Message::AuthBadMethod(bad_method), _) => {
+                    return Err(HandshakeError::AuthBadMethod(bad_method));
+                }
+                (m, _) => return Err(HandshakeError::UnexpectedTag(m.tag())),
+            },
+            Stage::Signatures(mut c) => match c.finish_rx(frame)? {
+                Message::AuthSignature(signature) => {
+                    let mut c = c
+                        .recv_signature(&signature)
+                        .map_err(HandshakeError::Signature)?;
+
+                    if let Some(request) = c.send_compression_request() {
+                        Self::queue(transmit, request)?;
+                        Stage::Compression(c)
+                    } else {
+                        Self::identify(transmit, &mut c, &self.ident)?;
+                        Stage::Ident(c)
+                    }
+                }
+                m => return Err(HandshakeError::UnexpectedTag(m.tag())),
+            },
+            Stage::Compression(mut c) => match c.finish_rx(frame)? {
+                Message::CompressionDone(done) => {
+                    c.recv_compression_done(&done)
+                        .map_err(HandshakeError::Compression)?;
+
+                    Self::identify(transmit, &mut c, &self.ident)?;
+                    Stage::Ident(c)
+                }
+                m => return Err(HandshakeError::UnexpectedTag(m.tag())),
+            },
+            Stage::Ident(mut c) => match c.finish_rx(frame)? {
+                Message::ServerIdent(ident) => {
+                    let c = c.recv_server_ident(&ident).map_err(HandshakeError::Ident)?;
+                    Stage::Done(c)
+                }
+                Message::IdentMissingFeatures(missing) => {
+                    return Err(HandshakeError::IdentMissingFeatures(missing));
+                }
+                Message::SessionReconnectOk(ok) => {
+                    let mut c = c.recv_session_reconnect_ok(&ok);
+
+                    while let Some(frame) = c.replay_next() {
+                        Self::queue(transmit, frame)?;
+                    }
+
+                    Stage::Done(c)
+                }
+                Message::SessionRetry(retry) => {
+                    Self::queue(transmit, c.recv_session_retry(&retry))?;
+                    Stage::Ident(c)
+                }
+                Message::SessionRetryGlobal(retry) => {
+                    Self::queue(transmit, c.recv_session_retry_global(&retry))?;
+                    Stage::Ident(c)
+                }
+                Message::SessionReset(reset) => {
+                    // The session can not be resumed, so we start a new one.
+                    c.recv_session_reset(&reset);
+                    Self::queue(transmit, c.send_client_ident(&self.ident))?;
+                    Stage::Ident(c)
+                }
+                Message::Wait(wait) => Stage::Wait(c.recv_wait(&wait)),
+                m => return Err(HandshakeError::UnexpectedTag(m.tag())),
+            },
+            Stage::Banner(_) | Stage::Done(_) | Stage::Wait(_) | Stage::Failed => {
+                return Err(HandshakeError::Finished);
+            }
+        };
+
+        Ok(stage)
+    }
+
+    /// Identify using `ident`, or resume the session of `c`
+    /// if it is established.
+    fn identify(
+        transmit: &mut VecDeque<Vec<u8>>,
+        c: &mut ClientConnection<Identifying>,
+        ident: &ClientIdent,
+    ) -> Result<(), HandshakeError> {
+        if c.session().is_established() {
+            Self::queue(transmit, c.send_session_reconnect())
+        } else {
+            Self::queue(transmit, c.send_client_ident(ident))
+        }
+    }
+
+    fn queue(transmit: &mut VecDeque<Vec<u8>>, frame: TxFrame<'_>) -> Result<(), HandshakeError> {
+        let mut data = Vec::new();
+        frame.write(&mut data)?;
+        transmit.push_back(data);
+        Ok(())
+    }
+}
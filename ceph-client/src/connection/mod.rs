@@ -1,11 +1,17 @@
 //! A sans-IO implementation of a `msgr2` connection, with support
 //! for authentication-less and CephX connections.
 
+mod cluster;
 mod config;
+#[cfg(feature = "tokio")]
+pub mod driver;
+mod handshake;
+pub mod server;
+mod session;
 pub mod state;
 
 use ::cephx::{CephXMessage, CephXMessageType};
-use cephx::{AuthServiceTicketReply, TicketsAndConnectionSecret};
+use cephx::{AuthServiceTicketReply, CephXAuthorizer, Ticket, TicketsAndConnectionSecret};
 use state::{
     Active, Authenticating, Established, ExchangeHello, ExchangingSignatures, Identifying, Inactive,
 };
@@ -14,15 +20,22 @@ use ceph_foundation::entity::EntityType;
 
 use msgr2::{
     Frame, FrameEncryption, Revision, Tag,
+    compression::{CompressionMethod, FrameCompression},
     frames::{
-        AuthBadMethod, AuthDone, AuthReplyMore, AuthRequest, AuthRequestMore, AuthSignature,
-        Banner, ClientIdent, ConMode, Hello, IdentMissingFeatures, Keepalive, KeepaliveAck,
-        MsgrFeatures, ServerIdent,
+        Ack, AuthBadMethod, AuthDone, AuthReplyMore, AuthRequest, AuthRequestMore, AuthSignature,
+        Banner, ClientIdent, CompressionDone, CompressionRequest, ConMode, Hello,
+        IdentMissingFeatures, Keepalive, KeepaliveAck, MsgrFeatures, ServerIdent, SessionReconnect,
+        SessionReconnectOk, SessionReset, SessionRetry, SessionRetryGlobal, Wait,
     },
     wire::{Completed, RxFrame, TxFrame, Unstarted},
 };
 
+pub use cluster::{ClusterConfig, ClusterConfigError};
 pub use config::*;
+pub use handshake::{Credentials, Handshake, HandshakeError};
+
+use crate::header::CephMessageHeader2;
+pub use session::Session;
 
 use ceph_foundation::{Decode, DecodeError, Encode, Timestamp, crypto::Key};
 
@@ -35,6 +48,9 @@ pub enum AuthError {
         expected: CephXMessageType,
     },
     UnexpectedData,
+    /// The ticket blob of a service ticket is encrypted, so it can
+    /// not be used in an authorizer.
+    EncryptedTicket,
 }
 
 impl From<DecodeError> for AuthError {
@@ -43,10 +59,22 @@ impl From<DecodeError> for AuthError {
     }
 }
 
+/// An error that occurred while sending a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    /// The peer has not acknowledged the [maximum amount](Config::max_unacked)
+    /// of messages that may be outstanding.
+    TooManyUnacked(usize),
+}
+
 #[derive(Debug)]
 pub struct ClientConnection<T> {
     state: T,
     config: Config,
+    session: Session,
+    /// The CephX challenge that the server sent while authenticating,
+    /// which is reused to renew the auth ticket over this connection.
+    server_challenge: Option<u64>,
     buffer: Vec<u8>,
 }
 
@@ -58,13 +86,30 @@ impl<T> ClientConnection<T> {
         ClientConnection {
             state: state(self.state),
             config: self.config,
+            session: self.session,
+            server_challenge: self.server_challenge,
             buffer: self.buffer,
         }
     }
+
+    /// The session of this connection.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
 }
 
 impl ClientConnection<Inactive> {
     pub fn new(config: Config) -> Self {
+        Self::resume(config, Session::default())
+    }
+
+    /// Create a new connection that will attempt to resume
+    /// `session`.
+    ///
+    /// If `session` is established, [`ClientConnection::send_session_reconnect`]
+    /// should be used instead of [`ClientConnection::send_client_ident`] when
+    /// identifying.
+    pub fn resume(config: Config, session: Session) -> Self {
         let mut me = Self {
             state: Inactive {
                 _reserved: (),
@@ -72,6 +117,8 @@ impl ClientConnection<Inactive> {
                 tx_buf: Vec::new(),
             },
             config,
+            session,
+            server_challenge: None,
             buffer: Vec::new(),
         };
 
@@ -88,6 +135,10 @@ impl ClientConnection<Inactive> {
             features.set_revision_21(true);
         }
 
+        // We can always decompress frames, even if we do
+        // not request compression ourselves.
+        features.set_compression(true);
+
         Banner::new(features, MsgrFeatures::empty())
     }
 
@@ -103,11 +154,7 @@ impl ClientConnection<Inactive> {
             .rx_buf
             .extend_from_slice(banner.to_bytes().as_slice());
 
-        if banner.required().compression() {
-            return Err("Peer requires compression, which we do not support".into());
-        }
-
-        if !self.banner().compatible(&banner) {
+        if !self.banner().compatible(banner) {
             return Err(format!(
                 "Peer requires unknown msgr2 features that we do not support. Supported: {:?}, peer required: 0x{:?}",
                 self.banner().supported(),
@@ -125,6 +172,7 @@ impl ClientConnection<Inactive> {
             rx_buf: state.rx_buf,
             tx_buf: state.tx_buf,
             revision,
+            peer_features: *banner.supported(),
             encryption: FrameEncryption::new(),
         }))
     }
@@ -146,6 +194,7 @@ impl ClientConnection<ExchangeHello> {
     pub fn recv_hello(self, _hello: &Hello) -> ClientConnection<Authenticating> {
         self.with_state(|state| Authenticating {
             revision: state.revision,
+            peer_features: state.peer_features,
             encryption: state.encryption,
             rx_buf: state.rx_buf,
             tx_buf: state.tx_buf,
@@ -166,17 +215,72 @@ impl ClientConnection<Authenticating> {
         self.tx_frame(&frame)
     }
 
+    /// Send an [`AuthRequest`] that authenticates to a service as
+    /// `global_id` using `ticket`, instead of using the master key.
+    ///
+    /// The returned authorizer should be passed to
+    /// [`ClientConnection::recv_authorizer_done`].
+    pub fn send_authorizer<'me>(
+        &'me mut self,
+        global_id: u64,
+        ticket: &Ticket,
+        preferred_modes: Vec<ConMode>,
+    ) -> Result<(CephXAuthorizer, TxFrame<'me>), AuthError> {
+        let blob = ticket.blob().ok_or(AuthError::EncryptedTicket)?;
+
+        let authorizer = CephXAuthorizer::build(
+            global_id,
+            ticket.ty,
+            blob,
+            &ticket.session_ticket.session_key,
+            self.config.rng().next_u64(),
+        );
+
+        let request = AuthRequest::with_payload(
+            msgr2::frames::AuthMethod::CephX,
+            preferred_modes,
+            authorizer.payload().to_vec(),
+        );
+
+        Ok((authorizer, self.send_req(&request)))
+    }
+
+    /// Receive the [`AuthReplyMore`] of a service that challenges the
+    /// `authorizer` sent using [`ClientConnection::send_authorizer`], and
+    /// send the authorizer again with the challenge included.
+    pub fn recv_authorizer_challenge<'me>(
+        &'me mut self,
+        authorizer: &mut CephXAuthorizer,
+        challenge: &AuthReplyMore,
+    ) -> Result<TxFrame<'me>, AuthError> {
+        authorizer.add_challenge(&challenge.payload)?;
+
+        let more = AuthRequestMore {
+            payload: authorizer.payload().to_vec(),
+        };
+
+        self.buffer.clear();
+        more.encode(&mut self.buffer);
+
+        let more = self.buffer.clone();
+        let frame = Frame::new(Tag::AuthRequestMore, &[&more]).unwrap();
+
+        frame.write(self.state.format(), &mut self.state.tx_buf);
+
+        Ok(self.tx_frame(&frame))
+    }
+
     pub fn recv_cephx_server_challenge<'me>(
         &'me mut self,
         master_key: &Key,
         challenge: &AuthReplyMore,
-    ) -> TxFrame<'me> {
+    ) -> Result<TxFrame<'me>, AuthError> {
         use ::cephx::*;
 
-        let challenge = CephXServerChallenge::decode(&mut challenge.payload.as_slice()).unwrap();
+        let challenge = CephXServerChallenge::decode(&mut challenge.payload.as_slice())?;
+        self.server_challenge = Some(challenge.challenge);
 
-        // TODO: this should be random data.
-        let client_challenge = 13377;
+        let client_challenge = self.config.rng().next_u64();
         let key = CephXAuthenticateKey::compute(challenge.challenge, client_challenge, master_key);
 
         let other_keys = self
@@ -212,7 +316,7 @@ impl ClientConnection<Authenticating> {
 
         frame.write(self.state.format(), &mut self.state.tx_buf);
 
-        self.tx_frame(&frame)
+        Ok(self.tx_frame(&frame))
     }
 
     pub fn recv_none_done(
@@ -224,10 +328,13 @@ impl ClientConnection<Authenticating> {
         } else {
             Ok(self.with_state(|state| ExchangingSignatures {
                 revision: state.revision,
+                peer_features: state.peer_features,
                 encryption: state.encryption,
                 rx_buf: state.rx_buf,
                 tx_buf: state.tx_buf,
                 tickets: Vec::new(),
+                session_key: None,
+                global_id: done.global_id,
             }))
         }
     }
@@ -237,7 +344,6 @@ impl ClientConnection<Authenticating> {
         master_key: &Key,
         done: &AuthDone,
     ) -> Result<ClientConnection<ExchangingSignatures>, AuthError> {
-        // TODO: save/use global ID somewhere?
         let cephx = CephXMessage::decode(&mut done.auth_payload.as_slice())?;
 
         if cephx.ty() != CephXMessageType::GetAuthSessionKey {
@@ -250,58 +356,93 @@ impl ClientConnection<Authenticating> {
         let mut tickets = cephx.payload();
 
         let service_ticket_infos = AuthServiceTicketReply::decode(&mut tickets)?;
-        assert!(tickets.is_empty());
+        if !tickets.is_empty() {
+            return Err(DecodeError::Custom("trailing ticket data".to_string()).into());
+        }
 
         let TicketsAndConnectionSecret {
             tickets,
             connection_secret,
-        } = service_ticket_infos.decrypt(&master_key)?;
+        } = service_ticket_infos.decrypt(master_key)?;
 
         if done.connection_mode == ConMode::Secure {
-            let encryption_key = connection_secret[00..16].try_into().unwrap();
-            let rx_nonce: [u8; 12] = connection_secret[16..28].try_into().unwrap();
-            let tx_nonce: [u8; 12] = connection_secret[28..40].try_into().unwrap();
-
-            let encryption_key = Key::new(
-                // TODO: probably best not to have this creation time be not completely BS
-                Timestamp {
-                    tv_sec: 0,
-                    tv_nsec: 0,
-                },
-                encryption_key,
-            );
-
-            let revision = self.state.revision;
-            self.state.encryption_mut().set_secret_data(
-                revision,
-                encryption_key,
-                rx_nonce,
-                tx_nonce,
-            );
+            self.set_connection_secret(&connection_secret)?;
         }
 
+        let session_key = tickets
+            .iter()
+            .find(|t| t.ty == EntityType::Auth)
+            .map(|t| t.session_ticket.session_key.clone());
+
         Ok(self.with_state(|state| ExchangingSignatures {
             tickets,
+            session_key,
+            global_id: done.global_id,
+            revision: state.revision,
+            peer_features: state.peer_features,
+            encryption: state.encryption,
+            rx_buf: state.rx_buf,
+            tx_buf: state.tx_buf,
+        }))
+    }
+
+    /// Receive the [`AuthDone`] of a service in reply to the
+    /// authorizer sent using [`ClientConnection::send_authorizer`].
+    pub fn recv_authorizer_done(
+        mut self,
+        authorizer: &CephXAuthorizer,
+        done: &AuthDone,
+    ) -> Result<ClientConnection<ExchangingSignatures>, AuthError> {
+        let connection_secret = authorizer.verify_reply(&done.auth_payload)?;
+
+        if done.connection_mode == ConMode::Secure {
+            self.set_connection_secret(&connection_secret)?;
+        }
+
+        Ok(self.with_state(|state| ExchangingSignatures {
+            tickets: Vec::new(),
+            session_key: Some(authorizer.session_key().clone()),
+            global_id: done.global_id,
             revision: state.revision,
+            peer_features: state.peer_features,
             encryption: state.encryption,
             rx_buf: state.rx_buf,
             tx_buf: state.tx_buf,
         }))
     }
+
+    /// Secure the connection using the key and nonces
+    /// in `connection_secret`.
+    fn set_connection_secret(&mut self, connection_secret: &[u8]) -> Result<(), AuthError> {
+        if connection_secret.len() < 40 {
+            return Err(AuthError::UnexpectedData);
+        }
+
+        let encryption_key = connection_secret[00..16].try_into().unwrap();
+        let rx_nonce: [u8; 12] = connection_secret[16..28].try_into().unwrap();
+        let tx_nonce: [u8; 12] = connection_secret[28..40].try_into().unwrap();
+
+        let encryption_key = Key::new(
+            // TODO: probably best not to have this creation time be not completely BS
+            Timestamp {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            encryption_key,
+        );
+
+        let revision = self.state.revision;
+        self.state
+            .encryption_mut()
+            .set_secret_data(revision, encryption_key, rx_nonce, tx_nonce);
+
+        Ok(())
+    }
 }
 
 impl ClientConnection<ExchangingSignatures> {
     pub fn send_signature(&mut self) -> TxFrame<'_> {
-        let auth_ticket = self.state.tickets.iter().find(|t| t.ty == EntityType::Auth);
-
-        let sha256_hmac = if let Some(session_key) =
-            auth_ticket.as_ref().map(|v| &v.session_ticket.session_key)
-        {
-            session_key.hmac_sha256(&self.state.rx_buf)
-        } else {
-            [0u8; 32]
-        };
-
+        let sha256_hmac = auth_signature(self.state.session_key.as_ref(), &self.state.rx_buf);
         let signature = AuthSignature { sha256_hmac };
 
         self.buffer.clear();
@@ -317,14 +458,7 @@ impl ClientConnection<ExchangingSignatures> {
         self,
         signature: &AuthSignature,
     ) -> Result<ClientConnection<Identifying>, String> {
-        let auth_ticket = self.state.tickets.iter().find(|t| t.ty == EntityType::Auth);
-
-        let valid_signature =
-            if let Some(session_key) = auth_ticket.map(|v| &v.session_ticket.session_key) {
-                session_key.hmac_sha256(&self.state.tx_buf)
-            } else {
-                [0u8; 32]
-            };
+        let valid_signature = auth_signature(self.state.session_key.as_ref(), &self.state.tx_buf);
 
         if signature.sha256_hmac != valid_signature {
             return Err("SHA256 mismatch".into());
@@ -332,14 +466,67 @@ impl ClientConnection<ExchangingSignatures> {
 
         Ok(self.with_state(|state| Identifying {
             revision: state.revision,
+            peer_features: state.peer_features,
             encryption: state.encryption,
+            compression: FrameCompression::new(),
             tickets: state.tickets,
+            global_id: state.global_id,
         }))
     }
 }
 
 impl ClientConnection<Identifying> {
+    /// Request compression of the frames exchanged on this connection.
+    ///
+    /// Returns `None` if the peer does not support compression, in
+    /// which case no [`CompressionDone`] should be expected either.
+    pub fn send_compression_request(&mut self) -> Option<TxFrame<'_>> {
+        if !self.state.peer_features.compression() {
+            return None;
+        }
+
+        let methods = self.config.compression_methods();
+        let request = CompressionRequest {
+            is_compress: !methods.is_empty(),
+            preferred_methods: methods.iter().copied().map(u32::from).collect(),
+        };
+
+        self.buffer.clear();
+        request.encode(&mut self.buffer);
+
+        let request = self.buffer.clone();
+        let frame = Frame::new(Tag::CompressionRequest, &[&request]).unwrap();
+
+        Some(self.tx_frame(&frame))
+    }
+
+    /// Receive the response to our [`CompressionRequest`].
+    pub fn recv_compression_done(&mut self, done: &CompressionDone) -> Result<(), String> {
+        if !done.is_compress {
+            return Ok(());
+        }
+
+        let method = CompressionMethod::try_from(done.method)
+            .ok()
+            .filter(|m| self.config.compression_methods().contains(m))
+            .ok_or_else(|| {
+                format!(
+                    "Peer selected unrequested compression method {}",
+                    done.method
+                )
+            })?;
+
+        let min_size = self.config.compression_min_size();
+        self.state.compression.set_method(method, min_size);
+
+        Ok(())
+    }
+
     pub fn send_client_ident(&mut self, ident: &ClientIdent) -> TxFrame<'_> {
+        self.session.addresses = ident.addresses.clone();
+        self.session.client_cookie = ident.cookie;
+        self.session.global_seq = ident.global_seq;
+
         self.buffer.clear();
         ident.encode(&mut self.buffer);
 
@@ -349,18 +536,85 @@ impl ClientConnection<Identifying> {
         self.tx_frame(&frame)
     }
 
-    #[expect(unused)]
     pub fn recv_server_ident(
-        self,
+        mut self,
         ident: &ServerIdent,
     ) -> Result<ClientConnection<Active>, String> {
-        // TODO: verify details from `ident`.
+        // TODO: verify more details from `ident`.
+        self.session.server_cookie = ident.cookie;
+
+        Ok(self.into_active())
+    }
+
+    /// Resume the current session, which must be
+    /// [established](Session::is_established).
+    pub fn send_session_reconnect(&mut self) -> TxFrame<'_> {
+        let reconnect = self.session.reconnect();
+
+        self.buffer.clear();
+        reconnect.encode(&mut self.buffer);
+
+        let reconnect = self.buffer.clone();
+        let frame = Frame::new(Tag::SessionReconnect, &[&reconnect]).unwrap();
 
-        Ok(self.with_state(|state| Active {
+        self.tx_frame(&frame)
+    }
+
+    /// Receive confirmation that the session was resumed.
+    ///
+    /// Messages that the peer did not receive yet can be retransmitted
+    /// using [`ClientConnection::replay_next`].
+    pub fn recv_session_reconnect_ok(
+        mut self,
+        ok: &SessionReconnectOk,
+    ) -> ClientConnection<Active> {
+        self.session.acked(ok.msg_seq);
+        self.into_active()
+    }
+
+    /// Receive a request to retry resuming the session with a
+    /// higher `connect_seq`, and send the new [`SessionReconnect`].
+    pub fn recv_session_retry(&mut self, retry: &SessionRetry) -> TxFrame<'_> {
+        self.session.connect_seq = retry.connect_seq + 1;
+        self.send_session_reconnect()
+    }
+
+    /// Receive a request to retry resuming the session with a
+    /// higher `global_seq`, and send the new [`SessionReconnect`].
+    pub fn recv_session_retry_global(&mut self, retry: &SessionRetryGlobal) -> TxFrame<'_> {
+        self.session.global_seq = self.session.global_seq.max(retry.global_seq) + 1;
+        self.send_session_reconnect()
+    }
+
+    /// Receive a notification that the session could not be resumed.
+    ///
+    /// A new session must be established using
+    /// [`ClientConnection::send_client_ident`].
+    pub fn recv_session_reset(&mut self, reset: &SessionReset) {
+        self.session.reset(reset);
+    }
+
+    /// Receive a request to back off. The connection should be
+    /// closed, and a new connection should be attempted later.
+    pub fn recv_wait(mut self, _wait: &Wait) -> ClientConnection<Inactive> {
+        self.session.next_connection();
+        ClientConnection::resume(self.config, self.session)
+    }
+
+    fn into_active(self) -> ClientConnection<Active> {
+        // All messages that have not been acknowledged must be
+        // retransmitted on the new connection.
+        let replay_until = self.session.unacked.back().map(|m| m.seq).unwrap_or(0);
+
+        self.with_state(|state| Active {
             revision: state.revision,
             encryption: state.encryption,
-            _tickets: state.tickets,
-        }))
+            compression: state.compression,
+            replayed: 0,
+            replay_until,
+            tickets: state.tickets,
+            global_id: state.global_id,
+        })
     }
 }
 
@@ -381,8 +635,93 @@ impl ClientConnection<Active> {
         self.tx_frame(&frame)
     }
 
-    pub fn send_raw<'me>(&'me mut self, frame: &Frame) -> TxFrame<'me> {
-        self.tx_frame(frame)
+    /// Send a message consisting of `header` and `data` segments.
+    ///
+    /// The sequence number of the message is assigned by the connection,
+    /// and it acknowledges all messages received so far.
+    ///
+    /// Fails if the peer has not acknowledged the [maximum
+    /// amount](Config::max_unacked) of sent messages yet.
+    pub fn send_message<'me>(
+        &'me mut self,
+        mut header: CephMessageHeader2,
+        data: &[&[u8]],
+    ) -> Result<TxFrame<'me>, SendError> {
+        self.session.check_unacked(self.config.max_unacked())?;
+        self.session.prepare(&mut header);
+
+        let header = header.to_vec();
+        let mut segments = vec![header.as_slice()];
+        segments.extend_from_slice(data);
+
+        let frame = Frame::new(Tag::Message, &segments).unwrap();
+        self.send_raw(&frame)
+    }
+
+    /// Acknowledge the messages that were received, but not yet
+    /// acknowledged by a sent message.
+    ///
+    /// Returns `None` if there is nothing to acknowledge.
+    pub fn send_ack(&mut self) -> Option<TxFrame<'_>> {
+        let seq = self.session.ack()?;
+        Some(self.send(Ack { seq }))
+    }
+
+    /// Send `frame` as-is.
+    ///
+    /// Message frames are subject to the same limit as
+    /// [`ClientConnection::send_message`].
+    pub fn send_raw<'me>(&'me mut self, frame: &Frame) -> Result<TxFrame<'me>, SendError> {
+        if frame.tag() == Tag::Message {
+            self.session.check_unacked(self.config.max_unacked())?;
+        }
+
+        self.session.sent(frame);
+        Ok(self.tx_frame(frame))
+    }
+
+    /// Retransmit the next message that was sent on a previous connection,
+    /// but not yet received by the peer.
+    ///
+    /// This should be called until it returns `None` before sending any
+    /// new messages.
+    pub fn replay_next(&mut self) -> Option<TxFrame<'_>> {
+        let replayed = self.state.replayed;
+        let replay_until = self.state.replay_until;
+
+        let message = self
+            .session
+            .unacked
+            .iter()
+            .find(|m| m.seq > replayed && m.seq <= replay_until)?
+            .clone();
+
+        self.state.replayed = message.seq;
+        Some(self.tx_frame(&message.frame()))
+    }
+
+    /// The global ID that the peer assigned to us while authenticating.
+    pub fn global_id(&self) -> u64 {
+        self.state.global_id
+    }
+
+    /// The service tickets that we obtained while authenticating.
+    pub fn tickets(&self) -> &[Ticket] {
+        &self.state.tickets
+    }
+
+    /// The CephX challenge of the server, if we authenticated using
+    /// our key. A [`CephXAuthenticate`](cephx::CephXAuthenticate) sent
+    /// to renew the auth ticket must respond to it.
+    pub fn server_challenge(&self) -> Option<u64> {
+        self.server_challenge
+    }
+
+    /// Close this connection, and prepare a new connection that
+    /// resumes the current session.
+    pub fn reconnect(mut self) -> ClientConnection<Inactive> {
+        self.session.next_connection();
+        ClientConnection::resume(self.config, self.session)
     }
 }
 
@@ -395,12 +734,7 @@ where
     }
 
     fn tx_frame<'me>(&'me mut self, frame: &Frame<'_>) -> TxFrame<'me> {
-        self.buffer.clear();
-        frame.send(
-            self.state.format(),
-            self.state.encryption_mut(),
-            &mut self.buffer,
-        )
+        tx_frame(&mut self.state, &mut self.buffer, frame)
     }
 
     pub fn start_rx<'enc, 'buf>(
@@ -411,20 +745,76 @@ where
     }
 
     pub fn finish_rx(&mut self, frame: RxFrame<'_, Completed>) -> Result<Message, DecodeError> {
-        let frame = self.finish_rx_raw(frame)?;
+        let frame = self
+            .finish_rx_raw(frame)?
+            .ok_or_else(|| DecodeError::Custom("Received duplicate message".into()))?;
+
         Message::decode(frame.tag(), frame.segments()[0])
     }
 
+    /// Finish receiving `frame`.
+    ///
+    /// Returns `None` if `frame` contains a message that was already
+    /// received (e.g. because the peer retransmitted it after a reconnect),
+    /// in which case it should be ignored.
     pub fn finish_rx_raw<'frame>(
         &mut self,
         frame: RxFrame<'frame, Completed>,
-    ) -> Result<Frame<'frame>, DecodeError> {
-        self.state.recv_data(frame.preamble_data());
+    ) -> Result<Option<Frame<'frame>>, DecodeError> {
+        finish_rx_raw(&mut self.state, &mut self.session, frame)
+    }
+}
+
+/// The signature of the data exchanged before the connection was secured,
+/// computed using `session_key` (if any).
+fn auth_signature(session_key: Option<&Key>, data: &[u8]) -> [u8; 32] {
+    if let Some(session_key) = session_key {
+        session_key.hmac_sha256(data)
+    } else {
+        [0u8; 32]
+    }
+}
+
+fn tx_frame<'me, T>(state: &'me mut T, buffer: &'me mut Vec<u8>, frame: &Frame<'_>) -> TxFrame<'me>
+where
+    T: Established,
+{
+    let mut compressed = Vec::new();
+    let compressed = state.compression().compress(frame, &mut compressed);
+    let frame = compressed.as_ref().unwrap_or(frame);
 
-        let (preamble, data) = frame.into_preamble_and_data();
-        self.state.recv_data(data);
+    buffer.clear();
+    frame.send(state.format(), state.encryption_mut(), buffer)
+}
 
-        Frame::decode(&preamble, data)
+fn finish_rx_raw<'frame, T>(
+    state: &mut T,
+    session: &mut Session,
+    mut frame: RxFrame<'frame, Completed>,
+) -> Result<Option<Frame<'frame>>, DecodeError>
+where
+    T: Established,
+{
+    state.recv_data(frame.preamble_data());
+
+    frame
+        .decompress(state.compression())
+        .map_err(|e| DecodeError::Custom(format!("Failed to decompress frame: {e:?}")))?;
+
+    let (preamble, data) = frame.into_preamble_and_data();
+    state.recv_data(data);
+
+    let frame = Frame::decode(&preamble, data)?;
+
+    if frame.tag() == Tag::Ack {
+        let ack = Ack::decode(&mut &*frame.segments()[0])?;
+        session.acked(ack.seq);
+    }
+
+    if session.received(&frame) {
+        Ok(Some(frame))
+    } else {
+        Ok(None)
     }
 }
 
@@ -461,6 +851,15 @@ message! {
     Keepalive,
     KeepaliveAck,
     IdentMissingFeatures,
+    CompressionRequest,
+    CompressionDone,
+    Ack,
+    SessionReconnect,
+    SessionReconnectOk,
+    SessionRetry,
+    SessionRetryGlobal,
+    SessionReset,
+    Wait,
 }
 
 impl Message {
@@ -478,6 +877,15 @@ impl Message {
             Message::AuthBadMethod(_) => Tag::AuthBadMethod,
             Message::AuthReplyMore(_) => Tag::AuthReplyMore,
             Message::AuthRequestMore(_) => Tag::AuthRequestMore,
+            Message::CompressionRequest(_) => Tag::CompressionRequest,
+            Message::CompressionDone(_) => Tag::CompressionDone,
+            Message::Ack(_) => Tag::Ack,
+            Message::SessionReconnect(_) => Tag::SessionReconnect,
+            Message::SessionReconnectOk(_) => Tag::SessionReconnectOk,
+            Message::SessionRetry(_) => Tag::SessionRetry,
+            Message::SessionRetryGlobal(_) => Tag::SessionRetryGlobal,
+            Message::SessionReset(_) => Tag::SessionReset,
+            Message::Wait(_) => Tag::Wait,
         }
     }
 
@@ -487,16 +895,25 @@ impl Message {
             Message::ClientIdent(client_ident) => client_ident.encode(buffer),
             Message::AuthRequest(auth_request) => auth_request.encode(buffer),
             Message::Keepalive(keepalive) => keepalive.encode(buffer),
-            Message::AuthDone(_) => todo!(),
+            Message::AuthDone(done) => done.encode(buffer),
             Message::AuthSignature(signature) => signature.encode(buffer),
             Message::IdentMissingFeatures(ident_missing_features) => {
                 ident_missing_features.encode(buffer)
             }
-            Message::ServerIdent(_) => todo!(),
-            Message::KeepaliveAck(_) => todo!(),
-            Message::AuthBadMethod(_) => todo!(),
-            Message::AuthReplyMore(_) => todo!(),
+            Message::ServerIdent(server_ident) => server_ident.encode(buffer),
+            Message::KeepaliveAck(keepalive_ack) => keepalive_ack.encode(buffer),
+            Message::AuthBadMethod(bad_method) => bad_method.encode(buffer),
+            Message::AuthReplyMore(auth_reply_more) => auth_reply_more.encode(buffer),
             Message::AuthRequestMore(auth_request_more) => auth_request_more.encode(buffer),
+            Message::CompressionRequest(request) => request.encode(buffer),
+            Message::CompressionDone(done) => done.encode(buffer),
+            Message::Ack(ack) => ack.encode(buffer),
+            Message::SessionReconnect(reconnect) => reconnect.encode(buffer),
+            Message::SessionReconnectOk(ok) => ok.encode(buffer),
+            Message::SessionRetry(retry) => retry.encode(buffer),
+            Message::SessionRetryGlobal(retry) => retry.encode(buffer),
+            Message::SessionReset(reset) => reset.encode(buffer),
+            Message::Wait(wait) => wait.encode(buffer),
         }
     }
 
@@ -510,12 +927,31 @@ impl Message {
                 IdentMissingFeatures::decode(&mut data)?,
             )),
             Tag::ServerIdent => Ok(Self::ServerIdent(ServerIdent::decode(&mut data)?)),
+            Tag::Keepalive2 => Ok(Self::Keepalive(Keepalive::decode(&mut data)?)),
             Tag::Keepalive2Ack => Ok(Self::KeepaliveAck(KeepaliveAck::decode(&mut data)?)),
             Tag::AuthBadMethod => Ok(Self::AuthBadMethod(AuthBadMethod::decode(&mut data)?)),
             Tag::AuthRequest => Ok(Self::AuthRequest(AuthRequest::decode(&mut data)?)),
             Tag::AuthReplyMore => Ok(Self::AuthReplyMore(AuthReplyMore::decode(&mut data)?)),
             Tag::AuthRequestMore => Ok(Self::AuthRequestMore(AuthRequestMore::decode(&mut data)?)),
-            _ => todo!("Unsupported tag {tag:?}"),
+            Tag::CompressionRequest => Ok(Self::CompressionRequest(CompressionRequest::decode(
+                &mut data,
+            )?)),
+            Tag::CompressionDone => Ok(Self::CompressionDone(CompressionDone::decode(&mut data)?)),
+            Tag::Ack => Ok(Self::Ack(Ack::decode(&mut data)?)),
+            Tag::SessionReconnect => {
+                Ok(Self::SessionReconnect(SessionReconnect::decode(&mut data)?))
+            }
+            Tag::SessionReconnectOk => Ok(Self::SessionReconnectOk(SessionReconnectOk::decode(
+                &mut data,
+            )?)),
+            Tag::SessionRetry => Ok(Self::SessionRetry(SessionRetry::decode(&mut data)?)),
+            Tag::SessionRetryGlobal => Ok(Self::SessionRetryGlobal(SessionRetryGlobal::decode(
+                &mut data,
+            )?)),
+            Tag::SessionReset => Ok(Self::SessionReset(SessionReset::decode(&mut data)?)),
+            Tag::Wait => Ok(Self::Wait(Wait::decode(&mut data)?)),
+            // Messages are decoded using `msgr2::frames::Message`.
+            Tag::Message => Err(DecodeError::unknown_value("Message", format!("{tag:?}"))),
         }
     }
 }
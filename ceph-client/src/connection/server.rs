@@ -0,0 +1,1157 @@
+//! A sans-IO implementation of the server side of a `msgr2` connection.
+//!
+//! This accepts authentication-less and CephX connections, and is
+//! mostly useful for implementing in-process peers (e.g. a fake
+//! monitor) for testing.
+
+use std::{collections::HashMap, time::SystemTime};
+
+use ceph_foundation::{
+    CephFeatureSet, Decode, DecodeError, Encode, Timestamp,
+    crypto::{Key, encode_encrypt},
+    entity::{EntityAddress, EntityName, EntityType},
+};
+use ceph_messages::{MessageAuth, MessageAuthReply};
+use cephx::{
+    AuthCapsInfo, AuthServiceTicketReply, AuthTicket, CephXAuthenticate, CephXAuthenticateKey,
+    CephXMessage, CephXMessageType, CephXServerChallenge, CephXServiceTicket,
+    CephXServiceTicketInfo, CephXTicketBlob, GetPrincipalSessionKey, MaybeEncryptedCephXTicketBlob,
+    PrincipalSessionKeyReply, Ticket, verify_authorizer,
+};
+use msgr2::{
+    Frame, FrameEncryption, Revision, Tag,
+    compression::{CompressionMethod, DEFAULT_MIN_SIZE, FrameCompression},
+    frames::{
+        Ack, AuthBadMethod, AuthDone, AuthMethod, AuthMethodCephX, AuthMethodNone, AuthReplyMore,
+        AuthRequest, AuthRequestMore, AuthSignature, Banner, ClientIdent, CompressionDone,
+        CompressionRequest, ConMode, Hello, IdentMissingFeatures, MsgrFeatures, ServerIdent,
+        SessionReconnect, SessionReconnectOk, SessionReset, Wait,
+    },
+    wire::{Completed, RxFrame, TxFrame, Unstarted},
+};
+
+use super::{
+    Message, Session, auth_signature, finish_rx_raw,
+    state::{
+        Active, Authenticating, Established, ExchangeHello, ExchangingSignatures, Identifying,
+        Inactive,
+    },
+    tx_frame,
+};
+use crate::header::CephMessageHeader2;
+
+/// The default validity of tickets handed out by a [`ServerConnection`], in seconds.
+const TICKET_VALIDITY: u32 = 3600;
+
+/// The first byte of the payload of a CephX [`AuthRequest`] that contains
+/// an authorizer, as opposed to an [`AuthMethodCephX`] (`AUTH_MODE_MON`).
+const AUTH_MODE_AUTHORIZER: u8 = 1;
+
+/// The configuration of a [`ServerConnection`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    support_rev21: bool,
+    auth_methods: Vec<AuthMethod>,
+    con_modes: Vec<ConMode>,
+    keys: HashMap<EntityName, Key>,
+    service_key: Key,
+    ticket_validity: HashMap<EntityType, u32>,
+    authorizer_challenge: bool,
+    global_id: u64,
+    gid: i64,
+    addresses: Vec<EntityAddress>,
+    supported_features: CephFeatureSet,
+    required_features: CephFeatureSet,
+    compression_methods: Vec<CompressionMethod>,
+    compression_min_size: usize,
+}
+
+impl ServerConfig {
+    /// Create a new configuration that allows CephX and authentication-less
+    /// connections in both secure and CRC mode.
+    pub fn new(support_rev21: bool) -> Self {
+        Self {
+            support_rev21,
+            auth_methods: vec![AuthMethod::CephX, AuthMethod::None],
+            con_modes: vec![ConMode::Secure, ConMode::Crc],
+            keys: HashMap::new(),
+            service_key: Key::new(now(), random()),
+            ticket_validity: HashMap::new(),
+            authorizer_challenge: false,
+            global_id: 1,
+            gid: 0,
+            addresses: Vec::new(),
+            supported_features: CephFeatureSet::ALL,
+            required_features: CephFeatureSet::EMPTY,
+            compression_methods: Vec::new(),
+            compression_min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    pub fn support_rev21(&self) -> bool {
+        self.support_rev21
+    }
+
+    /// Set the allowed authentication methods, in order of preference.
+    pub fn set_auth_methods(&mut self, methods: &[AuthMethod]) {
+        self.auth_methods = methods.to_vec();
+    }
+
+    pub fn auth_methods(&self) -> &[AuthMethod] {
+        &self.auth_methods
+    }
+
+    /// Set the allowed connection modes, in order of preference.
+    pub fn set_con_modes(&mut self, modes: &[ConMode]) {
+        self.con_modes = modes.to_vec();
+    }
+
+    pub fn con_modes(&self) -> &[ConMode] {
+        &self.con_modes
+    }
+
+    /// Allow `name` to authenticate using CephX with `key`.
+    pub fn add_key(&mut self, name: EntityName, key: Key) {
+        self.keys.insert(name, key);
+    }
+
+    pub fn key(&self, name: &EntityName) -> Option<&Key> {
+        self.keys.get(name)
+    }
+
+    /// Set the key that the ticket blobs handed out to clients are
+    /// encrypted with, and that authorizers are verified with.
+    ///
+    /// Servers that share this key accept each other's tickets.
+    pub fn set_service_key(&mut self, key: Key) {
+        self.service_key = key;
+    }
+
+    pub fn service_key(&self) -> &Key {
+        &self.service_key
+    }
+
+    /// Set the validity (in seconds) of the tickets for `service`
+    /// that are handed out.
+    pub fn set_ticket_validity(&mut self, service: EntityType, seconds: u32) {
+        self.ticket_validity.insert(service, seconds);
+    }
+
+    pub fn ticket_validity(&self, service: EntityType) -> u32 {
+        self.ticket_validity
+            .get(&service)
+            .copied()
+            .unwrap_or(TICKET_VALIDITY)
+    }
+
+    /// Require clients that authenticate using an authorizer to respond
+    /// to a challenge, like daemons with `cephx_require_version = 2`.
+    pub fn set_authorizer_challenge(&mut self, challenge: bool) {
+        self.authorizer_challenge = challenge;
+    }
+
+    pub fn authorizer_challenge(&self) -> bool {
+        self.authorizer_challenge
+    }
+
+    /// Set the global ID that is assigned to clients that do not
+    /// request a specific global ID.
+    pub fn set_global_id(&mut self, global_id: u64) {
+        self.global_id = global_id;
+    }
+
+    pub fn global_id(&self) -> u64 {
+        self.global_id
+    }
+
+    /// Set the identity of the server, as sent in [`ServerIdent`].
+    pub fn set_identity(&mut self, gid: i64, addresses: Vec<EntityAddress>) {
+        self.gid = gid;
+        self.addresses = addresses;
+    }
+
+    /// Set the features supported and required by the server.
+    pub fn set_features(&mut self, supported: CephFeatureSet, required: CephFeatureSet) {
+        self.supported_features = supported;
+        self.required_features = required;
+    }
+
+    /// Accept compression of frames of at least `min_size` bytes,
+    /// using one of `methods`.
+    ///
+    /// Methods that are not supported by this build are ignored.
+    pub fn accept_compression(&mut self, methods: &[CompressionMethod], min_size: usize) {
+        self.compression_methods = methods
+            .iter()
+            .copied()
+            .filter(CompressionMethod::is_supported)
+            .collect();
+        self.compression_min_size = min_size;
+    }
+
+    pub fn compression_methods(&self) -> &[CompressionMethod] {
+        &self.compression_methods
+    }
+
+    /// Create a ticket for `ty`, issued to `name` with `global_id`.
+    fn ticket(&self, ty: EntityType, name: &EntityName, global_id: u64) -> Ticket {
+        let created = now();
+        let seconds = self.ticket_validity(ty);
+        let validity = Timestamp::new(seconds, 0);
+        let expires = Timestamp::new(created.tv_sec + seconds, created.tv_nsec);
+        let session_key = Key::new(created.clone(), random());
+
+        let info = CephXServiceTicketInfo {
+            auth_ticket: AuthTicket {
+                name: name.clone(),
+                global_id,
+                created,
+                expires,
+                caps: AuthCapsInfo {
+                    allow_all: true,
+                    caps: Vec::new(),
+                },
+                flags: 0,
+            },
+            session_key: session_key.clone(),
+        };
+
+        // Clients treat the ticket blob as opaque, so we always use
+        // the same service key.
+        let refresh_ticket = MaybeEncryptedCephXTicketBlob::Unencrypted(CephXTicketBlob {
+            secret_id: 0,
+            blob: encode_encrypt(&info, &self.service_key),
+        });
+
+        Ticket {
+            ty,
+            session_ticket: CephXServiceTicket {
+                session_key,
+                validity,
+            },
+            refresh_ticket,
+        }
+    }
+}
+
+/// An error that occurred while accepting a connection.
+#[derive(Clone, Debug)]
+pub enum ServerError {
+    Decode(DecodeError),
+    /// The requested authentication method is not allowed.
+    BadMethod(AuthMethod),
+    /// None of the preferred connection modes are allowed.
+    BadConMode,
+    /// No key is known for the authenticating entity.
+    UnknownEntity(EntityName),
+    UnexpectedCephXMessage {
+        got: CephXMessageType,
+        expected: CephXMessageType,
+    },
+    /// The client failed to prove that it knows its key.
+    BadAuthenticateKey,
+    /// The authorizer sent by the client could not be verified.
+    BadAuthorizer,
+    /// An [`AuthRequestMore`] was received without a preceding
+    /// CephX [`AuthRequest`].
+    UnexpectedAuthRequestMore,
+    /// Authentication has not completed yet.
+    AuthIncomplete,
+    /// The signature sent by the client was incorrect.
+    BadSignature,
+    /// The client does not support some features that are
+    /// required.
+    MissingFeatures(CephFeatureSet),
+    /// The client has not identified itself yet.
+    NotIdentified,
+}
+
+impl From<DecodeError> for ServerError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+/// The progress of authentication on a [`ServerConnection`].
+#[derive(Debug, Default)]
+enum AuthProgress {
+    #[default]
+    None,
+    Challenged {
+        name: EntityName,
+        global_id: u64,
+        con_mode: ConMode,
+        challenge: u64,
+    },
+    /// An authorizer was challenged.
+    AuthorizerChallenged { con_mode: ConMode, challenge: u64 },
+    Done {
+        global_id: u64,
+        con_mode: ConMode,
+        tickets: Vec<Ticket>,
+        session_key: Option<Key>,
+        connection_secret: Vec<u8>,
+    },
+}
+
+/// An entity that authenticated using CephX, and the challenge
+/// it was sent, which it reuses to renew its auth ticket.
+#[derive(Debug, Clone)]
+struct CephXSession {
+    name: EntityName,
+    global_id: u64,
+    challenge: u64,
+}
+
+#[derive(Debug)]
+pub struct ServerConnection<T> {
+    state: T,
+    config: ServerConfig,
+    session: Session,
+    auth_method: AuthMethod,
+    auth: AuthProgress,
+    cephx: Option<CephXSession>,
+    buffer: Vec<u8>,
+}
+
+impl<T> ServerConnection<T> {
+    pub fn with_state<F, S>(self, state: F) -> ServerConnection<S>
+    where
+        F: FnOnce(T) -> S,
+    {
+        ServerConnection {
+            state: state(self.state),
+            config: self.config,
+            session: self.session,
+            auth_method: self.auth_method,
+            auth: self.auth,
+            cephx: self.cephx,
+            buffer: self.buffer,
+        }
+    }
+
+    /// Verify the proof of `authenticate` that the entity of `session`
+    /// holds its key, returning the auth ticket, the other requested
+    /// tickets, and the encrypted reply containing all of them.
+    fn authenticate(
+        &self,
+        session: &CephXSession,
+        authenticate: &CephXAuthenticate,
+        connection_secret: &[u8],
+    ) -> Result<(Ticket, Vec<Ticket>, AuthServiceTicketReply), ServerError> {
+        let master_key = self
+            .config
+            .key(&session.name)
+            .ok_or_else(|| ServerError::UnknownEntity(session.name.clone()))?;
+
+        let expected_key = CephXAuthenticateKey::compute(
+            session.challenge,
+            authenticate.client_challenge,
+            master_key,
+        );
+
+        if authenticate.key != expected_key {
+            return Err(ServerError::BadAuthenticateKey);
+        }
+
+        let auth_ticket = self
+            .config
+            .ticket(EntityType::Auth, &session.name, session.global_id);
+
+        // The auth ticket is always sent, so it is not repeated
+        // in the extra tickets.
+        let tickets: Vec<_> = authenticate
+            .other_keys
+            .iter()
+            .filter(|ty| **ty != EntityType::Auth)
+            .map(|ty| self.config.ticket(*ty, &session.name, session.global_id))
+            .collect();
+
+        let reply =
+            AuthServiceTicketReply::encrypt(master_key, &auth_ticket, &tickets, connection_secret);
+
+        Ok((auth_ticket, tickets, reply))
+    }
+}
+
+impl ServerConnection<Inactive> {
+    pub fn new(config: ServerConfig) -> Self {
+        Self::resume(config, Session::default())
+    }
+
+    /// Create a new connection that accepts a client resuming
+    /// `session`, which was established on an earlier connection.
+    ///
+    /// See [`ServerConnection::recv_session_reconnect`].
+    pub fn resume(config: ServerConfig, session: Session) -> Self {
+        let mut me = Self {
+            state: Inactive {
+                _reserved: (),
+                rx_buf: Vec::new(),
+                tx_buf: Vec::new(),
+            },
+            config,
+            session,
+            auth_method: AuthMethod::Unknown,
+            auth: AuthProgress::None,
+            cephx: None,
+            buffer: Vec::new(),
+        };
+
+        let banner = me.banner().to_bytes();
+        me.state.tx_buf.extend_from_slice(banner.as_slice());
+
+        me
+    }
+
+    pub fn banner(&self) -> Banner {
+        let mut features = MsgrFeatures::empty();
+
+        if self.config.support_rev21() {
+            features.set_revision_21(true);
+        }
+
+        features.set_compression(true);
+
+        Banner::new(features, MsgrFeatures::empty())
+    }
+
+    /// Receive the banner of the connecting client.
+    pub fn recv_banner(
+        mut self,
+        banner: &Banner,
+    ) -> Result<ServerConnection<ExchangeHello>, String> {
+        self.state
+            .rx_buf
+            .extend_from_slice(banner.to_bytes().as_slice());
+
+        if !self.banner().compatible(banner) {
+            return Err(format!(
+                "Peer requires unknown msgr2 features that we do not support. Supported: {:?}, peer required: 0x{:?}",
+                self.banner().supported(),
+                banner.required()
+            ));
+        }
+
+        let revision = if self.config.support_rev21() && banner.supported().revision_21() {
+            Revision::Rev1
+        } else {
+            Revision::Rev0
+        };
+
+        Ok(self.with_state(|state| ExchangeHello {
+            rx_buf: state.rx_buf,
+            tx_buf: state.tx_buf,
+            revision,
+            peer_features: *banner.supported(),
+            encryption: FrameEncryption::new(),
+        }))
+    }
+}
+
+impl ServerConnection<ExchangeHello> {
+    pub fn send_hello<'me>(&'me mut self, hello: &Hello) -> TxFrame<'me> {
+        self.buffer.clear();
+        hello.encode(&mut self.buffer);
+        let hello = self.buffer.clone();
+
+        let frame = Frame::new(Tag::Hello, &[&hello]).unwrap();
+
+        frame.write(self.state.format(), &mut self.state.tx_buf);
+
+        self.tx_frame(&frame)
+    }
+
+    pub fn recv_hello(self, _hello: &Hello) -> ServerConnection<Authenticating> {
+        self.with_state(|state| Authenticating {
+            revision: state.revision,
+            peer_features: state.peer_features,
+            encryption: state.encryption,
+            rx_buf: state.rx_buf,
+            tx_buf: state.tx_buf,
+        })
+    }
+}
+
+impl ServerConnection<Authenticating> {
+    /// Receive an authentication request, and send either an
+    /// [`AuthDone`] (for authentication-less connections) or an
+    /// [`AuthReplyMore`] containing a CephX server challenge.
+    ///
+    /// If the request is rejected, [`ServerConnection::send_auth_bad_method`]
+    /// should be used to inform the client.
+    pub fn recv_auth_request(&mut self, request: &AuthRequest) -> Result<TxFrame<'_>, ServerError> {
+        let method = request.method();
+        self.auth_method = method;
+        self.auth = AuthProgress::None;
+
+        if !self.config.auth_methods().contains(&method) {
+            return Err(ServerError::BadMethod(method));
+        }
+
+        // Authentication-less connections have no secret to secure
+        // the connection with.
+        let con_mode = request
+            .preferred_modes()
+            .iter()
+            .copied()
+            .filter(|m| method != AuthMethod::None || *m == ConMode::Crc)
+            .find(|m| self.config.con_modes().contains(m))
+            .ok_or(ServerError::BadConMode)?;
+
+        let mut payload = request.auth_payload();
+
+        match method {
+            AuthMethod::None => {
+                let none = AuthMethodNone::decode(&mut payload)?;
+                let global_id = self.assign_global_id(none.global_id);
+
+                self.auth = AuthProgress::Done {
+                    global_id,
+                    con_mode,
+                    tickets: Vec::new(),
+                    session_key: None,
+                    connection_secret: Vec::new(),
+                };
+
+                Ok(self.send_auth_done(global_id, con_mode, Vec::new()))
+            }
+            AuthMethod::CephX if payload.first() == Some(&AUTH_MODE_AUTHORIZER) => {
+                self.recv_authorizer(payload, con_mode, None)
+            }
+            AuthMethod::CephX => {
+                let cephx = AuthMethodCephX::decode(&mut payload)?;
+
+                if self.config.key(&cephx.name).is_none() {
+                    return Err(ServerError::UnknownEntity(cephx.name));
+                }
+
+                let challenge = CephXServerChallenge {
+                    challenge: u64::from_le_bytes(random()),
+                };
+
+                self.auth = AuthProgress::Challenged {
+                    global_id: self.assign_global_id(cephx.global_id),
+                    name: cephx.name,
+                    con_mode,
+                    challenge: challenge.challenge,
+                };
+
+                let more = AuthReplyMore {
+                    payload: challenge.to_vec(),
+                };
+
+                Ok(self.write_auth_frame(Tag::AuthReplyMore, &more))
+            }
+            method => Err(ServerError::BadMethod(method)),
+        }
+    }
+
+    /// Receive the authorizer in `payload`, and send either an [`AuthDone`],
+    /// or an [`AuthReplyMore`] containing a challenge if the authorizer
+    /// does not respond to the `challenge` that was sent (if any).
+    fn recv_authorizer(
+        &mut self,
+        payload: &[u8],
+        con_mode: ConMode,
+        challenge: Option<u64>,
+    ) -> Result<TxFrame<'_>, ServerError> {
+        let authorizer = verify_authorizer(payload, &self.config.service_key)
+            .map_err(|_| ServerError::BadAuthorizer)?;
+
+        match challenge {
+            Some(challenge) if !authorizer.responds_to(challenge) => {
+                return Err(ServerError::BadAuthorizer);
+            }
+            None if self.config.authorizer_challenge() => {
+                let challenge = u64::from_le_bytes(random());
+                self.auth = AuthProgress::AuthorizerChallenged {
+                    con_mode,
+                    challenge,
+                };
+
+                let more = AuthReplyMore {
+                    payload: authorizer.challenge(challenge),
+                };
+
+                return Ok(self.write_auth_frame(Tag::AuthReplyMore, &more));
+            }
+            _ => {}
+        }
+
+        // In CRC mode, the secret is empty and the reply is
+        // encoded as version 1, like Ceph does.
+        let connection_secret = Self::connection_secret(con_mode);
+        let reply = authorizer.reply(&connection_secret);
+        let global_id = authorizer.global_id;
+
+        self.auth = AuthProgress::Done {
+            global_id,
+            con_mode,
+            tickets: Vec::new(),
+            session_key: Some(authorizer.ticket_info.session_key),
+            connection_secret,
+        };
+
+        Ok(self.send_auth_done(global_id, con_mode, reply))
+    }
+
+    /// Receive the response to a CephX server challenge, and send an
+    /// [`AuthDone`] containing the requested tickets.
+    ///
+    /// This also receives an authorizer that responds to a challenge.
+    ///
+    /// If the request is rejected, [`ServerConnection::send_auth_bad_method`]
+    /// should be used to inform the client.
+    pub fn recv_auth_request_more(
+        &mut self,
+        more: &AuthRequestMore,
+    ) -> Result<TxFrame<'_>, ServerError> {
+        if let AuthProgress::AuthorizerChallenged {
+            con_mode,
+            challenge,
+        } = self.auth
+        {
+            self.auth = AuthProgress::None;
+            return self.recv_authorizer(&more.payload, con_mode, Some(challenge));
+        }
+
+        let AuthProgress::Challenged {
+            name,
+            global_id,
+            con_mode,
+            challenge,
+        } = core::mem::take(&mut self.auth)
+        else {
+            return Err(ServerError::UnexpectedAuthRequestMore);
+        };
+
+        let message = CephXMessage::decode_request(&mut more.payload.as_slice())?;
+
+        if message.ty() != CephXMessageType::GetAuthSessionKey {
+            return Err(ServerError::UnexpectedCephXMessage {
+                got: message.ty(),
+                expected: CephXMessageType::GetAuthSessionKey,
+            });
+        }
+
+        let authenticate = CephXAuthenticate::decode(&mut message.payload())?;
+
+        let session = CephXSession {
+            name,
+            global_id,
+            challenge,
+        };
+
+        let connection_secret = Self::connection_secret(con_mode);
+        let (auth_ticket, mut tickets, reply) =
+            self.authenticate(&session, &authenticate, &connection_secret)?;
+
+        let mut payload = Vec::new();
+        CephXMessage::new(CephXMessageType::GetAuthSessionKey, reply).encode_reply(&mut payload);
+
+        self.cephx = Some(session);
+
+        let session_key = Some(auth_ticket.session_ticket.session_key.clone());
+        tickets.push(auth_ticket);
+
+        self.auth = AuthProgress::Done {
+            global_id,
+            con_mode,
+            tickets,
+            session_key,
+            connection_secret,
+        };
+
+        Ok(self.send_auth_done(global_id, con_mode, payload))
+    }
+
+    /// Inform the client that its authentication request was rejected
+    /// because of `error`.
+    pub fn send_auth_bad_method(&mut self, error: &ServerError) -> TxFrame<'_> {
+        let result = match error {
+            ServerError::BadMethod(_) | ServerError::BadConMode => -95, // EOPNOTSUPP
+            _ => -13,                                                   // EACCES
+        };
+
+        let bad_method = AuthBadMethod {
+            method: self.auth_method,
+            result,
+            allowed_methods: self.config.auth_methods().to_vec(),
+            allowed_modes: self.config.con_modes().to_vec(),
+        };
+
+        self.write_auth_frame(Tag::AuthBadMethod, &bad_method)
+    }
+
+    /// Whether authentication has completed, and
+    /// [`ServerConnection::finish_auth`] can be called.
+    pub fn is_authenticated(&self) -> bool {
+        matches!(self.auth, AuthProgress::Done { .. })
+    }
+
+    /// Finish authentication, after the [`AuthDone`] was sent.
+    ///
+    /// Frames sent and received after this point are encrypted if
+    /// secure mode was negotiated.
+    pub fn finish_auth(mut self) -> Result<ServerConnection<ExchangingSignatures>, ServerError> {
+        let AuthProgress::Done {
+            global_id,
+            con_mode,
+            tickets,
+            session_key,
+            connection_secret,
+        } = core::mem::take(&mut self.auth)
+        else {
+            return Err(ServerError::AuthIncomplete);
+        };
+
+        if con_mode == ConMode::Secure {
+            let encryption_key = connection_secret[00..16].try_into().unwrap();
+            // The nonces are swapped with respect to the client.
+            let tx_nonce: [u8; 12] = connection_secret[16..28].try_into().unwrap();
+            let rx_nonce: [u8; 12] = connection_secret[28..40].try_into().unwrap();
+
+            let encryption_key = Key::new(now(), encryption_key);
+
+            let revision = self.state.revision;
+            self.state.encryption_mut().set_secret_data(
+                revision,
+                encryption_key,
+                rx_nonce,
+                tx_nonce,
+            );
+        }
+
+        Ok(self.with_state(|state| ExchangingSignatures {
+            tickets,
+            session_key,
+            global_id,
+            revision: state.revision,
+            peer_features: state.peer_features,
+            encryption: state.encryption,
+            rx_buf: state.rx_buf,
+            tx_buf: state.tx_buf,
+        }))
+    }
+
+    fn connection_secret(con_mode: ConMode) -> Vec<u8> {
+        match con_mode {
+            ConMode::Crc => Vec::new(),
+            ConMode::Secure => random::<40>().to_vec(),
+        }
+    }
+
+    fn assign_global_id(&self, requested: u64) -> u64 {
+        if requested != 0 {
+            requested
+        } else {
+            self.config.global_id()
+        }
+    }
+
+    fn send_auth_done(
+        &mut self,
+        global_id: u64,
+        con_mode: ConMode,
+        payload: Vec<u8>,
+    ) -> TxFrame<'_> {
+        let done = AuthDone {
+            global_id,
+            connection_mode: con_mode,
+            auth_payload: payload,
+        };
+
+        self.write_auth_frame(Tag::AuthDone, &done)
+    }
+
+    fn write_auth_frame<E>(&mut self, tag: Tag, value: &E) -> TxFrame<'_>
+    where
+        E: Encode,
+    {
+        self.buffer.clear();
+        value.encode(&mut self.buffer);
+
+        let data = self.buffer.clone();
+        let frame = Frame::new(tag, &[&data]).unwrap();
+
+        frame.write(self.state.format(), &mut self.state.tx_buf);
+
+        self.tx_frame(&frame)
+    }
+}
+
+impl ServerConnection<ExchangingSignatures> {
+    pub fn send_signature(&mut self) -> TxFrame<'_> {
+        let sha256_hmac = auth_signature(self.state.session_key.as_ref(), &self.state.rx_buf);
+        let signature = AuthSignature { sha256_hmac };
+
+        self.buffer.clear();
+        signature.encode(&mut self.buffer);
+
+        let signature = self.buffer.clone();
+        let frame = Frame::new(Tag::AuthSignature, &[&signature]).unwrap();
+
+        self.tx_frame(&frame)
+    }
+
+    pub fn recv_signature(
+        self,
+        signature: &AuthSignature,
+    ) -> Result<ServerConnection<Identifying>, ServerError> {
+        let valid_signature = auth_signature(self.state.session_key.as_ref(), &self.state.tx_buf);
+
+        if signature.sha256_hmac != valid_signature {
+            return Err(ServerError::BadSignature);
+        }
+
+        Ok(self.with_state(|state| Identifying {
+            revision: state.revision,
+            peer_features: state.peer_features,
+            encryption: state.encryption,
+            compression: FrameCompression::new(),
+            tickets: state.tickets,
+            global_id: state.global_id,
+        }))
+    }
+}
+
+impl ServerConnection<Identifying> {
+    /// Receive a [`CompressionRequest`], and send the selected
+    /// method in a [`CompressionDone`].
+    pub fn recv_compression_request(&mut self, request: &CompressionRequest) -> TxFrame<'_> {
+        let method = request
+            .preferred_methods
+            .iter()
+            .filter_map(|m| CompressionMethod::try_from(*m).ok())
+            .find(|m| request.is_compress && self.config.compression_methods().contains(m));
+
+        let done = CompressionDone {
+            is_compress: method.is_some(),
+            method: method.map(u32::from).unwrap_or(0),
+        };
+
+        self.buffer.clear();
+        done.encode(&mut self.buffer);
+
+        let done = self.buffer.clone();
+        let frame = Frame::new(Tag::CompressionDone, &[&done]).unwrap();
+
+        // The response itself is never compressed.
+        if let Some(method) = method {
+            let min_size = self.config.compression_min_size;
+            self.state.compression.set_method(method, min_size);
+        }
+
+        self.buffer.clear();
+        frame.send(
+            self.state.format(),
+            &mut self.state.encryption,
+            &mut self.buffer,
+        )
+    }
+
+    /// Receive a [`ClientIdent`], and send a [`ServerIdent`].
+    ///
+    /// If the client is missing required features,
+    /// [`ServerConnection::send_ident_missing_features`] should be
+    /// used to inform the client.
+    pub fn recv_client_ident(&mut self, ident: &ClientIdent) -> Result<TxFrame<'_>, ServerError> {
+        let supported = u64::from(&self.config.supported_features);
+        let required = u64::from(&self.config.required_features);
+        let client_supported = u64::from(&ident.supported_features);
+        let client_required = u64::from(&ident.required_features);
+
+        let missing = (client_required & !supported) | (required & !client_supported);
+
+        if missing != 0 {
+            let missing = CephFeatureSet::try_from(missing).expect("All bits are valid features");
+            return Err(ServerError::MissingFeatures(missing));
+        }
+
+        self.session.addresses = ident.addresses.clone();
+        self.session.client_cookie = ident.cookie;
+        self.session.global_seq = ident.global_seq;
+        self.session.server_cookie = u64::from_le_bytes(random()).max(1);
+
+        let server_ident = ServerIdent {
+            addresses: self.config.addresses.clone(),
+            gid: self.config.gid,
+            // Every server connection is independent, so this is
+            // always the first connection of the server.
+            global_seq: 1,
+            supported_features: self.config.supported_features,
+            required_features: self.config.required_features,
+            flags: 0,
+            cookie: self.session.server_cookie,
+        };
+
+        self.buffer.clear();
+        server_ident.encode(&mut self.buffer);
+
+        let server_ident = self.buffer.clone();
+        let frame = Frame::new(Tag::ServerIdent, &[&server_ident]).unwrap();
+
+        Ok(self.tx_frame(&frame))
+    }
+
+    /// Receive a [`SessionReconnect`] of a client that resumes its session,
+    /// and send a [`SessionReconnectOk`].
+    ///
+    /// If the session is not the one this connection was
+    /// [resumed](ServerConnection::resume) with, a [`SessionReset`] is
+    /// sent instead, after which the client identifies using a
+    /// [`ClientIdent`].
+    pub fn recv_session_reconnect(&mut self, reconnect: &SessionReconnect) -> TxFrame<'_> {
+        let resumed = self.session.is_established()
+            && self.session.client_cookie == reconnect.client_cookie
+            && self.session.server_cookie == reconnect.server_cookie;
+
+        if !resumed {
+            self.session = Session::default();
+            return self.send_ident_frame(Tag::SessionReset, &SessionReset { full: true });
+        }
+
+        self.session.acked(reconnect.msg_seq);
+        self.session.global_seq = reconnect.global_seq;
+        self.session.connect_seq = reconnect.connect_seq;
+
+        let ok = SessionReconnectOk {
+            msg_seq: self.session.in_seq(),
+        };
+
+        self.send_ident_frame(Tag::SessionReconnectOk, &ok)
+    }
+
+    /// Ask the client to back off, and to reconnect later.
+    pub fn send_wait(&mut self) -> TxFrame<'_> {
+        self.send_ident_frame(Tag::Wait, &Wait)
+    }
+
+    fn send_ident_frame(&mut self, tag: Tag, value: &impl Encode) -> TxFrame<'_> {
+        self.buffer.clear();
+        value.encode(&mut self.buffer);
+
+        let payload = self.buffer.clone();
+        let frame = Frame::new(tag, &[&payload]).unwrap();
+
+        self.tx_frame(&frame)
+    }
+
+    /// Inform the client that it does not support the `missing`
+    /// features.
+    pub fn send_ident_missing_features(&mut self, missing: CephFeatureSet) -> TxFrame<'_> {
+        let missing = IdentMissingFeatures { features: missing };
+
+        self.buffer.clear();
+        missing.encode(&mut self.buffer);
+
+        let missing = self.buffer.clone();
+        let frame = Frame::new(Tag::IdentMissingFeatures, &[&missing]).unwrap();
+
+        self.tx_frame(&frame)
+    }
+
+    /// Finish identification, after the [`ServerIdent`] was sent.
+    pub fn finish_ident(self) -> Result<ServerConnection<Active>, ServerError> {
+        if !self.session.is_established() {
+            return Err(ServerError::NotIdentified);
+        }
+
+        Ok(self.with_state(|state| Active {
+            revision: state.revision,
+            encryption: state.encryption,
+            compression: state.compression,
+            replayed: 0,
+            replay_until: 0,
+            tickets: state.tickets,
+            global_id: state.global_id,
+        }))
+    }
+}
+
+impl ServerConnection<Active> {
+    pub fn send<'me, M>(&'me mut self, message: M) -> TxFrame<'me>
+    where
+        M: Into<Message>,
+    {
+        self.send_msg(&message.into())
+    }
+
+    pub fn send_msg<'me>(&'me mut self, message: &Message) -> TxFrame<'me> {
+        self.buffer.clear();
+        message.write_to(&mut self.buffer);
+
+        let buffer = self.buffer.clone();
+        let frame = Frame::new(message.tag(), &[&buffer]).unwrap();
+        self.tx_frame(&frame)
+    }
+
+    /// Send a message consisting of `header` and `data` segments.
+    ///
+    /// The sequence number of the message is assigned by the connection,
+    /// and it acknowledges all messages received so far.
+    pub fn send_message<'me>(
+        &'me mut self,
+        mut header: CephMessageHeader2,
+        data: &[&[u8]],
+    ) -> TxFrame<'me> {
+        self.session.prepare(&mut header);
+
+        let header = header.to_vec();
+        let mut segments = vec![header.as_slice()];
+        segments.extend_from_slice(data);
+
+        let frame = Frame::new(Tag::Message, &segments).unwrap();
+        self.send_raw(&frame)
+    }
+
+    /// Acknowledge the messages that were received, but not yet
+    /// acknowledged by a sent message.
+    ///
+    /// Returns `None` if there is nothing to acknowledge.
+    pub fn send_ack(&mut self) -> Option<TxFrame<'_>> {
+        let seq = self.session.ack()?;
+        Some(self.send(Ack { seq }))
+    }
+
+    pub fn send_raw<'me>(&'me mut self, frame: &Frame) -> TxFrame<'me> {
+        self.session.sent(frame);
+        self.tx_frame(frame)
+    }
+
+    /// The session of this connection.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Handle the CephX request in `auth`, returning the reply containing
+    /// new tickets for the requested services.
+    ///
+    /// A `GetPrincipalSessionKey` request must be authenticated using an
+    /// authorizer containing an auth ticket that was handed out using
+    /// [`ServerConfig::service_key`]. A `GetAuthSessionKey` request renews
+    /// the auth ticket, and must prove that the client holds the key it
+    /// authenticated this connection with.
+    pub fn recv_auth(&self, auth: &MessageAuth) -> Result<MessageAuthReply, ServerError> {
+        let method = AuthMethod::try_from(auth.protocol)?;
+        if method != AuthMethod::CephX {
+            return Err(ServerError::BadMethod(method));
+        }
+
+        let message = CephXMessage::decode_request(&mut auth.auth_payload.as_slice())?;
+
+        if let (CephXMessageType::GetAuthSessionKey, Some(session)) = (message.ty(), &self.cephx) {
+            let authenticate = CephXAuthenticate::decode(&mut message.payload())?;
+            let (_, _, reply) = self.authenticate(session, &authenticate, &[])?;
+
+            let mut result_bl = Vec::new();
+            CephXMessage::new(CephXMessageType::GetAuthSessionKey, reply)
+                .encode_reply(&mut result_bl);
+
+            return Ok(MessageAuthReply {
+                protocol: auth.protocol,
+                result: 0,
+                global_id: session.global_id,
+                result_bl,
+                result_msg: String::new(),
+            });
+        }
+
+        if message.ty() != CephXMessageType::GetPrincipalSessionKey {
+            return Err(ServerError::UnexpectedCephXMessage {
+                got: message.ty(),
+                expected: CephXMessageType::GetPrincipalSessionKey,
+            });
+        }
+
+        let request = GetPrincipalSessionKey::decode(&mut message.payload())?;
+
+        let authorizer = verify_authorizer(request.authorizer(), &self.config.service_key)
+            .map_err(|_| ServerError::BadAuthorizer)?;
+
+        if authorizer.service != EntityType::Auth {
+            return Err(ServerError::BadAuthorizer);
+        }
+
+        let auth_ticket = &authorizer.ticket_info.auth_ticket;
+
+        let tickets: Vec<_> = request
+            .request
+            .keys
+            .iter()
+            .map(|ty| {
+                self.config
+                    .ticket(*ty, &auth_ticket.name, auth_ticket.global_id)
+            })
+            .collect();
+
+        let reply =
+            PrincipalSessionKeyReply::encrypt(&authorizer.ticket_info.session_key, &tickets);
+
+        let mut result_bl = Vec::new();
+        CephXMessage::new(CephXMessageType::GetPrincipalSessionKey, reply)
+            .encode_reply(&mut result_bl);
+
+        Ok(MessageAuthReply {
+            protocol: auth.protocol,
+            result: 0,
+            global_id: auth_ticket.global_id,
+            result_bl,
+            result_msg: String::new(),
+        })
+    }
+}
+
+impl<T> ServerConnection<T>
+where
+    T: Established,
+{
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    fn tx_frame<'me>(&'me mut self, frame: &Frame<'_>) -> TxFrame<'me> {
+        tx_frame(&mut self.state, &mut self.buffer, frame)
+    }
+
+    pub fn start_rx<'enc, 'buf>(
+        &'enc mut self,
+        buffer: &'buf mut Vec<u8>,
+    ) -> RxFrame<'buf, Unstarted<'enc>> {
+        RxFrame::new(self.state.format(), self.state.encryption_mut(), buffer)
+    }
+
+    pub fn finish_rx(&mut self, frame: RxFrame<'_, Completed>) -> Result<Message, DecodeError> {
+        let frame = self
+            .finish_rx_raw(frame)?
+            .ok_or_else(|| DecodeError::Custom("Received duplicate message".into()))?;
+
+        Message::decode(frame.tag(), frame.segments()[0])
+    }
+
+    /// Finish receiving `frame`.
+    ///
+    /// Returns `None` if `frame` contains a message that was already
+    /// received, in which case it should be ignored.
+    pub fn finish_rx_raw<'frame>(
+        &mut self,
+        frame: RxFrame<'frame, Completed>,
+    ) -> Result<Option<Frame<'frame>>, DecodeError> {
+        finish_rx_raw(&mut self.state, &mut self.session, frame)
+    }
+}
+
+fn now() -> Timestamp {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Timestamp::new(now.as_secs() as u32, now.subsec_nanos())
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut data = [0u8; N];
+    getrandom::fill(&mut data).expect("Failed to obtain random data");
+    data
+}
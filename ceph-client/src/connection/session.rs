@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+
+use ceph_foundation::{Decode, entity::EntityAddress};
+use msgr2::{
+    Frame, Tag,
+    frames::{SessionReconnect, SessionReset},
+};
+
+use super::SendError;
+use crate::header::CephMessageHeader2;
+
+/// A message that was sent, but not yet acknowledged
+/// by the peer.
+#[derive(Debug, Clone)]
+pub(crate) struct SentMessage {
+    pub(crate) seq: u64,
+    pub(crate) segments: Vec<Vec<u8>>,
+}
+
+impl SentMessage {
+    pub(crate) fn frame(&self) -> Frame<'_> {
+        let segments: Vec<&[u8]> = self.segments.iter().map(Vec::as_slice).collect();
+        Frame::new(Tag::Message, &segments).expect("Segments were taken from a valid frame")
+    }
+}
+
+/// The state of a session, which may outlive the connection it was
+/// established on.
+///
+/// A session is identified by the cookies exchanged in
+/// [`ClientIdent`](msgr2::frames::ClientIdent) and
+/// [`ServerIdent`](msgr2::frames::ServerIdent), and can be resumed on a new
+/// connection using [`SessionReconnect`].
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    pub(crate) addresses: Vec<EntityAddress>,
+    pub(crate) client_cookie: u64,
+    pub(crate) server_cookie: u64,
+    pub(crate) global_seq: u64,
+    pub(crate) connect_seq: u64,
+    pub(crate) out_seq: u64,
+    pub(crate) in_seq: u64,
+    pub(crate) in_seq_acked: u64,
+    pub(crate) unacked: VecDeque<SentMessage>,
+}
+
+impl Session {
+    /// Whether this session was established with a peer, and
+    /// can be resumed.
+    pub fn is_established(&self) -> bool {
+        self.server_cookie != 0
+    }
+
+    /// The addresses of the client of this session.
+    pub fn addresses(&self) -> &[EntityAddress] {
+        &self.addresses
+    }
+
+    /// The sequence number of the last message that was received
+    /// in this session.
+    pub fn in_seq(&self) -> u64 {
+        self.in_seq
+    }
+
+    /// The sequence number of the last message that was sent
+    /// in this session.
+    pub fn out_seq(&self) -> u64 {
+        self.out_seq
+    }
+
+    /// The amount of sent messages that have not been
+    /// acknowledged by the peer.
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+
+    /// Fail if `max` sent messages have not been acknowledged yet.
+    pub(crate) fn check_unacked(&self, max: usize) -> Result<(), SendError> {
+        if self.unacked() >= max {
+            return Err(SendError::TooManyUnacked(max));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn reconnect(&self) -> SessionReconnect {
+        SessionReconnect {
+            addresses: self.addresses.clone(),
+            client_cookie: self.client_cookie,
+            server_cookie: self.server_cookie,
+            global_seq: self.global_seq,
+            connect_seq: self.connect_seq,
+            msg_seq: self.in_seq,
+        }
+    }
+
+    /// Prepare for a new connection attempt.
+    pub(crate) fn next_connection(&mut self) {
+        self.global_seq += 1;
+
+        if self.is_established() {
+            self.connect_seq += 1;
+        }
+    }
+
+    /// Assign the next outgoing sequence number to `header`, and
+    /// acknowledge all messages received so far.
+    pub(crate) fn prepare(&mut self, header: &mut CephMessageHeader2) {
+        self.out_seq += 1;
+        header.seq = self.out_seq;
+        header.ack_seq = self.in_seq;
+        self.in_seq_acked = self.in_seq;
+    }
+
+    /// Record a sent message frame, so that it can be
+    /// replayed after a reconnect.
+    pub(crate) fn sent(&mut self, frame: &Frame) {
+        let Some(header) = message_header(frame) else {
+            return;
+        };
+
+        self.out_seq = self.out_seq.max(header.seq);
+        self.unacked.push_back(SentMessage {
+            seq: header.seq,
+            segments: frame.segments().iter().map(|s| s.to_vec()).collect(),
+        });
+    }
+
+    /// Record a received message frame.
+    ///
+    /// Returns `false` if the frame is a message that was
+    /// already received before.
+    pub(crate) fn received(&mut self, frame: &Frame) -> bool {
+        let Some(header) = message_header(frame) else {
+            return true;
+        };
+
+        // Received messages acknowledge our messages.
+        self.acked(header.ack_seq);
+
+        if header.seq <= self.in_seq {
+            return false;
+        }
+
+        self.in_seq = header.seq;
+        true
+    }
+
+    /// The sequence number to send in an [`Ack`](msgr2::frames::Ack),
+    /// if any received messages have not been acknowledged yet.
+    pub(crate) fn ack(&mut self) -> Option<u64> {
+        if self.in_seq > self.in_seq_acked {
+            self.in_seq_acked = self.in_seq;
+            Some(self.in_seq)
+        } else {
+            None
+        }
+    }
+
+    /// Discard all sent messages with a sequence number
+    /// of at most `seq`.
+    pub(crate) fn acked(&mut self, seq: u64) {
+        while self.unacked.front().is_some_and(|m| m.seq <= seq) {
+            self.unacked.pop_front();
+        }
+    }
+
+    pub(crate) fn reset(&mut self, reset: &SessionReset) {
+        if reset.full {
+            self.client_cookie = 0;
+            self.out_seq = 0;
+            self.unacked.clear();
+        }
+
+        self.server_cookie = 0;
+        self.connect_seq = 0;
+        self.in_seq = 0;
+        self.in_seq_acked = 0;
+    }
+}
+
+/// Get the header of the message in `frame`, if it is a
+/// message frame.
+fn message_header(frame: &Frame) -> Option<CephMessageHeader2> {
+    if frame.tag() != Tag::Message {
+        return None;
+    }
+
+    let mut header = *frame.segments().first()?;
+    CephMessageHeader2::decode(&mut header).ok()
+}
+
+#[cfg(test)]
+fn header() -> CephMessageHeader2 {
+    use crate::header::CephMessageHeader2Flags;
+
+    CephMessageHeader2 {
+        seq: 0,
+        transaction_id: 0,
+        ty: 0,
+        priority: 0,
+        version: 0,
+        data_pre_padding_len: 0,
+        data_off: 0,
+        ack_seq: 0,
+        flags: CephMessageHeader2Flags(0),
+        compat_version: None,
+        reserved: 0,
+    }
+}
+
+#[test]
+fn acked_messages_are_discarded() {
+    use ceph_foundation::Encode;
+
+    let mut session = Session::default();
+
+    for _ in 1u64..=4 {
+        let mut header = header();
+        session.prepare(&mut header);
+
+        let header = header.to_vec();
+        let frame = Frame::new(Tag::Message, &[&header]).unwrap();
+        session.sent(&frame);
+    }
+
+    let keepalive = Frame::new(Tag::Keepalive2, &[&[0; 8]]).unwrap();
+    session.sent(&keepalive);
+    assert_eq!(session.unacked(), 4);
+
+    session.acked(2);
+    assert_eq!(session.unacked(), 2);
+    assert_eq!(session.unacked[0].seq, 3);
+    assert_eq!(session.out_seq(), 4);
+}
+
+#[test]
+fn unacked_messages_are_limited() {
+    use ceph_foundation::Encode;
+
+    let mut session = Session::default();
+    assert_eq!(session.check_unacked(2), Ok(()));
+
+    for _ in 1u64..=2 {
+        let mut header = header();
+        session.prepare(&mut header);
+
+        let header = header.to_vec();
+        let frame = Frame::new(Tag::Message, &[&header]).unwrap();
+        session.sent(&frame);
+    }
+
+    assert_eq!(session.check_unacked(2), Err(SendError::TooManyUnacked(2)));
+    assert_eq!(session.check_unacked(3), Ok(()));
+
+    session.acked(1);
+    assert_eq!(session.check_unacked(2), Ok(()));
+}
+
+#[test]
+fn received_messages_are_acked() {
+    use ceph_foundation::Encode;
+
+    let mut session = Session::default();
+    assert_eq!(session.ack(), None);
+
+    let mut incoming = header();
+    incoming.seq = 1;
+    let encoded = incoming.to_vec();
+    let frame = Frame::new(Tag::Message, &[&encoded]).unwrap();
+
+    assert!(session.received(&frame));
+    assert!(!session.received(&frame));
+    assert_eq!(session.in_seq(), 1);
+
+    // Outgoing messages carry the acknowledgement...
+    let mut outgoing = header();
+    session.prepare(&mut outgoing);
+    assert_eq!(outgoing.ack_seq, 1);
+    assert_eq!(session.ack(), None);
+
+    // ... and otherwise an explicit ack is required.
+    incoming.seq = 2;
+    let encoded = incoming.to_vec();
+    let frame = Frame::new(Tag::Message, &[&encoded]).unwrap();
+    assert!(session.received(&frame));
+    assert_eq!(session.ack(), Some(2));
+    assert_eq!(session.ack(), None);
+}
@@ -1,7 +1,10 @@
 //! The different states that a connection can be in.
 
+use ceph_foundation::crypto::Key;
 use cephx::Ticket;
-use msgr2::{FrameEncryption, FrameFormat, Revision};
+use msgr2::{
+    FrameEncryption, FrameFormat, Revision, compression::FrameCompression, frames::MsgrFeatures,
+};
 
 /// A connection state that is capable of receiving [`RxFrame`][0]s and
 /// transmitting [`TxFrame`][1]s
@@ -17,6 +20,12 @@ pub trait Established {
     fn encryption_mut(&mut self) -> &mut FrameEncryption;
     fn set_revision(&mut self, revision: Revision);
 
+    /// Get the compression applied to frames exchanged over this connection.
+    fn compression(&self) -> &FrameCompression {
+        static NONE: FrameCompression = FrameCompression::new();
+        &NONE
+    }
+
     fn recv_data(&mut self, _data: &[u8]) {}
 }
 
@@ -33,6 +42,7 @@ pub struct Inactive {
 #[derive(Debug)]
 pub struct ExchangeHello {
     pub(crate) revision: Revision,
+    pub(crate) peer_features: MsgrFeatures,
     pub(crate) encryption: FrameEncryption,
     pub(crate) rx_buf: Vec<u8>,
     pub(crate) tx_buf: Vec<u8>,
@@ -43,6 +53,7 @@ pub struct ExchangeHello {
 #[derive(Debug)]
 pub struct Authenticating {
     pub(crate) revision: Revision,
+    pub(crate) peer_features: MsgrFeatures,
     pub(crate) encryption: FrameEncryption,
     pub(crate) rx_buf: Vec<u8>,
     pub(crate) tx_buf: Vec<u8>,
@@ -53,10 +64,14 @@ pub struct Authenticating {
 #[derive(Debug)]
 pub struct ExchangingSignatures {
     pub(crate) revision: Revision,
+    pub(crate) peer_features: MsgrFeatures,
     pub(crate) encryption: FrameEncryption,
     pub(crate) rx_buf: Vec<u8>,
     pub(crate) tx_buf: Vec<u8>,
     pub(crate) tickets: Vec<Ticket>,
+    /// The session key used to sign the exchanged data, if any.
+    pub(crate) session_key: Option<Key>,
+    pub(crate) global_id: u64,
 }
 
 /// A connection where the clien-server pair is exchanging
@@ -65,8 +80,11 @@ pub struct ExchangingSignatures {
 #[derive(Debug)]
 pub struct Identifying {
     pub(crate) revision: Revision,
+    pub(crate) peer_features: MsgrFeatures,
     pub(crate) encryption: FrameEncryption,
+    pub(crate) compression: FrameCompression,
     pub(crate) tickets: Vec<Ticket>,
+    pub(crate) global_id: u64,
 }
 
 /// An active connection, sending and receiving upper-protocol
@@ -75,11 +93,20 @@ pub struct Identifying {
 pub struct Active {
     pub(crate) revision: Revision,
     pub(crate) encryption: FrameEncryption,
-    pub(crate) _tickets: Vec<Ticket>,
+    pub(crate) compression: FrameCompression,
+    /// The sequence number of the last message that was
+    /// retransmitted after resuming a session.
+    pub(crate) replayed: u64,
+    /// The sequence number of the last message that must be
+    /// retransmitted after resuming a session.
+    pub(crate) replay_until: u64,
+    pub(crate) tickets: Vec<Ticket>,
+    /// The global ID that was assigned to us while authenticating.
+    pub(crate) global_id: u64,
 }
 
 macro_rules! established {
-    ($($st:ident $($rx_buf:ident)?),*) => {
+    ($($st:ident $(rx_buf = $rx_buf:ident)? $(compression = $compression:ident)?),*) => {
         $(
             impl Established for $st {
                 fn format(&self) -> FrameFormat {
@@ -108,9 +135,21 @@ macro_rules! established {
                         self.$rx_buf.extend_from_slice(data);
                     }
                 )?
+
+                $(
+                    fn compression(&self) -> &FrameCompression {
+                        &self.$compression
+                    }
+                )?
             }
         )*
     };
 }
 
-established!(ExchangeHello rx_buf, Authenticating rx_buf, ExchangingSignatures, Identifying, Active);
+established!(
+    ExchangeHello rx_buf = rx_buf,
+    Authenticating rx_buf = rx_buf,
+    ExchangingSignatures,
+    Identifying compression = compression,
+    Active compression = compression
+);
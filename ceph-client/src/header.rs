@@ -24,7 +24,7 @@ impl TryFrom<SomeOrZero> for Option<NonZeroU16> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CephMessageHeader2 {
     pub seq: u64,
     pub transaction_id: u64,
@@ -46,6 +46,27 @@ pub struct CephMessageHeader2 {
     pub reserved: u16,
 }
 
+impl CephMessageHeader2 {
+    /// A header for a message of type `ty`, encoded using `version`.
+    ///
+    /// The sequence number is assigned by the connection.
+    pub fn new(ty: u16, version: u16) -> Self {
+        Self {
+            seq: 0,
+            transaction_id: 0,
+            ty,
+            priority: 0,
+            version,
+            data_pre_padding_len: 0,
+            data_off: 0,
+            ack_seq: 0,
+            flags: CephMessageHeader2Flags(0),
+            compat_version: None,
+            reserved: 0,
+        }
+    }
+}
+
 ceph_foundation::write_decode_encode!(
     CephMessageHeader2 = seq
         | transaction_id
@@ -60,18 +81,16 @@ ceph_foundation::write_decode_encode!(
         | reserved
 );
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct CephMessageHeader2Flags(pub u8);
 
 impl Decode<'_> for CephMessageHeader2Flags {
     fn decode(buffer: &mut &'_ [u8]) -> Result<Self, DecodeError> {
-        let (value, rest) = buffer
-            .split_first()
-            .ok_or_else(|| DecodeError::NotEnoughData {
-                field: None,
-                have: 0,
-                need: 1,
-            })?;
+        let (value, rest) = buffer.split_first().ok_or(DecodeError::NotEnoughData {
+            field: None,
+            have: 0,
+            need: 1,
+        })?;
 
         *buffer = rest;
         Ok(Self(*value))
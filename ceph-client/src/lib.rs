@@ -1 +1,6 @@
 pub mod connection;
+pub mod header;
+pub mod mon_client;
+pub mod objecter;
+mod peer;
+pub mod rados;
@@ -0,0 +1,864 @@
+//! A client for the monitors of a cluster.
+//!
+//! The [`MonClient`] maintains a session with a single monitor, picked
+//! from the known monitors by [`MonInfo::priority`] and [`MonInfo::weight`].
+//! If connecting to a monitor fails or times out, or if the session drops,
+//! it hunts for another monitor.
+//!
+//! Subscriptions to maps are tracked by [`Subscriptions`], and are sent
+//! again to every new monitor, starting after the last received epoch.
+//!
+//! Tickets are tracked by the [`TicketManager`], and are renewed over
+//! the monitor session before they expire.
+
+mod subscriptions;
+mod tickets;
+
+pub use subscriptions::{Subscriptions, Topic};
+pub use tickets::{REQUEST_TIMEOUT, TicketManager};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use ceph_foundation::{
+    Decode, DecodeError, Encode, MonInfo, Uuid,
+    entity::{EntityAddress, EntityAddressType, EntityType},
+};
+use ceph_messages::{
+    CephMessage, DecodeMessageError, EncodeMessage, MessageAuth, MessageAuthReply, MonCommand,
+    MonMap, MonSubscribe,
+};
+use cephx::{
+    AuthServiceTicketReply, CephXAuthenticate, CephXAuthenticateKey, CephXAuthorizer, CephXMessage,
+    CephXMessageType, GetPrincipalSessionKey, PrincipalSessionKeyReply, Ticket,
+};
+use msgr2::{
+    frames::AuthMethod,
+    wire::{RxError, TxError},
+};
+
+use crate::{
+    connection::{Config, Credentials, HandshakeError, SendError},
+    header::CephMessageHeader2,
+    peer::{Peer, Received},
+};
+
+/// The default timeout for connecting to a monitor.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The shortest time that [`MonClient::recv`] waits for a message
+/// before checking for renewals again.
+const MIN_TICK: Duration = Duration::from_millis(100);
+
+/// An error that occurred while talking to the monitors.
+#[derive(Debug)]
+pub enum MonClientError {
+    Io(std::io::Error),
+    Rx(RxError),
+    Tx(TxError),
+    Decode(DecodeError),
+    Handshake(HandshakeError),
+    /// The peer did not acknowledge enough of our messages to
+    /// send another one.
+    Send(SendError),
+    /// A received message could not be decoded.
+    Message(DecodeMessageError),
+    /// None of the known monitors has a `msgr2` address.
+    NoMonitors,
+    /// Connecting to every known monitor failed.
+    Hunt(Vec<(String, MonClientError)>),
+}
+
+impl From<std::io::Error> for MonClientError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<RxError> for MonClientError {
+    fn from(value: RxError) -> Self {
+        Self::Rx(value)
+    }
+}
+
+impl From<TxError> for MonClientError {
+    fn from(value: TxError) -> Self {
+        Self::Tx(value)
+    }
+}
+
+impl From<DecodeError> for MonClientError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+impl From<HandshakeError> for MonClientError {
+    fn from(value: HandshakeError) -> Self {
+        Self::Handshake(value)
+    }
+}
+
+impl From<SendError> for MonClientError {
+    fn from(value: SendError) -> Self {
+        Self::Send(value)
+    }
+}
+
+impl From<DecodeMessageError> for MonClientError {
+    fn from(value: DecodeMessageError) -> Self {
+        Self::Message(value)
+    }
+}
+
+/// A session with a single monitor.
+#[derive(Debug)]
+struct MonSession {
+    name: String,
+    address: SocketAddr,
+    peer: Peer,
+}
+
+impl MonSession {
+    /// Send the subscriptions in `subscriptions` that need to be sent.
+    fn renew_subscriptions(
+        &mut self,
+        subscriptions: &mut Subscriptions,
+        hostname: &str,
+    ) -> Result<(), MonClientError> {
+        let Some(what) = subscriptions.renew(Instant::now()) else {
+            return Ok(());
+        };
+
+        let subscribe = MonSubscribe {
+            hostname: hostname.to_string(),
+            what,
+        };
+
+        let header =
+            CephMessageHeader2::new(CephMessage::MonSubscribe(subscribe.clone()).identifier(), 3);
+        self.peer.send(header, &[&subscribe.to_vec()])
+    }
+}
+
+/// A client for the monitors of a cluster.
+#[derive(Debug)]
+pub struct MonClient {
+    monitors: HashMap<String, MonInfo>,
+    mon_map: Option<MonMap>,
+    config: Config,
+    credentials: Credentials,
+    timeout: Duration,
+    gid: i64,
+    global_seq: u64,
+    hostname: String,
+    subscriptions: Subscriptions,
+    tickets: TicketManager,
+    last_tid: u64,
+    /// Messages that were received while waiting for a reply.
+    pending: VecDeque<Result<CephMessage, DecodeMessageError>>,
+    /// The amount of sessions that were established.
+    sessions: u64,
+    session: Option<MonSession>,
+}
+
+impl MonClient {
+    /// Create a new client for `monitors`, which connects
+    /// using `config` and authenticates using `credentials`.
+    ///
+    /// No connection is made until the first call to [`MonClient::hunt`],
+    /// [`MonClient::send`] or [`MonClient::recv`].
+    pub fn new(
+        monitors: impl IntoIterator<Item = MonInfo>,
+        config: Config,
+        credentials: Credentials,
+    ) -> Self {
+        let rng = config.rng();
+        let gid = (rng.next_u64() >> 1) as i64;
+        let global_seq = rng.next_u64() >> 32;
+
+        Self {
+            monitors: monitors
+                .into_iter()
+                .map(|info| (info.name.clone(), info))
+                .collect(),
+            mon_map: None,
+            config,
+            credentials,
+            timeout: DEFAULT_TIMEOUT,
+            gid,
+            global_seq,
+            hostname: String::new(),
+            subscriptions: Subscriptions::new(),
+            tickets: TicketManager::new(),
+            last_tid: 0,
+            pending: VecDeque::new(),
+            sessions: 0,
+            session: None,
+        }
+    }
+
+    /// Create a new client for the monitors in `mon_map`.
+    pub fn from_mon_map(mon_map: MonMap, config: Config, credentials: Credentials) -> Self {
+        let mut client = Self::new(mon_map.mon_info.values().cloned(), config, credentials);
+        client.mon_map = Some(mon_map);
+        client
+    }
+
+    /// Set the timeout for connecting to and completing the
+    /// handshake with a single monitor.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set the hostname that is reported to the monitors
+    /// when subscribing.
+    pub fn set_hostname(&mut self, hostname: impl Into<String>) {
+        self.hostname = hostname.into();
+    }
+
+    /// The most recent [`MonMap`] that was received, if any.
+    pub fn mon_map(&self) -> Option<&MonMap> {
+        self.mon_map.as_ref()
+    }
+
+    /// The name of the monitor that we currently have a session
+    /// with, if any.
+    pub fn monitor(&self) -> Option<&str> {
+        self.session.as_ref().map(|s| s.name.as_str())
+    }
+
+    /// The global ID that the current monitor assigned to us, if
+    /// we have a session.
+    pub fn global_id(&self) -> Option<u64> {
+        self.session.as_ref().map(|s| s.peer.global_id())
+    }
+
+    /// The credentials for authenticating to daemons of type `service`,
+    /// using the ticket that the monitors handed out for it.
+    ///
+    /// Returns `None` if we have no session, or if we have no ticket for
+    /// `service` that has not expired (see [`Config::request_ticket_for`]).
+    pub fn service_credentials(&self, service: EntityType) -> Option<Credentials> {
+        let peer = &self.session.as_ref()?.peer;
+        let ticket = self.tickets.get(service, Instant::now())?;
+
+        Some(Credentials::ticket(
+            peer.global_id(),
+            ticket.clone(),
+            self.credentials.modes().to_vec(),
+        ))
+    }
+
+    /// The tickets that the monitors handed out to us.
+    pub fn tickets(&self) -> &TicketManager {
+        &self.tickets
+    }
+
+    /// The current subscriptions.
+    pub fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
+    /// Subscribe to `topic`, starting at epoch `start`.
+    ///
+    /// If `onetime` is set, only a single map is requested. The
+    /// subscription is sent immediately if we have a session.
+    pub fn subscribe(
+        &mut self,
+        topic: Topic,
+        start: u64,
+        onetime: bool,
+    ) -> Result<(), MonClientError> {
+        if self.subscriptions.want(topic, start, onetime) && self.session.is_some() {
+            self.renew_subscriptions()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop subscribing to `topic`.
+    ///
+    /// Monitors keep sending updates for `topic` until the
+    /// session ends.
+    pub fn unsubscribe(&mut self, topic: Topic) {
+        self.subscriptions.unwant(topic);
+    }
+
+    /// Send subscriptions that are new, or that need to be renewed.
+    ///
+    /// This is done automatically by [`MonClient::tick`].
+    pub fn renew_subscriptions(&mut self) -> Result<(), MonClientError> {
+        let Some(session) = &mut self.session else {
+            return self.hunt();
+        };
+
+        let result = session.renew_subscriptions(&mut self.subscriptions, &self.hostname);
+
+        if result.is_err() {
+            // Hunting sends all subscriptions to the new monitor.
+            self.hunt()?;
+        }
+
+        Ok(())
+    }
+
+    /// Request new tickets for the services that tickets are requested
+    /// for (see [`Config::request_ticket_for`]) if they are about to expire.
+    ///
+    /// If the auth ticket itself is about to expire, we authenticate again
+    /// over the current session using a `GetAuthSessionKey` request, which
+    /// hands out new tickets for all services. If that is not possible, or
+    /// if the monitor is gone, we hunt for a new monitor instead.
+    ///
+    /// This is done automatically by [`MonClient::tick`].
+    pub fn renew_tickets(&mut self) -> Result<(), MonClientError> {
+        let Some(session) = &mut self.session else {
+            return self.hunt();
+        };
+
+        // Without an auth ticket, we did not authenticate using CephX.
+        if self.tickets.expires(EntityType::Auth).is_none() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+
+        let wanted: Vec<_> = self
+            .config
+            .tickets_for()
+            .iter()
+            .copied()
+            .chain([EntityType::Auth])
+            .collect();
+
+        let Some(keys) = self.tickets.renew(&wanted, now) else {
+            return Ok(());
+        };
+
+        let message = if keys.contains(&EntityType::Auth) {
+            let (Some(master_key), Some(server_challenge)) =
+                (self.credentials.key(), session.peer.server_challenge())
+            else {
+                return self.hunt();
+            };
+
+            let client_challenge = self.config.rng().next_u64();
+            let key = CephXAuthenticateKey::compute(server_challenge, client_challenge, master_key);
+
+            let old_ticket = self
+                .tickets
+                .get(EntityType::Auth, now)
+                .and_then(Ticket::blob)
+                .cloned()
+                .unwrap_or_default();
+
+            let authenticate = CephXAuthenticate {
+                client_challenge,
+                key,
+                old_ticket,
+                other_keys: wanted.into_iter().collect(),
+            };
+
+            CephXMessage::new(CephXMessageType::GetAuthSessionKey, authenticate).to_vec()
+        } else {
+            let auth = self
+                .tickets
+                .get(EntityType::Auth, now)
+                .expect("Auth ticket does not need renewal");
+
+            // An encrypted ticket blob can not be presented to the monitors.
+            let Some(blob) = auth.blob() else {
+                return Ok(());
+            };
+
+            let authorizer = CephXAuthorizer::build(
+                session.peer.global_id(),
+                EntityType::Auth,
+                blob,
+                &auth.session_ticket.session_key,
+                self.config.rng().next_u64(),
+            );
+
+            let request = GetPrincipalSessionKey::new(&authorizer, keys);
+            CephXMessage::new(CephXMessageType::GetPrincipalSessionKey, request).to_vec()
+        };
+
+        let auth = MessageAuth {
+            paxos: Default::default(),
+            protocol: AuthMethod::CephX.into(),
+            auth_payload: message,
+            monmap_epoch: self.mon_map.as_ref().map_or(0, |m| m.epoch),
+        };
+
+        let header = CephMessageHeader2::new(CephMessage::Auth(auth.clone()).identifier(), 1);
+
+        let mut segments = Vec::new();
+        auth.encode_message(&mut segments);
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+        if session
+            .peer
+            .send::<MonClientError>(header, &segments)
+            .is_err()
+        {
+            // Authenticating to the new monitor hands out new tickets.
+            self.hunt()?;
+        }
+
+        Ok(())
+    }
+
+    /// Renew subscriptions and tickets that are due.
+    ///
+    /// [`MonClient::recv`] does this while waiting for messages. Drivers
+    /// that do not call it should call this at [`MonClient::next_deadline`].
+    pub fn tick(&mut self) -> Result<(), MonClientError> {
+        self.renew_subscriptions()?;
+        self.renew_tickets()
+    }
+
+    /// When [`MonClient::tick`] must be called next, if ever.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let subscriptions = self.subscriptions.next_renewal();
+
+        // Without an auth ticket, we did not authenticate using CephX.
+        let tickets = self.tickets.expires(EntityType::Auth).and_then(|_| {
+            let mut wanted = self.config.tickets_for().to_vec();
+            wanted.push(EntityType::Auth);
+            self.tickets.next_renewal(&wanted, now)
+        });
+
+        subscriptions.into_iter().chain(tickets).min()
+    }
+
+    /// Close the current session (if any), and establish a
+    /// session with a new monitor.
+    ///
+    /// Monitors are tried in order of ascending priority. Monitors with
+    /// the same priority are tried in a random order, weighted by their
+    /// weight. Once connected, all subscriptions are sent to the new
+    /// monitor.
+    pub fn hunt(&mut self) -> Result<(), MonClientError> {
+        self.session = None;
+
+        let rng = self.config.rng();
+        let order = hunt_order(self.monitors.values(), &mut || rng.next_u64());
+        if order.is_empty() {
+            return Err(MonClientError::NoMonitors);
+        }
+
+        let mut errors = Vec::new();
+
+        for (name, address) in order {
+            let peer = match self.connect(address) {
+                Ok(peer) => peer,
+                Err(e) => {
+                    errors.push((name, e));
+                    continue;
+                }
+            };
+
+            let mut session = MonSession {
+                name,
+                address,
+                peer,
+            };
+
+            self.subscriptions.reload();
+
+            match session.renew_subscriptions(&mut self.subscriptions, &self.hostname) {
+                Ok(()) => {
+                    self.tickets.clear();
+                    self.tickets
+                        .got(session.peer.tickets().iter().cloned(), Instant::now());
+
+                    self.session = Some(session);
+                    self.sessions += 1;
+                    return Ok(());
+                }
+                Err(e) => errors.push((session.name, e)),
+            }
+        }
+
+        Err(MonClientError::Hunt(errors))
+    }
+
+    /// Send a message consisting of `header` and `data` segments to
+    /// the current monitor.
+    ///
+    /// If sending fails, we hunt for a new monitor and send the
+    /// message to it instead.
+    pub fn send(
+        &mut self,
+        header: CephMessageHeader2,
+        data: &[&[u8]],
+    ) -> Result<(), MonClientError> {
+        if let Some(session) = &mut self.session
+            && session
+                .peer
+                .send::<MonClientError>(header.clone(), data)
+                .is_ok()
+        {
+            return Ok(());
+        }
+
+        self.hunt()?;
+
+        let session = self
+            .session
+            .as_mut()
+            .expect("Hunting established a session");
+        session.peer.send(header, data)
+    }
+
+    /// Receive the next message from the current monitor.
+    ///
+    /// If the session drops, we hunt for a new monitor and wait for a
+    /// message from it instead. Received [`MonMap`]s replace the known
+    /// monitors, and cause a new hunt if the current monitor is no longer
+    /// part of the map.
+    ///
+    /// Received maps and `MonSubscribeAck`s update the subscriptions, and
+    /// tickets in received `AuthReply`s replace the current tickets. While
+    /// waiting, subscriptions and tickets are renewed when they are due.
+    pub fn recv(&mut self) -> Result<CephMessage, MonClientError> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(message?);
+        }
+
+        let (_, message) = self.recv_message()?;
+        Ok(message?)
+    }
+
+    /// Run `command` on the monitors, returning the result code, the
+    /// status string and the output of the command.
+    ///
+    /// `command` is a JSON object, such as `{"prefix": "osd lspools"}`.
+    /// If the session drops before the reply is received, the command
+    /// is sent again to the next monitor. Other messages that are received
+    /// in the meantime are returned by [`MonClient::recv`].
+    pub fn command(&mut self, command: &str) -> Result<(i32, String, Vec<u8>), MonClientError> {
+        let fsid = self.wait_for_mon_map()?;
+
+        self.last_tid += 1;
+        let tid = self.last_tid;
+
+        let command = MonCommand {
+            paxos: Default::default(),
+            fsid,
+            cmd: vec![command.to_string()],
+            input: Vec::new(),
+        };
+
+        let mut header =
+            CephMessageHeader2::new(CephMessage::MonCommand(command.clone()).identifier(), 1);
+        header.transaction_id = tid;
+
+        let mut segments = Vec::new();
+        command.encode_message(&mut segments);
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+        let mut sent_in = None;
+
+        loop {
+            if sent_in != Some(self.sessions) {
+                self.send(header.clone(), &segments)?;
+                sent_in = Some(self.sessions);
+            }
+
+            let (header, message) = self.recv_message()?;
+            let is_reply = header.transaction_id == tid;
+
+            match message {
+                Ok(CephMessage::MonCommandAck(ack)) if is_reply => {
+                    return Ok((ack.result, ack.status, ack.output));
+                }
+                Err(e) if is_reply => return Err(e.into()),
+                message => self.pending.push_back(message),
+            }
+        }
+    }
+
+    /// Wait until we have received a [`MonMap`], returning
+    /// the FSID of the cluster.
+    fn wait_for_mon_map(&mut self) -> Result<Uuid, MonClientError> {
+        if self.mon_map.is_none() && self.subscriptions.get(Topic::MonMap).is_none() {
+            self.subscribe(Topic::MonMap, 0, true)?;
+        }
+
+        loop {
+            if let Some(mon_map) = &self.mon_map {
+                return Ok(mon_map.fsid);
+            }
+
+            let (_, message) = self.recv_message()?;
+            self.pending.push_back(message);
+        }
+    }
+
+    /// Receive the next message from the current monitor, hunting
+    /// for a new monitor if the session drops.
+    fn recv_message(&mut self) -> Result<Received, MonClientError> {
+        loop {
+            self.tick()?;
+
+            let timeout = self.next_deadline().map(|deadline| {
+                deadline
+                    .saturating_duration_since(Instant::now())
+                    .max(MIN_TICK)
+            });
+
+            let Some(session) = &mut self.session else {
+                continue;
+            };
+
+            // Wake up when renewals are due, even if no message arrives.
+            // A dead session is detected using keepalives.
+            let (header, message) = match session.peer.recv::<MonClientError>(timeout) {
+                Ok(Some(received)) => received,
+                Ok(None) => continue,
+                Err(_) => {
+                    self.hunt()?;
+                    continue;
+                }
+            };
+
+            match &message {
+                Ok(CephMessage::MonMap(mon_map)) => {
+                    self.subscriptions
+                        .got(Topic::MonMap, u64::from(mon_map.epoch));
+                    self.update_mon_map(mon_map.clone())?;
+                }
+                Ok(CephMessage::OsdMap(osd_map)) => {
+                    let last = osd_map
+                        .maps
+                        .keys()
+                        .chain(osd_map.incremental_maps.keys())
+                        .max();
+
+                    if let Some(last) = last {
+                        self.subscriptions.got(Topic::OsdMap, u64::from(last.0));
+                    }
+                }
+                Ok(CephMessage::MonSubscribeAck(ack)) => {
+                    let interval = Duration::from_secs(u64::from(ack.interval));
+                    self.subscriptions.acked(interval);
+                }
+                Ok(CephMessage::AuthReply(reply)) => self.recv_auth_reply(reply)?,
+                _ => {}
+            }
+
+            return Ok((header, message));
+        }
+    }
+
+    /// Store the tickets in `reply` to a `GetPrincipalSessionKey` request.
+    ///
+    /// Failed requests are sent again after [`REQUEST_TIMEOUT`].
+    fn recv_auth_reply(&mut self, reply: &MessageAuthReply) -> Result<(), MonClientError> {
+        let now = Instant::now();
+
+        if reply.result != 0 || reply.protocol != u32::from(AuthMethod::CephX) {
+            return Ok(());
+        }
+
+        let message = CephXMessage::decode(&mut reply.result_bl.as_slice())?;
+
+        let tickets = match message.ty() {
+            CephXMessageType::GetPrincipalSessionKey => {
+                let Some(auth) = self.tickets.get(EntityType::Auth, now) else {
+                    return Ok(());
+                };
+
+                PrincipalSessionKeyReply::decode(&mut message.payload())?
+                    .decrypt(&auth.session_ticket.session_key)?
+            }
+            CephXMessageType::GetAuthSessionKey => {
+                let Some(master_key) = self.credentials.key() else {
+                    return Ok(());
+                };
+
+                AuthServiceTicketReply::decode(&mut message.payload())?
+                    .decrypt(master_key)?
+                    .tickets
+            }
+            _ => return Ok(()),
+        };
+
+        self.tickets.got(tickets, now);
+        Ok(())
+    }
+
+    fn update_mon_map(&mut self, mon_map: MonMap) -> Result<(), MonClientError> {
+        if self
+            .mon_map
+            .as_ref()
+            .is_some_and(|current| current.epoch >= mon_map.epoch)
+        {
+            return Ok(());
+        }
+
+        self.monitors = mon_map.mon_info.clone();
+        self.mon_map = Some(mon_map);
+
+        let Some(session) = &mut self.session else {
+            return Ok(());
+        };
+
+        let current = self
+            .monitors
+            .values()
+            .find(|info| msgr2_address(info) == Some(session.address));
+
+        if let Some(current) = current {
+            session.name = current.name.clone();
+            Ok(())
+        } else {
+            self.hunt()
+        }
+    }
+
+    fn connect(&mut self, address: SocketAddr) -> Result<Peer, MonClientError> {
+        self.global_seq += 1;
+
+        let target = EntityAddress {
+            ty: EntityAddressType::Msgr2,
+            nonce: 0,
+            address: Some(address),
+        };
+
+        Peer::connect(
+            &target,
+            &self.config,
+            &self.credentials,
+            self.gid,
+            self.global_seq,
+            self.timeout,
+        )
+    }
+}
+
+/// The `msgr2` address of `info`, if it has one.
+fn msgr2_address(info: &MonInfo) -> Option<SocketAddr> {
+    info.public_addrs
+        .iter()
+        .find(|a| a.ty == EntityAddressType::Msgr2)
+        .and_then(|a| a.address)
+}
+
+/// The order in which to try `monitors` while hunting: ascending by
+/// priority, and in a random order weighted by weight for monitors with
+/// the same priority.
+///
+/// Monitors without a `msgr2` address are skipped.
+fn hunt_order<'a>(
+    monitors: impl Iterator<Item = &'a MonInfo>,
+    random: &mut impl FnMut() -> u64,
+) -> Vec<(String, SocketAddr)> {
+    let mut by_priority: Vec<(&MonInfo, SocketAddr)> = monitors
+        .filter_map(|info| Some((info, msgr2_address(info)?)))
+        .collect();
+
+    // Sort by name first, so that the order only depends on `random`.
+    by_priority.sort_by(|(a, _), (b, _)| (a.priority, &a.name).cmp(&(b.priority, &b.name)));
+
+    let mut order = Vec::with_capacity(by_priority.len());
+
+    for group in by_priority.chunk_by(|(a, _), (b, _)| a.priority == b.priority) {
+        let mut group = group.to_vec();
+
+        while !group.is_empty() {
+            let total: u64 = group.iter().map(|(info, _)| u64::from(info.weight)).sum();
+
+            let index = if total == 0 {
+                (random() % group.len() as u64) as usize
+            } else {
+                let mut pick = random() % total;
+                group
+                    .iter()
+                    .position(|(info, _)| {
+                        let weight = u64::from(info.weight);
+                        if pick < weight {
+                            true
+                        } else {
+                            pick -= weight;
+                            false
+                        }
+                    })
+                    .expect("Pick is less than total weight")
+            };
+
+            let (info, address) = group.remove(index);
+            order.push((info.name.clone(), address));
+        }
+    }
+
+    order
+}
+
+#[cfg(test)]
+fn mon(name: &str, port: u16, priority: u16, weight: u16) -> MonInfo {
+    MonInfo {
+        name: name.to_string(),
+        public_addrs: vec![EntityAddress {
+            ty: EntityAddressType::Msgr2,
+            nonce: 0,
+            address: Some(([127, 0, 0, 1], port).into()),
+        }],
+        priority,
+        weight,
+        crush_location: Default::default(),
+        time_added: None,
+    }
+}
+
+#[test]
+fn hunt_order_priority() {
+    let monitors = [mon("c", 3, 1, 0), mon("a", 1, 2, 0), mon("b", 2, 0, 0)];
+
+    let mut zero = || 0;
+    let order: Vec<_> = hunt_order(monitors.iter(), &mut zero)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+
+    assert_eq!(order, ["b", "c", "a"]);
+}
+
+#[test]
+fn hunt_order_weight() {
+    let monitors = [mon("a", 1, 0, 0), mon("b", 2, 0, 1), mon("c", 3, 0, 10)];
+
+    // Monitors without weight are never picked before weighted monitors.
+    for seed in 0..11 {
+        let mut random = || seed;
+        let order: Vec<_> = hunt_order(monitors.iter(), &mut random)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        let expected = if seed == 0 {
+            ["b", "c", "a"]
+        } else {
+            ["c", "b", "a"]
+        };
+
+        assert_eq!(order, expected, "{seed}");
+    }
+}
+
+#[test]
+fn hunt_order_skips_legacy() {
+    let mut legacy = mon("a", 1, 0, 0);
+    legacy.public_addrs[0].ty = EntityAddressType::Legacy;
+
+    let monitors = [legacy, mon("b", 2, 0, 0)];
+    let order = hunt_order(monitors.iter(), &mut || 0);
+
+    assert_eq!(order, [("b".to_string(), ([127, 0, 0, 1], 2).into())]);
+}
@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use ceph_messages::MonSubscribeItem;
+
+/// A map that can be subscribed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+    MonMap,
+    OsdMap,
+    Config,
+    MgrMap,
+    FsMap,
+}
+
+impl Topic {
+    /// The name of this topic in a `MonSubscribe`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topic::MonMap => "monmap",
+            Topic::OsdMap => "osdmap",
+            Topic::Config => "config",
+            Topic::MgrMap => "mgrmap",
+            Topic::FsMap => "fsmap",
+        }
+    }
+}
+
+/// The subscriptions of a client to maps published by the monitors.
+///
+/// Subscriptions are _new_ until they are [renewed](Subscriptions::renew),
+/// after which they are _sent_. When connecting to a new monitor, all sent
+/// subscriptions should be [reloaded](Subscriptions::reload) so that they
+/// are sent again, starting after the last epoch that was received.
+#[derive(Debug, Clone, Default)]
+pub struct Subscriptions {
+    new: HashMap<Topic, MonSubscribeItem>,
+    sent: HashMap<Topic, MonSubscribeItem>,
+    renew_sent: Option<Instant>,
+    renew_after: Option<Instant>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to `topic`, starting at epoch `start`.
+    ///
+    /// If `onetime` is set, only a single map is requested and the
+    /// subscription ends once it is received.
+    ///
+    /// Returns whether the subscription changed, i.e. whether it
+    /// must be sent.
+    pub fn want(&mut self, topic: Topic, start: u64, onetime: bool) -> bool {
+        let flags = if onetime {
+            MonSubscribeItem::ONETIME
+        } else {
+            0
+        };
+        let item = MonSubscribeItem { start, flags };
+
+        let current = self.new.get(&topic).or_else(|| self.sent.get(&topic));
+        if current == Some(&item) {
+            return false;
+        }
+
+        self.new.insert(topic, item);
+        true
+    }
+
+    /// Stop subscribing to `topic`.
+    pub fn unwant(&mut self, topic: Topic) {
+        self.new.remove(&topic);
+        self.sent.remove(&topic);
+    }
+
+    /// The current subscription to `topic`, if any.
+    pub fn get(&self, topic: Topic) -> Option<&MonSubscribeItem> {
+        self.new.get(&topic).or_else(|| self.sent.get(&topic))
+    }
+
+    /// Record that `epoch` of `topic` was received.
+    ///
+    /// Onetime subscriptions that are satisfied by `epoch` end, and
+    /// other subscriptions continue after `epoch`.
+    pub fn got(&mut self, topic: Topic, epoch: u64) {
+        let subscriptions = if self.new.contains_key(&topic) {
+            &mut self.new
+        } else {
+            &mut self.sent
+        };
+
+        let Some(item) = subscriptions.get_mut(&topic) else {
+            return;
+        };
+
+        if item.start > epoch {
+            return;
+        }
+
+        if item.is_onetime() {
+            subscriptions.remove(&topic);
+        } else {
+            item.start = epoch + 1;
+        }
+    }
+
+    /// Whether there are subscriptions that were not sent yet.
+    pub fn have_new(&self) -> bool {
+        !self.new.is_empty()
+    }
+
+    /// Whether the subscriptions must be renewed at `now`, as
+    /// requested by the monitor that acknowledged them.
+    pub fn need_renew(&self, now: Instant) -> bool {
+        self.renew_after.is_some_and(|after| now >= after)
+    }
+
+    /// When the subscriptions must be renewed next, if the monitor
+    /// asked us to renew them.
+    pub fn next_renewal(&self) -> Option<Instant> {
+        self.renew_after
+    }
+
+    /// Mark all sent subscriptions as new, so that they are sent
+    /// again (e.g. to a new monitor).
+    ///
+    /// Returns whether there are any subscriptions to send.
+    pub fn reload(&mut self) -> bool {
+        for (topic, item) in &self.sent {
+            self.new.entry(*topic).or_insert_with(|| item.clone());
+        }
+
+        self.renew_after = None;
+        self.have_new()
+    }
+
+    /// Get the subscriptions to send at `now`, if any, and mark
+    /// them as sent.
+    ///
+    /// If the subscriptions [need to be renewed](Subscriptions::need_renew),
+    /// all of them are returned.
+    pub fn renew(&mut self, now: Instant) -> Option<HashMap<String, MonSubscribeItem>> {
+        if self.need_renew(now) {
+            self.reload();
+        }
+
+        if !self.have_new() {
+            return None;
+        }
+
+        let what = self
+            .new
+            .iter()
+            .map(|(topic, item)| (topic.name().to_string(), item.clone()))
+            .collect();
+
+        self.sent.extend(self.new.drain());
+        self.renew_sent = Some(now);
+        self.renew_after = None;
+
+        Some(what)
+    }
+
+    /// Record that the monitor acknowledged the last subscriptions we
+    /// sent, and that they should be renewed after `interval`.
+    pub fn acked(&mut self, interval: Duration) {
+        if let Some(sent) = self.renew_sent {
+            self.renew_after = Some(sent + interval / 2);
+        }
+    }
+}
+
+#[test]
+fn want_and_renew() {
+    let now = Instant::now();
+    let mut subs = Subscriptions::new();
+
+    assert!(subs.want(Topic::OsdMap, 5, false));
+    assert!(!subs.want(Topic::OsdMap, 5, false));
+
+    let what = subs.renew(now).unwrap();
+    assert_eq!(what.len(), 1);
+    assert_eq!(what["osdmap"], MonSubscribeItem { start: 5, flags: 0 });
+
+    // Nothing changed, so there is nothing to send.
+    assert!(!subs.want(Topic::OsdMap, 5, false));
+    assert!(subs.renew(now).is_none());
+
+    assert!(subs.want(Topic::OsdMap, 7, false));
+    assert_eq!(subs.renew(now).unwrap()["osdmap"].start, 7);
+}
+
+#[test]
+fn got() {
+    let now = Instant::now();
+    let mut subs = Subscriptions::new();
+
+    subs.want(Topic::OsdMap, 0, false);
+    subs.want(Topic::MonMap, 0, true);
+    subs.renew(now).unwrap();
+
+    subs.got(Topic::OsdMap, 10);
+    subs.got(Topic::MonMap, 3);
+
+    assert_eq!(subs.get(Topic::OsdMap).unwrap().start, 11);
+    assert!(subs.get(Topic::MonMap).is_none());
+
+    // Older epochs do not move the subscription backwards.
+    subs.got(Topic::OsdMap, 4);
+    assert_eq!(subs.get(Topic::OsdMap).unwrap().start, 11);
+}
+
+#[test]
+fn reload() {
+    let now = Instant::now();
+    let mut subs = Subscriptions::new();
+
+    subs.want(Topic::OsdMap, 0, false);
+    subs.want(Topic::Config, 0, false);
+    subs.renew(now).unwrap();
+    subs.got(Topic::OsdMap, 20);
+
+    assert!(subs.reload());
+
+    let what = subs.renew(now).unwrap();
+    assert_eq!(what.len(), 2);
+    assert_eq!(what["osdmap"].start, 21);
+    assert_eq!(what["config"].start, 0);
+}
+
+#[test]
+fn renew_interval() {
+    let now = Instant::now();
+    let mut subs = Subscriptions::new();
+
+    subs.want(Topic::MgrMap, 0, false);
+    subs.renew(now).unwrap();
+    assert!(!subs.need_renew(now + Duration::from_secs(1000)));
+
+    assert!(subs.next_renewal().is_none());
+
+    subs.acked(Duration::from_secs(300));
+    assert_eq!(subs.next_renewal(), Some(now + Duration::from_secs(150)));
+    assert!(!subs.need_renew(now + Duration::from_secs(149)));
+    assert!(subs.need_renew(now + Duration::from_secs(150)));
+
+    let what = subs.renew(now + Duration::from_secs(150)).unwrap();
+    assert_eq!(what["mgrmap"].start, 0);
+    assert!(!subs.need_renew(now + Duration::from_secs(150)));
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use ceph_foundation::entity::EntityType;
+use cephx::Ticket;
+
+/// The time after which a request for tickets that was not
+/// answered is sent again.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A ticket, and when it should be renewed.
+#[derive(Debug, Clone)]
+struct TrackedTicket {
+    ticket: Ticket,
+    renew_after: Instant,
+    expires: Instant,
+}
+
+/// The tickets that a client received from the monitors, and
+/// when they expire.
+///
+/// Tickets are renewed once three quarters of their validity has
+/// passed, like Ceph does. Tickets are [renewed](TicketManager::renew) over
+/// the monitor session, using a `GetAuthSessionKey` request if the auth
+/// ticket itself must be renewed, and a `GetPrincipalSessionKey` request
+/// otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct TicketManager {
+    tickets: HashMap<EntityType, TrackedTicket>,
+    requested: Option<Instant>,
+}
+
+impl TicketManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `tickets` were received at `now`, replacing the
+    /// current tickets for the same services.
+    pub fn got(&mut self, tickets: impl IntoIterator<Item = Ticket>, now: Instant) {
+        for ticket in tickets {
+            let validity = &ticket.session_ticket.validity;
+            let validity = Duration::new(u64::from(validity.tv_sec), validity.tv_nsec);
+
+            let tracked = TrackedTicket {
+                renew_after: now + validity - validity / 4,
+                expires: now + validity,
+                ticket,
+            };
+
+            self.tickets.insert(tracked.ticket.ty, tracked);
+        }
+
+        self.requested = None;
+    }
+
+    /// Forget all tickets, e.g. because the session they were
+    /// handed out in ended.
+    pub fn clear(&mut self) {
+        self.tickets.clear();
+        self.requested = None;
+    }
+
+    /// The ticket for `service`, if we have one that has
+    /// not expired at `now`.
+    pub fn get(&self, service: EntityType, now: Instant) -> Option<&Ticket> {
+        self.tickets
+            .get(&service)
+            .filter(|t| now < t.expires)
+            .map(|t| &t.ticket)
+    }
+
+    /// When the ticket for `service` expires, if we have one.
+    pub fn expires(&self, service: EntityType) -> Option<Instant> {
+        self.tickets.get(&service).map(|t| t.expires)
+    }
+
+    /// Whether the ticket for `service` must be renewed at `now`,
+    /// i.e. whether we have none or it is about to expire.
+    pub fn need_renew(&self, service: EntityType, now: Instant) -> bool {
+        self.tickets
+            .get(&service)
+            .is_none_or(|t| now >= t.renew_after)
+    }
+
+    /// When tickets for `wanted` must be requested next, as seen at `now`.
+    ///
+    /// Returns `None` if `wanted` is empty.
+    pub fn next_renewal(&self, wanted: &[EntityType], now: Instant) -> Option<Instant> {
+        let due = wanted
+            .iter()
+            .map(|ty| self.tickets.get(ty).map_or(now, |t| t.renew_after))
+            .min()?;
+
+        match self.requested {
+            Some(requested) => Some(due.max(requested + REQUEST_TIMEOUT)),
+            None => Some(due),
+        }
+    }
+
+    /// Get the services in `wanted` whose tickets must be requested at
+    /// `now`, if any, and mark them as requested.
+    ///
+    /// Nothing is requested while an earlier request was sent
+    /// less than [`REQUEST_TIMEOUT`] ago.
+    pub fn renew(&mut self, wanted: &[EntityType], now: Instant) -> Option<HashSet<EntityType>> {
+        if self
+            .requested
+            .is_some_and(|requested| now < requested + REQUEST_TIMEOUT)
+        {
+            return None;
+        }
+
+        let keys: HashSet<_> = wanted
+            .iter()
+            .copied()
+            .filter(|ty| self.need_renew(*ty, now))
+            .collect();
+
+        if keys.is_empty() {
+            return None;
+        }
+
+        self.requested = Some(now);
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+fn ticket(ty: EntityType, validity: u32) -> Ticket {
+    use ceph_foundation::{Timestamp, crypto::Key};
+    use cephx::{CephXServiceTicket, MaybeEncryptedCephXTicketBlob};
+
+    Ticket {
+        ty,
+        session_ticket: CephXServiceTicket {
+            session_key: Key::new(Timestamp::new(0, 0), [1; 16]),
+            validity: Timestamp::new(validity, 0),
+        },
+        refresh_ticket: MaybeEncryptedCephXTicketBlob::Unencrypted(Default::default()),
+    }
+}
+
+#[test]
+fn expiry() {
+    let now = Instant::now();
+    let mut tickets = TicketManager::new();
+
+    tickets.got([ticket(EntityType::Osd, 100)], now);
+
+    assert_eq!(
+        tickets.expires(EntityType::Osd),
+        Some(now + Duration::from_secs(100))
+    );
+    assert!(tickets.get(EntityType::Osd, now).is_some());
+    assert!(
+        tickets
+            .get(EntityType::Osd, now + Duration::from_secs(100))
+            .is_none()
+    );
+    assert!(tickets.get(EntityType::Mds, now).is_none());
+
+    assert!(!tickets.need_renew(EntityType::Osd, now + Duration::from_secs(74)));
+    assert!(tickets.need_renew(EntityType::Osd, now + Duration::from_secs(75)));
+    assert!(tickets.need_renew(EntityType::Mds, now));
+}
+
+#[test]
+fn renew() {
+    let now = Instant::now();
+    let mut tickets = TicketManager::new();
+    let wanted = [EntityType::Auth, EntityType::Osd, EntityType::Mgr];
+
+    tickets.got(
+        [
+            ticket(EntityType::Auth, 10),
+            ticket(EntityType::Osd, 100),
+            ticket(EntityType::Mgr, 1000),
+        ],
+        now,
+    );
+
+    assert!(tickets.renew(&wanted, now).is_none());
+    assert_eq!(
+        tickets.next_renewal(&wanted, now),
+        Some(now + Duration::from_millis(7500))
+    );
+
+    let soon = now + Duration::from_secs(8);
+    assert_eq!(
+        tickets.renew(&wanted, soon),
+        Some(HashSet::from([EntityType::Auth]))
+    );
+
+    tickets.got([ticket(EntityType::Auth, 100)], soon);
+
+    let later = now + Duration::from_secs(80);
+    assert_eq!(
+        tickets.renew(&wanted, later),
+        Some(HashSet::from([EntityType::Osd]))
+    );
+
+    // The request is only repeated after it timed out.
+    assert!(tickets.renew(&wanted, later).is_none());
+    assert_eq!(
+        tickets.next_renewal(&wanted, later),
+        Some(later + REQUEST_TIMEOUT)
+    );
+    assert!(tickets.renew(&wanted, later + REQUEST_TIMEOUT).is_some());
+
+    tickets.got([ticket(EntityType::Osd, 100)], later);
+    assert!(tickets.renew(&wanted, later).is_none());
+}
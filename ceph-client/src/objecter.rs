@@ -0,0 +1,622 @@
+//! Sending ops to the OSDs of a cluster.
+//!
+//! The [`Objecter`] maps every op to the primary OSD of the placement
+//! group of its object, using the most recent [`OsdMap`]. It maintains
+//! a session with every OSD that it sends ops to, and tracks the ops that
+//! are in flight by their transaction ID until a reply is received.
+//!
+//! Ops are sent again if a new map changes their placement group or
+//! acting set, if the session with their OSD drops, or if the OSD
+//! replies with `-EAGAIN` or a redirect.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::NonZeroU16,
+    time::{Duration, SystemTime},
+};
+
+use ceph_foundation::{
+    CephFeatureSet, DecodeError, Timestamp,
+    entity::{EntityAddressType, EntityType},
+};
+use ceph_messages::{
+    ApplyIncrementalError, CephMessage, DecodeMessageError, EncodeMessage, Epoch, MessageOsdMap,
+    MessageOsdOp, MessageOsdOpReply, ObjectLocator, OsdMap, OsdOp, OsdOpFlags, OsdReqId, PoolId,
+    SpgId,
+};
+use msgr2::wire::{RxError, TxError};
+
+use crate::{
+    connection::{Config, Credentials, HandshakeError, SendError},
+    header::CephMessageHeader2,
+    mon_client::{DEFAULT_TIMEOUT, MonClient, MonClientError, Topic},
+    peer::Peer,
+};
+
+/// The result of an op that must be sent again (`-EAGAIN`).
+const EAGAIN: i32 = -11;
+
+/// An error that occurred while sending ops to the OSDs.
+#[derive(Debug)]
+pub enum ObjecterError {
+    Io(std::io::Error),
+    Rx(RxError),
+    Tx(TxError),
+    Decode(DecodeError),
+    Handshake(HandshakeError),
+    /// The peer did not acknowledge enough of our messages to
+    /// send another one.
+    Send(SendError),
+    /// A received message could not be decoded.
+    Message(DecodeMessageError),
+    Mon(MonClientError),
+    /// A received OSD map could not be applied.
+    Map(ApplyIncrementalError),
+    /// The pool of an op does not exist.
+    PoolDoesNotExist(i64),
+    /// No op with the transaction ID is in flight.
+    UnknownTid(u64),
+}
+
+impl From<std::io::Error> for ObjecterError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<RxError> for ObjecterError {
+    fn from(value: RxError) -> Self {
+        Self::Rx(value)
+    }
+}
+
+impl From<TxError> for ObjecterError {
+    fn from(value: TxError) -> Self {
+        Self::Tx(value)
+    }
+}
+
+impl From<DecodeError> for ObjecterError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+impl From<HandshakeError> for ObjecterError {
+    fn from(value: HandshakeError) -> Self {
+        Self::Handshake(value)
+    }
+}
+
+impl From<SendError> for ObjecterError {
+    fn from(value: SendError) -> Self {
+        Self::Send(value)
+    }
+}
+
+impl From<DecodeMessageError> for ObjecterError {
+    fn from(value: DecodeMessageError) -> Self {
+        Self::Message(value)
+    }
+}
+
+impl From<MonClientError> for ObjecterError {
+    fn from(value: MonClientError) -> Self {
+        Self::Mon(value)
+    }
+}
+
+impl From<ApplyIncrementalError> for ObjecterError {
+    fn from(value: ApplyIncrementalError) -> Self {
+        Self::Map(value)
+    }
+}
+
+/// Where an op is sent to.
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    pgid: SpgId,
+    /// The full hash of the object.
+    hash: u32,
+    acting: Vec<i32>,
+    /// The acting primary, or `-1` if there is none.
+    primary: i32,
+}
+
+impl Target {
+    /// Compute the target of the object called `oid` at `locator`.
+    ///
+    /// Returns `None` if the pool does not exist.
+    fn compute(map: &OsdMap, oid: &str, locator: &ObjectLocator) -> Option<Self> {
+        let raw = map.object_locator_to_pg(oid, locator)?;
+        let pool = map.pools.get(&PoolId(locator.pool))?;
+        let pg = pool.raw_pg_to_pg(raw);
+        let (_, _, acting, primary) = map.pg_to_up_acting_osds(pg)?;
+
+        // Erasure coded pools address the shard that the primary holds.
+        let shard = if pool.can_shift_osds() {
+            SpgId::NO_SHARD
+        } else {
+            acting
+                .iter()
+                .position(|osd| *osd == primary)
+                .map_or(SpgId::NO_SHARD, |shard| shard as i8)
+        };
+
+        Some(Self {
+            pgid: SpgId { pgid: pg, shard },
+            hash: raw.seed,
+            acting,
+            primary,
+        })
+    }
+}
+
+/// An op that is in flight.
+#[derive(Debug)]
+struct Op {
+    locator: ObjectLocator,
+    oid: String,
+    ops: Vec<OsdOp>,
+    flags: OsdOpFlags,
+    mtime: Timestamp,
+    /// The target that the op was last sent to.
+    target: Option<Target>,
+    /// The OSD that the op was last sent to, or `None` if
+    /// it could not be sent.
+    osd: Option<i32>,
+    /// Whether the op is waiting for `osd` to acknowledge earlier
+    /// messages before it can be sent.
+    blocked: bool,
+    /// The amount of times that the op was sent.
+    attempts: i32,
+}
+
+/// A client for the OSDs of a cluster.
+#[derive(Debug)]
+pub struct Objecter {
+    mon_client: MonClient,
+    osd_map: Option<OsdMap>,
+    config: Config,
+    credentials: Credentials,
+    timeout: Duration,
+    global_seq: u64,
+    last_tid: u64,
+    ops: BTreeMap<u64, Op>,
+    /// The results of ops that completed, but were
+    /// not waited for yet.
+    completed: HashMap<u64, Result<MessageOsdOpReply, ObjecterError>>,
+    sessions: HashMap<i32, Peer>,
+}
+
+impl Objecter {
+    /// Create a new client that obtains OSD maps from the
+    /// monitors using `mon_client`, and connects to OSDs using
+    /// `config`.
+    ///
+    /// OSDs are authenticated to using the OSD ticket handed out by the
+    /// monitors, if the `mon_client` requested one. Otherwise, they are
+    /// authenticated to using `credentials`.
+    pub fn new(mon_client: MonClient, config: Config, credentials: Credentials) -> Self {
+        let global_seq = config.rng().next_u64() >> 32;
+
+        Self {
+            mon_client,
+            osd_map: None,
+            config,
+            credentials,
+            timeout: DEFAULT_TIMEOUT,
+            global_seq,
+            last_tid: 0,
+            ops: BTreeMap::new(),
+            completed: HashMap::new(),
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Set the timeout for connecting to and completing the
+    /// handshake with a single OSD.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The client used to talk to the monitors.
+    pub fn mon_client(&mut self) -> &mut MonClient {
+        &mut self.mon_client
+    }
+
+    /// The most recent [`OsdMap`], if any.
+    pub fn osd_map(&self) -> Option<&OsdMap> {
+        self.osd_map.as_ref()
+    }
+
+    /// The amount of ops that are in flight.
+    pub fn in_flight(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Wait until we have an [`OsdMap`] with at least `epoch`.
+    ///
+    /// Maps are requested from the monitors. Other messages that the
+    /// monitors send in the meantime are discarded.
+    pub fn wait_for_map(&mut self, epoch: Epoch) -> Result<(), ObjecterError> {
+        while self.osd_map.as_ref().is_none_or(|map| map.epoch < epoch) {
+            if self.mon_client.subscriptions().get(Topic::OsdMap).is_none() {
+                let start = self
+                    .osd_map
+                    .as_ref()
+                    .map_or(0, |m| u64::from(m.epoch.0) + 1);
+                self.mon_client.subscribe(Topic::OsdMap, start, true)?;
+            }
+
+            if let CephMessage::OsdMap(message) = self.mon_client.recv()? {
+                self.handle_osd_map(&message)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the maps in `message`, and send the ops whose
+    /// target changed again.
+    ///
+    /// Maps that are older than the current map, or that do not follow
+    /// it, are ignored.
+    pub fn handle_osd_map(&mut self, message: &MessageOsdMap) -> Result<(), ObjecterError> {
+        let before = self.osd_map.as_ref().map(|m| m.epoch);
+
+        let mut epochs: Vec<Epoch> = message
+            .incremental_maps
+            .keys()
+            .chain(message.maps.keys())
+            .copied()
+            .collect();
+        epochs.sort();
+        epochs.dedup();
+
+        for epoch in epochs {
+            let current = self.osd_map.as_ref().map(|m| m.epoch);
+
+            if current.is_some_and(|current| epoch <= current) {
+                continue;
+            }
+
+            let incremental = message
+                .incremental_maps
+                .get(&epoch)
+                .filter(|_| current.is_some_and(|current| current.0 + 1 == epoch.0));
+
+            if let (Some(map), Some(incremental)) = (&self.osd_map, incremental) {
+                self.osd_map = Some(map.apply_incremental(&incremental.0)?);
+            } else if let Some(map) = message.maps.get(&epoch) {
+                self.osd_map = Some(map.0.clone());
+            }
+        }
+
+        if self.osd_map.as_ref().map(|m| m.epoch) != before {
+            self.scan_ops()?;
+        }
+
+        Ok(())
+    }
+
+    /// Send `ops` on the object called `oid` at `locator`, returning
+    /// the transaction ID that identifies them.
+    ///
+    /// If no [`OsdMap`] was received yet, we wait for one first. The
+    /// reply is obtained using [`Objecter::wait`].
+    pub fn submit(
+        &mut self,
+        locator: ObjectLocator,
+        oid: &str,
+        ops: Vec<OsdOp>,
+    ) -> Result<u64, ObjecterError> {
+        self.wait_for_map(Epoch(0))?;
+
+        self.last_tid += 1;
+        let tid = self.last_tid;
+
+        let mut flags = 0;
+        if ops.iter().any(OsdOp::is_read) {
+            flags |= OsdOpFlags::READ;
+        }
+        if ops.iter().any(OsdOp::is_write) {
+            flags |= OsdOpFlags::WRITE | OsdOpFlags::ONDISK;
+        }
+
+        let op = Op {
+            locator,
+            oid: oid.to_string(),
+            ops,
+            flags: OsdOpFlags(flags),
+            mtime: now(),
+            target: None,
+            osd: None,
+            blocked: false,
+            attempts: 0,
+        };
+
+        self.ops.insert(tid, op);
+        self.send_op(tid)?;
+        Ok(tid)
+    }
+
+    /// Wait for the reply to the ops identified by `tid`.
+    ///
+    /// Replies to other ops that are received in the meantime are
+    /// kept until they are waited for.
+    pub fn wait(&mut self, tid: u64) -> Result<MessageOsdOpReply, ObjecterError> {
+        loop {
+            if let Some(result) = self.completed.remove(&tid) {
+                return result;
+            }
+
+            let op = self.ops.get(&tid).ok_or(ObjecterError::UnknownTid(tid))?;
+
+            if let Some(osd) = op.osd {
+                self.recv_from(osd)?;
+            } else {
+                // The op can only be sent once the map changes.
+                let next = self.osd_map.as_ref().map_or(0, |m| m.epoch.0 + 1);
+                self.wait_for_map(Epoch(next))?;
+            }
+        }
+    }
+
+    /// Send `ops` on the object called `oid` at `locator`, and wait
+    /// for the reply.
+    pub fn execute(
+        &mut self,
+        locator: ObjectLocator,
+        oid: &str,
+        ops: Vec<OsdOp>,
+    ) -> Result<MessageOsdOpReply, ObjecterError> {
+        let tid = self.submit(locator, oid, ops)?;
+        self.wait(tid)
+    }
+
+    /// Send the op identified by `tid` to the primary of its
+    /// current target.
+    ///
+    /// If the op cannot be sent, it is sent again once the map changes.
+    /// If the OSD has too many of our messages unacknowledged, it is sent
+    /// once the OSD acknowledges them instead.
+    fn send_op(&mut self, tid: u64) -> Result<(), ObjecterError> {
+        let Some(map) = &self.osd_map else {
+            return Ok(());
+        };
+
+        let op = self.ops.get_mut(&tid).expect("Op is in flight");
+        op.osd = None;
+        op.blocked = false;
+
+        let Some(target) = Target::compute(map, &op.oid, &op.locator) else {
+            let pool = op.locator.pool;
+            self.ops.remove(&tid);
+            self.completed
+                .insert(tid, Err(ObjecterError::PoolDoesNotExist(pool)));
+            return Ok(());
+        };
+
+        let osd = target.primary;
+        op.target = Some(target.clone());
+
+        if osd < 0 {
+            return Ok(());
+        }
+
+        let mut flags = op.flags;
+        if op.attempts > 0 {
+            flags.0 |= OsdOpFlags::RETRY;
+        }
+
+        let message = MessageOsdOp {
+            pgid: target.pgid,
+            hash: target.hash,
+            epoch: map.epoch,
+            flags,
+            reqid: OsdReqId {
+                entity_type: EntityType::Client,
+                entity_num: self.mon_client.global_id().unwrap_or(0) as i64,
+                tid,
+                inc: 0,
+            },
+            client_inc: 0,
+            mtime: op.mtime.clone(),
+            locator: op.locator.clone(),
+            oid: op.oid.clone(),
+            ops: op.ops.clone(),
+            snap_id: MessageOsdOp::SNAP_HEAD,
+            snap_seq: 0,
+            snaps: Vec::new(),
+            retry_attempt: op.attempts,
+            features: u64::from(&CephFeatureSet::ALL),
+        };
+
+        let mut segments = Vec::new();
+        message.encode_message(&mut segments);
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+        let mut header = CephMessageHeader2::new(
+            CephMessage::OsdOp(message).identifier(),
+            MessageOsdOp::VERSION,
+        );
+        header.transaction_id = tid;
+        header.compat_version = NonZeroU16::new(MessageOsdOp::COMPAT_VERSION);
+
+        let Some(session) = self.session(osd) else {
+            return Ok(());
+        };
+
+        let sent = match session.send::<ObjecterError>(header, &segments) {
+            Ok(()) => true,
+            Err(ObjecterError::Send(SendError::TooManyUnacked(_))) => false,
+            Err(_) => {
+                // The op is sent again once the map changes.
+                self.sessions.remove(&osd);
+                return Ok(());
+            }
+        };
+
+        let op = self.ops.get_mut(&tid).expect("Op is in flight");
+        op.osd = Some(osd);
+        op.blocked = !sent;
+        if sent {
+            op.attempts += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Get the session with `osd`, connecting to it if we
+    /// do not have one.
+    ///
+    /// Returns `None` if connecting to `osd` fails.
+    fn session(&mut self, osd: i32) -> Option<&mut Peer> {
+        if !self.sessions.contains_key(&osd) {
+            let map = self.osd_map.as_ref()?;
+            let address = map
+                .client_addrs
+                .get(usize::try_from(osd).ok()?)?
+                .iter()
+                .find(|a| a.ty == EntityAddressType::Msgr2)?;
+
+            self.global_seq += 1;
+            let gid = self.mon_client.global_id().unwrap_or(0) as i64;
+
+            let credentials = self
+                .mon_client
+                .service_credentials(EntityType::Osd)
+                .unwrap_or_else(|| self.credentials.clone());
+
+            let peer = Peer::connect::<ObjecterError>(
+                address,
+                &self.config,
+                &credentials,
+                gid,
+                self.global_seq,
+                self.timeout,
+            )
+            .ok()?;
+
+            self.sessions.insert(osd, peer);
+        }
+
+        self.sessions.get_mut(&osd)
+    }
+
+    /// Receive the next message from `osd`.
+    ///
+    /// If the session drops, the ops that were sent to `osd`
+    /// are sent again. Otherwise, the ops that were waiting for `osd`
+    /// to acknowledge earlier messages are sent.
+    fn recv_from(&mut self, osd: i32) -> Result<(), ObjecterError> {
+        let received = match self.sessions.get_mut(&osd) {
+            Some(session) => session.recv::<ObjecterError>(None),
+            None => Err(ObjecterError::Io(std::io::ErrorKind::NotConnected.into())),
+        };
+
+        match received {
+            Ok(Some((header, Ok(CephMessage::OsdOpReply(reply))))) => {
+                self.handle_reply(header.transaction_id, reply)
+            }
+            Ok(Some((_, Ok(CephMessage::OsdMap(message))))) => self.handle_osd_map(&message),
+            Ok(Some((header, Err(e)))) if self.ops.contains_key(&header.transaction_id) => {
+                self.ops.remove(&header.transaction_id);
+                self.completed.insert(header.transaction_id, Err(e.into()));
+                Ok(())
+            }
+            Ok(_) => Ok(()),
+            Err(_) => {
+                self.sessions.remove(&osd);
+
+                let tids: Vec<u64> = self
+                    .ops
+                    .iter()
+                    .filter(|(_, op)| op.osd == Some(osd))
+                    .map(|(tid, _)| *tid)
+                    .collect();
+
+                return tids.into_iter().try_for_each(|tid| self.send_op(tid));
+            }
+        }?;
+
+        let blocked: Vec<u64> = self
+            .ops
+            .iter()
+            .filter(|(_, op)| op.blocked && op.osd == Some(osd))
+            .map(|(tid, _)| *tid)
+            .collect();
+
+        blocked.into_iter().try_for_each(|tid| self.send_op(tid))
+    }
+
+    fn handle_reply(&mut self, tid: u64, reply: MessageOsdOpReply) -> Result<(), ObjecterError> {
+        let Some(op) = self.ops.get_mut(&tid) else {
+            return Ok(());
+        };
+
+        // This is a reply to an earlier attempt, which was
+        // sent again.
+        if reply.retry_attempt != op.attempts - 1 {
+            return Ok(());
+        }
+
+        if let Some(redirect) = reply.redirect {
+            op.locator = redirect.locator;
+            if !redirect.object.is_empty() {
+                op.oid = redirect.object;
+            }
+
+            return self.send_op(tid);
+        }
+
+        if reply.result == EAGAIN {
+            let attempts = op.attempts;
+
+            // The OSD may know about a newer map than we do, in
+            // which case the op may be sent again by applying it.
+            self.wait_for_map(reply.epoch)?;
+
+            if self.ops.get(&tid).is_some_and(|op| op.attempts == attempts) {
+                self.send_op(tid)?;
+            }
+
+            return Ok(());
+        }
+
+        self.ops.remove(&tid);
+        self.completed.insert(tid, Ok(reply));
+        Ok(())
+    }
+
+    /// Close the sessions with OSDs that are no longer up, and send the
+    /// ops whose target changed (or that could not be sent) again.
+    fn scan_ops(&mut self) -> Result<(), ObjecterError> {
+        let Some(map) = &self.osd_map else {
+            return Ok(());
+        };
+
+        self.sessions.retain(|osd, _| map.is_up(*osd));
+
+        let tids: Vec<u64> = self
+            .ops
+            .iter()
+            .filter(|(_, op)| {
+                op.osd.is_none_or(|osd| !self.sessions.contains_key(&osd))
+                    || Target::compute(map, &op.oid, &op.locator) != op.target
+            })
+            .map(|(tid, _)| *tid)
+            .collect();
+
+        tids.into_iter().try_for_each(|tid| self.send_op(tid))
+    }
+}
+
+fn now() -> Timestamp {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Timestamp::new(now.as_secs() as u32, now.subsec_nanos())
+}
@@ -0,0 +1,277 @@
+//! Blocking connections to monitors and OSDs, used by the
+//! [`MonClient`](crate::mon_client::MonClient) and the
+//! [`Objecter`](crate::objecter::Objecter).
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ceph_foundation::{CephFeatureSet, Decode, DecodeError, Timestamp, entity::EntityAddress};
+use ceph_messages::{CephMessage, DecodeMessageError};
+use cephx::Ticket;
+use msgr2::{
+    Tag,
+    frames::{Banner, ClientIdent, Keepalive, KeepaliveAck},
+    wire::{RxError, TxError},
+};
+
+use crate::{
+    connection::{
+        ClientConnection, Config, Credentials, Handshake, HandshakeError, Message, SendError,
+        state::Active,
+    },
+    header::CephMessageHeader2,
+};
+
+/// A received message, and its header.
+pub(crate) type Received = (CephMessageHeader2, Result<CephMessage, DecodeMessageError>);
+
+/// The errors that can occur while talking to a [`Peer`].
+pub(crate) trait PeerError:
+    From<std::io::Error>
+    + From<RxError>
+    + From<TxError>
+    + From<DecodeError>
+    + From<HandshakeError>
+    + From<SendError>
+{
+}
+
+impl<T> PeerError for T where
+    T: From<std::io::Error>
+        + From<RxError>
+        + From<TxError>
+        + From<DecodeError>
+        + From<HandshakeError>
+        + From<SendError>
+{
+}
+
+/// An established connection with a peer.
+///
+/// If nothing is received from the peer for the [keepalive
+/// interval](Config::keepalive_interval), a keepalive is sent. If that
+/// is not acknowledged within the interval either, the session is dead
+/// and receiving fails.
+#[derive(Debug)]
+pub(crate) struct Peer {
+    stream: TcpStream,
+    connection: ClientConnection<Active>,
+    keepalive_interval: Duration,
+    last_rx: Instant,
+    /// When the keepalive that was not acknowledged yet was sent.
+    keepalive_sent: Option<Instant>,
+}
+
+impl Peer {
+    /// Connect to `target` using `config`, authenticate using
+    /// `credentials` and identify as `gid`.
+    ///
+    /// Connecting and completing the handshake fails if it
+    /// takes longer than `timeout`.
+    pub fn connect<E: PeerError>(
+        target: &EntityAddress,
+        config: &Config,
+        credentials: &Credentials,
+        gid: i64,
+        global_seq: u64,
+        timeout: Duration,
+    ) -> Result<Self, E> {
+        let address = target.address.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Target has no address")
+        })?;
+
+        let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let ident = ClientIdent {
+            // Filled in using the address that the peer reports for us.
+            addresses: Vec::new(),
+            target: target.clone(),
+            gid,
+            global_seq,
+            supported_features: CephFeatureSet::ALL,
+            required_features: CephFeatureSet::EMPTY,
+            flags: 0,
+            cookie: config.rng().next_u64().max(1),
+        };
+
+        let connection = ClientConnection::new(config.clone());
+        let mut handshake = Handshake::new(connection, credentials.clone(), ident);
+
+        stream.write_all(&handshake.banner().to_bytes())?;
+
+        let mut banner = [0u8; Banner::SERIALIZED_SIZE];
+        stream.read_exact(&mut banner)?;
+
+        let banner = Banner::parse(&banner).map_err(HandshakeError::Banner)?;
+        handshake.recv_banner(&banner)?;
+
+        loop {
+            while let Some(data) = handshake.poll_transmit() {
+                stream.write_all(&data)?;
+            }
+
+            let mut buffer = Vec::new();
+            let Some(frame) = handshake.start_rx(&mut buffer) else {
+                break;
+            };
+
+            let frame = frame.read_preamble(&mut stream)?;
+            let frame = frame.read_rest(&mut stream)?;
+            handshake.recv_frame(frame)?;
+        }
+
+        // A peer that stops sending in the middle of a frame is dead.
+        let keepalive_interval = config.keepalive_interval();
+        stream.set_read_timeout(Some(keepalive_interval))?;
+
+        let connection = handshake.finish().expect("Handshake is done");
+        Ok(Self {
+            stream,
+            connection,
+            keepalive_interval,
+            last_rx: Instant::now(),
+            keepalive_sent: None,
+        })
+    }
+
+    /// The global ID that the peer assigned to us.
+    pub fn global_id(&self) -> u64 {
+        self.connection.global_id()
+    }
+
+    /// The service tickets that the peer handed out to us.
+    pub fn tickets(&self) -> &[Ticket] {
+        self.connection.tickets()
+    }
+
+    /// The CephX challenge that the peer sent while authenticating us.
+    pub fn server_challenge(&self) -> Option<u64> {
+        self.connection.server_challenge()
+    }
+
+    pub fn send<E: PeerError>(
+        &mut self,
+        header: CephMessageHeader2,
+        data: &[&[u8]],
+    ) -> Result<(), E> {
+        self.connection
+            .send_message(header, data)?
+            .write(&mut self.stream)?;
+        Ok(())
+    }
+
+    /// Receive the next frame, returning the message that it
+    /// contains (if any).
+    ///
+    /// Returns `Ok(None)` if nothing arrived within `timeout`, or if a
+    /// keepalive was sent instead. Fails if the session is dead.
+    pub fn recv<E: PeerError>(&mut self, timeout: Option<Duration>) -> Result<Option<Received>, E> {
+        let keepalive_at = match self.keepalive_sent {
+            Some(sent) => sent + self.keepalive_interval,
+            None => self.last_rx + self.keepalive_interval,
+        };
+
+        let mut wait = keepalive_at.saturating_duration_since(Instant::now());
+        if let Some(timeout) = timeout {
+            wait = wait.min(timeout);
+        }
+
+        if !self.wait(wait)? {
+            if Instant::now() < keepalive_at {
+                return Ok(None);
+            }
+
+            if self.keepalive_sent.is_some() {
+                let error = std::io::Error::new(ErrorKind::TimedOut, "Keepalive was not acked");
+                return Err(error.into());
+            }
+
+            let keepalive = Keepalive {
+                timestamp: timestamp(),
+            };
+
+            self.connection.send(keepalive).write(&mut self.stream)?;
+            self.keepalive_sent = Some(Instant::now());
+            return Ok(None);
+        }
+
+        let mut buffer = Vec::new();
+        let frame = self.connection.start_rx(&mut buffer);
+        let frame = frame.read_preamble(&mut self.stream)?;
+        let frame = frame.read_rest(&mut self.stream)?;
+
+        self.last_rx = Instant::now();
+
+        let Some(frame) = self.connection.finish_rx_raw(frame)? else {
+            return Ok(None);
+        };
+
+        match frame.tag() {
+            Tag::Message => {
+                let message = msgr2::frames::Message::from_frame(&frame)?;
+                let header = CephMessageHeader2::decode(&mut message.header())?;
+                let message = CephMessage::decode_message(header.ty, message.data_segments());
+
+                // If the session dropped, the next receive fails instead, so
+                // that the message is not lost.
+                if let Some(ack) = self.connection.send_ack() {
+                    let _ = ack.write(&mut self.stream);
+                }
+
+                Ok(Some((header, message)))
+            }
+            Tag::Keepalive2 => {
+                let Message::Keepalive(keepalive) =
+                    Message::decode(frame.tag(), frame.segments()[0])?
+                else {
+                    unreachable!("Keepalive2 frames decode to keepalives");
+                };
+
+                let ack = KeepaliveAck {
+                    timestamp: keepalive.timestamp,
+                };
+                self.connection.send(ack).write(&mut self.stream)?;
+                Ok(None)
+            }
+            Tag::Keepalive2Ack => {
+                self.keepalive_sent = None;
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Wait up to `timeout` for data to arrive, returning
+    /// whether it did.
+    ///
+    /// Nothing is read, so a frame that arrives is not cut
+    /// short by the timeout.
+    fn wait(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        // A zero timeout is rejected.
+        let timeout = timeout.max(Duration::from_millis(1));
+
+        self.stream.set_read_timeout(Some(timeout))?;
+        let result = self.stream.peek(&mut [0]);
+        self.stream
+            .set_read_timeout(Some(self.keepalive_interval))?;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn timestamp() -> Timestamp {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Timestamp::new(now.as_secs() as u32, now.subsec_nanos())
+}
@@ -0,0 +1,233 @@
+//! A high-level API for objects in a cluster, modelled after librados.
+//!
+//! A [`Rados`] handle connects to the monitors and receives the current
+//! [`OsdMap`](ceph_messages::OsdMap). An [`IoCtx`] for a pool is then
+//! used to perform operations on the objects in that pool.
+
+use std::collections::HashMap;
+
+use ceph_foundation::{DecodeError, MonInfo, Timestamp, entity::EntityType};
+use ceph_messages::{Epoch, ObjectLocator, OsdOp};
+
+use crate::{
+    connection::{Config, Credentials},
+    mon_client::MonClient,
+    objecter::{Objecter, ObjecterError},
+};
+
+/// An error that occurred while performing operations on objects.
+#[derive(Debug)]
+pub enum RadosError {
+    Objecter(ObjecterError),
+    /// No pool with the name exists.
+    PoolNotFound(String),
+    /// The OSD failed the operation with a (negative) errno.
+    Osd(i32),
+    /// The reply did not contain the output of an operation.
+    MissingOutput,
+    /// The output of an operation could not be decoded.
+    Decode(DecodeError),
+}
+
+impl From<ObjecterError> for RadosError {
+    fn from(value: ObjecterError) -> Self {
+        Self::Objecter(value)
+    }
+}
+
+impl From<DecodeError> for RadosError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+/// A handle to a cluster.
+#[derive(Debug)]
+pub struct Rados {
+    objecter: Objecter,
+}
+
+impl Rados {
+    /// Connect to the cluster that `monitors` belong to, using
+    /// `config` and `credentials`.
+    ///
+    /// A ticket for the OSDs is requested from the monitors, which
+    /// is used to authenticate to the OSDs.
+    pub fn connect(
+        monitors: impl IntoIterator<Item = MonInfo>,
+        config: Config,
+        credentials: Credentials,
+    ) -> Result<Self, RadosError> {
+        let mut mon_config = config.clone();
+        mon_config.request_ticket_for(EntityType::Osd);
+
+        let mon_client = MonClient::new(monitors, mon_config, credentials.clone());
+        Self::with_objecter(Objecter::new(mon_client, config, credentials))
+    }
+
+    /// Connect to the cluster using `objecter`.
+    ///
+    /// This establishes a session with a monitor and waits
+    /// for the first [`OsdMap`](ceph_messages::OsdMap).
+    pub fn with_objecter(mut objecter: Objecter) -> Result<Self, RadosError> {
+        objecter.mon_client().hunt().map_err(ObjecterError::from)?;
+        objecter.wait_for_map(Epoch(0))?;
+
+        Ok(Self { objecter })
+    }
+
+    /// The objecter that operations are sent with.
+    pub fn objecter(&mut self) -> &mut Objecter {
+        &mut self.objecter
+    }
+
+    /// The ID of the pool called `name`, if it exists.
+    pub fn pool_lookup(&self, name: &str) -> Option<i64> {
+        let map = self.objecter.osd_map()?;
+        map.lookup_pool(name).map(|id| id.0)
+    }
+
+    /// Create an I/O context for the pool called `pool_name`.
+    pub fn ioctx(&mut self, pool_name: &str) -> Result<IoCtx<'_>, RadosError> {
+        let pool = self
+            .pool_lookup(pool_name)
+            .ok_or_else(|| RadosError::PoolNotFound(pool_name.to_string()))?;
+
+        Ok(IoCtx {
+            objecter: &mut self.objecter,
+            locator: ObjectLocator::new(pool),
+        })
+    }
+}
+
+/// An I/O context, used to perform operations on the objects in a pool.
+///
+/// The namespace and locator key of the context apply to all
+/// objects that it operates on.
+#[derive(Debug)]
+pub struct IoCtx<'a> {
+    objecter: &'a mut Objecter,
+    locator: ObjectLocator,
+}
+
+impl IoCtx<'_> {
+    /// The ID of the pool of this context.
+    pub fn pool(&self) -> i64 {
+        self.locator.pool
+    }
+
+    /// The namespace of this context.
+    pub fn namespace(&self) -> &str {
+        &self.locator.namespace
+    }
+
+    /// Set the namespace of this context, where an empty
+    /// namespace is the default namespace.
+    pub fn set_namespace(&mut self, namespace: impl Into<String>) {
+        self.locator.namespace = namespace.into();
+    }
+
+    /// The locator key of this context.
+    pub fn locator_key(&self) -> &str {
+        &self.locator.key
+    }
+
+    /// Set the locator key of this context.
+    ///
+    /// If not empty, the locator key is hashed to place objects instead
+    /// of their name, so that objects with the same key are stored in
+    /// the same placement group.
+    pub fn set_locator_key(&mut self, key: impl Into<String>) {
+        self.locator.key = key.into();
+    }
+
+    /// Perform `ops` on the object called `oid`, returning the ops
+    /// containing their output.
+    fn operate(&mut self, oid: &str, ops: Vec<OsdOp>) -> Result<Vec<OsdOp>, RadosError> {
+        let reply = self.objecter.execute(self.locator.clone(), oid, ops)?;
+
+        if reply.result < 0 {
+            return Err(RadosError::Osd(reply.result));
+        }
+
+        Ok(reply.ops)
+    }
+
+    /// Perform a single `op` on the object called `oid`,
+    /// returning its output.
+    fn operate_one(&mut self, oid: &str, op: OsdOp) -> Result<OsdOp, RadosError> {
+        let mut ops = self.operate(oid, vec![op])?;
+        ops.pop().ok_or(RadosError::MissingOutput)
+    }
+
+    /// Read `len` bytes at `offset` from the object called `oid`,
+    /// where a `len` of zero reads the whole object.
+    pub fn read(&mut self, oid: &str, offset: u64, len: u64) -> Result<Vec<u8>, RadosError> {
+        Ok(self.operate_one(oid, OsdOp::read(offset, len))?.data)
+    }
+
+    /// Write `data` at `offset` to the object called `oid`,
+    /// creating it if it does not exist.
+    pub fn write(&mut self, oid: &str, offset: u64, data: &[u8]) -> Result<(), RadosError> {
+        self.operate_one(oid, OsdOp::write(offset, data.to_vec()))?;
+        Ok(())
+    }
+
+    /// Replace the contents of the object called `oid` with `data`.
+    pub fn write_full(&mut self, oid: &str, data: &[u8]) -> Result<(), RadosError> {
+        self.operate_one(oid, OsdOp::write_full(data.to_vec()))?;
+        Ok(())
+    }
+
+    /// Get the size and modification time of the object called `oid`.
+    pub fn stat(&mut self, oid: &str) -> Result<(u64, Timestamp), RadosError> {
+        Ok(self.operate_one(oid, OsdOp::stat())?.stat_output()?)
+    }
+
+    /// Delete the object called `oid`.
+    pub fn remove(&mut self, oid: &str) -> Result<(), RadosError> {
+        self.operate_one(oid, OsdOp::delete())?;
+        Ok(())
+    }
+
+    /// Get the value of the extended attribute `name` of
+    /// the object called `oid`.
+    pub fn getxattr(&mut self, oid: &str, name: &str) -> Result<Vec<u8>, RadosError> {
+        Ok(self.operate_one(oid, OsdOp::get_xattr(name))?.data)
+    }
+
+    /// Set the extended attribute `name` of the object
+    /// called `oid` to `value`.
+    pub fn setxattr(&mut self, oid: &str, name: &str, value: &[u8]) -> Result<(), RadosError> {
+        self.operate_one(oid, OsdOp::set_xattr(name, value))?;
+        Ok(())
+    }
+
+    /// Get at most `max` omap entries of the object called `oid`
+    /// after `start_after` whose keys start with `prefix`, and
+    /// whether there are more entries.
+    pub fn omap_get_vals(
+        &mut self,
+        oid: &str,
+        start_after: &str,
+        prefix: &str,
+        max: u64,
+    ) -> Result<(HashMap<String, Vec<u8>>, bool), RadosError> {
+        let op = OsdOp::omap_get_vals(start_after, prefix, max);
+        Ok(self.operate_one(oid, op)?.omap_vals_output()?)
+    }
+
+    /// Call `method` of object class `class` on the object called
+    /// `oid` with `input`, returning its output.
+    pub fn exec(
+        &mut self,
+        oid: &str,
+        class: &str,
+        method: &str,
+        input: &[u8],
+    ) -> Result<Vec<u8>, RadosError> {
+        Ok(self
+            .operate_one(oid, OsdOp::call(class, method, input))?
+            .data)
+    }
+}
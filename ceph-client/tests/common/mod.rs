@@ -0,0 +1,237 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Not every test uses every fixture.
+#![allow(dead_code)]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use ceph_client::{
+    connection::{
+        Credentials, Message,
+        server::{ServerConfig, ServerConnection},
+        state::{Active, Established},
+    },
+    header::CephMessageHeader2,
+};
+use ceph_foundation::{
+    CephFeatureSet, Decode, Timestamp,
+    crypto::Key,
+    entity::{EntityAddress, EntityAddressType, EntityName, EntityType},
+};
+use ceph_messages::{CephMessage, EncodeMessage, MessageOsdOpReply};
+use msgr2::{
+    Tag,
+    frames::{AuthMethodCephX, Banner, ClientIdent, ConMode, Hello},
+};
+
+/// The global ID that fake daemons assign to the client.
+pub const GLOBAL_ID: u64 = 4100;
+
+/// The secret of the key of [`name`], as known to the fake monitors.
+pub const SECRET: u8 = 3;
+
+/// The port in [`ident`].
+pub const PORT: u16 = 3300;
+
+pub fn name() -> EntityName {
+    EntityName {
+        ty: EntityType::Client,
+        name: "admin".to_string(),
+    }
+}
+
+pub fn key(secret: u8) -> Key {
+    Key::new(Timestamp::new(0, 0), [secret; 16])
+}
+
+/// CephX credentials for [`name`], using the key with [`SECRET`].
+pub fn credentials() -> Credentials {
+    let method = AuthMethodCephX {
+        name: name(),
+        global_id: 0,
+    };
+
+    Credentials::cephx(method, key(SECRET), vec![ConMode::Secure])
+}
+
+pub fn address(port: u16) -> EntityAddress {
+    EntityAddress {
+        ty: EntityAddressType::Msgr2,
+        nonce: 0,
+        address: Some(([127, 0, 0, 1], port).into()),
+    }
+}
+
+pub fn ident() -> ClientIdent {
+    ClientIdent {
+        addresses: vec![address(PORT)],
+        target: address(PORT),
+        gid: 0,
+        global_seq: 1,
+        supported_features: CephFeatureSet::ALL,
+        required_features: CephFeatureSet::EMPTY,
+        flags: 0,
+        cookie: 1337,
+    }
+}
+
+pub fn listen() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+pub fn recv<S: Established>(server: &mut ServerConnection<S>, stream: &mut TcpStream) -> Message {
+    let mut buffer = Vec::new();
+    let frame = server.start_rx(&mut buffer);
+    let frame = frame.read_preamble(&mut *stream).unwrap();
+    let frame = frame.read_rest(&mut *stream).unwrap();
+    server.finish_rx(frame).unwrap()
+}
+
+/// The configuration of a fake daemon, which knows the key of the client.
+pub fn server_config() -> ServerConfig {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(SECRET));
+    config.set_global_id(GLOBAL_ID);
+    config
+}
+
+/// Accept a single connection on `listener`, as a fake daemon of type `ty`.
+pub fn accept(listener: &TcpListener, ty: EntityType) -> (ServerConnection<Active>, TcpStream) {
+    accept_with(listener, ty, server_config())
+}
+
+/// Accept a single connection on `listener`, as a fake daemon of
+/// type `ty` using `config`.
+pub fn accept_with(
+    listener: &TcpListener,
+    ty: EntityType,
+    config: ServerConfig,
+) -> (ServerConnection<Active>, TcpStream) {
+    let (mut stream, _) = listener.accept().unwrap();
+
+    let server = ServerConnection::new(config);
+
+    stream.write_all(&server.banner().to_bytes()).unwrap();
+
+    let mut banner = [0u8; Banner::SERIALIZED_SIZE];
+    stream.read_exact(&mut banner).unwrap();
+    let mut server = server
+        .recv_banner(&Banner::parse(&banner).unwrap())
+        .unwrap();
+
+    let Message::Hello(hello) = recv(&mut server, &mut stream) else {
+        panic!("Expected Hello");
+    };
+
+    let server_hello = Hello {
+        entity_type: ty,
+        peer_address: address(0),
+    };
+    server.send_hello(&server_hello).write(&mut stream).unwrap();
+    let mut server = server.recv_hello(&hello);
+
+    while !server.is_authenticated() {
+        let frame = match recv(&mut server, &mut stream) {
+            Message::AuthRequest(request) => server.recv_auth_request(&request),
+            Message::AuthRequestMore(more) => server.recv_auth_request_more(&more),
+            m => panic!("Unexpected {m:?}"),
+        };
+
+        frame.unwrap().write(&mut stream).unwrap();
+    }
+
+    let mut server = server.finish_auth().unwrap();
+
+    let Message::AuthSignature(signature) = recv(&mut server, &mut stream) else {
+        panic!("Expected AuthSignature");
+    };
+    server.send_signature().write(&mut stream).unwrap();
+    let mut server = server.recv_signature(&signature).unwrap();
+
+    loop {
+        match recv(&mut server, &mut stream) {
+            Message::CompressionRequest(request) => {
+                let frame = server.recv_compression_request(&request);
+                frame.write(&mut stream).unwrap();
+            }
+            Message::ClientIdent(ident) => {
+                let frame = server.recv_client_ident(&ident).unwrap();
+                frame.write(&mut stream).unwrap();
+                break;
+            }
+            m => panic!("Unexpected {m:?}"),
+        }
+    }
+
+    (server.finish_ident().unwrap(), stream)
+}
+
+/// Receive a message from the client, skipping other frames.
+///
+/// Returns `None` once the client closes the connection.
+pub fn try_recv_message(
+    server: &mut ServerConnection<Active>,
+    stream: &mut TcpStream,
+) -> Option<(CephMessageHeader2, CephMessage)> {
+    let mut buffer = Vec::new();
+
+    let frame = loop {
+        let frame = server.start_rx(&mut buffer);
+        let frame = frame.read_preamble(&mut *stream).ok()?;
+        let frame = frame.read_rest(&mut *stream).ok()?;
+        let frame = server.finish_rx_raw(frame).unwrap().unwrap();
+
+        if frame.tag() == Tag::Message {
+            break frame;
+        }
+    };
+
+    let message = msgr2::frames::Message::from_frame(&frame).unwrap();
+    let header = CephMessageHeader2::decode(&mut message.header()).unwrap();
+    let decoded = CephMessage::decode_message(header.ty, message.data_segments()).unwrap();
+    Some((header, decoded))
+}
+
+/// Receive a message from the client, skipping other frames.
+pub fn recv_message(
+    server: &mut ServerConnection<Active>,
+    stream: &mut TcpStream,
+) -> (CephMessageHeader2, CephMessage) {
+    try_recv_message(server, stream).expect("The client closed the connection")
+}
+
+/// Send a message of type `ty` to the client.
+pub fn send_message(
+    server: &mut ServerConnection<Active>,
+    stream: &mut TcpStream,
+    ty: u16,
+    transaction_id: u64,
+    segments: &[&[u8]],
+) {
+    let mut header = CephMessageHeader2::new(ty, 1);
+    header.transaction_id = transaction_id;
+
+    server
+        .send_message(header, segments)
+        .write(&mut *stream)
+        .unwrap();
+}
+
+pub fn send_reply(
+    server: &mut ServerConnection<Active>,
+    stream: &mut TcpStream,
+    tid: u64,
+    reply: MessageOsdOpReply,
+) {
+    let mut segments = Vec::new();
+    reply.encode_message(&mut segments);
+    let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+    let ty = CephMessage::OsdOpReply(reply).identifier();
+    send_message(server, stream, ty, tid, &segments);
+}
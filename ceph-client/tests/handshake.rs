@@ -0,0 +1,610 @@
+mod common;
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ceph_client::{
+    connection::{
+        ClientConnection, Config, Credentials, Handshake, HandshakeError, Message, Rng, SendError,
+        server::{ServerConfig, ServerConnection, ServerError},
+        state::{Active, Established, Identifying, Inactive},
+    },
+    header::CephMessageHeader2,
+};
+use ceph_foundation::{
+    CephFeatureSet, Decode,
+    entity::{EntityAddress, EntityType},
+};
+use msgr2::{
+    Frame, FrameEncryption, FrameFormat, Tag,
+    compression::CompressionMethod,
+    frames::{
+        AuthMethod, AuthMethodCephX, AuthMethodNone, ClientIdent, ConMode, Hello, SessionReconnect,
+    },
+    wire::TxFrame,
+};
+
+use common::{PORT, address, key, name};
+
+/// Our identity, leaving it to the handshake to fill in our addresses.
+fn ident() -> ClientIdent {
+    ClientIdent {
+        addresses: Vec::new(),
+        ..common::ident()
+    }
+}
+
+fn cephx(secret: u8) -> Credentials {
+    let method = AuthMethodCephX {
+        name: name(),
+        global_id: 0,
+    };
+
+    Credentials::cephx(method, key(secret), vec![ConMode::Secure])
+}
+
+fn send(frame: TxFrame<'_>, pipe: &mut VecDeque<u8>) {
+    let mut data = Vec::new();
+    frame.write(&mut data).unwrap();
+    pipe.extend(data);
+}
+
+fn recv<S: Established>(server: &mut ServerConnection<S>, pipe: &mut VecDeque<u8>) -> Message {
+    let mut buffer = Vec::new();
+    let frame = server.start_rx(&mut buffer);
+    let frame = frame.read_preamble(&mut *pipe).unwrap();
+    let frame = frame.read_rest(&mut *pipe).unwrap();
+    server.finish_rx(frame).unwrap()
+}
+
+/// Pass all frames in `to_client` to `handshake`, and move
+/// everything that it wants to transmit to `to_server`.
+fn client_turn(
+    handshake: &mut Handshake,
+    to_server: &mut VecDeque<u8>,
+    to_client: &mut VecDeque<u8>,
+) -> Result<(), HandshakeError> {
+    while !to_client.is_empty() {
+        let mut buffer = Vec::new();
+        let frame = handshake.start_rx(&mut buffer).expect("Expected a frame");
+        let frame = frame.read_preamble(&mut *to_client).unwrap();
+        let frame = frame.read_rest(&mut *to_client).unwrap();
+        handshake.recv_frame(frame)?;
+    }
+
+    while let Some(data) = handshake.poll_transmit() {
+        to_server.extend(data);
+    }
+
+    Ok(())
+}
+
+/// Run `handshake` against a server using `config`.
+fn run(
+    handshake: &mut Handshake,
+    config: ServerConfig,
+) -> Result<ServerConnection<Active>, HandshakeError> {
+    let server = ServerConnection::new(config);
+    run_session(handshake, server, ServerConnection::recv_session_reconnect)
+        .map(|(server, _)| server)
+}
+
+/// Run `handshake` against `server`, which replies to a `SessionReconnect`
+/// using `on_reconnect`.
+///
+/// Also returns the data that the client sent after the handshake.
+fn run_session(
+    handshake: &mut Handshake,
+    server: ServerConnection<Inactive>,
+    on_reconnect: for<'a> fn(
+        &'a mut ServerConnection<Identifying>,
+        &SessionReconnect,
+    ) -> TxFrame<'a>,
+) -> Result<(ServerConnection<Active>, VecDeque<u8>), HandshakeError> {
+    let mut to_server = VecDeque::new();
+    let mut to_client = VecDeque::new();
+
+    handshake.recv_banner(&server.banner())?;
+    let mut server = server.recv_banner(&handshake.banner()).unwrap();
+
+    client_turn(handshake, &mut to_server, &mut to_client)?;
+
+    let Message::Hello(hello) = recv(&mut server, &mut to_server) else {
+        panic!("Expected Hello");
+    };
+
+    let server_hello = Hello {
+        entity_type: EntityType::Mon,
+        peer_address: address(PORT),
+    };
+    send(server.send_hello(&server_hello), &mut to_client);
+    let mut server = server.recv_hello(&hello);
+
+    client_turn(handshake, &mut to_server, &mut to_client)?;
+
+    while !server.is_authenticated() {
+        let result = match recv(&mut server, &mut to_server) {
+            Message::AuthRequest(request) => server.recv_auth_request(&request),
+            Message::AuthRequestMore(more) => server.recv_auth_request_more(&more),
+            m => panic!("Unexpected {m:?}"),
+        };
+
+        match result {
+            Ok(frame) => send(frame, &mut to_client),
+            Err(e) => send(server.send_auth_bad_method(&e), &mut to_client),
+        }
+
+        client_turn(handshake, &mut to_server, &mut to_client)?;
+    }
+
+    let mut server = server.finish_auth().unwrap();
+
+    let Message::AuthSignature(signature) = recv(&mut server, &mut to_server) else {
+        panic!("Expected AuthSignature");
+    };
+    send(server.send_signature(), &mut to_client);
+    let mut server = server.recv_signature(&signature).unwrap();
+
+    client_turn(handshake, &mut to_server, &mut to_client)?;
+
+    loop {
+        match recv(&mut server, &mut to_server) {
+            Message::CompressionRequest(request) => {
+                send(server.recv_compression_request(&request), &mut to_client);
+            }
+            Message::ClientIdent(ident) => {
+                match server.recv_client_ident(&ident) {
+                    Ok(frame) => send(frame, &mut to_client),
+                    Err(ServerError::MissingFeatures(missing)) => {
+                        send(server.send_ident_missing_features(missing), &mut to_client)
+                    }
+                    Err(e) => panic!("Unexpected {e:?}"),
+                }
+
+                client_turn(handshake, &mut to_server, &mut to_client)?;
+                break;
+            }
+            Message::SessionReconnect(reconnect) => {
+                send(on_reconnect(&mut server, &reconnect), &mut to_client);
+                client_turn(handshake, &mut to_server, &mut to_client)?;
+
+                // Otherwise, the client identifies again.
+                if handshake.is_done() {
+                    break;
+                }
+            }
+            m => panic!("Unexpected {m:?}"),
+        }
+
+        client_turn(handshake, &mut to_server, &mut to_client)?;
+    }
+
+    assert!(handshake.is_done());
+    Ok((server.finish_ident().unwrap(), to_server))
+}
+
+#[test]
+fn cephx_secure() {
+    for rev21 in [false, true] {
+        let mut config = ServerConfig::new(true);
+        config.add_key(name(), key(7));
+
+        let connection = ClientConnection::new(Config::new(rev21));
+        let mut handshake = Handshake::new(connection, cephx(7), ident());
+
+        let server = run(&mut handshake, config).unwrap();
+        let client = handshake.finish().unwrap();
+
+        let expected = if rev21 {
+            FrameFormat::Rev1Secure
+        } else {
+            FrameFormat::Rev0Secure
+        };
+        assert_eq!(client.state().format(), expected);
+        assert_eq!(server.state().format(), expected);
+    }
+}
+
+/// A deterministic [`Rng`], which counts up from its initial value.
+#[derive(Debug)]
+struct Counter(AtomicU64);
+
+impl Rng for Counter {
+    fn next_u64(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn deterministic_rng() {
+    let mut server_config = ServerConfig::new(true);
+    server_config.add_key(name(), key(7));
+
+    let mut config = Config::new(true);
+    config.set_rng(Counter(AtomicU64::new(42)));
+    assert_eq!(config.nonce(), 42);
+
+    let connection = ClientConnection::new(config.clone());
+    let mut handshake = Handshake::new(connection, cephx(7), ident());
+    let server = run(&mut handshake, server_config).unwrap();
+
+    // We identify using the address that the server reported, with our nonce.
+    let expected = EntityAddress {
+        nonce: 42,
+        ..address(PORT)
+    };
+    assert_eq!(server.session().addresses(), [expected]);
+
+    // The client challenge was drawn from the shared counter.
+    assert_eq!(config.rng().next_u64(), 44);
+}
+
+#[test]
+fn authorizer() {
+    let mut mon = ServerConfig::new(true);
+    mon.add_key(name(), key(7));
+    mon.set_service_key(key(9));
+    mon.set_global_id(4100);
+
+    let mut config = Config::new(true);
+    config.request_ticket_for(EntityType::Osd);
+
+    let connection = ClientConnection::new(config);
+    let mut handshake = Handshake::new(connection, cephx(7), ident());
+    run(&mut handshake, mon).unwrap();
+
+    let client = handshake.finish().unwrap();
+    let ticket = client
+        .tickets()
+        .iter()
+        .find(|t| t.ty == EntityType::Osd)
+        .unwrap()
+        .clone();
+
+    let credentials =
+        |ticket| Credentials::ticket(client.global_id(), ticket, vec![ConMode::Secure]);
+
+    // The OSD does not know our key, but can decrypt the ticket.
+    for challenge in [false, true] {
+        let mut osd = ServerConfig::new(true);
+        osd.set_service_key(key(9));
+        osd.set_authorizer_challenge(challenge);
+
+        let connection = ClientConnection::new(Config::new(true));
+        let mut handshake = Handshake::new(connection, credentials(ticket.clone()), ident());
+
+        let server = run(&mut handshake, osd).unwrap();
+        let client = handshake.finish().unwrap();
+        assert_eq!(client.global_id(), 4100);
+        assert_eq!(client.state().format(), FrameFormat::Rev1Secure);
+        assert_eq!(server.state().format(), FrameFormat::Rev1Secure);
+    }
+
+    // A service with another key can not.
+    let mut other = ServerConfig::new(true);
+    other.set_service_key(key(10));
+
+    let connection = ClientConnection::new(Config::new(true));
+    let mut handshake = Handshake::new(connection, credentials(ticket), ident());
+
+    let Err(HandshakeError::AuthBadMethod(bad_method)) = run(&mut handshake, other) else {
+        panic!("Expected AuthBadMethod");
+    };
+    assert_eq!(bad_method.result, -13);
+}
+
+#[test]
+fn none() {
+    let credentials = Credentials::none(AuthMethodNone {
+        name: name(),
+        global_id: 0,
+    });
+
+    let connection = ClientConnection::new(Config::new(true));
+    let mut handshake = Handshake::new(connection, credentials, ident());
+
+    run(&mut handshake, ServerConfig::new(true)).unwrap();
+
+    let client = handshake.finish().unwrap();
+    assert_eq!(client.state().format(), FrameFormat::Rev1Crc);
+}
+
+#[test]
+fn compression() {
+    const ALL: [CompressionMethod; 4] = [
+        CompressionMethod::Snappy,
+        CompressionMethod::Zlib,
+        CompressionMethod::Zstd,
+        CompressionMethod::Lz4,
+    ];
+
+    let mut server_config = ServerConfig::new(true);
+    server_config.add_key(name(), key(7));
+    server_config.accept_compression(&ALL, 64);
+
+    // Only methods that were compiled in are advertised.
+    let mut config = Config::new(true);
+    config.request_compression(&ALL, 64);
+    assert!(
+        config
+            .compression_methods()
+            .iter()
+            .all(|m| CompressionMethod::SUPPORTED.contains(m))
+    );
+    assert_eq!(
+        config.compression_methods().len(),
+        CompressionMethod::SUPPORTED.len()
+    );
+
+    let connection = ClientConnection::new(config);
+    let mut handshake = Handshake::new(connection, cephx(7), ident());
+    let server = run(&mut handshake, server_config).unwrap();
+    let client = handshake.finish().unwrap();
+
+    // The server picks the first method that the client prefers.
+    let expected = ALL.into_iter().find(CompressionMethod::is_supported);
+    assert_eq!(client.state().compression().method(), expected);
+    assert_eq!(server.state().compression().method(), expected);
+}
+
+#[test]
+fn bad_method() {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(7));
+
+    let connection = ClientConnection::new(Config::new(true));
+    let mut handshake = Handshake::new(connection, cephx(8), ident());
+
+    let Err(HandshakeError::AuthBadMethod(bad_method)) = run(&mut handshake, config) else {
+        panic!("Expected AuthBadMethod");
+    };
+    assert_eq!(bad_method.method, AuthMethod::CephX);
+
+    assert!(!handshake.is_done());
+    assert!(handshake.finish().is_none());
+}
+
+#[test]
+fn missing_features() {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(7));
+    config.set_features(CephFeatureSet::ALL, CephFeatureSet::ALL);
+
+    let mut ident = ident();
+    ident.supported_features = CephFeatureSet::EMPTY;
+
+    let connection = ClientConnection::new(Config::new(true));
+    let mut handshake = Handshake::new(connection, cephx(7), ident);
+
+    let Err(HandshakeError::IdentMissingFeatures(missing)) = run(&mut handshake, config) else {
+        panic!("Expected IdentMissingFeatures");
+    };
+    assert_eq!(missing.features, CephFeatureSet::ALL);
+}
+
+#[test]
+fn unexpected_tag() {
+    for tag in [Tag::AuthSignature, Tag::Message] {
+        let server = ServerConnection::new(ServerConfig::new(true));
+
+        let connection = ClientConnection::new(Config::new(true));
+        let mut handshake = Handshake::new(connection, cephx(7), ident());
+        handshake.recv_banner(&server.banner()).unwrap();
+
+        let payload = [0u8; 32];
+        let frame = Frame::new(tag, &[&payload]).unwrap();
+
+        let mut encryption = FrameEncryption::new();
+        let mut buffer = Vec::new();
+        let mut wire = VecDeque::new();
+        send(
+            frame.send(FrameFormat::Rev1Crc, &mut encryption, &mut buffer),
+            &mut wire,
+        );
+
+        let mut buffer = Vec::new();
+        let frame = handshake.start_rx(&mut buffer).unwrap();
+        let frame = frame.read_preamble(&mut wire).unwrap();
+        let frame = frame.read_rest(&mut wire).unwrap();
+
+        let error = handshake.recv_frame(frame).unwrap_err();
+        assert!(
+            matches!(error, HandshakeError::UnexpectedTag(t) if t == tag),
+            "{error:?}"
+        );
+
+        let mut buffer = Vec::new();
+        assert!(handshake.start_rx(&mut buffer).is_none());
+    }
+
+    assert!(Message::decode(Tag::Message, &[]).is_err());
+}
+
+/// Send a message containing `data` to the server, returning its
+/// sequence number.
+fn send_data(client: &mut ClientConnection<Active>, data: &[u8], pipe: &mut VecDeque<u8>) -> u64 {
+    send(
+        client
+            .send_message(CephMessageHeader2::new(1, 1), &[data])
+            .unwrap(),
+        pipe,
+    );
+    client.session().out_seq()
+}
+
+/// Receive a message from the client, returning its sequence
+/// number and data.
+fn recv_data(server: &mut ServerConnection<Active>, pipe: &mut VecDeque<u8>) -> (u64, Vec<u8>) {
+    let mut buffer = Vec::new();
+    let frame = server.start_rx(&mut buffer);
+    let frame = frame.read_preamble(&mut *pipe).unwrap();
+    let frame = frame.read_rest(&mut *pipe).unwrap();
+    let frame = server.finish_rx_raw(frame).unwrap().unwrap();
+
+    let message = msgr2::frames::Message::from_frame(&frame).unwrap();
+    let header = CephMessageHeader2::decode(&mut message.header()).unwrap();
+    (header.seq, message.data_segments()[0].to_vec())
+}
+
+fn wait<'a>(server: &'a mut ServerConnection<Identifying>, _: &SessionReconnect) -> TxFrame<'a> {
+    server.send_wait()
+}
+
+/// Establish a session, and send two messages in it of which
+/// only the first arrives.
+fn lose_message(config: &ServerConfig) -> (ClientConnection<Active>, ServerConnection<Active>) {
+    let connection = ClientConnection::new(Config::new(true));
+    let mut handshake = Handshake::new(connection, cephx(7), ident());
+    let mut server = run(&mut handshake, config.clone()).unwrap();
+    let mut client = handshake.finish().unwrap();
+
+    let mut to_server = VecDeque::new();
+    assert_eq!(send_data(&mut client, b"first", &mut to_server), 1);
+    assert_eq!(
+        recv_data(&mut server, &mut to_server),
+        (1, b"first".to_vec())
+    );
+
+    send_data(&mut client, b"second", &mut VecDeque::new());
+    assert_eq!(client.session().unacked(), 2);
+
+    (client, server)
+}
+
+#[test]
+fn reconnect() {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(7));
+
+    let (client, server) = lose_message(&config);
+
+    let resumed = ServerConnection::resume(config, server.session().clone());
+    let mut handshake = Handshake::new(client.reconnect(), cephx(7), ident());
+    let (mut server, mut to_server) = run_session(
+        &mut handshake,
+        resumed,
+        ServerConnection::recv_session_reconnect,
+    )
+    .unwrap();
+
+    // Only the message that was lost is sent again.
+    let mut client = handshake.finish().unwrap();
+    assert_eq!(client.session().unacked(), 1);
+    assert_eq!(
+        recv_data(&mut server, &mut to_server),
+        (2, b"second".to_vec())
+    );
+    assert!(to_server.is_empty());
+
+    // New messages continue the sequence.
+    assert_eq!(send_data(&mut client, b"third", &mut to_server), 3);
+    assert_eq!(
+        recv_data(&mut server, &mut to_server),
+        (3, b"third".to_vec())
+    );
+}
+
+#[test]
+fn reconnect_reset() {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(7));
+
+    let (client, _) = lose_message(&config);
+    let addresses = client.session().addresses().to_vec();
+
+    // A server that does not know the session resets it.
+    let mut handshake = Handshake::new(client.reconnect(), cephx(7), ident());
+    let (mut server, to_server) = run_session(
+        &mut handshake,
+        ServerConnection::new(config),
+        ServerConnection::recv_session_reconnect,
+    )
+    .unwrap();
+    assert!(to_server.is_empty());
+
+    let mut client = handshake.finish().unwrap();
+    assert!(client.session().is_established());
+    assert_eq!(client.session().addresses(), addresses);
+    assert_eq!(client.session().unacked(), 0);
+
+    let mut to_server = VecDeque::new();
+    assert_eq!(send_data(&mut client, b"new", &mut to_server), 1);
+    assert_eq!(recv_data(&mut server, &mut to_server), (1, b"new".to_vec()));
+}
+
+#[test]
+fn reconnect_wait() {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(7));
+
+    let (client, server) = lose_message(&config);
+    let session = server.session().clone();
+
+    let mut handshake = Handshake::new(client.reconnect(), cephx(7), ident());
+    let resumed = ServerConnection::resume(config.clone(), session.clone());
+    let Err(HandshakeError::Wait) = run_session(&mut handshake, resumed, wait) else {
+        panic!("Expected Wait");
+    };
+
+    let mut buffer = Vec::new();
+    assert!(handshake.start_rx(&mut buffer).is_none());
+
+    // Retrying on a new connection resumes the session.
+    let mut handshake = handshake.retry().unwrap();
+    let resumed = ServerConnection::resume(config, session);
+    let (mut server, mut to_server) = run_session(
+        &mut handshake,
+        resumed,
+        ServerConnection::recv_session_reconnect,
+    )
+    .unwrap();
+
+    assert_eq!(
+        recv_data(&mut server, &mut to_server),
+        (2, b"second".to_vec())
+    );
+}
+
+#[test]
+fn max_unacked() {
+    let mut server_config = ServerConfig::new(true);
+    server_config.add_key(name(), key(7));
+
+    let mut config = Config::new(true);
+    config.set_max_unacked(2);
+
+    let connection = ClientConnection::new(config);
+    let mut handshake = Handshake::new(connection, cephx(7), ident());
+    let mut server = run(&mut handshake, server_config).unwrap();
+    let mut client = handshake.finish().unwrap();
+
+    let mut to_server = VecDeque::new();
+    send_data(&mut client, b"first", &mut to_server);
+    send_data(&mut client, b"second", &mut to_server);
+
+    // The third message is refused, without using up a sequence number.
+    let result = client.send_message(CephMessageHeader2::new(1, 1), &[b"third"]);
+    assert_eq!(result.err(), Some(SendError::TooManyUnacked(2)));
+    assert_eq!(client.session().out_seq(), 2);
+    assert_eq!(client.session().unacked(), 2);
+
+    recv_data(&mut server, &mut to_server);
+    recv_data(&mut server, &mut to_server);
+
+    let mut to_client = VecDeque::new();
+    send(server.send_ack().unwrap(), &mut to_client);
+
+    let mut buffer = Vec::new();
+    let frame = client.start_rx(&mut buffer);
+    let frame = frame.read_preamble(&mut to_client).unwrap();
+    let frame = frame.read_rest(&mut to_client).unwrap();
+    let Message::Ack(ack) = client.finish_rx(frame).unwrap() else {
+        panic!("Expected Ack");
+    };
+    assert_eq!(ack.seq, 2);
+    assert_eq!(client.session().unacked(), 0);
+
+    assert_eq!(send_data(&mut client, b"third", &mut to_server), 3);
+}
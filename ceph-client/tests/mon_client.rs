@@ -0,0 +1,493 @@
+mod common;
+
+use std::{
+    io::Read,
+    net::{TcpListener, TcpStream},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use ceph_client::{
+    connection::{Config, Message, server::ServerConnection, state::Active},
+    mon_client::{MonClient, MonClientError, Topic},
+};
+use ceph_foundation::{Encode, MonInfo, Timestamp, Uuid, entity::EntityType};
+use ceph_messages::{
+    CephMessage, EncodeMessage, MonCommandAck, MonFeatures, MonMap, MonSubscribeAck,
+    MonSubscribeItem, PaxosServiceHeader,
+};
+use cephx::{CephXMessage, CephXMessageType};
+use msgr2::frames::KeepaliveAck;
+
+use common::{
+    accept, accept_with, address, credentials, recv, recv_message, send_message, server_config,
+};
+
+fn mon(name: &str, port: u16, priority: u16) -> MonInfo {
+    MonInfo {
+        name: name.to_string(),
+        public_addrs: vec![address(port)],
+        priority,
+        weight: 0,
+        crush_location: Default::default(),
+        time_added: None,
+    }
+}
+
+fn client(monitors: impl IntoIterator<Item = MonInfo>) -> MonClient {
+    let mut client = MonClient::new(monitors, Config::new(true), credentials());
+    client.set_timeout(Duration::from_millis(500));
+    client
+}
+
+/// A port that nothing listens on.
+fn closed_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Start a fake monitor that accepts a single connection, and
+/// passes it to `f`.
+fn monitor<F>(f: F) -> (u16, JoinHandle<()>)
+where
+    F: FnOnce(ServerConnection<Active>, TcpStream) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = std::thread::spawn(move || {
+        let (server, stream) = accept(&listener, EntityType::Mon);
+        drop(listener);
+        f(server, stream)
+    });
+
+    (port, handle)
+}
+
+fn send_subscribe_ack(server: &mut ServerConnection<Active>, stream: &mut TcpStream) {
+    let ack = MonSubscribeAck {
+        interval: 300,
+        fsid: Uuid([0; 16]),
+    };
+
+    let ty = CephMessage::MonSubscribeAck(ack.clone()).identifier();
+    send_message(server, stream, ty, 0, &[&ack.to_vec()]);
+}
+
+fn send_command_ack(
+    server: &mut ServerConnection<Active>,
+    stream: &mut TcpStream,
+    transaction_id: u64,
+    ack: MonCommandAck,
+) {
+    let mut segments = Vec::new();
+    ack.encode_message(&mut segments);
+    let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+    let ty = CephMessage::MonCommandAck(ack).identifier();
+    send_message(server, stream, ty, transaction_id, &segments);
+}
+
+#[test]
+fn no_monitors() {
+    let mut client = client([]);
+    assert!(matches!(client.hunt(), Err(MonClientError::NoMonitors)));
+}
+
+#[test]
+fn all_monitors_fail() {
+    let mut client = client([mon("a", closed_port(), 0), mon("b", closed_port(), 0)]);
+
+    let Err(MonClientError::Hunt(errors)) = client.hunt() else {
+        panic!("Expected hunting to fail");
+    };
+
+    assert_eq!(errors.len(), 2);
+    assert!(client.monitor().is_none());
+}
+
+#[test]
+fn priority() {
+    let (port, handle) = monitor(|_, _| {});
+    let (unused, _) = monitor(|_, _| {});
+
+    let mut client = client([mon("a", unused, 1), mon("b", port, 0)]);
+    client.hunt().unwrap();
+
+    assert_eq!(client.monitor(), Some("b"));
+    handle.join().unwrap();
+}
+
+#[test]
+fn failover_on_connect_failure() {
+    let (port, handle) = monitor(|_, _| {});
+
+    let mut client = client([mon("a", closed_port(), 0), mon("b", port, 1)]);
+    client.hunt().unwrap();
+
+    assert_eq!(client.monitor(), Some("b"));
+    handle.join().unwrap();
+}
+
+#[test]
+fn failover_on_timeout() {
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent_port = silent.local_addr().unwrap().port();
+
+    let (port, handle) = monitor(|_, _| {});
+
+    let mut client = client([mon("a", silent_port, 0), mon("b", port, 1)]);
+    client.set_timeout(Duration::from_millis(100));
+    client.hunt().unwrap();
+
+    assert_eq!(client.monitor(), Some("b"));
+    handle.join().unwrap();
+}
+
+#[test]
+fn rehunt_on_session_drop() {
+    let (dropped, dropped_handle) = monitor(|_, _| {});
+    let (port, handle) = monitor(|mut server, mut stream| {
+        send_subscribe_ack(&mut server, &mut stream);
+    });
+
+    let mut client = client([mon("a", dropped, 0), mon("b", port, 1)]);
+    client.hunt().unwrap();
+    assert_eq!(client.monitor(), Some("a"));
+
+    dropped_handle.join().unwrap();
+
+    let message = client.recv().unwrap();
+    assert!(matches!(message, CephMessage::MonSubscribeAck(_)));
+    assert_eq!(client.monitor(), Some("b"));
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn rehunt_on_keepalive_timeout() {
+    let (silent, silent_handle) = monitor(|mut server, mut stream| {
+        // Acknowledge the first keepalive, but not the second.
+        let Message::Keepalive(keepalive) = recv(&mut server, &mut stream) else {
+            panic!("Expected Keepalive");
+        };
+
+        let ack = KeepaliveAck {
+            timestamp: keepalive.timestamp,
+        };
+        server.send(ack).write(&mut stream).unwrap();
+
+        let message = recv(&mut server, &mut stream);
+        assert!(matches!(message, Message::Keepalive(_)));
+
+        // Keep the session open until the client gives up on it.
+        let _ = stream.read_to_end(&mut Vec::new());
+    });
+
+    let (port, handle) = monitor(|mut server, mut stream| {
+        send_subscribe_ack(&mut server, &mut stream);
+    });
+
+    let mut config = Config::new(true);
+    config.set_keepalive_interval(Duration::from_millis(200));
+
+    let monitors = [mon("a", silent, 0), mon("b", port, 1)];
+    let mut client = MonClient::new(monitors, config, credentials());
+    client.hunt().unwrap();
+    assert_eq!(client.monitor(), Some("a"));
+
+    let start = Instant::now();
+    let message = client.recv().unwrap();
+    assert!(matches!(message, CephMessage::MonSubscribeAck(_)));
+    assert_eq!(client.monitor(), Some("b"));
+    assert!(start.elapsed() >= Duration::from_millis(600));
+
+    silent_handle.join().unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn resubscribe_after_failover() {
+    let expected = MonSubscribeItem { start: 5, flags: 0 };
+
+    let (dropped, dropped_handle) = monitor(move |mut server, mut stream| {
+        let (_, CephMessage::MonSubscribe(subscribe)) = recv_message(&mut server, &mut stream)
+        else {
+            panic!("Expected MonSubscribe");
+        };
+
+        assert_eq!(subscribe.what.len(), 1);
+        assert_eq!(
+            subscribe.what["osdmap"],
+            MonSubscribeItem { start: 5, flags: 0 }
+        );
+    });
+
+    let (port, handle) = monitor(move |mut server, mut stream| {
+        let (_, CephMessage::MonSubscribe(subscribe)) = recv_message(&mut server, &mut stream)
+        else {
+            panic!("Expected MonSubscribe");
+        };
+
+        assert_eq!(subscribe.hostname, "client");
+        assert_eq!(subscribe.what.len(), 2);
+        assert_eq!(subscribe.what["osdmap"], expected);
+        assert_eq!(
+            subscribe.what["monmap"],
+            MonSubscribeItem {
+                start: 0,
+                flags: MonSubscribeItem::ONETIME
+            }
+        );
+
+        send_subscribe_ack(&mut server, &mut stream);
+    });
+
+    let mut client = client([mon("a", dropped, 0), mon("b", port, 1)]);
+    client.set_hostname("client");
+
+    client.subscribe(Topic::OsdMap, 5, false).unwrap();
+    client.hunt().unwrap();
+    assert_eq!(client.monitor(), Some("a"));
+
+    dropped_handle.join().unwrap();
+
+    // The client has not noticed yet that the session with `a` dropped.
+    client.subscribe(Topic::MonMap, 0, true).unwrap();
+
+    let message = client.recv().unwrap();
+    assert!(matches!(message, CephMessage::MonSubscribeAck(_)));
+    assert_eq!(client.monitor(), Some("b"));
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn command() {
+    let fsid = Uuid([9; 16]);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&listener, EntityType::Mon);
+
+        let (_, CephMessage::MonSubscribe(subscribe)) = recv_message(&mut server, &mut stream)
+        else {
+            panic!("Expected MonSubscribe");
+        };
+        assert!(subscribe.what.contains_key("monmap"));
+
+        let mon_map = MonMap {
+            epoch: 1,
+            fsid,
+            last_changed: Timestamp::new(0, 0),
+            created: Timestamp::new(0, 0),
+            mon_info: [("a".to_string(), mon("a", port, 0))].into(),
+            ranks: vec!["a".to_string()],
+            removed_ranks: Vec::new(),
+            persistent_features: MonFeatures::default(),
+            optional_features: MonFeatures::default(),
+            min_mon_release: [0],
+            strategy: [0],
+            disallowed_leaders: Default::default(),
+            stretch_mode_enabled: false,
+            tiebreaker_mon: String::new(),
+            stretch_marked_down_mons: Default::default(),
+        };
+
+        // The map is carried as a length-prefixed blob.
+        let front = Encode::to_vec(mon_map.to_vec().as_slice());
+        let ty = CephMessage::MonMap(mon_map).identifier();
+        send_message(&mut server, &mut stream, ty, 0, &[&front]);
+
+        let (_, CephMessage::MonCommand(command)) = recv_message(&mut server, &mut stream) else {
+            panic!("Expected MonCommand");
+        };
+        assert_eq!(command.fsid, fsid);
+        assert_eq!(command.cmd, [r#"{"prefix": "osd lspools"}"#]);
+
+        let ack = |result: i32, status: &str, output: &[u8]| MonCommandAck {
+            paxos: PaxosServiceHeader::default(),
+            result,
+            status: status.to_string(),
+            cmd: command.cmd.clone(),
+            output: output.to_vec(),
+        };
+
+        // A reply to another command is not a reply to this one.
+        send_command_ack(&mut server, &mut stream, 1234, ack(-2, "other", b""));
+        // The first command of a client has transaction ID 1.
+        send_command_ack(&mut server, &mut stream, 1, ack(0, "ok", b"[]"));
+    });
+
+    let mut client = client([mon("a", port, 0)]);
+
+    let (result, status, output) = client.command(r#"{"prefix": "osd lspools"}"#).unwrap();
+    assert_eq!(result, 0);
+    assert_eq!(status, "ok");
+    assert_eq!(output, b"[]");
+
+    // Messages received while waiting for the reply are kept.
+    assert!(matches!(client.recv().unwrap(), CephMessage::MonMap(_)));
+
+    let CephMessage::MonCommandAck(other) = client.recv().unwrap() else {
+        panic!("Expected MonCommandAck");
+    };
+    assert_eq!(other.status, "other");
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn renew_tickets() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = std::thread::spawn(move || {
+        let mut config = server_config();
+        config.set_ticket_validity(EntityType::Osd, 2);
+        let (mut server, mut stream) = accept_with(&listener, EntityType::Mon, config);
+
+        let (_, CephMessage::Auth(auth)) = recv_message(&mut server, &mut stream) else {
+            panic!("Expected Auth");
+        };
+
+        let reply = server.recv_auth(&auth).unwrap();
+
+        let mut segments = Vec::new();
+        reply.encode_message(&mut segments);
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+        let ty = CephMessage::AuthReply(reply).identifier();
+        send_message(&mut server, &mut stream, ty, 0, &segments);
+    });
+
+    let mut config = Config::new(true);
+    config.request_ticket_for(EntityType::Osd);
+
+    let mut client = MonClient::new([mon("a", port, 0)], config, credentials());
+    client.hunt().unwrap();
+
+    let tickets = client.tickets();
+    let auth_expires = tickets.expires(EntityType::Auth).unwrap();
+    let osd_expires = tickets.expires(EntityType::Osd).unwrap();
+    assert!(client.service_credentials(EntityType::Osd).is_some());
+
+    // Nothing is requested until three quarters of the validity have passed.
+    client.renew_tickets().unwrap();
+    std::thread::sleep(Duration::from_millis(1600));
+    client.renew_tickets().unwrap();
+
+    let message = client.recv().unwrap();
+    assert!(matches!(message, CephMessage::AuthReply(_)));
+
+    let tickets = client.tickets();
+    assert_eq!(tickets.expires(EntityType::Auth), Some(auth_expires));
+    assert!(tickets.expires(EntityType::Osd).unwrap() > osd_expires);
+    assert!(client.service_credentials(EntityType::Osd).is_some());
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn renew_auth_ticket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = std::thread::spawn(move || {
+        let mut config = server_config();
+        config.set_ticket_validity(EntityType::Auth, 2);
+        let (mut server, mut stream) = accept_with(&listener, EntityType::Mon, config);
+
+        // The auth ticket is renewed over the same session.
+        let (_, CephMessage::Auth(auth)) = recv_message(&mut server, &mut stream) else {
+            panic!("Expected Auth");
+        };
+
+        let request = CephXMessage::decode_request(&mut auth.auth_payload.as_slice()).unwrap();
+        assert_eq!(request.ty(), CephXMessageType::GetAuthSessionKey);
+
+        let reply = server.recv_auth(&auth).unwrap();
+
+        let mut segments = Vec::new();
+        reply.encode_message(&mut segments);
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+        let ty = CephMessage::AuthReply(reply).identifier();
+        send_message(&mut server, &mut stream, ty, 0, &segments);
+    });
+
+    let mut config = Config::new(true);
+    config.request_ticket_for(EntityType::Osd);
+
+    let mut client = MonClient::new([mon("a", port, 0)], config, credentials());
+    client.hunt().unwrap();
+
+    let tickets = client.tickets();
+    let auth_expires = tickets.expires(EntityType::Auth).unwrap();
+    let osd_expires = tickets.expires(EntityType::Osd).unwrap();
+
+    std::thread::sleep(Duration::from_millis(1600));
+    client.renew_tickets().unwrap();
+
+    let message = client.recv().unwrap();
+    assert!(matches!(message, CephMessage::AuthReply(_)));
+
+    // All tickets were handed out again.
+    let tickets = client.tickets();
+    assert!(tickets.expires(EntityType::Auth).unwrap() > auth_expires);
+    assert!(tickets.expires(EntityType::Osd).unwrap() > osd_expires);
+    assert!(client.service_credentials(EntityType::Osd).is_some());
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn renew_while_idle() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = std::thread::spawn(move || {
+        let mut config = server_config();
+        config.set_ticket_validity(EntityType::Osd, 2);
+        let (mut server, mut stream) = accept_with(&listener, EntityType::Mon, config);
+
+        // Nothing is sent to the client until it renews its tickets.
+        let auth = loop {
+            if let (_, CephMessage::Auth(auth)) = recv_message(&mut server, &mut stream) {
+                break auth;
+            }
+        };
+
+        let reply = server.recv_auth(&auth).unwrap();
+
+        let mut segments = Vec::new();
+        reply.encode_message(&mut segments);
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+
+        let ty = CephMessage::AuthReply(reply).identifier();
+        send_message(&mut server, &mut stream, ty, 0, &segments);
+    });
+
+    let mut config = Config::new(true);
+    config.request_ticket_for(EntityType::Osd);
+
+    let mut client = MonClient::new([mon("a", port, 0)], config, credentials());
+    client.hunt().unwrap();
+
+    let osd_expires = client.tickets().expires(EntityType::Osd).unwrap();
+    let deadline = client.next_deadline().unwrap();
+    assert!(deadline < osd_expires);
+
+    // Ticking before the deadline requests nothing.
+    client.tick().unwrap();
+    assert_eq!(client.next_deadline(), Some(deadline));
+
+    let message = client.recv().unwrap();
+    assert!(matches!(message, CephMessage::AuthReply(_)));
+    assert!(client.tickets().expires(EntityType::Osd).unwrap() > osd_expires);
+    assert!(client.next_deadline().unwrap() > deadline);
+
+    handle.join().unwrap();
+}
@@ -0,0 +1,505 @@
+mod common;
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::TcpStream,
+    time::Duration,
+};
+
+use ceph_client::{
+    connection::{Config, server::ServerConnection, state::Active},
+    mon_client::MonClient,
+    objecter::Objecter,
+};
+use ceph_foundation::{
+    Encode, Encoder, MonInfo, Timestamp, Uuid,
+    entity::{AddrVec, EntityAddress, EntityType},
+};
+use ceph_messages::{
+    CephMessage, EVersion, Epoch, MessageOsdOp, MessageOsdOpReply, ObjectLocator, Opaque, OsdMap,
+    OsdOp, OsdOpFlags, OsdState, PgId, PgMergeMeta, Pool, PoolFlags, PoolId, PoolType,
+    RequestRedirect,
+    crush::{Bucket, BucketKind, CrushMap, Rule, RuleStep, Tunables},
+};
+
+use common::{
+    GLOBAL_ID, accept, address, credentials, listen, recv_message, send_message, send_reply,
+};
+
+fn objecter(mon_port: u16) -> Objecter {
+    objecter_with(mon_port, Config::new(true))
+}
+
+/// Create an objecter that connects to OSDs using `config`.
+fn objecter_with(mon_port: u16, config: Config) -> Objecter {
+    let monitor = MonInfo {
+        name: "a".to_string(),
+        public_addrs: vec![address(mon_port)],
+        priority: 0,
+        weight: 0,
+        crush_location: Default::default(),
+        time_added: None,
+    };
+
+    let mut mon_client = MonClient::new([monitor], Config::new(true), credentials());
+    mon_client.set_timeout(Duration::from_millis(500));
+
+    let mut objecter = Objecter::new(mon_client, config, credentials());
+    objecter.set_timeout(Duration::from_millis(500));
+    objecter
+}
+
+fn recv_op(server: &mut ServerConnection<Active>, stream: &mut TcpStream) -> (u64, MessageOsdOp) {
+    match recv_message(server, stream) {
+        (header, CephMessage::OsdOp(op)) => (header.transaction_id, op),
+        (_, m) => panic!("Expected OsdOp, got {m:?}"),
+    }
+}
+
+fn recv_subscribe(server: &mut ServerConnection<Active>, stream: &mut TcpStream) -> u64 {
+    match recv_message(server, stream) {
+        (_, CephMessage::MonSubscribe(subscribe)) => subscribe.what["osdmap"].start,
+        (_, m) => panic!("Expected MonSubscribe, got {m:?}"),
+    }
+}
+
+/// Send a `MOSDMap` containing the full map `epoch`, in which
+/// `osd.0` and `osd.1` listen at `ports` and are up if `up`.
+fn send_osd_map(
+    server: &mut ServerConnection<Active>,
+    stream: &mut TcpStream,
+    epoch: u32,
+    ports: [u16; 2],
+    up: [bool; 2],
+) {
+    let mut front = Vec::new();
+    Uuid([1; 16]).encode(&mut front);
+    // Incremental maps
+    0u32.encode(&mut front);
+    // Full maps
+    1u32.encode(&mut front);
+    epoch.encode(&mut front);
+    osd_map(epoch, ports, up).as_slice().encode(&mut front);
+    1u32.encode(&mut front);
+    epoch.encode(&mut front);
+
+    send_message(server, stream, 41, 0, &[&front]);
+}
+
+fn reply(op: &MessageOsdOp, result: i32, epoch: u32) -> MessageOsdOpReply {
+    MessageOsdOpReply {
+        oid: op.oid.clone(),
+        pgid: op.pgid.pgid,
+        flags: u64::from(op.flags.0),
+        result,
+        bad_replay_version: EVersion::default(),
+        epoch: Epoch(epoch),
+        ops: op.ops.clone(),
+        retry_attempt: op.retry_attempt,
+        replay_version: EVersion::default(),
+        user_version: 0,
+        redirect: None,
+    }
+}
+
+fn pool() -> Pool {
+    Pool {
+        ty: PoolType::Replicated,
+        size: 2,
+        min_size: 1,
+        crush_rule: 0,
+        object_hash: Pool::HASH_RJENKINS,
+        pg_num: 8,
+        pgp_num: 8,
+        last_change: Epoch(1),
+        snap_seq: 0,
+        snap_epoch: Epoch(0),
+        snaps: HashMap::new(),
+        removed_snaps: HashMap::new(),
+        auid: 0,
+        flags: PoolFlags(PoolFlags::HASHPSPOOL),
+        quota_max_bytes: 0,
+        quota_max_objects: 0,
+        tiers: HashSet::new(),
+        tier_of: -1,
+        cache_mode: 0,
+        read_tier: -1,
+        write_tier: -1,
+        properties: HashMap::new(),
+        hit_set_params: Opaque {
+            version: 1,
+            compat: 1,
+            data: vec![0],
+        },
+        hit_set_period: 0,
+        hit_set_count: 0,
+        stripe_width: 0,
+        target_max_bytes: 0,
+        target_max_objects: 0,
+        cache_target_dirty_ratio_micro: 0,
+        cache_target_full_ratio_micro: 0,
+        cache_min_flush_age: 0,
+        cache_min_evict_age: 0,
+        erasure_code_profile: String::new(),
+        last_force_op_resend_preluminous: Epoch(0),
+        min_read_recency_for_promote: 0,
+        expected_num_objects: 0,
+        cache_target_dirty_high_ratio_micro: 0,
+        min_write_recency_for_promote: 0,
+        use_gmt_hitset: true,
+        fast_read: false,
+        hit_set_grade_decay_rate: 0,
+        hit_set_search_last_n: 0,
+        opts: Opaque {
+            version: 2,
+            compat: 1,
+            data: vec![0; 4],
+        },
+        last_force_op_resend_prenautilus: Epoch(0),
+        application_metadata: HashMap::new(),
+        create_time: Timestamp::new(0, 0),
+        pg_num_target: 8,
+        pgp_num_target: 8,
+        pg_num_pending: 8,
+        last_force_op_resend: Epoch(0),
+        pg_autoscale_mode: 0,
+        last_pg_merge_meta: PgMergeMeta::default(),
+        peering_crush_bucket_count: 0,
+        peering_crush_bucket_target: 0,
+        peering_crush_bucket_barrier: 0,
+        peering_crush_mandatory_member: 0x7fffffff,
+    }
+}
+
+/// A CRUSH map that places both replicas on `osd.0` and `osd.1`.
+fn crush_map() -> CrushMap {
+    let root = Bucket {
+        id: -1,
+        ty: 1,
+        hash: 0,
+        weight: 0x20000,
+        items: vec![0, 1],
+        kind: BucketKind::Straw2 {
+            item_weights: vec![0x10000; 2],
+        },
+    };
+
+    let step = |op, arg1, arg2| RuleStep { op, arg1, arg2 };
+
+    let rule = Rule {
+        ruleset: 0,
+        ty: 1,
+        min_size: 1,
+        max_size: 10,
+        steps: vec![
+            step(RuleStep::TAKE, -1, 0),
+            step(RuleStep::CHOOSE_FIRSTN, 0, 0),
+            step(RuleStep::EMIT, 0, 0),
+        ],
+    };
+
+    CrushMap {
+        max_devices: 2,
+        buckets: vec![Some(root)],
+        rules: vec![Some(rule)],
+        type_map: HashMap::new(),
+        name_map: HashMap::new(),
+        rule_name_map: HashMap::new(),
+        tunables: Tunables::default(),
+        class_map: HashMap::new(),
+        class_name: HashMap::new(),
+        class_bucket: HashMap::new(),
+        choose_args: HashMap::new(),
+    }
+}
+
+/// An encoded map with pool 1, in which `osd.0` and `osd.1`
+/// listen at `ports` and are up if `up`.
+fn osd_map(epoch: u32, ports: [u16; 2], up: [bool; 2]) -> Vec<u8> {
+    let addrs = ports.map(|port| AddrVec::from(&vec![address(port)]));
+    let state = up.map(|up| {
+        let up = if up { OsdState::UP } else { 0 };
+        OsdState(OsdState::EXISTS | up)
+    });
+
+    let mut encoded = Vec::new();
+
+    {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(&mut encoded, 8, 7);
+
+        {
+            let client = &mut ceph_foundation::write_versions_and_data!(buffer, 10, 1);
+            Uuid([1; 16]).encode(client);
+            epoch.encode(client);
+            Timestamp::new(0, 0).encode(client);
+            Timestamp::new(0, 0).encode(client);
+            HashMap::from([(PoolId(1), pool())]).encode(client);
+            HashMap::from([(PoolId(1), "data".to_string())]).encode(client);
+            1i32.encode(client); // Pool max
+            0u32.encode(client); // Flags
+            2i32.encode(client); // Max OSD
+            state.as_slice().encode(client);
+            [OsdMap::WEIGHT_IN; 2].as_slice().encode(client);
+            addrs.as_slice().encode(client);
+            0u32.encode(client); // `pg_temp`
+            HashMap::<PgId, i32>::new().encode(client);
+            0u32.encode(client); // Primary affinity
+            crush_map().to_vec().as_slice().encode(client);
+            HashMap::<String, HashMap<String, String>>::new().encode(client);
+            0u32.encode(client); // `pg_upmap`
+            0u32.encode(client); // `pg_upmap_items`
+            1i32.encode(client); // Crush version
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            Timestamp::new(0, 0).encode(client);
+            Timestamp::new(0, 0).encode(client);
+            HashMap::<PgId, i32>::new().encode(client);
+        }
+
+        {
+            let osd = &mut ceph_foundation::write_versions_and_data!(buffer, 9, 1);
+            // Only the data that clients need is included.
+            0u32.encode(osd); // Heartbeat back addresses
+            0u32.encode(osd); // OSD info
+            HashMap::<EntityAddress, Timestamp>::new().encode(osd);
+            0u32.encode(osd); // Cluster addresses
+            Epoch(0).encode(osd);
+            String::new().encode(osd);
+            0u32.encode(osd); // OSD UUIDs
+            0u32.encode(osd); // OSD extended info
+            0u32.encode(osd); // Heartbeat front addresses
+        }
+
+        // CRC
+        0u32.encode(buffer);
+    }
+
+    encoded
+}
+
+#[test]
+fn resend_on_eagain_and_redirect() {
+    let (mon, mon_port) = listen();
+    let (osd, osd_port) = listen();
+
+    let mon_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&mon, EntityType::Mon);
+        assert_eq!(recv_subscribe(&mut server, &mut stream), 0);
+        send_osd_map(&mut server, &mut stream, 1, [osd_port, 0], [true, false]);
+
+        // The OSD knows about a newer map.
+        assert_eq!(recv_subscribe(&mut server, &mut stream), 2);
+        send_osd_map(&mut server, &mut stream, 2, [osd_port, 0], [true, false]);
+    });
+
+    let osd_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&osd, EntityType::Osd);
+
+        let (tid, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(op.oid, "object");
+        assert_eq!(op.epoch, Epoch(1));
+        assert_eq!(op.retry_attempt, 0);
+        assert_eq!(op.reqid.entity_num, GLOBAL_ID as i64);
+        assert_eq!(op.reqid.tid, tid);
+        assert!(op.flags.contains(OsdOpFlags::READ));
+        assert!(!op.flags.contains(OsdOpFlags::RETRY));
+        send_reply(&mut server, &mut stream, tid, reply(&op, -11, 2));
+
+        let (resent, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(resent, tid);
+        assert_eq!(op.epoch, Epoch(2));
+        assert_eq!(op.retry_attempt, 1);
+        assert!(op.flags.contains(OsdOpFlags::RETRY));
+
+        let mut redirected = reply(&op, 0, 2);
+        redirected.redirect = Some(RequestRedirect {
+            locator: ObjectLocator::new(1),
+            object: "other".to_string(),
+        });
+        send_reply(&mut server, &mut stream, tid, redirected);
+
+        let (_, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(op.oid, "other");
+        assert_eq!(op.retry_attempt, 2);
+
+        let mut done = reply(&op, 0, 2);
+        done.ops[0].data = b"data".to_vec();
+        send_reply(&mut server, &mut stream, tid, done);
+    });
+
+    let mut objecter = objecter(mon_port);
+
+    let reply = objecter
+        .execute(ObjectLocator::new(1), "object", vec![OsdOp::read(0, 4)])
+        .unwrap();
+    assert_eq!(reply.result, 0);
+    assert_eq!(reply.oid, "other");
+    assert_eq!(reply.ops[0].data, b"data");
+    assert_eq!(objecter.in_flight(), 0);
+
+    osd_handle.join().unwrap();
+    mon_handle.join().unwrap();
+}
+
+#[test]
+fn resend_on_map_change() {
+    let (mon, mon_port) = listen();
+    let (first, first_port) = listen();
+    let (second, second_port) = listen();
+
+    let ports = [first_port, second_port];
+
+    let mon_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&mon, EntityType::Mon);
+        recv_subscribe(&mut server, &mut stream);
+        send_osd_map(&mut server, &mut stream, 1, ports, [true, false]);
+    });
+
+    let first_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&first, EntityType::Osd);
+        let (_, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(op.retry_attempt, 0);
+
+        // Instead of replying, share a map in which
+        // `osd.0` is down.
+        send_osd_map(&mut server, &mut stream, 2, ports, [false, true]);
+    });
+
+    let second_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&second, EntityType::Osd);
+        let (tid, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(op.epoch, Epoch(2));
+        assert_eq!(op.retry_attempt, 1);
+        assert!(op.flags.contains(OsdOpFlags::WRITE));
+        send_reply(&mut server, &mut stream, tid, reply(&op, 0, 2));
+    });
+
+    let mut objecter = objecter(mon_port);
+
+    let tid = objecter
+        .submit(
+            ObjectLocator::new(1),
+            "object",
+            vec![OsdOp::write_full(b"data".to_vec())],
+        )
+        .unwrap();
+    assert_eq!(objecter.in_flight(), 1);
+
+    let reply = objecter.wait(tid).unwrap();
+    assert_eq!(reply.result, 0);
+    assert_eq!(objecter.osd_map().unwrap().epoch, Epoch(2));
+
+    first_handle.join().unwrap();
+    second_handle.join().unwrap();
+    mon_handle.join().unwrap();
+}
+
+#[test]
+fn resend_after_session_drop() {
+    let (mon, mon_port) = listen();
+    let (first, first_port) = listen();
+    let (second, second_port) = listen();
+
+    let ports = [first_port, second_port];
+
+    let mon_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&mon, EntityType::Mon);
+        assert_eq!(recv_subscribe(&mut server, &mut stream), 0);
+        send_osd_map(&mut server, &mut stream, 1, ports, [true, false]);
+
+        // `osd.0` can not be reached anymore, so a new map is needed.
+        assert_eq!(recv_subscribe(&mut server, &mut stream), 2);
+        send_osd_map(&mut server, &mut stream, 2, ports, [false, true]);
+    });
+
+    let first_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&first, EntityType::Osd);
+        drop(first);
+
+        recv_op(&mut server, &mut stream);
+    });
+
+    let second_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&second, EntityType::Osd);
+        let (tid, op) = recv_op(&mut server, &mut stream);
+        send_reply(&mut server, &mut stream, tid, reply(&op, 0, 2));
+    });
+
+    let mut objecter = objecter(mon_port);
+
+    let reply = objecter
+        .execute(ObjectLocator::new(1), "object", vec![OsdOp::stat()])
+        .unwrap();
+    assert_eq!(reply.result, 0);
+
+    first_handle.join().unwrap();
+    second_handle.join().unwrap();
+    mon_handle.join().unwrap();
+}
+
+#[test]
+fn wait_for_acks() {
+    let (mon, mon_port) = listen();
+    let (osd, osd_port) = listen();
+
+    let mon_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&mon, EntityType::Mon);
+        recv_subscribe(&mut server, &mut stream);
+        send_osd_map(&mut server, &mut stream, 1, [osd_port, 0], [true, false]);
+    });
+
+    let osd_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&osd, EntityType::Osd);
+        let (first, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(op.oid, "first");
+
+        // The second op is only sent once the reply acknowledges the first.
+        send_reply(&mut server, &mut stream, first, reply(&op, 0, 1));
+
+        let (second, op) = recv_op(&mut server, &mut stream);
+        assert_eq!(op.oid, "second");
+        assert_eq!(op.retry_attempt, 0);
+        assert!(!op.flags.contains(OsdOpFlags::RETRY));
+        send_reply(&mut server, &mut stream, second, reply(&op, 0, 1));
+    });
+
+    let mut config = Config::new(true);
+    config.set_max_unacked(1);
+    let mut objecter = objecter_with(mon_port, config);
+
+    let first = objecter
+        .submit(ObjectLocator::new(1), "first", vec![OsdOp::stat()])
+        .unwrap();
+    let second = objecter
+        .submit(ObjectLocator::new(1), "second", vec![OsdOp::stat()])
+        .unwrap();
+    assert_eq!(objecter.in_flight(), 2);
+
+    assert_eq!(objecter.wait(second).unwrap().oid, "second");
+    assert_eq!(objecter.wait(first).unwrap().oid, "first");
+    assert_eq!(objecter.in_flight(), 0);
+
+    osd_handle.join().unwrap();
+    mon_handle.join().unwrap();
+}
+
+#[test]
+fn unknown_pool() {
+    let (mon, mon_port) = listen();
+
+    let mon_handle = std::thread::spawn(move || {
+        let (mut server, mut stream) = accept(&mon, EntityType::Mon);
+        recv_subscribe(&mut server, &mut stream);
+        send_osd_map(&mut server, &mut stream, 1, [0, 0], [false, false]);
+    });
+
+    let mut objecter = objecter(mon_port);
+
+    let result = objecter.execute(ObjectLocator::new(2), "object", vec![OsdOp::stat()]);
+    assert!(matches!(
+        result,
+        Err(ceph_client::objecter::ObjecterError::PoolDoesNotExist(2))
+    ));
+
+    mon_handle.join().unwrap();
+}
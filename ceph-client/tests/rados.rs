@@ -0,0 +1,530 @@
+mod common;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use ceph_client::{
+    connection::{
+        Config,
+        server::{ServerConfig, ServerConnection},
+        state::Active,
+    },
+    mon_client::MonClient,
+    objecter::Objecter,
+    rados::{Rados, RadosError},
+};
+use ceph_foundation::{
+    Decode, Encode, Encoder, MonInfo, Timestamp, Uuid,
+    entity::{AddrVec, EntityAddress, EntityType},
+};
+use ceph_messages::{
+    CephMessage, EVersion, Epoch, MessageOsdOpReply, Opaque, OsdMap, OsdOp, OsdOpArgs, OsdState,
+    PgId, PgMergeMeta, Pool, PoolFlags, PoolId, PoolType,
+    crush::{Bucket, BucketKind, CrushMap, Rule, RuleStep, Tunables},
+};
+
+use common::{
+    GLOBAL_ID, SECRET, accept_with, address, credentials, key, listen, name, send_message,
+    send_reply, try_recv_message,
+};
+
+const ENOENT: i32 = -2;
+const ENODATA: i32 = -61;
+
+fn rados(mon_port: u16) -> Result<Rados, RadosError> {
+    let monitor = MonInfo {
+        name: "a".to_string(),
+        public_addrs: vec![address(mon_port)],
+        priority: 0,
+        weight: 0,
+        crush_location: Default::default(),
+        time_added: None,
+    };
+
+    let mut config = Config::new(true);
+    config.request_ticket_for(EntityType::Osd);
+
+    let mut mon_client = MonClient::new([monitor], config, credentials());
+    mon_client.set_timeout(Duration::from_millis(500));
+
+    let mut objecter = Objecter::new(mon_client, Config::new(true), credentials());
+    objecter.set_timeout(Duration::from_millis(500));
+    Rados::with_objecter(objecter)
+}
+
+/// The configuration of a fake daemon of type `ty`.
+///
+/// Only monitors know the key of the client: other daemons only
+/// accept the tickets that the monitors hand out, and challenge
+/// the authorizers that contain them.
+fn server_config(ty: EntityType) -> ServerConfig {
+    let mut config = ServerConfig::new(true);
+    if ty == EntityType::Mon {
+        config.add_key(name(), key(SECRET));
+    } else {
+        config.set_authorizer_challenge(true);
+    }
+    config.set_service_key(key(9));
+    config.set_global_id(GLOBAL_ID);
+    config
+}
+
+/// Accept a single connection on `listener`, as a fake daemon of type `ty`.
+fn accept(listener: &TcpListener, ty: EntityType) -> (ServerConnection<Active>, TcpStream) {
+    accept_with(listener, ty, server_config(ty))
+}
+
+/// Serve the monitor at `listener`, which sends a map in
+/// which `osd.0` listens at `osd_port`.
+fn serve_mon(listener: TcpListener, osd_port: u16) {
+    let (mut server, mut stream) = accept(&listener, EntityType::Mon);
+
+    match try_recv_message(&mut server, &mut stream) {
+        Some((_, CephMessage::MonSubscribe(subscribe))) => {
+            assert_eq!(subscribe.what["osdmap"].start, 0);
+        }
+        m => panic!("Expected MonSubscribe, got {m:?}"),
+    }
+
+    let mut front = Vec::new();
+    Uuid([1; 16]).encode(&mut front);
+    // Incremental maps
+    0u32.encode(&mut front);
+    // Full maps
+    1u32.encode(&mut front);
+    1u32.encode(&mut front);
+    osd_map(osd_port).as_slice().encode(&mut front);
+    1u32.encode(&mut front);
+    1u32.encode(&mut front);
+
+    send_message(&mut server, &mut stream, 41, 0, &[&front]);
+}
+
+#[derive(Debug, Default)]
+struct Object {
+    data: Vec<u8>,
+    mtime: Option<Timestamp>,
+    xattrs: HashMap<String, Vec<u8>>,
+    omap: BTreeMap<String, Vec<u8>>,
+}
+
+/// The objects of a fake OSD, by namespace, locator key and name.
+type Store = HashMap<(String, String, String), Object>;
+
+/// Apply `op` to the object called `id` in `store`, returning
+/// the result of the op.
+fn apply(
+    store: &mut Store,
+    id: &(String, String, String),
+    mtime: &Timestamp,
+    op: &mut OsdOp,
+) -> i32 {
+    if op.is_write() && op.op != OsdOp::DELETE {
+        let object = store.entry(id.clone()).or_default();
+        object.mtime = Some(mtime.clone());
+    }
+
+    let Some(object) = store.get_mut(id) else {
+        return ENOENT;
+    };
+
+    match op.op {
+        OsdOp::WRITE => {
+            let OsdOpArgs::Extent { offset, .. } = op.args else {
+                panic!("Expected extent");
+            };
+
+            let end = offset as usize + op.data.len();
+            if object.data.len() < end {
+                object.data.resize(end, 0);
+            }
+            object.data[offset as usize..end].copy_from_slice(&op.data);
+        }
+        OsdOp::WRITE_FULL => object.data = std::mem::take(&mut op.data),
+        OsdOp::READ => {
+            let OsdOpArgs::Extent { offset, length, .. } = op.args else {
+                panic!("Expected extent");
+            };
+
+            let start = (offset as usize).min(object.data.len());
+            let end = match length {
+                0 => object.data.len(),
+                length => (start + length as usize).min(object.data.len()),
+            };
+            op.data = object.data[start..end].to_vec();
+        }
+        OsdOp::STAT => {
+            let mut output = Vec::new();
+            (object.data.len() as u64).encode(&mut output);
+            object.mtime.clone().unwrap().encode(&mut output);
+            op.data = output;
+        }
+        OsdOp::DELETE => {
+            store.remove(id);
+        }
+        OsdOp::SET_XATTR | OsdOp::GET_XATTR => {
+            let OsdOpArgs::Xattr { name_len, .. } = op.args else {
+                panic!("Expected xattr");
+            };
+
+            let (name, value) = op.data.split_at(name_len as usize);
+            let name = String::from_utf8(name.to_vec()).unwrap();
+
+            if op.op == OsdOp::SET_XATTR {
+                object.xattrs.insert(name, value.to_vec());
+            } else {
+                let Some(value) = object.xattrs.get(&name) else {
+                    return ENODATA;
+                };
+                op.data = value.clone();
+            }
+        }
+        OsdOp::OMAP_GET_VALS => {
+            let input = &mut op.data.as_slice();
+            let start_after = String::decode(input).unwrap();
+            let max = u64::decode(input).unwrap();
+            let prefix = String::decode(input).unwrap();
+
+            let entries: Vec<_> = object
+                .omap
+                .iter()
+                .filter(|(key, _)| **key > start_after && key.starts_with(&prefix))
+                .collect();
+            let returned = entries.len().min(max as usize);
+
+            let mut output = Vec::new();
+            (returned as u32).encode(&mut output);
+            for (key, value) in &entries[..returned] {
+                key.encode(&mut output);
+                value.as_slice().encode(&mut output);
+            }
+            (returned < entries.len()).encode(&mut output);
+            op.data = output;
+        }
+        OsdOp::CALL => {
+            let OsdOpArgs::Call {
+                class_len,
+                method_len,
+                ..
+            } = op.args
+            else {
+                panic!("Expected call");
+            };
+
+            let (class, rest) = op.data.split_at(class_len as usize);
+            let (method, input) = rest.split_at(method_len as usize);
+            assert_eq!((class, method), (&b"hello"[..], &b"say_hello"[..]));
+
+            let mut output = b"Hello, ".to_vec();
+            output.extend_from_slice(input);
+            op.data = output;
+        }
+        op => panic!("Unexpected op {op:#x}"),
+    }
+
+    0
+}
+
+/// Serve the OSD at `listener`, which stores objects in `store`,
+/// until the client disconnects.
+fn serve_osd(listener: TcpListener, mut store: Store) {
+    let (mut server, mut stream) = accept(&listener, EntityType::Osd);
+
+    while let Some((header, message)) = try_recv_message(&mut server, &mut stream) {
+        let CephMessage::OsdOp(mut op) = message else {
+            panic!("Expected OsdOp, got {message:?}");
+        };
+
+        let id = (
+            op.locator.namespace.clone(),
+            op.locator.key.clone(),
+            op.oid.clone(),
+        );
+
+        let mut result = 0;
+        for osd_op in &mut op.ops {
+            osd_op.rval = apply(&mut store, &id, &op.mtime, osd_op);
+            result = result.min(osd_op.rval);
+        }
+
+        let reply = MessageOsdOpReply {
+            oid: op.oid.clone(),
+            pgid: op.pgid.pgid,
+            flags: u64::from(op.flags.0),
+            result,
+            bad_replay_version: EVersion::default(),
+            epoch: op.epoch,
+            ops: op.ops,
+            retry_attempt: op.retry_attempt,
+            replay_version: EVersion::default(),
+            user_version: 0,
+            redirect: None,
+        };
+
+        send_reply(&mut server, &mut stream, header.transaction_id, reply);
+    }
+}
+
+fn pool() -> Pool {
+    Pool {
+        ty: PoolType::Replicated,
+        size: 2,
+        min_size: 1,
+        crush_rule: 0,
+        object_hash: Pool::HASH_RJENKINS,
+        pg_num: 8,
+        pgp_num: 8,
+        last_change: Epoch(1),
+        snap_seq: 0,
+        snap_epoch: Epoch(0),
+        snaps: HashMap::new(),
+        removed_snaps: HashMap::new(),
+        auid: 0,
+        flags: PoolFlags(PoolFlags::HASHPSPOOL),
+        quota_max_bytes: 0,
+        quota_max_objects: 0,
+        tiers: HashSet::new(),
+        tier_of: -1,
+        cache_mode: 0,
+        read_tier: -1,
+        write_tier: -1,
+        properties: HashMap::new(),
+        hit_set_params: Opaque {
+            version: 1,
+            compat: 1,
+            data: vec![0],
+        },
+        hit_set_period: 0,
+        hit_set_count: 0,
+        stripe_width: 0,
+        target_max_bytes: 0,
+        target_max_objects: 0,
+        cache_target_dirty_ratio_micro: 0,
+        cache_target_full_ratio_micro: 0,
+        cache_min_flush_age: 0,
+        cache_min_evict_age: 0,
+        erasure_code_profile: String::new(),
+        last_force_op_resend_preluminous: Epoch(0),
+        min_read_recency_for_promote: 0,
+        expected_num_objects: 0,
+        cache_target_dirty_high_ratio_micro: 0,
+        min_write_recency_for_promote: 0,
+        use_gmt_hitset: true,
+        fast_read: false,
+        hit_set_grade_decay_rate: 0,
+        hit_set_search_last_n: 0,
+        opts: Opaque {
+            version: 2,
+            compat: 1,
+            data: vec![0; 4],
+        },
+        last_force_op_resend_prenautilus: Epoch(0),
+        application_metadata: HashMap::new(),
+        create_time: Timestamp::new(0, 0),
+        pg_num_target: 8,
+        pgp_num_target: 8,
+        pg_num_pending: 8,
+        last_force_op_resend: Epoch(0),
+        pg_autoscale_mode: 0,
+        last_pg_merge_meta: PgMergeMeta::default(),
+        peering_crush_bucket_count: 0,
+        peering_crush_bucket_target: 0,
+        peering_crush_bucket_barrier: 0,
+        peering_crush_mandatory_member: 0x7fffffff,
+    }
+}
+
+/// A CRUSH map that places both replicas on `osd.0` and `osd.1`.
+fn crush_map() -> CrushMap {
+    let root = Bucket {
+        id: -1,
+        ty: 1,
+        hash: 0,
+        weight: 0x20000,
+        items: vec![0, 1],
+        kind: BucketKind::Straw2 {
+            item_weights: vec![0x10000; 2],
+        },
+    };
+
+    let step = |op, arg1, arg2| RuleStep { op, arg1, arg2 };
+
+    let rule = Rule {
+        ruleset: 0,
+        ty: 1,
+        min_size: 1,
+        max_size: 10,
+        steps: vec![
+            step(RuleStep::TAKE, -1, 0),
+            step(RuleStep::CHOOSE_FIRSTN, 0, 0),
+            step(RuleStep::EMIT, 0, 0),
+        ],
+    };
+
+    CrushMap {
+        max_devices: 2,
+        buckets: vec![Some(root)],
+        rules: vec![Some(rule)],
+        type_map: HashMap::new(),
+        name_map: HashMap::new(),
+        rule_name_map: HashMap::new(),
+        tunables: Tunables::default(),
+        class_map: HashMap::new(),
+        class_name: HashMap::new(),
+        class_bucket: HashMap::new(),
+        choose_args: HashMap::new(),
+    }
+}
+
+/// An encoded map with pool 1 called `data`, in which `osd.0`
+/// listens at `port`.
+fn osd_map(port: u16) -> Vec<u8> {
+    let addrs = [AddrVec::from(&vec![address(port)]), AddrVec::from(&vec![])];
+    let state = [OsdState(OsdState::EXISTS | OsdState::UP), OsdState(0)];
+
+    let mut encoded = Vec::new();
+
+    {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(&mut encoded, 8, 7);
+
+        {
+            let client = &mut ceph_foundation::write_versions_and_data!(buffer, 10, 1);
+            Uuid([1; 16]).encode(client);
+            1u32.encode(client);
+            Timestamp::new(0, 0).encode(client);
+            Timestamp::new(0, 0).encode(client);
+            HashMap::from([(PoolId(1), pool())]).encode(client);
+            HashMap::from([(PoolId(1), "data".to_string())]).encode(client);
+            1i32.encode(client); // Pool max
+            0u32.encode(client); // Flags
+            2i32.encode(client); // Max OSD
+            state.as_slice().encode(client);
+            [OsdMap::WEIGHT_IN; 2].as_slice().encode(client);
+            addrs.as_slice().encode(client);
+            0u32.encode(client); // `pg_temp`
+            HashMap::<PgId, i32>::new().encode(client);
+            0u32.encode(client); // Primary affinity
+            crush_map().to_vec().as_slice().encode(client);
+            HashMap::<String, HashMap<String, String>>::new().encode(client);
+            0u32.encode(client); // `pg_upmap`
+            0u32.encode(client); // `pg_upmap_items`
+            1i32.encode(client); // Crush version
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            Timestamp::new(0, 0).encode(client);
+            Timestamp::new(0, 0).encode(client);
+            HashMap::<PgId, i32>::new().encode(client);
+        }
+
+        {
+            let osd = &mut ceph_foundation::write_versions_and_data!(buffer, 9, 1);
+            // Only the data that clients need is included.
+            0u32.encode(osd); // Heartbeat back addresses
+            0u32.encode(osd); // OSD info
+            HashMap::<EntityAddress, Timestamp>::new().encode(osd);
+            0u32.encode(osd); // Cluster addresses
+            Epoch(0).encode(osd);
+            String::new().encode(osd);
+            0u32.encode(osd); // OSD UUIDs
+            0u32.encode(osd); // OSD extended info
+            0u32.encode(osd); // Heartbeat front addresses
+        }
+
+        // CRC
+        0u32.encode(buffer);
+    }
+
+    encoded
+}
+
+#[test]
+fn io_ctx() {
+    let (mon, mon_port) = listen();
+    let (osd, osd_port) = listen();
+
+    let omap = (String::new(), String::new(), "omap".to_string());
+    let mut store = Store::new();
+    store.entry(omap).or_default().omap = BTreeMap::from([
+        ("a".to_string(), b"1".to_vec()),
+        ("b.1".to_string(), b"2".to_vec()),
+        ("b.2".to_string(), b"3".to_vec()),
+        ("b.3".to_string(), b"4".to_vec()),
+    ]);
+
+    let mon_handle = std::thread::spawn(move || serve_mon(mon, osd_port));
+    let osd_handle = std::thread::spawn(move || serve_osd(osd, store));
+
+    let mut rados = rados(mon_port).unwrap();
+    assert_eq!(rados.pool_lookup("data"), Some(1));
+
+    let mut ioctx = rados.ioctx("data").unwrap();
+    assert_eq!(ioctx.pool(), 1);
+
+    ioctx.write_full("object", b"Hello, world!").unwrap();
+    ioctx.write("object", 7, b"Ceph!").unwrap();
+    assert_eq!(ioctx.read("object", 0, 0).unwrap(), b"Hello, Ceph!!");
+    assert_eq!(ioctx.read("object", 7, 4).unwrap(), b"Ceph");
+
+    let (size, _) = ioctx.stat("object").unwrap();
+    assert_eq!(size, 13);
+
+    ioctx.setxattr("object", "owner", b"admin").unwrap();
+    assert_eq!(ioctx.getxattr("object", "owner").unwrap(), b"admin");
+    assert!(matches!(
+        ioctx.getxattr("object", "other"),
+        Err(RadosError::Osd(ENODATA))
+    ));
+
+    let (values, more) = ioctx.omap_get_vals("omap", "b.1", "b.", 1).unwrap();
+    assert_eq!(values, HashMap::from([("b.2".to_string(), b"3".to_vec())]));
+    assert!(more);
+
+    let output = ioctx.exec("object", "hello", "say_hello", b"Ceph").unwrap();
+    assert_eq!(output, b"Hello, Ceph");
+
+    // Objects in other namespaces, or with other locator
+    // keys, are distinct objects.
+    ioctx.set_namespace("ns");
+    assert_eq!(ioctx.namespace(), "ns");
+    assert!(matches!(ioctx.stat("object"), Err(RadosError::Osd(ENOENT))));
+    ioctx.write_full("object", b"In a namespace").unwrap();
+
+    ioctx.set_locator_key("key");
+    assert_eq!(ioctx.locator_key(), "key");
+    ioctx.write_full("object", b"With a key").unwrap();
+    assert_eq!(ioctx.read("object", 0, 0).unwrap(), b"With a key");
+
+    ioctx.set_locator_key("");
+    assert_eq!(ioctx.read("object", 0, 0).unwrap(), b"In a namespace");
+    ioctx.remove("object").unwrap();
+    assert!(matches!(
+        ioctx.remove("object"),
+        Err(RadosError::Osd(ENOENT))
+    ));
+
+    ioctx.set_namespace("");
+    assert_eq!(ioctx.read("object", 0, 0).unwrap(), b"Hello, Ceph!!");
+
+    drop(rados);
+    osd_handle.join().unwrap();
+    mon_handle.join().unwrap();
+}
+
+#[test]
+fn pool_not_found() {
+    let (mon, mon_port) = listen();
+
+    let mon_handle = std::thread::spawn(move || serve_mon(mon, 0));
+
+    let mut rados = rados(mon_port).unwrap();
+    assert_eq!(rados.pool_lookup("other"), None);
+    assert!(matches!(
+        rados.ioctx("other"),
+        Err(RadosError::PoolNotFound(name)) if name == "other"
+    ));
+
+    mon_handle.join().unwrap();
+}
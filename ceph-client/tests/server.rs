@@ -0,0 +1,425 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use ceph_client::{
+    connection::{
+        ClientConnection, Config, Message,
+        server::{ServerConfig, ServerConnection, ServerError},
+        state::{Active, Authenticating, Established},
+    },
+    header::{CephMessageHeader2, CephMessageHeader2Flags},
+};
+use ceph_foundation::{CephFeatureSet, Timestamp, crypto::Key, entity::EntityType};
+use msgr2::{
+    FrameFormat,
+    frames::{
+        AuthMethod, AuthMethodCephX, AuthMethodNone, AuthRequest, ConMode, Hello, Keepalive,
+        KeepaliveAck,
+    },
+    wire::TxFrame,
+};
+
+use common::{PORT, address, ident, key, name};
+
+/// A single direction of an in-memory connection.
+#[derive(Default)]
+struct Pipe(VecDeque<u8>);
+
+impl Pipe {
+    fn send(&mut self, frame: TxFrame<'_>) {
+        let mut data = Vec::new();
+        frame.write(&mut data).unwrap();
+        self.0.extend(data);
+    }
+
+    fn recv_client<S: Established>(&mut self, connection: &mut ClientConnection<S>) -> Message {
+        let mut buffer = Vec::new();
+        let frame = connection.start_rx(&mut buffer);
+        let frame = frame.read_preamble(&mut self.0).unwrap();
+        let frame = frame.read_rest(&mut self.0).unwrap();
+        connection.finish_rx(frame).unwrap()
+    }
+
+    fn recv_server<S: Established>(&mut self, connection: &mut ServerConnection<S>) -> Message {
+        let mut buffer = Vec::new();
+        let frame = connection.start_rx(&mut buffer);
+        let frame = frame.read_preamble(&mut self.0).unwrap();
+        let frame = frame.read_rest(&mut self.0).unwrap();
+        connection.finish_rx(frame).unwrap()
+    }
+}
+
+struct Connected<C, S> {
+    client: ClientConnection<C>,
+    server: ServerConnection<S>,
+    to_server: Pipe,
+    to_client: Pipe,
+}
+
+/// Exchange banners and `Hello`s between a new client and server.
+fn hello(rev21: bool, server: ServerConfig) -> Connected<Authenticating, Authenticating> {
+    let mut to_server = Pipe::default();
+    let mut to_client = Pipe::default();
+
+    let client = ClientConnection::new(Config::new(rev21));
+    let server = ServerConnection::new(server);
+
+    let client_banner = client.banner();
+    let mut client = client.recv_banner(&server.banner()).unwrap();
+    let mut server = server.recv_banner(&client_banner).unwrap();
+
+    let hello = Hello {
+        entity_type: EntityType::Client,
+        peer_address: address(PORT),
+    };
+    to_server.send(client.send_hello(&hello));
+    let Message::Hello(hello) = to_server.recv_server(&mut server) else {
+        panic!("Expected Hello");
+    };
+
+    let server_hello = Hello {
+        entity_type: EntityType::Mon,
+        peer_address: address(PORT),
+    };
+    to_client.send(server.send_hello(&server_hello));
+    let server = server.recv_hello(&hello);
+
+    let Message::Hello(server_hello) = to_client.recv_client(&mut client) else {
+        panic!("Expected Hello");
+    };
+    let client = client.recv_hello(&server_hello);
+
+    Connected {
+        client,
+        server,
+        to_server,
+        to_client,
+    }
+}
+
+/// Connect a client and a server, authenticating with `request`.
+fn connect(
+    rev21: bool,
+    request: AuthRequest,
+    client_key: &Key,
+    server: ServerConfig,
+) -> Connected<Active, Active> {
+    let Connected {
+        mut client,
+        mut server,
+        mut to_server,
+        mut to_client,
+    } = hello(rev21, server);
+
+    to_server.send(client.send_req(&request));
+    let Message::AuthRequest(request) = to_server.recv_server(&mut server) else {
+        panic!("Expected AuthRequest");
+    };
+    to_client.send(server.recv_auth_request(&request).unwrap());
+
+    let mut client = match to_client.recv_client(&mut client) {
+        Message::AuthDone(done) => client.recv_none_done(&done).unwrap(),
+        Message::AuthReplyMore(more) => {
+            to_server.send(
+                client
+                    .recv_cephx_server_challenge(client_key, &more)
+                    .unwrap(),
+            );
+            let Message::AuthRequestMore(more) = to_server.recv_server(&mut server) else {
+                panic!("Expected AuthRequestMore");
+            };
+            to_client.send(server.recv_auth_request_more(&more).unwrap());
+
+            let Message::AuthDone(done) = to_client.recv_client(&mut client) else {
+                panic!("Expected AuthDone");
+            };
+            client.recv_cephx_done(client_key, &done).unwrap()
+        }
+        m => panic!("Unexpected {m:?}"),
+    };
+
+    assert!(server.is_authenticated());
+    let mut server = server.finish_auth().unwrap();
+
+    to_client.send(server.send_signature());
+    to_server.send(client.send_signature());
+
+    let Message::AuthSignature(signature) = to_client.recv_client(&mut client) else {
+        panic!("Expected AuthSignature");
+    };
+    let mut client = client.recv_signature(&signature).unwrap();
+
+    let Message::AuthSignature(signature) = to_server.recv_server(&mut server) else {
+        panic!("Expected AuthSignature");
+    };
+    let mut server = server.recv_signature(&signature).unwrap();
+
+    to_server.send(client.send_client_ident(&ident()));
+    let Message::ClientIdent(ident) = to_server.recv_server(&mut server) else {
+        panic!("Expected ClientIdent");
+    };
+    to_client.send(server.recv_client_ident(&ident).unwrap());
+    let server = server.finish_ident().unwrap();
+
+    let Message::ServerIdent(ident) = to_client.recv_client(&mut client) else {
+        panic!("Expected ServerIdent");
+    };
+    let client = client.recv_server_ident(&ident).unwrap();
+
+    Connected {
+        client,
+        server,
+        to_server,
+        to_client,
+    }
+}
+
+fn exchange_messages(mut connection: Connected<Active, Active>) {
+    let Connected {
+        client,
+        server,
+        to_server,
+        to_client,
+    } = &mut connection;
+
+    let keepalive = Keepalive {
+        timestamp: Timestamp::new(1, 2),
+    };
+    to_server.send(client.send(keepalive));
+    let Message::Keepalive(keepalive) = to_server.recv_server(server) else {
+        panic!("Expected Keepalive");
+    };
+
+    to_client.send(server.send(KeepaliveAck {
+        timestamp: keepalive.timestamp,
+    }));
+    let Message::KeepaliveAck(ack) = to_client.recv_client(client) else {
+        panic!("Expected KeepaliveAck");
+    };
+    assert_eq!(ack.timestamp, Timestamp::new(1, 2));
+
+    let header = CephMessageHeader2 {
+        seq: 0,
+        transaction_id: 0,
+        ty: 4,
+        priority: 0,
+        version: 0,
+        data_pre_padding_len: 0,
+        data_off: 0,
+        ack_seq: 0,
+        flags: CephMessageHeader2Flags(0),
+        compat_version: None,
+        reserved: 0,
+    };
+
+    to_server.send(client.send_message(header.clone(), &[b"front"]).unwrap());
+    let mut buffer = Vec::new();
+    let frame = server.start_rx(&mut buffer);
+    let frame = frame.read_preamble(&mut to_server.0).unwrap();
+    let frame = frame.read_rest(&mut to_server.0).unwrap();
+    let frame = server.finish_rx_raw(frame).unwrap().unwrap();
+    assert_eq!(frame.segments()[1], b"front");
+
+    assert_eq!(server.session().in_seq(), 1);
+    to_client.send(server.send_message(header, &[b"reply"]));
+
+    let mut buffer = Vec::new();
+    let frame = client.start_rx(&mut buffer);
+    let frame = frame.read_preamble(&mut to_client.0).unwrap();
+    let frame = frame.read_rest(&mut to_client.0).unwrap();
+    let frame = client.finish_rx_raw(frame).unwrap().unwrap();
+    assert_eq!(frame.segments()[1], b"reply");
+
+    // The reply acknowledged our message.
+    assert_eq!(client.session().unacked(), 0);
+}
+
+#[test]
+fn auth_none() {
+    let request = AuthRequest::new(
+        AuthMethodNone {
+            name: name(),
+            global_id: 0,
+        },
+        vec![ConMode::Secure, ConMode::Crc],
+    );
+
+    let connection = connect(true, request, &key(0), ServerConfig::new(true));
+    assert_eq!(connection.client.state().format(), FrameFormat::Rev1Crc);
+
+    exchange_messages(connection);
+}
+
+#[test]
+fn auth_cephx_secure() {
+    for rev21 in [false, true] {
+        let mut config = ServerConfig::new(true);
+        config.add_key(name(), key(7));
+
+        let request = AuthRequest::new(
+            AuthMethodCephX {
+                name: name(),
+                global_id: 0,
+            },
+            vec![ConMode::Secure],
+        );
+
+        let connection = connect(rev21, request, &key(7), config);
+
+        let expected = if rev21 {
+            FrameFormat::Rev1Secure
+        } else {
+            FrameFormat::Rev0Secure
+        };
+        assert_eq!(connection.client.state().format(), expected);
+        assert_eq!(connection.server.state().format(), expected);
+
+        exchange_messages(connection);
+    }
+}
+
+#[test]
+fn auth_cephx_crc() {
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(7));
+    config.set_con_modes(&[ConMode::Crc]);
+
+    let request = AuthRequest::new(
+        AuthMethodCephX {
+            name: name(),
+            global_id: 0,
+        },
+        vec![ConMode::Secure, ConMode::Crc],
+    );
+
+    let connection = connect(true, request, &key(7), config);
+    assert_eq!(connection.client.state().format(), FrameFormat::Rev1Crc);
+
+    exchange_messages(connection);
+}
+
+#[test]
+fn auth_cephx_wrong_key() {
+    let mut config = ServerConfig::new(false);
+    config.add_key(name(), key(7));
+
+    let Connected {
+        mut client,
+        mut server,
+        mut to_server,
+        mut to_client,
+    } = hello(false, config);
+
+    let request = AuthRequest::new(
+        AuthMethodCephX {
+            name: name(),
+            global_id: 0,
+        },
+        vec![ConMode::Secure],
+    );
+
+    to_server.send(client.send_req(&request));
+    let Message::AuthRequest(request) = to_server.recv_server(&mut server) else {
+        panic!("Expected AuthRequest");
+    };
+    to_client.send(server.recv_auth_request(&request).unwrap());
+
+    let Message::AuthReplyMore(more) = to_client.recv_client(&mut client) else {
+        panic!("Expected AuthReplyMore");
+    };
+    to_server.send(client.recv_cephx_server_challenge(&key(8), &more).unwrap());
+
+    let Message::AuthRequestMore(more) = to_server.recv_server(&mut server) else {
+        panic!("Expected AuthRequestMore");
+    };
+
+    let error = server.recv_auth_request_more(&more).unwrap_err();
+    assert!(matches!(error, ServerError::BadAuthenticateKey));
+    assert!(!server.is_authenticated());
+
+    to_client.send(server.send_auth_bad_method(&error));
+    let Message::AuthBadMethod(bad_method) = to_client.recv_client(&mut client) else {
+        panic!("Expected AuthBadMethod");
+    };
+    assert_eq!(bad_method.method, AuthMethod::CephX);
+}
+
+#[test]
+fn auth_cephx_truncated_challenge() {
+    let mut config = ServerConfig::new(false);
+    config.add_key(name(), key(7));
+
+    let Connected {
+        mut client,
+        mut server,
+        mut to_server,
+        mut to_client,
+    } = hello(false, config);
+
+    let request = AuthRequest::new(
+        AuthMethodCephX {
+            name: name(),
+            global_id: 0,
+        },
+        vec![ConMode::Secure],
+    );
+
+    to_server.send(client.send_req(&request));
+    let Message::AuthRequest(request) = to_server.recv_server(&mut server) else {
+        panic!("Expected AuthRequest");
+    };
+    to_client.send(server.recv_auth_request(&request).unwrap());
+
+    let Message::AuthReplyMore(mut more) = to_client.recv_client(&mut client) else {
+        panic!("Expected AuthReplyMore");
+    };
+
+    // A challenge cut short by the peer is an error, not a panic.
+    more.payload.truncate(more.payload.len() - 1);
+    assert!(client.recv_cephx_server_challenge(&key(7), &more).is_err());
+}
+
+#[test]
+fn missing_features() {
+    let mut config = ServerConfig::new(true);
+    config.set_features(CephFeatureSet::ALL, CephFeatureSet::ALL);
+
+    let request = AuthRequest::new(
+        AuthMethodNone {
+            name: name(),
+            global_id: 0,
+        },
+        vec![ConMode::Crc],
+    );
+
+    let Connected {
+        mut client,
+        mut server,
+        mut to_server,
+        ..
+    } = hello(true, config);
+
+    to_server.send(client.send_req(&request));
+    let Message::AuthRequest(request) = to_server.recv_server(&mut server) else {
+        panic!("Expected AuthRequest");
+    };
+    let _ = server.recv_auth_request(&request).unwrap();
+    let mut server = server.finish_auth().unwrap();
+    let _ = server.send_signature();
+
+    let signature = msgr2::frames::AuthSignature {
+        sha256_hmac: [0; 32],
+    };
+    let mut server = server.recv_signature(&signature).unwrap();
+
+    let mut ident = ident();
+    ident.supported_features = CephFeatureSet::EMPTY;
+
+    let error = server.recv_client_ident(&ident).unwrap_err();
+    assert!(matches!(error, ServerError::MissingFeatures(f) if f == CephFeatureSet::ALL));
+    assert!(matches!(
+        server.finish_ident(),
+        Err(ServerError::NotIdentified)
+    ));
+}
@@ -0,0 +1,191 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use ceph_client::connection::{
+    ClientConnection, Config, Credentials, HandshakeError, Message,
+    driver::{self, DriverError},
+    server::{ServerConfig, ServerConnection},
+    state::{Active, Established},
+};
+use ceph_foundation::{Timestamp, entity::EntityType};
+use msgr2::{
+    FrameFormat,
+    frames::{
+        AuthMethod, AuthMethodCephX, AuthMethodNone, Banner, ConMode, Hello, Keepalive,
+        KeepaliveAck,
+    },
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use common::{PORT, SECRET, address, ident, key, name};
+
+async fn recv<S: Established>(
+    connection: &mut ServerConnection<S>,
+    stream: &mut DuplexStream,
+) -> Message {
+    let mut buffer = Vec::new();
+    let frame = connection.start_rx(&mut buffer);
+    let frame = frame.read_preamble_async(&mut *stream).await.unwrap();
+    let frame = frame.read_rest_async(&mut *stream).await.unwrap();
+    connection.finish_rx(frame).unwrap()
+}
+
+/// Accept a single connection on `stream`, as a fake monitor.
+async fn serve(
+    mut stream: DuplexStream,
+    config: ServerConfig,
+) -> Option<(ServerConnection<Active>, DuplexStream)> {
+    let server = ServerConnection::new(config);
+
+    stream.write_all(&server.banner().to_bytes()).await.unwrap();
+
+    let mut banner = [0u8; Banner::SERIALIZED_SIZE];
+    stream.read_exact(&mut banner).await.unwrap();
+    let mut server = server
+        .recv_banner(&Banner::parse(&banner).unwrap())
+        .unwrap();
+
+    let Message::Hello(hello) = recv(&mut server, &mut stream).await else {
+        panic!("Expected Hello");
+    };
+
+    let server_hello = Hello {
+        entity_type: EntityType::Mon,
+        peer_address: address(PORT),
+    };
+    let frame = server.send_hello(&server_hello);
+    frame.write_async(&mut stream).await.unwrap();
+
+    let mut server = server.recv_hello(&hello);
+
+    while !server.is_authenticated() {
+        let result = match recv(&mut server, &mut stream).await {
+            Message::AuthRequest(request) => server.recv_auth_request(&request),
+            Message::AuthRequestMore(more) => server.recv_auth_request_more(&more),
+            m => panic!("Unexpected {m:?}"),
+        };
+
+        match result {
+            Ok(frame) => {
+                frame.write_async(&mut stream).await.unwrap();
+            }
+            Err(e) => {
+                let frame = server.send_auth_bad_method(&e);
+                frame.write_async(&mut stream).await.unwrap();
+                return None;
+            }
+        }
+    }
+
+    let mut server = server.finish_auth().unwrap();
+
+    let Message::AuthSignature(signature) = recv(&mut server, &mut stream).await else {
+        panic!("Expected AuthSignature");
+    };
+    let frame = server.send_signature();
+    frame.write_async(&mut stream).await.unwrap();
+
+    let mut server = server.recv_signature(&signature).unwrap();
+
+    loop {
+        match recv(&mut server, &mut stream).await {
+            Message::CompressionRequest(request) => {
+                let frame = server.recv_compression_request(&request);
+                frame.write_async(&mut stream).await.unwrap();
+            }
+            Message::ClientIdent(ident) => {
+                let frame = server.recv_client_ident(&ident).unwrap();
+                frame.write_async(&mut stream).await.unwrap();
+                break;
+            }
+            m => panic!("Unexpected {m:?}"),
+        }
+    }
+
+    Some((server.finish_ident().unwrap(), stream))
+}
+
+#[tokio::test]
+async fn handshake_cephx() {
+    let (mut client_stream, server_stream) = tokio::io::duplex(4096);
+
+    let mut config = ServerConfig::new(true);
+    config.add_key(name(), key(SECRET));
+
+    let credentials = Credentials::cephx(
+        AuthMethodCephX {
+            name: name(),
+            global_id: 0,
+        },
+        key(SECRET),
+        vec![ConMode::Secure],
+    );
+
+    let connection = ClientConnection::new(Config::new(true));
+    let ident = ident();
+
+    let (client, server) = tokio::join!(
+        driver::handshake(&mut client_stream, connection, &credentials, &ident),
+        serve(server_stream, config),
+    );
+
+    let mut client = client.unwrap();
+    let (mut server, mut server_stream) = server.unwrap();
+    assert_eq!(client.state().format(), FrameFormat::Rev1Secure);
+
+    let keepalive = Keepalive {
+        timestamp: Timestamp::new(5, 6),
+    };
+    driver::send(client.send(keepalive), &mut client_stream)
+        .await
+        .unwrap();
+
+    let Message::Keepalive(keepalive) = recv(&mut server, &mut server_stream).await else {
+        panic!("Expected Keepalive");
+    };
+
+    let ack = KeepaliveAck {
+        timestamp: keepalive.timestamp,
+    };
+    server
+        .send(ack)
+        .write_async(&mut server_stream)
+        .await
+        .unwrap();
+
+    let Message::KeepaliveAck(ack) = driver::recv(&mut client, &mut client_stream).await.unwrap()
+    else {
+        panic!("Expected KeepaliveAck");
+    };
+    assert_eq!(ack.timestamp, Timestamp::new(5, 6));
+}
+
+#[tokio::test]
+async fn handshake_bad_method() {
+    let (mut client_stream, server_stream) = tokio::io::duplex(4096);
+
+    let mut config = ServerConfig::new(false);
+    config.set_auth_methods(&[AuthMethod::CephX]);
+
+    let credentials = Credentials::none(AuthMethodNone {
+        name: name(),
+        global_id: 0,
+    });
+
+    let connection = ClientConnection::new(Config::new(false));
+    let ident = ident();
+
+    let (client, server) = tokio::join!(
+        driver::handshake(&mut client_stream, connection, &credentials, &ident),
+        serve(server_stream, config),
+    );
+
+    assert!(server.is_none());
+
+    let Err(DriverError::Handshake(HandshakeError::AuthBadMethod(bad_method))) = client else {
+        panic!("Expected AuthBadMethod");
+    };
+    assert_eq!(bad_method.method, AuthMethod::None);
+    assert_eq!(bad_method.allowed_methods, vec![AuthMethod::CephX]);
+}
@@ -0,0 +1,705 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    MonInfo,
+    entity::{EntityAddress, EntityAddressType, EntityName, EntityType},
+    ini::{Line, normalize_key, parse_line},
+};
+
+/// The name of the cluster if none is configured.
+pub const DEFAULT_CLUSTER: &str = "ceph";
+
+/// The default port of `msgr2` monitor addresses.
+const MON_PORT: u16 = 3300;
+
+/// The default port of legacy (`msgr1`) monitor addresses.
+const MON_PORT_LEGACY: u16 = 6789;
+
+/// The keyrings that are searched if no keyring is configured.
+const DEFAULT_KEYRING: &str = "/etc/ceph/$cluster.$name.keyring,/etc/ceph/$cluster.keyring,/etc/ceph/keyring,/etc/ceph/keyring.bin";
+
+/// An error that occurred while loading a [`CephConf`] or [`CephArgs`].
+///
+/// Line numbers start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CephConfError {
+    /// The line is not a section header, `key = value` pair or comment.
+    Syntax(usize),
+    /// The configuration file could not be read.
+    Io(PathBuf, std::io::ErrorKind),
+    /// The address in `mon_host` could not be parsed.
+    BadMonHost(String),
+    /// An argument is not an option.
+    BadArgument(String),
+    /// No value was given for an option.
+    MissingValue(String),
+    /// The value of `--name` is not a valid entity name.
+    BadEntityName(String),
+}
+
+/// Command-line options, as found in `CEPH_ARGS`.
+///
+/// Options are of the form `--mon-host <value>` or `--mon_host=<value>`,
+/// where dashes, underscores and spaces are equivalent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CephArgs {
+    /// The entity name given by `--name`/`-n`, or `--id`/`-i`.
+    pub name: Option<EntityName>,
+    /// The cluster name given by `--cluster`.
+    pub cluster: Option<String>,
+    /// The configuration file given by `--conf`/`-c`.
+    pub conf: Option<PathBuf>,
+    /// All other options, with normalized keys, such as
+    /// `mon host` or `keyring` (`-k`).
+    pub options: Vec<(String, String)>,
+}
+
+impl CephArgs {
+    /// Parse `args`.
+    pub fn parse<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Self, CephConfError> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (option, value) = if let Some(option) = arg.strip_prefix("--") {
+                match option.split_once('=') {
+                    Some((option, value)) => (option.to_string(), Some(value)),
+                    None => (option.to_string(), None),
+                }
+            } else {
+                let option = match arg {
+                    "-c" => "conf",
+                    "-n" => "name",
+                    "-i" => "id",
+                    "-k" => "keyring",
+                    _ => return Err(CephConfError::BadArgument(arg.to_string())),
+                };
+
+                (option.to_string(), None)
+            };
+
+            let option = normalize_key(&option.replace('-', " "));
+
+            let value = value
+                .or_else(|| args.next())
+                .ok_or_else(|| CephConfError::MissingValue(option.clone()))?;
+
+            match option.as_str() {
+                "conf" => parsed.conf = Some(PathBuf::from(value)),
+                "cluster" => parsed.cluster = Some(value.to_string()),
+                "name" => {
+                    let name = value
+                        .parse()
+                        .map_err(|_| CephConfError::BadEntityName(value.to_string()))?;
+                    parsed.name = Some(name);
+                }
+                "id" | "user" => {
+                    parsed.name = Some(EntityName {
+                        ty: EntityType::Client,
+                        name: value.to_string(),
+                    });
+                }
+                _ => parsed.options.push((option, value.to_string())),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Parse the whitespace-separated arguments in the
+    /// `CEPH_ARGS` environment variable, if it is set.
+    pub fn from_env() -> Result<Self, CephConfError> {
+        let args = std::env::var("CEPH_ARGS").unwrap_or_default();
+        Self::parse(args.split_whitespace())
+    }
+}
+
+/// A configuration in the INI dialect of `ceph.conf`.
+///
+/// Options are looked up in the section of an entity (e.g. `[client.admin]`),
+/// then in the section of its type (`[client]`) and finally in `[global]`.
+/// Option names are normalized, so that `mon_host`, `mon host` and
+/// `mon  host` are equivalent.
+///
+/// The metavariables `$cluster`, `$type`, `$id` and `$name` in values
+/// are expanded for the entity that an option is looked up for.
+#[derive(Debug, Clone)]
+pub struct CephConf {
+    cluster: String,
+    sections: HashMap<String, HashMap<String, String>>,
+    /// Options that take precedence over all sections,
+    /// such as those from [`CephArgs`].
+    overrides: HashMap<String, String>,
+}
+
+impl Default for CephConf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CephConf {
+    /// Create an empty configuration for the default cluster.
+    pub fn new() -> Self {
+        Self {
+            cluster: DEFAULT_CLUSTER.to_string(),
+            sections: HashMap::new(),
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Parse the configuration in `text`.
+    ///
+    /// Lines ending in a backslash are continued on the next line, and
+    /// options that appear before the first section belong to `[global]`.
+    pub fn parse(text: &str) -> Result<Self, CephConfError> {
+        let mut conf = Self::new();
+        let mut section = "global".to_string();
+
+        let mut lines = text.lines().enumerate();
+
+        while let Some((number, line)) = lines.next() {
+            let number = number + 1;
+            let mut line = line.to_string();
+
+            while let Some(continued) = line.strip_suffix('\\') {
+                line = continued.to_string();
+                match lines.next() {
+                    Some((_, next)) => line.push_str(next),
+                    None => break,
+                }
+            }
+
+            match parse_line(&line).map_err(|_| CephConfError::Syntax(number))? {
+                None => {}
+                Some(Line::Section(name)) => section = name.to_string(),
+                Some(Line::Entry { key, value }) => {
+                    conf.sections
+                        .entry(section.clone())
+                        .or_default()
+                        .insert(normalize_key(key), value.to_string());
+                }
+            }
+        }
+
+        Ok(conf)
+    }
+
+    /// Read and parse the configuration file at `path`.
+    pub fn read(path: &Path) -> Result<Self, CephConfError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CephConfError::Io(path.to_path_buf(), e.kind()))?;
+
+        Self::parse(&text)
+    }
+
+    /// The files that are searched for the configuration of `cluster`
+    /// if no configuration file is given.
+    pub fn search_path(cluster: &str) -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from(format!("/etc/ceph/{cluster}.conf"))];
+
+        if let Some(home) = std::env::var_os("HOME") {
+            paths.push(Path::new(&home).join(format!(".ceph/{cluster}.conf")));
+        }
+
+        paths.push(PathBuf::from(format!("{cluster}.conf")));
+        paths
+    }
+
+    /// Load the configuration the way Ceph tools do.
+    ///
+    /// The configuration file given by `args` is read, or else the one in
+    /// the `CEPH_CONF` environment variable, or else the first file in the
+    /// [search path](CephConf::search_path) that exists. If none exists, the
+    /// configuration is empty. The options in `args` override the options
+    /// in the file.
+    pub fn load(args: &CephArgs) -> Result<Self, CephConfError> {
+        let cluster = args.cluster.as_deref().unwrap_or(DEFAULT_CLUSTER);
+
+        let path = args
+            .conf
+            .clone()
+            .or_else(|| std::env::var_os("CEPH_CONF").map(PathBuf::from))
+            .or_else(|| {
+                Self::search_path(cluster)
+                    .into_iter()
+                    .find(|path| path.exists())
+            });
+
+        let mut conf = match path {
+            Some(path) => Self::read(&path)?,
+            None => Self::new(),
+        };
+
+        conf.set_cluster(cluster);
+
+        for (key, value) in &args.options {
+            conf.set(key, value);
+        }
+
+        Ok(conf)
+    }
+
+    /// The name of the cluster, which `$cluster` expands to.
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    pub fn set_cluster(&mut self, cluster: impl Into<String>) {
+        self.cluster = cluster.into();
+    }
+
+    /// Set `key` to `value` for all entities, overriding
+    /// the value in any section.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.overrides.insert(normalize_key(key), value.into());
+    }
+
+    /// The value of `key` for `name`, with metavariables expanded.
+    pub fn get(&self, name: &EntityName, key: &str) -> Option<String> {
+        let key = normalize_key(key);
+        let sections = [name.to_string(), name.ty.name().to_string()];
+
+        let value = self.overrides.get(&key).or_else(|| {
+            sections
+                .iter()
+                .map(String::as_str)
+                .chain(["global"])
+                .find_map(|section| self.sections.get(section)?.get(&key))
+        })?;
+
+        Some(self.expand(value, name))
+    }
+
+    /// The list of values of `key` for `name`, separated by commas,
+    /// semicolons or whitespace.
+    pub fn get_list(&self, name: &EntityName, key: &str) -> Option<Vec<String>> {
+        let value = self.get(name, key)?;
+        Some(split_list(&value).into_iter().map(str::to_string).collect())
+    }
+
+    /// Expand the metavariables in `value` for `name`.
+    fn expand(&self, value: &str, name: &EntityName) -> String {
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+
+        while let Some(start) = rest.find('$') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+
+            let (variable, after) = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+                Some(braced) => braced,
+                None => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+
+            match variable {
+                "cluster" => expanded.push_str(&self.cluster),
+                "type" => expanded.push_str(name.ty.name()),
+                "id" => expanded.push_str(&name.name),
+                "name" => expanded.push_str(&name.to_string()),
+                _ => {
+                    expanded.push('$');
+                    continue;
+                }
+            }
+
+            rest = after;
+        }
+
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// The monitors in `mon_host` for `name`, named `noname-a`,
+    /// `noname-b`, and so on.
+    ///
+    /// See [`parse_mon_host`] for the supported syntax.
+    pub fn mon_host(&self, name: &EntityName) -> Result<Vec<MonInfo>, CephConfError> {
+        let Some(mon_host) = self.get(name, "mon host") else {
+            return Ok(Vec::new());
+        };
+
+        let monitors = parse_mon_host(&mon_host)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, public_addrs)| MonInfo {
+                name: mon_name(index),
+                public_addrs,
+                priority: 0,
+                weight: 0,
+                crush_location: Default::default(),
+                time_added: None,
+            })
+            .collect();
+
+        Ok(monitors)
+    }
+
+    /// The keyrings that are searched for the key of `name`,
+    /// in order of preference.
+    pub fn keyring_paths(&self, name: &EntityName) -> Vec<PathBuf> {
+        let keyring = self
+            .get(name, "keyring")
+            .unwrap_or_else(|| self.expand(DEFAULT_KEYRING, name));
+
+        split_list(&keyring)
+            .into_iter()
+            .map(PathBuf::from)
+            .collect()
+    }
+}
+
+/// The name of the monitor at `index` in `mon_host`.
+fn mon_name(index: usize) -> String {
+    match u8::try_from(index) {
+        Ok(index) if index < 26 => format!("noname-{}", char::from(b'a' + index)),
+        _ => format!("noname-{index}"),
+    }
+}
+
+/// Split `value` on commas, semicolons and whitespace that
+/// are not enclosed in brackets.
+fn split_list(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (index, c) in value.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ',' | ';' | ' ' | '\t' if depth == 0 => {
+                if start < index {
+                    items.push(&value[start..index]);
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if start < value.len() {
+        items.push(&value[start..]);
+    }
+
+    items
+}
+
+/// Parse the addresses of the monitors in `mon_host`.
+///
+/// Each monitor is one of:
+/// * an address vector, such as `[v2:10.0.0.1:3300/0,v1:10.0.0.1:6789/0]`,
+/// * a single typed address, such as `v2:10.0.0.1:3300`,
+/// * an untyped address, such as `10.0.0.1` (which gets both a `msgr2`
+///   and legacy address on the default ports), `10.0.0.1:6789` (legacy)
+///   or `[::1]:3300` (`msgr2`). Host names are resolved.
+pub fn parse_mon_host(mon_host: &str) -> Result<Vec<Vec<EntityAddress>>, CephConfError> {
+    split_list(mon_host)
+        .into_iter()
+        .map(|monitor| {
+            parse_monitor(monitor).ok_or_else(|| CephConfError::BadMonHost(monitor.to_string()))
+        })
+        .collect()
+}
+
+fn parse_monitor(monitor: &str) -> Option<Vec<EntityAddress>> {
+    if let Some(vector) = monitor.strip_prefix('[').and_then(|m| m.strip_suffix(']'))
+        && is_typed(vector)
+    {
+        return split_list(vector).into_iter().map(parse_typed).collect();
+    }
+
+    if is_typed(monitor) {
+        return Some(vec![parse_typed(monitor)?]);
+    }
+
+    let (host, port) = split_host_port(monitor)?;
+
+    let address = |ty, port| {
+        Some(EntityAddress {
+            ty,
+            nonce: 0,
+            address: Some(resolve(host, port)?),
+        })
+    };
+
+    match port {
+        None => Some(vec![
+            address(EntityAddressType::Msgr2, MON_PORT)?,
+            address(EntityAddressType::Legacy, MON_PORT_LEGACY)?,
+        ]),
+        Some(MON_PORT_LEGACY) => Some(vec![address(EntityAddressType::Legacy, MON_PORT_LEGACY)?]),
+        Some(port) => Some(vec![address(EntityAddressType::Msgr2, port)?]),
+    }
+}
+
+fn is_typed(address: &str) -> bool {
+    ["v1:", "v2:", "any:"]
+        .iter()
+        .any(|prefix| address.starts_with(prefix))
+}
+
+/// Parse a typed address, such as `v2:10.0.0.1:3300/0`.
+fn parse_typed(address: &str) -> Option<EntityAddress> {
+    let (ty, address) = address.split_once(':')?;
+
+    let (ty, default_port) = match ty {
+        "v1" => (EntityAddressType::Legacy, MON_PORT_LEGACY),
+        "v2" => (EntityAddressType::Msgr2, MON_PORT),
+        "any" => (EntityAddressType::Any, MON_PORT),
+        _ => return None,
+    };
+
+    let (address, nonce) = match address.rsplit_once('/') {
+        Some((address, nonce)) => (address, nonce.parse().ok()?),
+        None => (address, 0),
+    };
+
+    let (host, port) = split_host_port(address)?;
+
+    Some(EntityAddress {
+        ty,
+        nonce,
+        address: Some(resolve(host, port.unwrap_or(default_port))?),
+    })
+}
+
+/// Split `address` into its host and optional port, where
+/// IPv6 addresses with a port are enclosed in brackets.
+fn split_host_port(address: &str) -> Option<(&str, Option<u16>)> {
+    if let Some(bracketed) = address.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']')?;
+
+        return match rest.strip_prefix(':') {
+            Some(port) => Some((host, Some(port.parse().ok()?))),
+            None if rest.is_empty() => Some((host, None)),
+            None => None,
+        };
+    }
+
+    match address.split_once(':') {
+        // More than one colon: an IPv6 address without port.
+        Some((_, rest)) if rest.contains(':') => Some((address, None)),
+        Some((host, port)) => Some((host, Some(port.parse().ok()?))),
+        None => Some((address, None)),
+    }
+}
+
+fn resolve(host: &str, port: u16) -> Option<SocketAddr> {
+    if host.is_empty() {
+        return None;
+    }
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, port));
+    }
+
+    (host, port).to_socket_addrs().ok()?.next()
+}
+
+#[cfg(test)]
+fn admin() -> EntityName {
+    "client.admin".parse().unwrap()
+}
+
+#[test]
+fn sections_and_metavariables() {
+    let text = "\
+        ; Global options\n\
+        fsid = 1234\n\
+        [global]\n\
+        log_file = /var/log/ceph/$cluster-$name.log\n\
+        keyring = /etc/ceph/${cluster}.$type.$id.keyring\n\
+        [client]\n\
+        rbd cache = true\n\
+        mon  host = 10.0.0.1,\\\n\
+        \t10.0.0.2\n\
+        [client.admin]\n\
+        rbd_cache = false\n\
+        cost = $5\n";
+
+    let mut conf = CephConf::parse(text).unwrap();
+    conf.set_cluster("prod");
+
+    let admin = admin();
+    let other = "client.other".parse().unwrap();
+    let osd = "osd.0".parse().unwrap();
+
+    assert_eq!(conf.get(&admin, "fsid").as_deref(), Some("1234"));
+    assert_eq!(conf.get(&admin, "rbd_cache").as_deref(), Some("false"));
+    assert_eq!(conf.get(&other, "rbd cache").as_deref(), Some("true"));
+    assert_eq!(conf.get(&osd, "rbd cache"), None);
+    assert_eq!(conf.get(&admin, "cost").as_deref(), Some("$5"));
+
+    assert_eq!(
+        conf.get(&osd, "log file").as_deref(),
+        Some("/var/log/ceph/prod-osd.0.log")
+    );
+    assert_eq!(
+        conf.keyring_paths(&admin),
+        [PathBuf::from("/etc/ceph/prod.client.admin.keyring")]
+    );
+    assert_eq!(
+        conf.get_list(&admin, "mon host").unwrap(),
+        ["10.0.0.1", "10.0.0.2"]
+    );
+
+    conf.set("rbd cache", "maybe");
+    assert_eq!(conf.get(&admin, "rbd_cache").as_deref(), Some("maybe"));
+
+    assert_eq!(
+        CephConf::parse("[global]\nmon host").unwrap_err(),
+        CephConfError::Syntax(2)
+    );
+}
+
+#[test]
+fn default_keyring() {
+    let conf = CephConf::new();
+
+    assert_eq!(
+        conf.keyring_paths(&admin()),
+        [
+            "/etc/ceph/ceph.client.admin.keyring",
+            "/etc/ceph/ceph.keyring",
+            "/etc/ceph/keyring",
+            "/etc/ceph/keyring.bin",
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn mon_host() {
+    let address = |ty, address: &str, nonce| EntityAddress {
+        ty,
+        nonce,
+        address: Some(address.parse().unwrap()),
+    };
+
+    let v1 = EntityAddressType::Legacy;
+    let v2 = EntityAddressType::Msgr2;
+
+    let monitors = parse_mon_host(
+        "[v2:10.0.0.1:3300/0,v1:10.0.0.1:6789/0] v2:10.0.0.2:3301/7, 10.0.0.3;\
+         10.0.0.4:6789 [::1]:3300 v1:[::2]",
+    )
+    .unwrap();
+
+    assert_eq!(
+        monitors,
+        [
+            vec![
+                address(v2, "10.0.0.1:3300", 0),
+                address(v1, "10.0.0.1:6789", 0)
+            ],
+            vec![address(v2, "10.0.0.2:3301", 7)],
+            vec![
+                address(v2, "10.0.0.3:3300", 0),
+                address(v1, "10.0.0.3:6789", 0)
+            ],
+            vec![address(v1, "10.0.0.4:6789", 0)],
+            vec![address(v2, "[::1]:3300", 0)],
+            vec![address(v1, "[::2]:6789", 0)],
+        ]
+    );
+
+    assert_eq!(
+        parse_mon_host("v3:10.0.0.1").unwrap_err(),
+        CephConfError::BadMonHost("v3:10.0.0.1".to_string())
+    );
+    assert_eq!(
+        parse_mon_host("10.0.0.1:port").unwrap_err(),
+        CephConfError::BadMonHost("10.0.0.1:port".to_string())
+    );
+
+    let mut conf = CephConf::new();
+    conf.set("mon_host", "10.0.0.1 10.0.0.2");
+
+    let names: Vec<_> = conf
+        .mon_host(&admin())
+        .unwrap()
+        .into_iter()
+        .map(|m| m.name)
+        .collect();
+    assert_eq!(names, ["noname-a", "noname-b"]);
+}
+
+#[test]
+fn args() {
+    let args = CephArgs::parse([
+        "--id",
+        "foo",
+        "-c",
+        "/tmp/ceph.conf",
+        "--cluster=prod",
+        "--mon-host",
+        "10.0.0.1",
+        "-k",
+        "/tmp/keyring",
+    ])
+    .unwrap();
+
+    assert_eq!(
+        args,
+        CephArgs {
+            name: Some("client.foo".parse().unwrap()),
+            cluster: Some("prod".to_string()),
+            conf: Some(PathBuf::from("/tmp/ceph.conf")),
+            options: vec![
+                ("mon host".to_string(), "10.0.0.1".to_string()),
+                ("keyring".to_string(), "/tmp/keyring".to_string()),
+            ],
+        }
+    );
+
+    let args = CephArgs::parse(["--name", "osd.3"]).unwrap();
+    assert_eq!(args.name, Some("osd.3".parse().unwrap()));
+
+    assert_eq!(
+        CephArgs::parse(["--keyring"]).unwrap_err(),
+        CephConfError::MissingValue("keyring".to_string())
+    );
+    assert_eq!(
+        CephArgs::parse(["admin"]).unwrap_err(),
+        CephConfError::BadArgument("admin".to_string())
+    );
+    assert_eq!(
+        CephArgs::parse(["--name=admin"]).unwrap_err(),
+        CephConfError::BadEntityName("admin".to_string())
+    );
+}
+
+#[test]
+fn load() {
+    let path = std::env::temp_dir().join(format!("ceph-conf-{}.conf", std::process::id()));
+    std::fs::write(&path, "[global]\nmon_host = 10.0.0.1\nkeyring = /a\n").unwrap();
+
+    let args = CephArgs {
+        conf: Some(path.clone()),
+        cluster: Some("prod".to_string()),
+        options: vec![("keyring".to_string(), "/$cluster/b".to_string())],
+        ..Default::default()
+    };
+
+    let conf = CephConf::load(&args).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(conf.cluster(), "prod");
+    assert_eq!(conf.mon_host(&admin()).unwrap().len(), 1);
+    assert_eq!(conf.keyring_paths(&admin()), [PathBuf::from("/prod/b")]);
+
+    assert_eq!(
+        CephConf::load(&args).unwrap_err(),
+        CephConfError::Io(path, std::io::ErrorKind::NotFound)
+    );
+}
@@ -9,7 +9,7 @@ pub const AUTH_MAGIC: u64 = 0xff009cad8826aa55;
 pub const CEPH_AES_IV: &[u8; 16] = b"cephsageyudagreg";
 pub const AES_GCM_SIG_SIZE: usize = 16;
 
-pub fn encode_encrypt_enc_bl<T: Encode>(t: &T, key: &Key) -> Vec<u8> {
+pub fn encode_encrypt_enc_bl<T: Encode + ?Sized>(t: &T, key: &Key) -> Vec<u8> {
     let mut buffer = Vec::new();
 
     // Struct version
@@ -61,7 +61,7 @@ where
     T::decode(buf)
 }
 
-pub fn encode_encrypt<T: Encode>(t: &T, key: &Key) -> Vec<u8> {
+pub fn encode_encrypt<T: Encode + ?Sized>(t: &T, key: &Key) -> Vec<u8> {
     let encode_encrypt_bl = encode_encrypt_enc_bl(t, key);
     let mut encoded = Vec::new();
     encode_encrypt_bl.encode(&mut encoded);
@@ -73,6 +73,7 @@ pub fn encode_encrypt<T: Encode>(t: &T, key: &Key) -> Vec<u8> {
 /// This is the equivalent of the `Key` struct in the
 /// ceph source code.
 // TODO: zeroize...
+#[derive(Clone)]
 pub struct Key {
     ty: u16,
     created: Timestamp,
@@ -136,6 +137,25 @@ impl Key {
         }
     }
 
+    /// Decode a key from its base64 representation, as found
+    /// in a `ceph.keyring`.
+    pub fn from_base64(base64: &str) -> Result<Self, DecodeError> {
+        use base64::Engine;
+
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(base64)
+            .map_err(|e| DecodeError::Custom(format!("Invalid base64 key: {e}")))?;
+
+        Self::decode(&mut data.as_slice())
+    }
+
+    /// Encode this key into its base64 representation.
+    pub fn to_base64(&self) -> String {
+        use base64::Engine;
+
+        base64::engine::general_purpose::STANDARD.encode(self.to_vec())
+    }
+
     pub fn hmac_sha256(&self, buf: &[u8]) -> [u8; 32] {
         let mut maybe_expected =
             <hmac::Hmac<sha2::Sha256> as Mac>::new_from_slice(&self.secret).unwrap();
@@ -191,6 +211,38 @@ impl Key {
         Some(data)
     }
 
+    /// Decrypt the start of an AES-GCM ciphertext that was produced using `nonce`,
+    /// without authenticating it.
+    ///
+    /// This is necessary when a prefix of a message must be inspected
+    /// before the rest of the message (and its tag) is available. The output
+    /// must not be trusted until the full ciphertext has been authenticated using
+    /// [`Key::decrypt_gcm`].
+    pub fn peek_gcm(&self, nonce: &[u8; 12], data: &mut [u8]) {
+        use aes::cipher::BlockEncrypt;
+
+        let aes = aes::Aes128::new_from_slice(&self.secret).unwrap();
+
+        // For 96-bit nonces, the counter block used for the
+        // first block of plaintext is `nonce || 2`.
+        let mut counter = 2u32;
+
+        for chunk in data.chunks_mut(16) {
+            let mut block = [0u8; 16];
+            block[..12].copy_from_slice(nonce);
+            block[12..].copy_from_slice(&counter.to_be_bytes());
+
+            let mut block = block.into();
+            aes.encrypt_block(&mut block);
+
+            for (byte, key_stream) in chunk.iter_mut().zip(block.iter()) {
+                *byte ^= key_stream;
+            }
+
+            counter = counter.wrapping_add(1);
+        }
+    }
+
     pub fn encrypt_gcm(&self, nonce: &[u8; 12], data: &mut [u8]) -> [u8; 16] {
         let gcm = Aes128Gcm::new_from_slice(&self.secret).unwrap();
         let nonce = (*nonce).into();
@@ -223,3 +275,21 @@ fn decode_key() {
         ]
     );
 }
+
+#[test]
+fn peek_gcm_matches_decrypt() {
+    let key = Key::new(Timestamp::default(), [7; 16]);
+    let nonce = [3; 12];
+
+    let plaintext: Vec<u8> = (0..77).collect();
+    let mut ciphertext = plaintext.clone();
+    let tag = key.encrypt_gcm(&nonce, &mut ciphertext);
+
+    let mut peeked = ciphertext[..40].to_vec();
+    key.peek_gcm(&nonce, &mut peeked);
+    assert_eq!(peeked, plaintext[..40]);
+
+    ciphertext.extend_from_slice(&tag);
+    let decrypted = key.decrypt_gcm(&nonce, &mut ciphertext).unwrap();
+    assert_eq!(decrypted, plaintext);
+}
@@ -278,6 +278,9 @@ pub trait Encoder {
     fn reserve(&mut self, len: usize);
     fn push(&mut self, value: u8);
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     /// Write `data` to a previously initialized sub-slice
     /// starting at `start` and ending at a `start + data.len()`
     fn write_at(&mut self, start: usize, data: &[u8]);
@@ -472,6 +475,27 @@ impl Decode<'_> for bool {
     }
 }
 
+impl<A, B> Encode for (A, B)
+where
+    A: Encode,
+    B: Encode,
+{
+    fn encode(&self, buffer: &mut impl Encoder) {
+        self.0.encode(buffer);
+        self.1.encode(buffer);
+    }
+}
+
+impl<'a, A, B> Decode<'a> for (A, B)
+where
+    A: Decode<'a>,
+    B: Decode<'a>,
+{
+    fn decode(buffer: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(buffer)?, B::decode(buffer)?))
+    }
+}
+
 impl<K> Encode for HashSet<K>
 where
     K: Encode + Eq + core::hash::Hash,
@@ -58,7 +58,7 @@ impl Decode<'_> for SocketAddressWrapper {
                 address, port, flowinfo, scope_id,
             ))))
         } else {
-            return Err(DecodeError::unknown_value("AddressFamily", family));
+            Err(DecodeError::unknown_value("AddressFamily", family))
         }
     }
 }
@@ -77,7 +77,7 @@ pub struct EntityAddress {
 impl Encode for EntityAddress {
     fn encode(&self, buffer: &mut impl Encoder) {
         let address = self.address.map(SocketAddressWrapper);
-        let address_len = address.map(|v| 2 + v.encoded_len()).unwrap_or(0) as u32;
+        let address_len = address.map(|v| 2 + v.encoded_len()).unwrap_or(0);
 
         let len = 3 // Version bytes
             + 4 // Len
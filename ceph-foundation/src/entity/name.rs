@@ -1,7 +1,7 @@
-use crate::entity::EntityType;
+use crate::{DecodeError, entity::EntityType};
 
 /// An entity name.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct EntityName {
     /// The entity type.
     pub ty: EntityType,
@@ -10,3 +10,26 @@ pub struct EntityName {
 }
 
 crate::write_decode_encode!(EntityName = ty as u32 | name as crate::WireString);
+
+/// Formats as `<type>.<name>`, e.g. `client.admin`.
+impl core::fmt::Display for EntityName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}", self.ty, self.name)
+    }
+}
+
+/// Parses `<type>.<name>`, e.g. `client.admin`.
+impl core::str::FromStr for EntityName {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((ty, name)) = s.split_once('.') else {
+            return Err(DecodeError::unknown_value("EntityName", s));
+        };
+
+        Ok(Self {
+            ty: ty.parse()?,
+            name: name.to_string(),
+        })
+    }
+}
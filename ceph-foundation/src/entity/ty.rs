@@ -76,3 +76,43 @@ impl TryFrom<u32> for EntityType {
         }
     }
 }
+
+impl EntityType {
+    /// The name of this type, as used in entity names (e.g. `client.admin`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntityType::Mon => "mon",
+            EntityType::Mds => "mds",
+            EntityType::Osd => "osd",
+            EntityType::Client => "client",
+            EntityType::Mgr => "mgr",
+            EntityType::Auth => "auth",
+            EntityType::Any => "any",
+        }
+    }
+}
+
+impl core::fmt::Display for EntityType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl core::str::FromStr for EntityType {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let res = match s {
+            "mon" => Self::Mon,
+            "mds" => Self::Mds,
+            "osd" => Self::Osd,
+            "client" => Self::Client,
+            "mgr" => Self::Mgr,
+            "auth" => Self::Auth,
+            "any" => Self::Any,
+            _ => return Err(DecodeError::unknown_value("EntityType", s)),
+        };
+
+        Ok(res)
+    }
+}
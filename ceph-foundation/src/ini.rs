@@ -0,0 +1,102 @@
+//! The INI dialect shared by `ceph.conf` and keyrings.
+
+/// A line of an INI file.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Line<'a> {
+    /// A section header, such as `[client.admin]`.
+    Section(&'a str),
+    /// A `key = value` pair.
+    ///
+    /// Quotes around the value are removed.
+    Entry { key: &'a str, value: &'a str },
+}
+
+/// Parse a single `line`, returning `None` for empty lines and comments.
+///
+/// Comments start with `#` or `;`, and may follow an unquoted value.
+/// Returns `Err` if the line is not a section header, entry or comment.
+pub(crate) fn parse_line(line: &str) -> Result<Option<Line<'_>>, ()> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with(['#', ';']) {
+        return Ok(None);
+    }
+
+    if let Some(section) = line.strip_prefix('[') {
+        let (section, rest) = section.split_once(']').ok_or(())?;
+
+        if !is_comment(rest) {
+            return Err(());
+        }
+
+        return Ok(Some(Line::Section(section.trim())));
+    }
+
+    let (key, value) = line.split_once('=').ok_or(())?;
+    let key = key.trim();
+    let value = value.trim();
+
+    if key.is_empty() {
+        return Err(());
+    }
+
+    let value = if let Some(quoted) = value.strip_prefix('"') {
+        let (value, rest) = quoted.split_once('"').ok_or(())?;
+
+        if !is_comment(rest) {
+            return Err(());
+        }
+
+        value
+    } else {
+        let end = value.find(['#', ';']).unwrap_or(value.len());
+        value[..end].trim_end()
+    };
+
+    Ok(Some(Line::Entry { key, value }))
+}
+
+/// Whether `rest` of a line is empty or a comment.
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    rest.is_empty() || rest.starts_with(['#', ';'])
+}
+
+/// Normalize `key`, so that `caps  mon` and `caps mon` (and for
+/// `ceph.conf`, `mon_host` and `mon host`) are equivalent.
+pub(crate) fn normalize_key(key: &str) -> String {
+    key.split([' ', '\t', '_'])
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn lines() {
+    assert_eq!(parse_line("  # comment"), Ok(None));
+    assert_eq!(parse_line(""), Ok(None));
+    assert_eq!(
+        parse_line("[client.admin] ; comment"),
+        Ok(Some(Line::Section("client.admin")))
+    );
+    assert_eq!(
+        parse_line("\tcaps mon = \"allow *\" # comment"),
+        Ok(Some(Line::Entry {
+            key: "caps mon",
+            value: "allow *"
+        }))
+    );
+    assert_eq!(
+        parse_line("key = AQ== ; comment"),
+        Ok(Some(Line::Entry {
+            key: "key",
+            value: "AQ=="
+        }))
+    );
+
+    assert_eq!(parse_line("[client.admin"), Err(()));
+    assert_eq!(parse_line("no value"), Err(()));
+    assert_eq!(parse_line("key = \"unterminated"), Err(()));
+
+    assert_eq!(normalize_key("mon_host"), normalize_key("mon  host"));
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    crypto::Key,
+    entity::EntityName,
+    ini::{Line, normalize_key, parse_line},
+};
+
+/// An error that occurred while parsing a [`Keyring`].
+///
+/// Line numbers start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyringError {
+    /// The line is not a section header, `key = value` pair or comment.
+    Syntax(usize),
+    /// The section header on the line is not a valid entity name.
+    BadEntityName(usize),
+    /// The entry on the line appears before the first section.
+    NoSection(usize),
+    /// The key on the line is not a valid base64-encoded key.
+    BadKey(usize),
+    /// The section for the entity does not contain a key.
+    MissingKey(EntityName),
+}
+
+/// The key and capabilities of a single entity in a [`Keyring`].
+#[derive(Debug, Clone)]
+pub struct KeyringEntry {
+    pub name: EntityName,
+    pub key: Key,
+    /// The capabilities of the entity per service, such
+    /// as `mon` => `allow *`.
+    pub caps: BTreeMap<String, String>,
+}
+
+/// A keyring, as stored in a `ceph.keyring`.
+///
+/// A keyring is an INI-style file with a section per entity:
+///
+/// ```text
+/// [client.admin]
+///     key = AQBKp1Zp...==
+///     caps mon = "allow *"
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    entries: Vec<KeyringEntry>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the keyring in `text`.
+    ///
+    /// Unknown entries (such as `auid`) are ignored.
+    pub fn parse(text: &str) -> Result<Self, KeyringError> {
+        let mut keyring = Self::new();
+        let mut current: Option<(EntityName, Option<Key>, BTreeMap<String, String>)> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+
+            let line = parse_line(line).map_err(|_| KeyringError::Syntax(number))?;
+
+            match line {
+                None => {}
+                Some(Line::Section(section)) => {
+                    let name = section
+                        .parse()
+                        .map_err(|_| KeyringError::BadEntityName(number))?;
+
+                    if let Some(entry) = current.replace((name, None, BTreeMap::new())) {
+                        keyring.finish_entry(entry)?;
+                    }
+                }
+                Some(Line::Entry { key, value }) => {
+                    let (_, entry_key, caps) =
+                        current.as_mut().ok_or(KeyringError::NoSection(number))?;
+
+                    let key = normalize_key(key);
+
+                    if key == "key" {
+                        let key =
+                            Key::from_base64(value).map_err(|_| KeyringError::BadKey(number))?;
+                        *entry_key = Some(key);
+                    } else if let Some(service) = key.strip_prefix("caps ") {
+                        caps.insert(service.to_string(), value.to_string());
+                    }
+                }
+            }
+        }
+
+        if let Some(entry) = current {
+            keyring.finish_entry(entry)?;
+        }
+
+        Ok(keyring)
+    }
+
+    fn finish_entry(
+        &mut self,
+        (name, key, caps): (EntityName, Option<Key>, BTreeMap<String, String>),
+    ) -> Result<(), KeyringError> {
+        let key = key.ok_or_else(|| KeyringError::MissingKey(name.clone()))?;
+        self.insert(KeyringEntry { name, key, caps });
+        Ok(())
+    }
+
+    /// Add `entry`, replacing the entry with the same name (if any).
+    pub fn insert(&mut self, entry: KeyringEntry) {
+        if let Some(existing) = self.entries.iter_mut().find(|e| e.name == entry.name) {
+            *existing = entry;
+        } else {
+            self.entries.push(entry);
+        }
+    }
+
+    /// Remove the entry for `name`, returning it if it existed.
+    pub fn remove(&mut self, name: &EntityName) -> Option<KeyringEntry> {
+        let index = self.entries.iter().position(|e| &e.name == name)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// The entry for `name`, if any.
+    pub fn entry(&self, name: &EntityName) -> Option<&KeyringEntry> {
+        self.entries.iter().find(|e| &e.name == name)
+    }
+
+    /// The key of `name`, if any.
+    pub fn key(&self, name: &EntityName) -> Option<&Key> {
+        self.entry(name).map(|e| &e.key)
+    }
+
+    /// All entries, in the order in which they were added.
+    pub fn entries(&self) -> &[KeyringEntry] {
+        &self.entries
+    }
+}
+
+/// Formats the keyring in the format that [`Keyring::parse`] accepts.
+impl core::fmt::Display for Keyring {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "[{}]", entry.name)?;
+            writeln!(f, "\tkey = {}", entry.key.to_base64())?;
+
+            for (service, caps) in &entry.caps {
+                writeln!(f, "\tcaps {service} = \"{caps}\"")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_key() -> Key {
+    use crate::Decode;
+
+    let key_data = include_bytes!("../../test-data/key.bin");
+    Key::decode(&mut &key_data[..]).unwrap()
+}
+
+#[test]
+fn parse_keyring() {
+    use crate::{Encode, entity::EntityType};
+
+    let key = test_key().to_base64();
+    let text = format!(
+        "# Written by ceph-authtool\n\
+         [client.admin]\n\
+         \tkey = {key}\n\
+         \tcaps mds = \"allow *\"\n\
+         \tcaps  mon = \"allow *\"\n\
+         \n\
+         [osd.0]\n\
+         key = {key}\n\
+         auid = 0\n"
+    );
+
+    let keyring = Keyring::parse(&text).unwrap();
+    assert_eq!(keyring.entries().len(), 2);
+
+    let admin = EntityName {
+        ty: EntityType::Client,
+        name: "admin".to_string(),
+    };
+
+    let entry = keyring.entry(&admin).unwrap();
+    assert_eq!(entry.key.to_vec(), test_key().to_vec());
+    assert_eq!(entry.caps["mon"], "allow *");
+    assert_eq!(entry.caps["mds"], "allow *");
+
+    let osd = "osd.0".parse().unwrap();
+    assert!(keyring.key(&osd).is_some());
+    assert!(keyring.entry(&osd).unwrap().caps.is_empty());
+
+    assert!(keyring.key(&"client.other".parse().unwrap()).is_none());
+}
+
+#[test]
+fn write_keyring() {
+    let mut keyring = Keyring::new();
+    keyring.insert(KeyringEntry {
+        name: "client.admin".parse().unwrap(),
+        key: test_key(),
+        caps: [("mon".to_string(), "allow r".to_string())].into(),
+    });
+
+    let text = keyring.to_string();
+    assert_eq!(
+        text,
+        format!(
+            "[client.admin]\n\tkey = {}\n\tcaps mon = \"allow r\"\n",
+            test_key().to_base64()
+        )
+    );
+
+    let parsed = Keyring::parse(&text).unwrap();
+    assert_eq!(parsed.to_string(), text);
+}
+
+#[test]
+fn keyring_errors() {
+    let key = test_key().to_base64();
+
+    assert_eq!(
+        Keyring::parse(&format!("key = {key}")).unwrap_err(),
+        KeyringError::NoSection(1)
+    );
+    assert_eq!(
+        Keyring::parse("[admin]").unwrap_err(),
+        KeyringError::BadEntityName(1)
+    );
+    assert_eq!(
+        Keyring::parse("[client.admin]\nkey = ???").unwrap_err(),
+        KeyringError::BadKey(2)
+    );
+    assert_eq!(
+        Keyring::parse("[client.admin]\ncaps mon = \"allow *\"").unwrap_err(),
+        KeyringError::MissingKey("client.admin".parse().unwrap())
+    );
+    assert_eq!(
+        Keyring::parse("[client.admin]\nkey").unwrap_err(),
+        KeyringError::Syntax(2)
+    );
+}
@@ -1,12 +1,17 @@
+mod conf;
 pub mod crypto;
 mod encdec;
 pub mod entity;
 mod features;
+mod ini;
+mod keyring;
 mod mon_info;
 mod uuid;
 
+pub use conf::{CephArgs, CephConf, CephConfError, DEFAULT_CLUSTER, parse_mon_host};
 pub use encdec::{Decode, DecodeError, Encode, Encoder, WireString, decode_full_mut_slice};
 pub use features::CephFeatureSet;
+pub use keyring::{Keyring, KeyringEntry, KeyringError};
 pub use mon_info::MonInfo;
 pub use uuid::Uuid;
 
@@ -26,7 +26,7 @@ impl Encode for MonInfo {
     fn encode(&self, buffer: &mut impl Encoder) {
         let buffer = &mut crate::write_versions_and_data!(buffer, Self::VERSION, 3);
         self.name.encode(buffer);
-        self.public_addrs.encode(buffer);
+        AddrVec::from(&self.public_addrs).encode(buffer);
         self.priority.encode(buffer);
         self.weight.encode(buffer);
         self.crush_location.encode(buffer);
@@ -0,0 +1,132 @@
+use ceph_foundation::{Decode, Encode};
+
+use crate::{
+    DecodeMessage, DecodeMessageError, EncodeMessage, PaxosServiceHeader,
+    mon_command::{front_and_data, push_front_and_data},
+};
+
+/// An authentication request for the monitors (`MAuth`), sent
+/// over an established session.
+///
+/// This is used to request (new) service tickets, for instance using
+/// a CephX `GetPrincipalSessionKey` request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageAuth {
+    pub paxos: PaxosServiceHeader,
+    /// The authentication protocol of `auth_payload`.
+    pub protocol: u32,
+    pub auth_payload: Vec<u8>,
+    /// The epoch of the newest monitor map that the client has.
+    pub monmap_epoch: u32,
+}
+
+impl DecodeMessage<'_> for MessageAuth {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        let (mut front, _) = front_and_data(segments)?;
+        let front = &mut front;
+
+        Ok(Self {
+            paxos: Decode::decode(front)?,
+            protocol: Decode::decode(front)?,
+            auth_payload: Decode::decode(front)?,
+            monmap_epoch: Decode::decode(front)?,
+        })
+    }
+}
+
+impl EncodeMessage for MessageAuth {
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>) {
+        let mut front = Vec::new();
+        self.paxos.encode(&mut front);
+        self.protocol.encode(&mut front);
+        self.auth_payload.encode(&mut front);
+        self.monmap_epoch.encode(&mut front);
+
+        push_front_and_data(output_segments, front, &[]);
+    }
+}
+
+/// The reply to a [`MessageAuth`] (`MAuthReply`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageAuthReply {
+    pub protocol: u32,
+    /// The result of the request: zero or a negative errno.
+    pub result: i32,
+    pub global_id: u64,
+    /// The protocol-specific reply.
+    pub result_bl: Vec<u8>,
+    pub result_msg: String,
+}
+
+impl DecodeMessage<'_> for MessageAuthReply {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        let (mut front, _) = front_and_data(segments)?;
+        let front = &mut front;
+
+        Ok(Self {
+            protocol: Decode::decode(front)?,
+            result: Decode::decode(front)?,
+            global_id: Decode::decode(front)?,
+            result_bl: Decode::decode(front)?,
+            result_msg: Decode::decode(front)?,
+        })
+    }
+}
+
+impl EncodeMessage for MessageAuthReply {
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>) {
+        let mut front = Vec::new();
+        self.protocol.encode(&mut front);
+        self.result.encode(&mut front);
+        self.global_id.encode(&mut front);
+        self.result_bl.encode(&mut front);
+        self.result_msg.encode(&mut front);
+
+        push_front_and_data(output_segments, front, &[]);
+    }
+}
+
+#[test]
+fn message_auth() {
+    let auth = MessageAuth {
+        paxos: PaxosServiceHeader::default(),
+        protocol: 2,
+        auth_payload: vec![0, 2, 1, 2, 3],
+        monmap_epoch: 3,
+    };
+
+    let mut segments = Vec::new();
+    auth.encode_message(&mut segments);
+    assert_eq!(segments.len(), 1);
+
+    let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+    assert_eq!(MessageAuth::decode_message(&segments).unwrap(), auth);
+}
+
+#[test]
+fn message_auth_reply() {
+    #[rustfmt::skip]
+    let front = [
+        2, 0, 0, 0, // Protocol
+        243, 255, 255, 255, // Result
+        4, 16, 0, 0, 0, 0, 0, 0, // Global ID
+        2, 0, 0, 0, 1, 2, // Result bl
+        2, 0, 0, 0, b'n', b'o', // Result message
+    ];
+
+    let reply = MessageAuthReply::decode_message(&[&front]).unwrap();
+
+    let expected = MessageAuthReply {
+        protocol: 2,
+        result: -13,
+        global_id: 4100,
+        result_bl: vec![1, 2],
+        result_msg: "no".to_string(),
+    };
+
+    assert_eq!(reply, expected);
+
+    let mut segments = Vec::new();
+    expected.encode_message(&mut segments);
+    assert_eq!(segments, [front.to_vec()]);
+}
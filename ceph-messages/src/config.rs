@@ -20,7 +20,7 @@ impl DecodeMessage<'_> for Config {
             return Err(crate::DecodeMessageError::NotEnoughSegments { have: 0, need: 1 });
         }
 
-        let config = Decode::decode(&mut segments[0].as_ref())?;
+        let config = Decode::decode(&mut &*segments[0])?;
 
         Ok(Self { config })
     }
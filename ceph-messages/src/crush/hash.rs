@@ -0,0 +1,138 @@
+//! The Robert Jenkins hashes used by CRUSH and for mapping
+//! object names to placement groups.
+
+/// The identifier of the `rjenkins1` hash in a [`Bucket`](super::Bucket).
+pub const HASH_RJENKINS1: u8 = 0;
+
+const SEED: u32 = 1315423911;
+
+fn mix(a: &mut u32, b: &mut u32, c: &mut u32) {
+    *a = a.wrapping_sub(*b).wrapping_sub(*c) ^ (*c >> 13);
+    *b = b.wrapping_sub(*c).wrapping_sub(*a) ^ (*a << 8);
+    *c = c.wrapping_sub(*a).wrapping_sub(*b) ^ (*b >> 13);
+    *a = a.wrapping_sub(*b).wrapping_sub(*c) ^ (*c >> 12);
+    *b = b.wrapping_sub(*c).wrapping_sub(*a) ^ (*a << 16);
+    *c = c.wrapping_sub(*a).wrapping_sub(*b) ^ (*b >> 5);
+    *a = a.wrapping_sub(*b).wrapping_sub(*c) ^ (*c >> 3);
+    *b = b.wrapping_sub(*c).wrapping_sub(*a) ^ (*a << 10);
+    *c = c.wrapping_sub(*a).wrapping_sub(*b) ^ (*b >> 15);
+}
+
+/// `crush_hash32_rjenkins1_2`.
+pub fn hash32_2(mut a: u32, mut b: u32) -> u32 {
+    let mut hash = SEED ^ a ^ b;
+    let (mut x, mut y) = (231232, 1232);
+    mix(&mut a, &mut b, &mut hash);
+    mix(&mut x, &mut a, &mut hash);
+    mix(&mut b, &mut y, &mut hash);
+    hash
+}
+
+/// `crush_hash32_rjenkins1_3`.
+pub fn hash32_3(mut a: u32, mut b: u32, mut c: u32) -> u32 {
+    let mut hash = SEED ^ a ^ b ^ c;
+    let (mut x, mut y) = (231232, 1232);
+    mix(&mut a, &mut b, &mut hash);
+    mix(&mut c, &mut x, &mut hash);
+    mix(&mut y, &mut a, &mut hash);
+    mix(&mut b, &mut x, &mut hash);
+    mix(&mut y, &mut c, &mut hash);
+    hash
+}
+
+/// `crush_hash32_rjenkins1_4`.
+pub fn hash32_4(mut a: u32, mut b: u32, mut c: u32, mut d: u32) -> u32 {
+    let mut hash = SEED ^ a ^ b ^ c ^ d;
+    let (mut x, mut y) = (231232, 1232);
+    mix(&mut a, &mut b, &mut hash);
+    mix(&mut c, &mut d, &mut hash);
+    mix(&mut a, &mut x, &mut hash);
+    mix(&mut y, &mut b, &mut hash);
+    mix(&mut c, &mut x, &mut hash);
+    mix(&mut y, &mut d, &mut hash);
+    hash
+}
+
+/// Hash an object name (`ceph_str_hash_rjenkins`).
+pub fn str_hash_rjenkins(data: &[u8]) -> u32 {
+    let word = |k: &[u8]| u32::from_le_bytes(k.try_into().unwrap());
+
+    let mut a = 0x9e3779b9u32;
+    let mut b = a;
+    let mut c = 0u32;
+
+    let mut chunks = data.chunks_exact(12);
+    for chunk in &mut chunks {
+        a = a.wrapping_add(word(&chunk[0..4]));
+        b = b.wrapping_add(word(&chunk[4..8]));
+        c = c.wrapping_add(word(&chunk[8..12]));
+        mix(&mut a, &mut b, &mut c);
+    }
+
+    // The last 11 bytes, where the lowest byte of `c` is
+    // reserved for the length.
+    let mut tail = [0u8; 12];
+    let rest = chunks.remainder();
+    tail[..rest.len()].copy_from_slice(rest);
+
+    a = a.wrapping_add(word(&tail[0..4]));
+    b = b.wrapping_add(word(&tail[4..8]));
+    c = c
+        .wrapping_add(data.len() as u32)
+        .wrapping_add(word(&tail[8..12]) << 8);
+
+    mix(&mut a, &mut b, &mut c);
+    c
+}
+
+/// Hash an object name (`ceph_str_hash_linux`).
+pub fn str_hash_linux(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |hash, &c| {
+        let c = c as u32;
+        hash.wrapping_add(c << 4)
+            .wrapping_add(c >> 4)
+            .wrapping_mul(11)
+    })
+}
+
+#[test]
+fn hashes() {
+    // Both hashes of an empty name only depend on the initial state.
+    let (mut a, mut b, mut c) = (0x9e3779b9, 0x9e3779b9, 0);
+    mix(&mut a, &mut b, &mut c);
+    assert_eq!(str_hash_rjenkins(b""), c);
+    assert_eq!(str_hash_linux(b""), 0);
+
+    assert_eq!(str_hash_linux(b"a"), (0x61 * 16 + 6) * 11);
+
+    // The trailing bytes are placed the same way as full blocks,
+    // except for the lowest byte of `c`.
+    assert_ne!(str_hash_rjenkins(b"foo"), str_hash_rjenkins(b"oof"));
+    assert_ne!(
+        str_hash_rjenkins(b"12345678901"),
+        str_hash_rjenkins(b"123456789012")
+    );
+    assert_ne!(hash32_2(1, 2), hash32_2(2, 1));
+}
+
+#[test]
+fn known_hashes() {
+    // From `ceph osd map`: `object 'foo' -> pg 0.7fc1f406`.
+    assert_eq!(str_hash_rjenkins(b"foo"), 0x7fc1f406);
+    assert_eq!(str_hash_rjenkins(b"rbd_directory"), 0x30a98c1c);
+
+    // Computed with Ceph's `crush/hash.c` and `ceph_hash.cc`.
+    assert_eq!(str_hash_rjenkins(b""), 0xbd49d10d);
+    assert_eq!(str_hash_rjenkins(b"12345678901"), 0x79f32d84);
+    assert_eq!(str_hash_rjenkins(b"123456789012"), 0xf7e45e60);
+    assert_eq!(
+        str_hash_rjenkins(b"benchmark_data_host_1234_object0"),
+        0xb6409ff6
+    );
+    assert_eq!(hash32_2(1, 2), 0xb78dee9c);
+    assert_eq!(hash32_2(0x7fc1f406, 1), 0x8ddb71b1);
+    assert_eq!(hash32_3(1, 2, 3), 0x735ad42b);
+    assert_eq!(hash32_3(12345, -2i32 as u32, 0), 0x2dc4d5e0);
+    assert_eq!(hash32_4(1, 2, 3, 4), 0x696d1f16);
+    assert_eq!(hash32_4(12345, 3, 1, -1i32 as u32), 0xe06be55a);
+}
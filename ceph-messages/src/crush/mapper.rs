@@ -0,0 +1,923 @@
+//! The CRUSH placement algorithm (`crush_do_rule`).
+
+use std::collections::HashMap;
+
+use super::{
+    Bucket, BucketKind, ChooseArg, CrushMap, ITEM_NONE, RuleStep,
+    hash::{self, HASH_RJENKINS1},
+    ln::crush_ln,
+};
+
+/// A result position that has not been decided yet.
+const ITEM_UNDEF: i32 = 0x7ffffffe;
+
+/// The index of the `choose_args` that are used for pools
+/// without their own set.
+pub const DEFAULT_CHOOSE_ARGS: i64 = -1;
+
+/// The permutation state of a bucket (`crush_work_bucket`).
+#[derive(Debug, Clone)]
+struct Permutation {
+    x: u32,
+    n: u32,
+    perm: Vec<u32>,
+}
+
+/// The state of a single [`CrushMap::do_rule`] invocation.
+struct Mapper<'a> {
+    map: &'a CrushMap,
+    x: u32,
+    weights: &'a [u32],
+    choose_args: Option<&'a HashMap<u32, ChooseArg>>,
+    work: Vec<Permutation>,
+}
+
+impl CrushMap {
+    /// Get the `choose_args` that should be used for the pool
+    /// with ID `pool`, falling back to the default set.
+    pub fn choose_args_for(&self, pool: i64) -> Option<&HashMap<u32, ChooseArg>> {
+        self.choose_args
+            .get(&pool)
+            .or_else(|| self.choose_args.get(&DEFAULT_CHOOSE_ARGS))
+    }
+
+    /// Map input `x` to at most `result_max` items using `rule`.
+    ///
+    /// `weights` contains the weight of each device, where
+    /// `0x10000` is fully in and devices that are not in `weights`
+    /// are out. Positions that could not be filled by an `INDEP`
+    /// step are set to [`ITEM_NONE`].
+    pub fn do_rule(
+        &self,
+        rule: u32,
+        x: u32,
+        result_max: usize,
+        weights: &[u32],
+        choose_args: Option<&HashMap<u32, ChooseArg>>,
+    ) -> Vec<i32> {
+        let Some(rule) = self.rule(rule) else {
+            return Vec::new();
+        };
+
+        let work = self
+            .buckets
+            .iter()
+            .map(|bucket| Permutation {
+                x: 0,
+                n: 0,
+                perm: vec![0; bucket.as_ref().map(|b| b.items.len()).unwrap_or(0)],
+            })
+            .collect();
+
+        let mut mapper = Mapper {
+            map: self,
+            x,
+            weights,
+            choose_args,
+            work,
+        };
+
+        let tunables = &self.tunables;
+        // The original `choose_total_tries` counted retries
+        // instead of tries, so add one.
+        let mut choose_tries = tunables.choose_total_tries + 1;
+        let mut choose_leaf_tries = 0;
+        // The local tries are counted as retries, which is correct.
+        let mut choose_local_retries = tunables.choose_local_tries;
+        let mut choose_local_fallback_retries = tunables.choose_local_fallback_tries;
+        let mut vary_r = tunables.chooseleaf_vary_r as u32;
+        let mut stable = tunables.chooseleaf_stable != 0;
+
+        let mut result = Vec::new();
+        let mut w = Vec::new();
+
+        for step in &rule.steps {
+            let arg1 = step.arg1;
+
+            match step.op {
+                RuleStep::TAKE
+                    if (arg1 >= 0 && arg1 < self.max_devices) || self.bucket(arg1).is_some() =>
+                {
+                    w = vec![arg1];
+                }
+                RuleStep::SET_CHOOSE_TRIES if arg1 > 0 => choose_tries = arg1 as u32,
+                RuleStep::SET_CHOOSELEAF_TRIES if arg1 > 0 => choose_leaf_tries = arg1 as u32,
+                RuleStep::SET_CHOOSE_LOCAL_TRIES if arg1 >= 0 => choose_local_retries = arg1 as u32,
+                RuleStep::SET_CHOOSE_LOCAL_FALLBACK_TRIES if arg1 >= 0 => {
+                    choose_local_fallback_retries = arg1 as u32
+                }
+                RuleStep::SET_CHOOSELEAF_VARY_R if arg1 >= 0 => vary_r = arg1 as u32,
+                RuleStep::SET_CHOOSELEAF_STABLE if arg1 >= 0 => stable = arg1 != 0,
+                op @ (RuleStep::CHOOSE_FIRSTN
+                | RuleStep::CHOOSELEAF_FIRSTN
+                | RuleStep::CHOOSE_INDEP
+                | RuleStep::CHOOSELEAF_INDEP) => {
+                    if w.is_empty() {
+                        continue;
+                    }
+
+                    let firstn =
+                        matches!(op, RuleStep::CHOOSE_FIRSTN | RuleStep::CHOOSELEAF_FIRSTN);
+                    let recurse_to_leaf =
+                        matches!(op, RuleStep::CHOOSELEAF_FIRSTN | RuleStep::CHOOSELEAF_INDEP);
+
+                    let mut o = vec![0; result_max];
+                    let mut c = vec![0; result_max];
+                    let mut osize = 0;
+
+                    for item in &w {
+                        let mut numrep = arg1;
+                        if numrep <= 0 {
+                            numrep += result_max as i32;
+                            if numrep <= 0 {
+                                continue;
+                            }
+                        }
+
+                        // `item` is probably `ITEM_NONE` if it is not a bucket.
+                        let Some(bucket) = self.bucket(*item) else {
+                            continue;
+                        };
+
+                        if firstn {
+                            let recurse_tries = if choose_leaf_tries != 0 {
+                                choose_leaf_tries
+                            } else if tunables.chooseleaf_descend_once != 0 {
+                                1
+                            } else {
+                                choose_tries
+                            };
+
+                            osize += mapper.choose_firstn(
+                                bucket,
+                                numrep,
+                                step.arg2,
+                                &mut o[osize..],
+                                0,
+                                result_max - osize,
+                                choose_tries,
+                                recurse_tries,
+                                choose_local_retries,
+                                choose_local_fallback_retries,
+                                recurse_to_leaf,
+                                vary_r,
+                                stable,
+                                &mut c[osize..],
+                                0,
+                            );
+                        } else {
+                            let out_size = (numrep as usize).min(result_max - osize);
+
+                            mapper.choose_indep(
+                                bucket,
+                                out_size,
+                                numrep,
+                                step.arg2,
+                                &mut o[osize..],
+                                0,
+                                choose_tries,
+                                if choose_leaf_tries != 0 {
+                                    choose_leaf_tries
+                                } else {
+                                    1
+                                },
+                                recurse_to_leaf,
+                                Some(&mut c[osize..]),
+                                0,
+                            );
+
+                            osize += out_size;
+                        }
+                    }
+
+                    // Use the leaves as the output.
+                    if recurse_to_leaf {
+                        o[..osize].copy_from_slice(&c[..osize]);
+                    }
+
+                    o.truncate(osize);
+                    w = o;
+                }
+                RuleStep::EMIT => {
+                    let len = w.len().min(result_max - result.len());
+                    result.extend_from_slice(&w[..len]);
+                    w.clear();
+                }
+                _ => {}
+            }
+        }
+
+        result
+    }
+}
+
+impl<'a> Mapper<'a> {
+    fn hash3(bucket: &Bucket, a: u32, b: u32, c: u32) -> u32 {
+        if bucket.hash == HASH_RJENKINS1 {
+            hash::hash32_3(a, b, c)
+        } else {
+            0
+        }
+    }
+
+    fn hash4(bucket: &Bucket, a: u32, b: u32, c: u32, d: u32) -> u32 {
+        if bucket.hash == HASH_RJENKINS1 {
+            hash::hash32_4(a, b, c, d)
+        } else {
+            0
+        }
+    }
+
+    /// The type of `item`, or `None` if it is an invalid bucket.
+    fn item_type(&self, item: i32) -> Option<i32> {
+        if item >= 0 {
+            Some(0)
+        } else {
+            self.map.bucket(item).map(|b| b.ty as i32)
+        }
+    }
+
+    /// Whether device `item` is (probabilistically) out.
+    fn is_out(&self, item: i32) -> bool {
+        let Some(&weight) = self.weights.get(item as usize) else {
+            return true;
+        };
+
+        if weight >= 0x10000 {
+            false
+        } else if weight == 0 {
+            true
+        } else {
+            (hash::hash32_2(self.x, item as u32) & 0xffff) >= weight
+        }
+    }
+
+    /// Choose an item from `bucket` using a pseudo-random
+    /// permutation of its items.
+    fn perm_choose(&mut self, bucket: &Bucket, r: i32) -> i32 {
+        let x = self.x;
+        let size = bucket.items.len() as u32;
+        let pr = (r as u32) % size;
+        let work = &mut self.work[(-1 - bucket.id) as usize];
+
+        // Start a new permutation if `x` has changed.
+        if work.x != x || work.n == 0 {
+            work.x = x;
+
+            // Optimize the common `r = 0` case.
+            if pr == 0 {
+                let s = Self::hash3(bucket, x, bucket.id as u32, 0) % size;
+                work.perm[0] = s;
+                work.n = 0xffff;
+                return bucket.items[s as usize];
+            }
+
+            for (i, p) in work.perm.iter_mut().enumerate() {
+                *p = i as u32;
+            }
+            work.n = 0;
+        } else if work.n == 0xffff {
+            // Clean up after the `r = 0` case above.
+            for i in 1..size {
+                work.perm[i as usize] = i;
+            }
+            let first = work.perm[0] as usize;
+            work.perm[first] = 0;
+            work.n = 1;
+        }
+
+        while work.n <= pr {
+            let p = work.n;
+
+            // There is no point in swapping the final entry.
+            if p < size - 1 {
+                let i = Self::hash3(bucket, x, bucket.id as u32, p) % (size - p);
+                work.perm.swap(p as usize, (p + i) as usize);
+            }
+
+            work.n += 1;
+        }
+
+        bucket.items[work.perm[pr as usize] as usize]
+    }
+
+    fn list_choose(
+        bucket: &Bucket,
+        x: u32,
+        r: i32,
+        item_weights: &[u32],
+        sum_weights: &[u32],
+    ) -> i32 {
+        for i in (0..bucket.items.len()).rev() {
+            let item = bucket.items[i];
+            let w = Self::hash4(bucket, x, item as u32, r as u32, bucket.id as u32) & 0xffff;
+            let w = (w as u64 * sum_weights[i] as u64) >> 16;
+
+            if w < item_weights[i] as u64 {
+                return item;
+            }
+        }
+
+        bucket.items[0]
+    }
+
+    fn tree_choose(bucket: &Bucket, x: u32, r: i32, node_weights: &[u32]) -> i32 {
+        let weight = |n: usize| node_weights.get(n).copied().unwrap_or(0) as u64;
+
+        // Start at the root.
+        let mut n = node_weights.len() >> 1;
+
+        // Descend until we reach a leaf, which have odd indices.
+        while n != 0 && n & 1 == 0 {
+            let half = 1 << (n.trailing_zeros() - 1);
+            let t = Self::hash4(bucket, x, n as u32, r as u32, bucket.id as u32) as u64;
+            let t = (t * weight(n)) >> 32;
+
+            let left = n - half;
+            n = if t < weight(left) { left } else { n + half };
+        }
+
+        bucket.items.get(n >> 1).copied().unwrap_or(bucket.items[0])
+    }
+
+    fn straw_choose(bucket: &Bucket, x: u32, r: i32, straws: &[u32]) -> i32 {
+        let mut high = 0;
+        let mut high_draw = 0;
+
+        for (i, (item, straw)) in bucket.items.iter().zip(straws).enumerate() {
+            let draw = (Self::hash3(bucket, x, *item as u32, r as u32) & 0xffff) as u64;
+            let draw = draw * *straw as u64;
+
+            if i == 0 || draw > high_draw {
+                high = i;
+                high_draw = draw;
+            }
+        }
+
+        bucket.items[high]
+    }
+
+    fn straw2_choose(
+        &self,
+        bucket: &Bucket,
+        r: i32,
+        item_weights: &'a [u32],
+        position: usize,
+    ) -> i32 {
+        let arg = self
+            .choose_args
+            .and_then(|args| args.get(&((-1 - bucket.id) as u32)));
+
+        let weights = match arg {
+            Some(arg) if !arg.weight_set.is_empty() => {
+                &arg.weight_set[position.min(arg.weight_set.len() - 1)]
+            }
+            _ => item_weights,
+        };
+
+        let ids = match arg {
+            Some(arg) if !arg.ids.is_empty() => &arg.ids,
+            _ => &bucket.items,
+        };
+
+        let mut high = 0;
+        let mut high_draw = 0;
+
+        for i in 0..bucket.items.len() {
+            let weight = weights.get(i).copied().unwrap_or(0);
+            let id = ids.get(i).copied().unwrap_or(bucket.items[i]);
+
+            let draw = if weight != 0 {
+                // Generate an exponentially distributed random
+                // variable using the inversion method. The logarithm
+                // is negative, so a larger weight gives a larger draw.
+                let u = Self::hash3(bucket, self.x, id as u32, r as u32) & 0xffff;
+                let ln = crush_ln(u) as i64 - 0x1000000000000;
+                ln / weight as i64
+            } else {
+                i64::MIN
+            };
+
+            if i == 0 || draw > high_draw {
+                high = i;
+                high_draw = draw;
+            }
+        }
+
+        bucket.items[high]
+    }
+
+    fn bucket_choose(&mut self, bucket: &'a Bucket, r: i32, position: usize) -> i32 {
+        let x = self.x;
+
+        match &bucket.kind {
+            BucketKind::Uniform { .. } => self.perm_choose(bucket, r),
+            BucketKind::List {
+                item_weights,
+                sum_weights,
+            } => Self::list_choose(bucket, x, r, item_weights, sum_weights),
+            BucketKind::Tree { node_weights } => Self::tree_choose(bucket, x, r, node_weights),
+            BucketKind::Straw { straws, .. } => Self::straw_choose(bucket, x, r, straws),
+            BucketKind::Straw2 { item_weights } => {
+                self.straw2_choose(bucket, r, item_weights, position)
+            }
+        }
+    }
+
+    /// Choose `numrep` distinct items of type `ty`, retrying
+    /// until the desired number of items has been found
+    /// (`crush_choose_firstn`).
+    #[allow(clippy::too_many_arguments)]
+    fn choose_firstn(
+        &mut self,
+        bucket: &'a Bucket,
+        numrep: i32,
+        ty: i32,
+        out: &mut [i32],
+        mut outpos: usize,
+        out_size: usize,
+        tries: u32,
+        recurse_tries: u32,
+        local_retries: u32,
+        local_fallback_retries: u32,
+        recurse_to_leaf: bool,
+        vary_r: u32,
+        stable: bool,
+        out2: &mut [i32],
+        parent_r: i32,
+    ) -> usize {
+        let map = self.map;
+        let mut count = out_size;
+        let mut rep = if stable { 0 } else { outpos as i32 };
+
+        while rep < numrep && count > 0 {
+            // Keep trying until we get a non-out, non-colliding item.
+            let mut ftotal = 0u32;
+            let mut skip_rep = false;
+            let mut item = 0;
+
+            loop {
+                let mut retry_descent = false;
+                let mut current = bucket;
+                let mut flocal = 0u32;
+
+                // Choose through intervening buckets.
+                loop {
+                    let mut retry_bucket = false;
+                    let r = rep + parent_r + ftotal as i32;
+                    let size = current.items.len() as u32;
+
+                    let (reject, collide) = if size == 0 {
+                        (true, false)
+                    } else {
+                        item = if local_fallback_retries > 0
+                            && flocal >= (size >> 1)
+                            && flocal > local_fallback_retries
+                        {
+                            self.perm_choose(current, r)
+                        } else {
+                            self.bucket_choose(current, r, outpos)
+                        };
+
+                        if item >= map.max_devices {
+                            skip_rep = true;
+                            break;
+                        }
+
+                        let Some(item_type) = self.item_type(item) else {
+                            skip_rep = true;
+                            break;
+                        };
+
+                        // Keep going?
+                        if item_type != ty {
+                            match map.bucket(item) {
+                                Some(bucket) => {
+                                    current = bucket;
+                                    continue;
+                                }
+                                None => {
+                                    skip_rep = true;
+                                    break;
+                                }
+                            }
+                        }
+
+                        let collide = out[..outpos].contains(&item);
+                        let mut reject = false;
+
+                        if !collide && recurse_to_leaf {
+                            if let Some(leaf_bucket) = map.bucket(item) {
+                                let sub_r = if vary_r != 0 { r >> (vary_r - 1) } else { 0 };
+
+                                let got = self.choose_firstn(
+                                    leaf_bucket,
+                                    if stable { 1 } else { outpos as i32 + 1 },
+                                    0,
+                                    out2,
+                                    outpos,
+                                    count,
+                                    recurse_tries,
+                                    0,
+                                    local_retries,
+                                    local_fallback_retries,
+                                    false,
+                                    vary_r,
+                                    stable,
+                                    &mut [],
+                                    sub_r,
+                                );
+
+                                // Didn't get a leaf.
+                                reject = got <= outpos;
+                            } else {
+                                // We already have a leaf!
+                                out2[outpos] = item;
+                            }
+                        }
+
+                        if !reject && !collide && item_type == 0 {
+                            reject = self.is_out(item);
+                        }
+
+                        (reject, collide)
+                    };
+
+                    if reject || collide {
+                        ftotal += 1;
+                        flocal += 1;
+
+                        if collide && flocal <= local_retries {
+                            // Retry locally a few times.
+                            retry_bucket = true;
+                        } else if local_fallback_retries > 0
+                            && flocal <= size + local_fallback_retries
+                        {
+                            // Exhaustive bucket search.
+                            retry_bucket = true;
+                        } else if ftotal < tries {
+                            // Then retry the descent.
+                            retry_descent = true;
+                        } else {
+                            // Else give up.
+                            skip_rep = true;
+                        }
+                    }
+
+                    if !retry_bucket {
+                        break;
+                    }
+                }
+
+                if !retry_descent {
+                    break;
+                }
+            }
+
+            if !skip_rep {
+                out[outpos] = item;
+                outpos += 1;
+                count -= 1;
+            }
+
+            rep += 1;
+        }
+
+        outpos
+    }
+
+    /// Choose `left` items of type `ty`, where the item in each
+    /// position is chosen independently of the other positions
+    /// (`crush_choose_indep`).
+    #[allow(clippy::too_many_arguments)]
+    fn choose_indep(
+        &mut self,
+        bucket: &'a Bucket,
+        mut left: usize,
+        numrep: i32,
+        ty: i32,
+        out: &mut [i32],
+        outpos: usize,
+        tries: u32,
+        recurse_tries: u32,
+        recurse_to_leaf: bool,
+        mut out2: Option<&mut [i32]>,
+        parent_r: i32,
+    ) {
+        let map = self.map;
+        let endpos = outpos + left;
+
+        // Initially, the result is undefined.
+        for rep in outpos..endpos {
+            out[rep] = ITEM_UNDEF;
+            if let Some(out2) = out2.as_deref_mut() {
+                out2[rep] = ITEM_UNDEF;
+            }
+        }
+
+        let mut ftotal = 0;
+        while left > 0 && ftotal < tries {
+            for rep in outpos..endpos {
+                if out[rep] != ITEM_UNDEF {
+                    continue;
+                }
+
+                let mut current = bucket;
+
+                // Choose through intervening buckets.
+                loop {
+                    // The choice is based on the position, even in
+                    // the nested call, so that a bucket that was chosen
+                    // in a different position will tend to give a
+                    // different item.
+                    let size = current.items.len();
+                    let mut r = rep as i32 + parent_r;
+
+                    if matches!(current.kind, BucketKind::Uniform { .. })
+                        && size.is_multiple_of(numrep as usize)
+                    {
+                        r += (numrep + 1) * ftotal as i32;
+                    } else {
+                        r += numrep * ftotal as i32;
+                    }
+
+                    if size == 0 {
+                        break;
+                    }
+
+                    let item = self.bucket_choose(current, r, outpos);
+                    let item_type = self.item_type(item);
+
+                    let bad_item = item >= map.max_devices
+                        || item_type.is_none()
+                        || (item_type != Some(ty) && item >= 0);
+
+                    if bad_item {
+                        out[rep] = ITEM_NONE;
+                        if let Some(out2) = out2.as_deref_mut() {
+                            out2[rep] = ITEM_NONE;
+                        }
+                        left -= 1;
+                        break;
+                    }
+
+                    // Keep going?
+                    if item_type != Some(ty) {
+                        current = map.bucket(item).unwrap();
+                        continue;
+                    }
+
+                    if out[outpos..endpos].contains(&item) {
+                        break;
+                    }
+
+                    if recurse_to_leaf {
+                        let out2 = out2
+                            .as_deref_mut()
+                            .expect("Recursing to leaves requires a leaf output");
+
+                        if let Some(leaf_bucket) = map.bucket(item) {
+                            self.choose_indep(
+                                leaf_bucket,
+                                1,
+                                numrep,
+                                0,
+                                out2,
+                                rep,
+                                recurse_tries,
+                                0,
+                                false,
+                                None,
+                                r,
+                            );
+
+                            // Placed nothing, so there is no leaf.
+                            if out2[rep] == ITEM_NONE {
+                                break;
+                            }
+                        } else {
+                            // We already have a leaf!
+                            out2[rep] = item;
+                        }
+                    }
+
+                    if item_type == Some(0) && self.is_out(item) {
+                        break;
+                    }
+
+                    out[rep] = item;
+                    left -= 1;
+                    break;
+                }
+            }
+
+            ftotal += 1;
+        }
+
+        for rep in outpos..endpos {
+            if out[rep] == ITEM_UNDEF {
+                out[rep] = ITEM_NONE;
+            }
+
+            if let Some(out2) = out2.as_deref_mut()
+                && out2[rep] == ITEM_UNDEF
+            {
+                out2[rep] = ITEM_NONE;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn all_in() -> Vec<u32> {
+    vec![0x10000; 4]
+}
+
+#[test]
+fn replicated_rule() {
+    let map = super::crush_map();
+    let weights = all_in();
+
+    for x in 0..1000 {
+        let result = map.do_rule(0, x, 3, &weights, None);
+
+        // One OSD from each host.
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().any(|o| *o == 0 || *o == 1));
+        assert!(result.iter().any(|o| *o == 2 || *o == 3));
+        assert_eq!(result, map.do_rule(0, x, 3, &weights, None));
+    }
+}
+
+#[test]
+fn replicated_rule_out() {
+    let map = super::crush_map();
+    let mut weights = all_in();
+    weights[1] = 0;
+    weights[3] = 0x8000;
+
+    let mut counts = [0; 4];
+
+    for x in 0..2000 {
+        for osd in map.do_rule(0, x, 2, &weights, None) {
+            counts[osd as usize] += 1;
+        }
+    }
+
+    assert_eq!(counts[0], 2000);
+    assert_eq!(counts[1], 0);
+    // `osd.3` is out for about half of the inputs.
+    assert_eq!(counts[2] + counts[3], 2000);
+    assert!((1400..1600).contains(&counts[2]), "{counts:?}");
+}
+
+#[test]
+fn known_mappings() {
+    let map = super::crush_map();
+    let weights = all_in();
+    let mut some_out = all_in();
+    some_out[1] = 0;
+    some_out[3] = 0x8000;
+
+    // Computed with Ceph's `crush_do_rule` for the same map.
+    let known: [(u32, &[i32], &[i32]); 8] = [
+        (0, &[3, 0], &[0, 2]),
+        (1, &[0, 2], &[0, 2]),
+        (2, &[1, 3], &[0, 2]),
+        (3, &[3, 1], &[2, 0]),
+        (4, &[1, 3], &[0, 3]),
+        (5, &[3, 0], &[2, 0]),
+        (6, &[1, 2], &[2, 0]),
+        (7, &[1, 2], &[2, 0]),
+    ];
+
+    for (x, up, up_some_out) in known {
+        assert_eq!(map.do_rule(0, x, 3, &weights, None), up, "{x}");
+        assert_eq!(map.do_rule(0, x, 3, &some_out, None), up_some_out, "{x}");
+    }
+
+    // Object `foo` in pool 1 with 8 PGs and `HASHPSPOOL` maps
+    // to PG `1.6`, which maps to `[1, 3]`.
+    let seed = hash::str_hash_rjenkins(b"foo");
+    let ps = crate::osd_map::stable_mod(seed, 8, 7);
+    assert_eq!(ps, 6);
+
+    let pps = hash::hash32_2(ps, 1);
+    assert_eq!(pps, 0xd77484f9);
+    assert_eq!(map.do_rule(0, pps, 3, &weights, None), [1, 3]);
+}
+
+#[test]
+fn erasure_rule() {
+    let map = super::crush_map();
+    let mut weights = all_in();
+    let mut moved = 0;
+
+    for x in 0..1000 {
+        weights[2] = 0x10000;
+        let result = map.do_rule(1, x, 3, &weights, None);
+
+        assert_eq!(result.len(), 3);
+        assert!(result.iter().all(|o| (0..4).contains(o)));
+        assert!((1..3).all(|i| !result[..i].contains(&result[i])));
+
+        weights[2] = 0;
+        let without = map.do_rule(1, x, 3, &weights, None);
+        assert!(!without.contains(&2));
+
+        moved += result
+            .iter()
+            .zip(&without)
+            .filter(|(a, b)| **a != 2 && a != b)
+            .count();
+    }
+
+    // Other positions are mostly not affected by an OSD going out.
+    assert!(moved < 200, "{moved}");
+}
+
+#[test]
+fn erasure_rule_not_enough() {
+    let map = super::crush_map();
+    let weights = [0x10000, 0x10000];
+
+    let result = map.do_rule(1, 1, 3, &weights, None);
+    assert_eq!(result.iter().filter(|o| **o == ITEM_NONE).count(), 1);
+}
+
+#[test]
+fn bucket_kinds() {
+    let kinds = [
+        BucketKind::Uniform {
+            item_weight: 0x10000,
+        },
+        BucketKind::List {
+            item_weights: vec![0x10000; 4],
+            sum_weights: vec![0x10000, 0x20000, 0x30000, 0x40000],
+        },
+        BucketKind::Tree {
+            node_weights: vec![
+                0, 0x10000, 0x20000, 0x10000, 0x40000, 0x10000, 0x20000, 0x10000,
+            ],
+        },
+        BucketKind::Straw {
+            item_weights: vec![0x10000; 4],
+            straws: vec![0x10000; 4],
+        },
+    ];
+
+    for kind in kinds {
+        let mut map = super::crush_map();
+        map.buckets[0] = Some(Bucket {
+            id: -1,
+            ty: 10,
+            hash: 0,
+            weight: 0x40000,
+            items: vec![0, 1, 2, 3],
+            kind: kind.clone(),
+        });
+
+        let take = |op, arg1, arg2| RuleStep { op, arg1, arg2 };
+        map.rules[0].as_mut().unwrap().steps = vec![
+            take(RuleStep::TAKE, -1, 0),
+            take(RuleStep::CHOOSE_FIRSTN, 0, 0),
+            take(RuleStep::EMIT, 0, 0),
+        ];
+
+        let mut weights = all_in();
+        weights[3] = 0;
+        let mut counts = [0; 4];
+
+        for x in 0..100 {
+            let result = map.do_rule(0, x, 3, &weights, None);
+
+            assert_eq!(result.len(), 3, "{kind:?}");
+            for osd in result {
+                counts[osd as usize] += 1;
+            }
+        }
+
+        assert_eq!(counts, [100, 100, 100, 0], "{kind:?}");
+    }
+}
+
+#[test]
+fn choose_args() {
+    let mut map = super::crush_map();
+    let weights = all_in();
+
+    // Never choose `osd.0` in host `a` for pool 2.
+    let arg = ChooseArg {
+        weight_set: vec![vec![0, 0x10000]],
+        ids: Vec::new(),
+    };
+    map.choose_args.insert(2, HashMap::from([(1, arg)]));
+
+    assert!(map.choose_args_for(1).is_none());
+    let args = map.choose_args_for(2);
+
+    for x in 0..100 {
+        let result = map.do_rule(0, x, 2, &weights, args);
+        assert!(result.contains(&1));
+    }
+}
@@ -0,0 +1,698 @@
+//! CRUSH maps (`CrushWrapper`).
+
+use std::collections::HashMap;
+
+mod hash;
+mod ln;
+mod mapper;
+
+use ceph_foundation::{Decode, DecodeError, Encode, Encoder};
+
+pub use hash::{HASH_RJENKINS1, hash32_2, hash32_3, hash32_4, str_hash_linux, str_hash_rjenkins};
+pub use mapper::DEFAULT_CHOOSE_ARGS;
+
+/// The magic value at the start of an encoded [`CrushMap`].
+const MAGIC: u32 = 0x00010000;
+
+/// An item that does not exist.
+pub const ITEM_NONE: i32 = 0x7fffffff;
+
+/// The algorithm of a [`Bucket`], and the data that is
+/// specific to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BucketKind {
+    /// All items have the same weight.
+    Uniform {
+        item_weight: u32,
+    },
+    List {
+        item_weights: Vec<u32>,
+        sum_weights: Vec<u32>,
+    },
+    Tree {
+        node_weights: Vec<u32>,
+    },
+    Straw {
+        item_weights: Vec<u32>,
+        straws: Vec<u32>,
+    },
+    Straw2 {
+        item_weights: Vec<u32>,
+    },
+}
+
+impl BucketKind {
+    pub const UNIFORM: u8 = 1;
+    pub const LIST: u8 = 2;
+    pub const TREE: u8 = 3;
+    pub const STRAW: u8 = 4;
+    pub const STRAW2: u8 = 5;
+
+    /// The algorithm identifier of this kind of bucket.
+    pub fn alg(&self) -> u8 {
+        match self {
+            BucketKind::Uniform { .. } => Self::UNIFORM,
+            BucketKind::List { .. } => Self::LIST,
+            BucketKind::Tree { .. } => Self::TREE,
+            BucketKind::Straw { .. } => Self::STRAW,
+            BucketKind::Straw2 { .. } => Self::STRAW2,
+        }
+    }
+}
+
+/// A bucket in a [`CrushMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    /// The ID of this bucket, which is always negative.
+    pub id: i32,
+    /// The type of this bucket (see [`CrushMap::type_map`]).
+    pub ty: u16,
+    pub hash: u8,
+    /// The total weight of this bucket, in 16.16 fixed point.
+    pub weight: u32,
+    pub items: Vec<i32>,
+    pub kind: BucketKind,
+}
+
+impl Bucket {
+    fn decode(alg: u8, buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let id = Decode::decode(buffer)?;
+        let ty = Decode::decode(buffer)?;
+        let [_alg, hash] = Decode::decode(buffer)?;
+        let weight = Decode::decode(buffer)?;
+        let size = u32::decode(buffer)?;
+
+        let items = (0..size)
+            .map(|_| i32::decode(buffer))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let weights = |buffer: &mut &[u8]| {
+            (0..size)
+                .map(|_| u32::decode(buffer))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let weight_pairs = |buffer: &mut &[u8]| {
+            let mut first = Vec::with_capacity(size as usize);
+            let mut second = Vec::with_capacity(size as usize);
+
+            for _ in 0..size {
+                first.push(u32::decode(buffer)?);
+                second.push(u32::decode(buffer)?);
+            }
+
+            Ok::<_, DecodeError>((first, second))
+        };
+
+        let kind = match alg {
+            BucketKind::UNIFORM => BucketKind::Uniform {
+                item_weight: Decode::decode(buffer)?,
+            },
+            BucketKind::LIST => {
+                let (item_weights, sum_weights) = weight_pairs(buffer)?;
+                BucketKind::List {
+                    item_weights,
+                    sum_weights,
+                }
+            }
+            BucketKind::TREE => {
+                let [num_nodes] = Decode::decode(buffer)?;
+                let node_weights = (0..num_nodes)
+                    .map(|_| u32::decode(buffer))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                BucketKind::Tree { node_weights }
+            }
+            BucketKind::STRAW => {
+                let (item_weights, straws) = weight_pairs(buffer)?;
+                BucketKind::Straw {
+                    item_weights,
+                    straws,
+                }
+            }
+            BucketKind::STRAW2 => BucketKind::Straw2 {
+                item_weights: weights(buffer)?,
+            },
+            _ => return Err(DecodeError::unknown_value("BucketKind", alg)),
+        };
+
+        Ok(Self {
+            id,
+            ty,
+            hash,
+            weight,
+            items,
+            kind,
+        })
+    }
+}
+
+impl Encode for Bucket {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let alg = self.kind.alg();
+
+        u32::from(alg).encode(buffer);
+        self.id.encode(buffer);
+        self.ty.encode(buffer);
+        buffer.extend_from_slice(&[alg, self.hash]);
+        self.weight.encode(buffer);
+        (self.items.len() as u32).encode(buffer);
+        self.items.iter().for_each(|item| item.encode(buffer));
+
+        match &self.kind {
+            BucketKind::Uniform { item_weight } => item_weight.encode(buffer),
+            BucketKind::List {
+                item_weights: first,
+                sum_weights: second,
+            }
+            | BucketKind::Straw {
+                item_weights: first,
+                straws: second,
+            } => {
+                for (first, second) in first.iter().zip(second) {
+                    first.encode(buffer);
+                    second.encode(buffer);
+                }
+            }
+            BucketKind::Tree { node_weights } => {
+                let num_nodes = u8::try_from(node_weights.len()).expect("Too many tree nodes");
+                buffer.push(num_nodes);
+                node_weights.iter().for_each(|w| w.encode(buffer));
+            }
+            BucketKind::Straw2 { item_weights } => {
+                item_weights.iter().for_each(|w| w.encode(buffer));
+            }
+        }
+    }
+}
+
+/// A step of a [`Rule`] (`crush_rule_step`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleStep {
+    pub op: u32,
+    pub arg1: i32,
+    pub arg2: i32,
+}
+
+impl RuleStep {
+    pub const NOOP: u32 = 0;
+    pub const TAKE: u32 = 1;
+    pub const CHOOSE_FIRSTN: u32 = 2;
+    pub const CHOOSE_INDEP: u32 = 3;
+    pub const EMIT: u32 = 4;
+    pub const CHOOSELEAF_FIRSTN: u32 = 6;
+    pub const CHOOSELEAF_INDEP: u32 = 7;
+    pub const SET_CHOOSE_TRIES: u32 = 8;
+    pub const SET_CHOOSELEAF_TRIES: u32 = 9;
+    pub const SET_CHOOSE_LOCAL_TRIES: u32 = 10;
+    pub const SET_CHOOSE_LOCAL_FALLBACK_TRIES: u32 = 11;
+    pub const SET_CHOOSELEAF_VARY_R: u32 = 12;
+    pub const SET_CHOOSELEAF_STABLE: u32 = 13;
+}
+
+ceph_foundation::write_decode_encode!(RuleStep = op | arg1 | arg2);
+
+/// A placement rule in a [`CrushMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// The legacy ruleset of this rule, which is equal to its ID.
+    pub ruleset: u8,
+    /// The type of pool that this rule is for.
+    pub ty: u8,
+    pub min_size: u8,
+    pub max_size: u8,
+    pub steps: Vec<RuleStep>,
+}
+
+impl Decode<'_> for Rule {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = u32::decode(buffer)?;
+        let [ruleset, ty, min_size, max_size] = Decode::decode(buffer)?;
+        let steps = (0..len)
+            .map(|_| RuleStep::decode(buffer))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            ruleset,
+            ty,
+            min_size,
+            max_size,
+            steps,
+        })
+    }
+}
+
+impl Encode for Rule {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        (self.steps.len() as u32).encode(buffer);
+        buffer.extend_from_slice(&[self.ruleset, self.ty, self.min_size, self.max_size]);
+        self.steps.iter().for_each(|step| step.encode(buffer));
+    }
+}
+
+/// The tunables of a [`CrushMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tunables {
+    pub choose_local_tries: u32,
+    pub choose_local_fallback_tries: u32,
+    pub choose_total_tries: u32,
+    pub chooseleaf_descend_once: u32,
+    pub chooseleaf_vary_r: u8,
+    pub straw_calc_version: u8,
+    /// A bitmask of the allowed [`BucketKind`] algorithms.
+    pub allowed_bucket_algs: u32,
+    pub chooseleaf_stable: u8,
+    /// The MSR tunables, which are only present in maps of
+    /// Squid and later.
+    pub msr: Option<(u32, u32)>,
+}
+
+impl Default for Tunables {
+    /// The legacy (argonaut) tunables, which are used for values
+    /// that are missing from an encoded map.
+    fn default() -> Self {
+        Self {
+            choose_local_tries: 2,
+            choose_local_fallback_tries: 5,
+            choose_total_tries: 19,
+            chooseleaf_descend_once: 0,
+            chooseleaf_vary_r: 0,
+            straw_calc_version: 0,
+            allowed_bucket_algs: (1 << BucketKind::UNIFORM)
+                | (1 << BucketKind::LIST)
+                | (1 << BucketKind::STRAW),
+            chooseleaf_stable: 0,
+            msr: None,
+        }
+    }
+}
+
+/// Alternative weights and IDs for the items of a bucket,
+/// used instead of the ones in the [`Bucket`] itself
+/// (`crush_choose_arg`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChooseArg {
+    /// Item weights, for each position in the result.
+    pub weight_set: Vec<Vec<u32>>,
+    pub ids: Vec<i32>,
+}
+
+impl Decode<'_> for ChooseArg {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            weight_set: Decode::decode(buffer)?,
+            ids: Decode::decode(buffer)?,
+        })
+    }
+}
+
+impl Encode for ChooseArg {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        (self.weight_set.len() as u32).encode(buffer);
+        self.weight_set.iter().for_each(|w| w.encode(buffer));
+        self.ids.encode(buffer);
+    }
+}
+
+/// A CRUSH map (`CrushWrapper`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrushMap {
+    pub max_devices: i32,
+    /// The buckets of this map, where the bucket with ID `id`
+    /// is at index `-1 - id`.
+    pub buckets: Vec<Option<Bucket>>,
+    /// The rules of this map, indexed by rule ID.
+    pub rules: Vec<Option<Rule>>,
+    /// The names of the bucket types.
+    pub type_map: HashMap<i32, String>,
+    /// The names of the buckets and devices.
+    pub name_map: HashMap<i32, String>,
+    /// The names of the rules.
+    pub rule_name_map: HashMap<i32, String>,
+    pub tunables: Tunables,
+    /// The device class of each device.
+    pub class_map: HashMap<i32, i32>,
+    /// The names of the device classes.
+    pub class_name: HashMap<i32, String>,
+    /// The shadow bucket of each bucket for each device class.
+    pub class_bucket: HashMap<i32, HashMap<i32, i32>>,
+    /// Sets of alternative weights, keyed by the index of the set
+    /// and then by bucket index.
+    pub choose_args: HashMap<i64, HashMap<u32, ChooseArg>>,
+}
+
+impl CrushMap {
+    /// Get the bucket with `id`.
+    pub fn bucket(&self, id: i32) -> Option<&Bucket> {
+        let index = usize::try_from(-1 - id).ok()?;
+        self.buckets.get(index)?.as_ref()
+    }
+
+    /// Get the rule with `id`.
+    pub fn rule(&self, id: u32) -> Option<&Rule> {
+        self.rules.get(id as usize)?.as_ref()
+    }
+
+    /// Find the ID of the rule called `name`.
+    pub fn rule_id(&self, name: &str) -> Option<i32> {
+        self.rule_name_map
+            .iter()
+            .find_map(|(id, n)| (n == name).then_some(*id))
+    }
+}
+
+impl Decode<'_> for CrushMap {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let magic = u32::decode(buffer)?;
+        if magic != MAGIC {
+            return Err(DecodeError::Custom(format!(
+                "Invalid CRUSH map magic 0x{magic:08x}"
+            )));
+        }
+
+        let max_buckets = i32::decode(buffer)?;
+        let max_rules = u32::decode(buffer)?;
+        let max_devices = i32::decode(buffer)?;
+
+        let mut buckets = Vec::new();
+        for _ in 0..max_buckets {
+            let alg = u32::decode(buffer)?;
+
+            if alg == 0 {
+                buckets.push(None);
+                continue;
+            }
+
+            let alg =
+                u8::try_from(alg).map_err(|_| DecodeError::unknown_value("BucketKind", alg))?;
+            buckets.push(Some(Bucket::decode(alg, buffer)?));
+        }
+
+        let mut rules = Vec::new();
+        for _ in 0..max_rules {
+            if u32::decode(buffer)? == 0 {
+                rules.push(None);
+            } else {
+                rules.push(Some(Rule::decode(buffer)?));
+            }
+        }
+
+        let type_map = Decode::decode(buffer)?;
+        let name_map = Decode::decode(buffer)?;
+        let rule_name_map = Decode::decode(buffer)?;
+
+        // Later additions are only present if there is data left.
+        let mut tunables = Tunables::default();
+
+        if !buffer.is_empty() {
+            tunables.choose_local_tries = Decode::decode(buffer)?;
+            tunables.choose_local_fallback_tries = Decode::decode(buffer)?;
+            tunables.choose_total_tries = Decode::decode(buffer)?;
+        }
+
+        if !buffer.is_empty() {
+            tunables.chooseleaf_descend_once = Decode::decode(buffer)?;
+        }
+
+        if !buffer.is_empty() {
+            [tunables.chooseleaf_vary_r] = Decode::decode(buffer)?;
+        }
+
+        if !buffer.is_empty() {
+            [tunables.straw_calc_version] = Decode::decode(buffer)?;
+        }
+
+        if !buffer.is_empty() {
+            tunables.allowed_bucket_algs = Decode::decode(buffer)?;
+        }
+
+        if !buffer.is_empty() {
+            [tunables.chooseleaf_stable] = Decode::decode(buffer)?;
+        }
+
+        let (class_map, class_name, class_bucket) = if !buffer.is_empty() {
+            (
+                Decode::decode(buffer)?,
+                Decode::decode(buffer)?,
+                Decode::decode(buffer)?,
+            )
+        } else {
+            Default::default()
+        };
+
+        let choose_args = if !buffer.is_empty() {
+            Decode::decode(buffer)?
+        } else {
+            Default::default()
+        };
+
+        if !buffer.is_empty() {
+            tunables.msr = Some(Decode::decode(buffer)?);
+        }
+
+        Ok(Self {
+            max_devices,
+            buckets,
+            rules,
+            type_map,
+            name_map,
+            rule_name_map,
+            tunables,
+            class_map,
+            class_name,
+            class_bucket,
+            choose_args,
+        })
+    }
+}
+
+impl Encode for CrushMap {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        MAGIC.encode(buffer);
+        (self.buckets.len() as i32).encode(buffer);
+        (self.rules.len() as u32).encode(buffer);
+        self.max_devices.encode(buffer);
+
+        for bucket in &self.buckets {
+            match bucket {
+                Some(bucket) => bucket.encode(buffer),
+                None => 0u32.encode(buffer),
+            }
+        }
+
+        for rule in &self.rules {
+            match rule {
+                Some(rule) => {
+                    1u32.encode(buffer);
+                    rule.encode(buffer);
+                }
+                None => 0u32.encode(buffer),
+            }
+        }
+
+        self.type_map.encode(buffer);
+        self.name_map.encode(buffer);
+        self.rule_name_map.encode(buffer);
+
+        let tunables = &self.tunables;
+        tunables.choose_local_tries.encode(buffer);
+        tunables.choose_local_fallback_tries.encode(buffer);
+        tunables.choose_total_tries.encode(buffer);
+        tunables.chooseleaf_descend_once.encode(buffer);
+        buffer.push(tunables.chooseleaf_vary_r);
+        buffer.push(tunables.straw_calc_version);
+        tunables.allowed_bucket_algs.encode(buffer);
+        buffer.push(tunables.chooseleaf_stable);
+
+        self.class_map.encode(buffer);
+        self.class_name.encode(buffer);
+        self.class_bucket.encode(buffer);
+        self.choose_args.encode(buffer);
+
+        if let Some(msr) = &tunables.msr {
+            msr.encode(buffer);
+        }
+    }
+}
+
+/// A small map with a root, two hosts with two OSDs each, a
+/// replicated rule and an erasure coded rule.
+#[cfg(test)]
+pub(crate) fn crush_map() -> CrushMap {
+    let host = |id: i32, osds: [i32; 2]| Bucket {
+        id,
+        ty: 1,
+        hash: 0,
+        weight: 0x20000,
+        items: Vec::from(osds),
+        kind: BucketKind::Straw2 {
+            item_weights: vec![0x10000; 2],
+        },
+    };
+
+    let root = Bucket {
+        id: -1,
+        ty: 10,
+        hash: 0,
+        weight: 0x40000,
+        items: vec![-2, -3],
+        kind: BucketKind::Straw2 {
+            item_weights: vec![0x20000; 2],
+        },
+    };
+
+    let step = |op, arg1, arg2| RuleStep { op, arg1, arg2 };
+
+    let replicated = Rule {
+        ruleset: 0,
+        ty: 1,
+        min_size: 1,
+        max_size: 10,
+        steps: vec![
+            step(RuleStep::TAKE, -1, 0),
+            step(RuleStep::CHOOSELEAF_FIRSTN, 0, 1),
+            step(RuleStep::EMIT, 0, 0),
+        ],
+    };
+
+    let erasure = Rule {
+        ruleset: 1,
+        ty: 3,
+        min_size: 3,
+        max_size: 3,
+        steps: vec![
+            step(RuleStep::SET_CHOOSELEAF_TRIES, 5, 0),
+            step(RuleStep::SET_CHOOSE_TRIES, 100, 0),
+            step(RuleStep::TAKE, -1, 0),
+            step(RuleStep::CHOOSE_INDEP, 0, 0),
+            step(RuleStep::EMIT, 0, 0),
+        ],
+    };
+
+    let names = |names: &[(i32, &str)]| {
+        names
+            .iter()
+            .map(|(id, name)| (*id, name.to_string()))
+            .collect()
+    };
+
+    CrushMap {
+        max_devices: 4,
+        buckets: vec![Some(root), Some(host(-2, [0, 1])), Some(host(-3, [2, 3]))],
+        rules: vec![Some(replicated), Some(erasure)],
+        type_map: names(&[(0, "osd"), (1, "host"), (10, "root")]),
+        name_map: names(&[
+            (-1, "default"),
+            (-2, "a"),
+            (-3, "b"),
+            (0, "osd.0"),
+            (1, "osd.1"),
+            (2, "osd.2"),
+            (3, "osd.3"),
+        ]),
+        rule_name_map: names(&[(0, "replicated_rule"), (1, "ec")]),
+        tunables: Tunables {
+            choose_local_tries: 0,
+            choose_local_fallback_tries: 0,
+            choose_total_tries: 50,
+            chooseleaf_descend_once: 1,
+            chooseleaf_vary_r: 1,
+            straw_calc_version: 1,
+            allowed_bucket_algs: 54,
+            chooseleaf_stable: 1,
+            msr: None,
+        },
+        class_map: HashMap::new(),
+        class_name: HashMap::new(),
+        class_bucket: HashMap::new(),
+        choose_args: HashMap::new(),
+    }
+}
+
+#[test]
+fn roundtrip() {
+    let mut map = crush_map();
+
+    let encoded = map.to_vec();
+    assert_eq!(encoded[..4], MAGIC.to_le_bytes());
+    assert_eq!(CrushMap::decode(&mut encoded.as_slice()).unwrap(), map);
+
+    map.buckets.push(None);
+    map.buckets.push(Some(Bucket {
+        id: -5,
+        ty: 1,
+        hash: 0,
+        weight: 0x20000,
+        items: vec![0, 1],
+        kind: BucketKind::Uniform {
+            item_weight: 0x10000,
+        },
+    }));
+    map.buckets.push(Some(Bucket {
+        id: -6,
+        ty: 1,
+        hash: 0,
+        weight: 0x30000,
+        items: vec![0, 1],
+        kind: BucketKind::List {
+            item_weights: vec![0x10000, 0x20000],
+            sum_weights: vec![0x10000, 0x30000],
+        },
+    }));
+    map.buckets.push(Some(Bucket {
+        id: -7,
+        ty: 1,
+        hash: 0,
+        weight: 0x20000,
+        items: vec![2, 3],
+        kind: BucketKind::Tree {
+            node_weights: vec![0x10000, 0x20000, 0x10000],
+        },
+    }));
+    map.buckets.push(Some(Bucket {
+        id: -8,
+        ty: 1,
+        hash: 0,
+        weight: 0x20000,
+        items: vec![2, 3],
+        kind: BucketKind::Straw {
+            item_weights: vec![0x10000; 2],
+            straws: vec![0x10000; 2],
+        },
+    }));
+    map.rules.push(None);
+    map.class_map.insert(0, 1);
+    map.class_name.insert(1, "ssd".to_string());
+    map.class_bucket.insert(-1, HashMap::from([(1, -9)]));
+    map.choose_args.insert(
+        -1,
+        HashMap::from([(
+            0,
+            ChooseArg {
+                weight_set: vec![vec![0x10000, 0x30000]],
+                ids: vec![],
+            },
+        )]),
+    );
+    map.tunables.msr = Some((100, 100));
+
+    let encoded = map.to_vec();
+    let decoded = CrushMap::decode(&mut encoded.as_slice()).unwrap();
+    assert_eq!(decoded, map);
+    assert_eq!(decoded.bucket(-8).unwrap().kind.alg(), BucketKind::STRAW);
+    assert!(decoded.bucket(-4).is_none());
+    assert_eq!(decoded.rule_id("ec"), Some(1));
+}
+
+#[test]
+fn legacy_tunables() {
+    let mut map = crush_map();
+    map.tunables = Tunables::default();
+
+    // Drop everything after the rule names.
+    let mut encoded = map.to_vec();
+    let tunables_len = 4 * 4 + 2 + 4 + 1 + 4 * 4;
+    encoded.truncate(encoded.len() - tunables_len);
+
+    assert_eq!(CrushMap::decode(&mut encoded.as_slice()).unwrap(), map);
+}
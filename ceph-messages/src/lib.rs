@@ -1,18 +1,32 @@
+mod auth;
 mod config;
+pub mod crush;
 mod message;
+mod mon_command;
 mod mon_map;
 mod mon_sub;
 mod osd_map;
+mod osd_op;
 
 use ceph_foundation::DecodeError;
 
+pub use auth::{MessageAuth, MessageAuthReply};
 pub use config::Config;
 pub use message::CephMessage;
-pub use mon_map::MonMap;
-pub use mon_sub::{MonSubscribe, MonSubscribeItem};
-pub use osd_map::MessageOsdMap;
+pub use mon_command::{MonCommand, MonCommandAck, PaxosServiceHeader};
+pub use mon_map::{MonFeatures, MonMap};
+pub use mon_sub::{MonSubscribe, MonSubscribeAck, MonSubscribeItem};
+pub use osd_map::{
+    ApplyIncrementalError, ByteArrayEncoded, EVersion, MessageOsdMap, Opaque, OsdInfo, OsdMap,
+    OsdMapIncremental, OsdState, OsdXInfo, PgId, PgMergeMeta, Pool, PoolFlags, PoolId, PoolMax,
+    PoolSnapInfo, PoolType, stable_mod,
+};
+pub use osd_op::{
+    MessageOsdOp, MessageOsdOpReply, ObjectLocator, OsdOp, OsdOpArgs, OsdOpFlags, OsdReqId,
+    RequestRedirect, SpgId,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Epoch(pub u32);
 
 impl ceph_foundation::Encode for Epoch {
@@ -45,6 +59,9 @@ pub trait DecodeMessage<'a>: Sized {
     fn decode_message(segments: &[&'a [u8]]) -> Result<Self, DecodeMessageError>;
 }
 
-pub trait EncodeMessage<'a>: Sized {
-    fn encode_message(output_segments: &mut Vec<Vec<u8>>);
+pub trait EncodeMessage {
+    /// Encode this message into its front, middle and data segments.
+    ///
+    /// Trailing empty segments are omitted.
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>);
 }
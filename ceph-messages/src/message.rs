@@ -43,12 +43,16 @@ msg_type! {
     StatFs = 13,
     StatFsReply = 14,
     MonSubscribe(MonSubscribe) = 15,
-    MonSubscribeAck = 16,
-    Auth = 17,
-    AuthReply = 18,
+    MonSubscribeAck(MonSubscribeAck) = 16,
+    Auth(MessageAuth) = 17,
+    AuthReply(MessageAuthReply) = 18,
     MonGetVersion = 19,
     MonGetVersionReply = 20,
     OsdMap(MessageOsdMap) = 41,
+    OsdOp(MessageOsdOp) = 42,
+    OsdOpReply(MessageOsdOpReply) = 43,
+    MonCommand(MonCommand) = 50,
+    MonCommandAck(MonCommandAck) = 51,
     GetPoolStats = 58,
     GetPoolStatsReply = 59,
     Config(Config) = 62,
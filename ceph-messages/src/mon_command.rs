@@ -0,0 +1,174 @@
+use ceph_foundation::{Decode, Encode, Uuid, write_decode_encode};
+
+use crate::{DecodeMessage, DecodeMessageError, EncodeMessage};
+
+/// The header of messages that are handled by a paxos
+/// service of the monitors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaxosServiceHeader {
+    pub version: u64,
+    pub deprecated_session_mon: i16,
+    pub deprecated_session_mon_tid: u64,
+}
+
+impl Default for PaxosServiceHeader {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            deprecated_session_mon: -1,
+            deprecated_session_mon_tid: 0,
+        }
+    }
+}
+
+write_decode_encode!(
+    PaxosServiceHeader = version | deprecated_session_mon | deprecated_session_mon_tid
+);
+
+/// Split `segments` into the front and data segments of a message.
+pub(crate) fn front_and_data<'a>(
+    segments: &[&'a [u8]],
+) -> Result<(&'a [u8], &'a [u8]), DecodeMessageError> {
+    match segments {
+        [] => Err(DecodeMessageError::NotEnoughSegments { have: 0, need: 1 }),
+        [front] | [front, _] => Ok((front, &[])),
+        [front, _, data] => Ok((front, data)),
+        _ => Err(DecodeMessageError::TooManySegments {
+            have: segments.len(),
+            want: 3,
+        }),
+    }
+}
+
+pub(crate) fn push_front_and_data(output_segments: &mut Vec<Vec<u8>>, front: Vec<u8>, data: &[u8]) {
+    output_segments.push(front);
+
+    if !data.is_empty() {
+        output_segments.push(Vec::new());
+        output_segments.push(data.to_vec());
+    }
+}
+
+/// A command for the monitors (`MMonCommand`).
+///
+/// `cmd` usually consists of a single JSON object, such
+/// as `{"prefix": "osd lspools", "format": "json"}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonCommand {
+    pub paxos: PaxosServiceHeader,
+    pub fsid: Uuid,
+    pub cmd: Vec<String>,
+    /// Input for the command, carried in the data segment.
+    pub input: Vec<u8>,
+}
+
+impl DecodeMessage<'_> for MonCommand {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        let (mut front, input) = front_and_data(segments)?;
+        let front = &mut front;
+
+        Ok(Self {
+            paxos: Decode::decode(front)?,
+            fsid: Decode::decode(front)?,
+            cmd: Decode::decode(front)?,
+            input: input.to_vec(),
+        })
+    }
+}
+
+impl EncodeMessage for MonCommand {
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>) {
+        let mut front = Vec::new();
+        self.paxos.encode(&mut front);
+        self.fsid.encode(&mut front);
+        self.cmd.encode(&mut front);
+
+        push_front_and_data(output_segments, front, &self.input);
+    }
+}
+
+/// The reply to a [`MonCommand`] (`MMonCommandAck`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonCommandAck {
+    pub paxos: PaxosServiceHeader,
+    /// The result of the command: zero or a negative errno.
+    pub result: i32,
+    pub status: String,
+    pub cmd: Vec<String>,
+    /// Output of the command, carried in the data segment.
+    pub output: Vec<u8>,
+}
+
+impl DecodeMessage<'_> for MonCommandAck {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        let (mut front, output) = front_and_data(segments)?;
+        let front = &mut front;
+
+        Ok(Self {
+            paxos: Decode::decode(front)?,
+            result: Decode::decode(front)?,
+            status: Decode::decode(front)?,
+            cmd: Decode::decode(front)?,
+            output: output.to_vec(),
+        })
+    }
+}
+
+impl EncodeMessage for MonCommandAck {
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>) {
+        let mut front = Vec::new();
+        self.paxos.encode(&mut front);
+        self.result.encode(&mut front);
+        self.status.encode(&mut front);
+        self.cmd.encode(&mut front);
+
+        push_front_and_data(output_segments, front, &self.output);
+    }
+}
+
+#[test]
+fn mon_command() {
+    let command = MonCommand {
+        paxos: PaxosServiceHeader::default(),
+        fsid: Uuid([7; 16]),
+        cmd: vec![r#"{"prefix": "status"}"#.to_string()],
+        input: Vec::new(),
+    };
+
+    let mut segments = Vec::new();
+    command.encode_message(&mut segments);
+    assert_eq!(segments.len(), 1);
+
+    let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+    assert_eq!(MonCommand::decode_message(&segments).unwrap(), command);
+}
+
+#[test]
+fn mon_command_ack() {
+    #[rustfmt::skip]
+    let front = [
+        0, 0, 0, 0, 0, 0, 0, 0, // Version
+        255, 255, // Deprecated session mon
+        0, 0, 0, 0, 0, 0, 0, 0, // Deprecated session mon tid
+        254, 255, 255, 255, // Result
+        2, 0, 0, 0, b'n', b'o', // Status
+        1, 0, 0, 0, // Command len
+            1, 0, 0, 0, b'x',
+    ];
+
+    let ack = MonCommandAck::decode_message(&[&front, &[], b"output"]).unwrap();
+
+    let expected = MonCommandAck {
+        paxos: PaxosServiceHeader::default(),
+        result: -2,
+        status: "no".to_string(),
+        cmd: vec!["x".to_string()],
+        output: b"output".to_vec(),
+    };
+
+    assert_eq!(ack, expected);
+
+    let mut segments = Vec::new();
+    expected.encode_message(&mut segments);
+    assert_eq!(segments, [front.to_vec(), Vec::new(), b"output".to_vec()]);
+}
@@ -90,11 +90,44 @@ impl DecodeMessage<'_> for MonMap {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Encodes the map itself. In an `MMonMap`, the encoded map is carried
+/// as a length-prefixed bufferlist.
+impl Encode for MonMap {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 9, 6);
+        self.fsid.encode(buffer);
+        self.epoch.encode(buffer);
+        self.last_changed.encode(buffer);
+        self.created.encode(buffer);
+        self.persistent_features.encode(buffer);
+        self.optional_features.encode(buffer);
+        self.mon_info.encode(buffer);
+        self.ranks.encode(buffer);
+        self.min_mon_release.encode(buffer);
+        self.removed_ranks.encode(buffer);
+        self.strategy.encode(buffer);
+        self.disallowed_leaders.encode(buffer);
+        self.stretch_mode_enabled.encode(buffer);
+        self.tiebreaker_mon.encode(buffer);
+        self.stretch_marked_down_mons.encode(buffer);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct MonFeatures {
     value: u64,
 }
 
+impl MonFeatures {
+    pub fn new(value: u64) -> Self {
+        Self { value }
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
 impl Encode for MonFeatures {
     fn encode(&self, buffer: &mut impl Encoder) {
         let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 1, 1);
@@ -147,7 +180,11 @@ fn mon_map_9_6() {
         0, 0, 0, 0, 0, 0, 0, 0,
     ];
 
-    let mon_map = MonMap::decode_message(&[&data]).unwrap();
+    // `MMonMap` carries the encoded map as a length-prefixed bufferlist.
+    let mut segment = (data.len() as u32).to_le_bytes().to_vec();
+    segment.extend_from_slice(&data);
+
+    let mon_map = MonMap::decode_message(&[&segment]).unwrap();
 
     let ip = Ipv4Addr::new(10, 0, 1, 222);
     let addr = EntityAddress {
@@ -196,4 +233,14 @@ fn mon_map_9_6() {
     };
 
     assert_eq!(mon_map, expected);
+
+    // We always encode `time_added`.
+    let mut expected = expected;
+    for info in expected.mon_info.values_mut() {
+        info.time_added = Some(Timestamp::new(1, 2));
+    }
+
+    let mut segment = Vec::new();
+    expected.to_vec().as_slice().encode(&mut segment);
+    assert_eq!(MonMap::decode_message(&[&segment]).unwrap(), expected);
 }
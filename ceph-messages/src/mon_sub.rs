@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use ceph_foundation::{Decode, Encode, write_decode_encode};
+use ceph_foundation::{Decode, Encode, Uuid, write_decode_encode};
 
 use crate::{DecodeMessage, DecodeMessageError};
 
@@ -19,18 +19,28 @@ impl DecodeMessage<'_> for MonSubscribe {
             });
         }
 
-        Self::decode(&mut segments[0].as_ref()).map_err(Into::into)
+        Self::decode(&mut &*segments[0]).map_err(Into::into)
     }
 }
 
 write_decode_encode!(MonSubscribe = what | hostname);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MonSubscribeItem {
     pub start: u64,
     pub flags: u8,
 }
 
+impl MonSubscribeItem {
+    /// Only send the next version of the map, instead of
+    /// all future versions.
+    pub const ONETIME: u8 = 1;
+
+    pub fn is_onetime(&self) -> bool {
+        self.flags & Self::ONETIME != 0
+    }
+}
+
 impl Encode for MonSubscribeItem {
     fn encode(&self, buffer: &mut impl ceph_foundation::Encoder) {
         self.start.encode(buffer);
@@ -46,3 +56,47 @@ impl Decode<'_> for MonSubscribeItem {
         Ok(Self { start, flags })
     }
 }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonSubscribeAck {
+    /// The interval (in seconds) after which subscriptions
+    /// should be renewed.
+    pub interval: u32,
+    pub fsid: Uuid,
+}
+
+impl DecodeMessage<'_> for MonSubscribeAck {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        if segments.len() > 1 {
+            return Err(DecodeMessageError::TooManySegments {
+                have: segments.len(),
+                want: 1,
+            });
+        } else if segments.is_empty() {
+            return Err(DecodeMessageError::NotEnoughSegments { have: 0, need: 1 });
+        }
+
+        Self::decode(&mut &*segments[0]).map_err(Into::into)
+    }
+}
+
+write_decode_encode!(MonSubscribeAck = interval | fsid);
+
+#[test]
+fn mon_subscribe_ack() {
+    #[rustfmt::skip]
+    let data = [
+        44, 1, 0, 0, // Interval
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, // FSID
+    ];
+
+    let ack = MonSubscribeAck::decode_message(&[&data]).unwrap();
+
+    let expected = MonSubscribeAck {
+        interval: 300,
+        fsid: Uuid([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]),
+    };
+
+    assert_eq!(ack, expected);
+    assert_eq!(expected.to_vec(), data);
+}
@@ -0,0 +1,641 @@
+use std::collections::{HashMap, HashSet};
+
+use ceph_foundation::{
+    Decode, DecodeError, Timestamp, Uuid,
+    entity::{AddrVec, EntityAddress},
+};
+
+use crate::{Epoch, crush::CrushMap};
+
+use super::{OsdInfo, OsdMap, OsdState, OsdXInfo, PgId, Pool, PoolId, PoolMax};
+
+/// The default primary affinity of an OSD.
+const DEFAULT_PRIMARY_AFFINITY: u32 = 0x10000;
+
+/// The changes between two consecutive epochs of an [`OsdMap`]
+/// (`OSDMap::Incremental`).
+///
+/// Only the data that is relevant to clients and monitoring is
+/// decoded: the OSD-only data after `new_hb_front_up` is ignored.
+#[derive(Debug, Clone)]
+pub struct OsdMapIncremental {
+    pub fsid: Uuid,
+    pub epoch: Epoch,
+    pub modified: Timestamp,
+    /// The new `pool_max`, or `-1` if it did not change.
+    pub new_pool_max: i64,
+    /// The new flags, or `-1` if they did not change.
+    pub new_flags: i32,
+    /// An encoded full map that replaces the current map, if not empty.
+    pub fullmap: Vec<u8>,
+    /// An encoded CRUSH map that replaces the current one, if not empty.
+    pub crush: Vec<u8>,
+    /// The new `max_osd`, or `-1` if it did not change.
+    pub new_max_osd: i32,
+    pub new_pools: HashMap<PoolId, Pool>,
+    pub new_pool_names: HashMap<PoolId, String>,
+    pub old_pools: HashSet<PoolId>,
+    pub new_up_client: HashMap<i32, Vec<EntityAddress>>,
+    /// State bits to toggle for OSDs, where `0` toggles [`OsdState::UP`].
+    pub new_state: HashMap<i32, u32>,
+    pub new_weight: HashMap<i32, u32>,
+    /// New `pg_temp` entries, where an empty list removes the entry.
+    pub new_pg_temp: HashMap<PgId, Vec<i32>>,
+    /// New `primary_temp` entries, where `-1` removes the entry.
+    pub new_primary_temp: HashMap<PgId, i32>,
+    pub new_primary_affinity: HashMap<i32, u32>,
+    pub new_erasure_code_profiles: HashMap<String, HashMap<String, String>>,
+    pub old_erasure_code_profiles: Vec<String>,
+    pub new_pg_upmap: HashMap<PgId, Vec<i32>>,
+    pub old_pg_upmap: HashSet<PgId>,
+    pub new_pg_upmap_items: HashMap<PgId, Vec<(i32, i32)>>,
+    pub old_pg_upmap_items: HashSet<PgId>,
+    pub new_removed_snaps: HashMap<i64, HashMap<u64, u64>>,
+    pub new_purged_snaps: HashMap<i64, HashMap<u64, u64>>,
+    pub new_last_up_change: Timestamp,
+    pub new_last_in_change: Timestamp,
+    pub new_pg_upmap_primary: HashMap<PgId, i32>,
+    pub old_pg_upmap_primary: HashSet<PgId>,
+    pub new_hb_back_up: HashMap<i32, Vec<EntityAddress>>,
+    pub new_up_thru: HashMap<i32, Epoch>,
+    pub new_last_clean_interval: HashMap<i32, (Epoch, Epoch)>,
+    pub new_lost: HashMap<i32, Epoch>,
+    pub new_blocklist: HashMap<EntityAddress, Timestamp>,
+    pub old_blocklist: Vec<EntityAddress>,
+    pub new_up_cluster: HashMap<i32, Vec<EntityAddress>>,
+    pub cluster_snapshot: String,
+    pub new_uuid: HashMap<i32, Uuid>,
+    pub new_xinfo: HashMap<i32, OsdXInfo>,
+    pub new_hb_front_up: HashMap<i32, Vec<EntityAddress>>,
+}
+
+/// Decode a map of OSDs to `entity_addrvec_t`s.
+fn decode_addr_map(buffer: &mut &[u8]) -> Result<HashMap<i32, Vec<EntityAddress>>, DecodeError> {
+    HashMap::<i32, AddrVec>::decode(buffer)?
+        .into_iter()
+        .map(|(osd, addrs)| Ok((osd, Vec::try_from(addrs)?)))
+        .collect()
+}
+
+impl Decode<'_> for OsdMapIncremental {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (_, mut buffer) = ceph_foundation::get_versions_and_data!(OsdMapIncremental: buffer, 8);
+        let buffer = &mut buffer;
+
+        // Client-usable data
+        let (version, mut client) =
+            ceph_foundation::get_versions_and_data!(OsdMapIncremental: buffer, 9);
+        let client = &mut client;
+
+        if version < 6 {
+            return Err(DecodeError::UnexpectedVersion {
+                ty: "OsdMapIncremental.client",
+                got: version,
+                expected: 6..=9,
+            });
+        }
+
+        let fsid = Decode::decode(client)?;
+        let epoch = Decode::decode(client)?;
+        let modified = Decode::decode(client)?;
+        let new_pool_max = Decode::decode(client)?;
+        let new_flags = Decode::decode(client)?;
+        let fullmap = Decode::decode(client)?;
+        let crush = Decode::decode(client)?;
+        let new_max_osd = Decode::decode(client)?;
+        let new_pools = Decode::decode(client)?;
+        let new_pool_names = Decode::decode(client)?;
+        let old_pools = Decode::decode(client)?;
+        let new_up_client = decode_addr_map(client)?;
+        let new_state = Decode::decode(client)?;
+        let new_weight = Decode::decode(client)?;
+        let new_pg_temp = Decode::decode(client)?;
+        let new_primary_temp = Decode::decode(client)?;
+        let new_primary_affinity = Decode::decode(client)?;
+        let new_erasure_code_profiles = Decode::decode(client)?;
+        let old_erasure_code_profiles = Decode::decode(client)?;
+        let new_pg_upmap = Decode::decode(client)?;
+        let old_pg_upmap = Decode::decode(client)?;
+        let new_pg_upmap_items = Decode::decode(client)?;
+        let old_pg_upmap_items = Decode::decode(client)?;
+        let new_removed_snaps = Decode::decode(client)?;
+        let new_purged_snaps = Decode::decode(client)?;
+
+        let (new_last_up_change, new_last_in_change) = if version >= 8 {
+            (Decode::decode(client)?, Decode::decode(client)?)
+        } else {
+            Default::default()
+        };
+
+        let (new_pg_upmap_primary, old_pg_upmap_primary) = if version >= 9 {
+            (Decode::decode(client)?, Decode::decode(client)?)
+        } else {
+            Default::default()
+        };
+
+        // OSD-only data
+        let (version, mut osd) =
+            ceph_foundation::get_versions_and_data!(OsdMapIncremental: buffer, 12);
+        let osd = &mut osd;
+
+        if version < 7 {
+            return Err(DecodeError::UnexpectedVersion {
+                ty: "OsdMapIncremental.osd",
+                got: version,
+                expected: 7..=12,
+            });
+        }
+
+        let new_hb_back_up = decode_addr_map(osd)?;
+        let new_up_thru = Decode::decode(osd)?;
+        let new_last_clean_interval = Decode::decode(osd)?;
+        let new_lost = Decode::decode(osd)?;
+        let new_blocklist = Decode::decode(osd)?;
+        let old_blocklist = Decode::decode(osd)?;
+        let new_up_cluster = decode_addr_map(osd)?;
+        let cluster_snapshot = Decode::decode(osd)?;
+        let new_uuid = Decode::decode(osd)?;
+        let new_xinfo = Decode::decode(osd)?;
+        let new_hb_front_up = decode_addr_map(osd)?;
+
+        Ok(Self {
+            fsid,
+            epoch,
+            modified,
+            new_pool_max,
+            new_flags,
+            fullmap,
+            crush,
+            new_max_osd,
+            new_pools,
+            new_pool_names,
+            old_pools,
+            new_up_client,
+            new_state,
+            new_weight,
+            new_pg_temp,
+            new_primary_temp,
+            new_primary_affinity,
+            new_erasure_code_profiles,
+            old_erasure_code_profiles,
+            new_pg_upmap,
+            old_pg_upmap,
+            new_pg_upmap_items,
+            old_pg_upmap_items,
+            new_removed_snaps,
+            new_purged_snaps,
+            new_last_up_change,
+            new_last_in_change,
+            new_pg_upmap_primary,
+            old_pg_upmap_primary,
+            new_hb_back_up,
+            new_up_thru,
+            new_last_clean_interval,
+            new_lost,
+            new_blocklist,
+            old_blocklist,
+            new_up_cluster,
+            cluster_snapshot,
+            new_uuid,
+            new_xinfo,
+            new_hb_front_up,
+        })
+    }
+}
+
+/// An error that occurred while applying an [`OsdMapIncremental`].
+#[derive(Debug, Clone)]
+pub enum ApplyIncrementalError {
+    /// The incremental is for a different cluster.
+    FsidMismatch { expected: Uuid, got: Uuid },
+    /// The incremental does not follow the epoch of the map.
+    UnexpectedEpoch { expected: Epoch, got: Epoch },
+    /// The incremental refers to an OSD that does not exist.
+    InvalidOsd(i32),
+    /// The full map in the incremental could not be decoded.
+    Decode(DecodeError),
+}
+
+impl From<DecodeError> for ApplyIncrementalError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+impl OsdMap {
+    /// Apply `inc` to this map, producing the map of the next epoch.
+    pub fn apply_incremental(
+        &self,
+        inc: &OsdMapIncremental,
+    ) -> Result<OsdMap, ApplyIncrementalError> {
+        if inc.epoch != Epoch(1) && inc.fsid != self.fsid {
+            return Err(ApplyIncrementalError::FsidMismatch {
+                expected: self.fsid,
+                got: inc.fsid,
+            });
+        }
+
+        let expected = Epoch(self.epoch.0 + 1);
+        if inc.epoch != expected {
+            return Err(ApplyIncrementalError::UnexpectedEpoch {
+                expected,
+                got: inc.epoch,
+            });
+        }
+
+        if !inc.fullmap.is_empty() {
+            return Ok(OsdMap::decode(&mut inc.fullmap.as_slice())?);
+        }
+
+        let mut map = self.clone();
+        map.fsid = inc.fsid;
+        map.epoch = inc.epoch;
+        map.modified = inc.modified.clone();
+
+        if inc.new_flags >= 0 {
+            map.flags = inc.new_flags as u32;
+        }
+
+        map.set_max_osd(if inc.new_max_osd >= 0 {
+            inc.new_max_osd
+        } else {
+            map.max_osd
+        });
+
+        if inc.new_pool_max != -1 {
+            map.pool_max = PoolMax(inc.new_pool_max as i32);
+        }
+
+        for (id, pool) in &inc.new_pools {
+            let mut pool = pool.clone();
+            pool.last_change = map.epoch;
+            map.pools.insert(*id, pool);
+        }
+
+        map.new_removed_snaps = inc.new_removed_snaps.clone();
+        map.new_purged_snaps = inc.new_purged_snaps.clone();
+
+        for (id, name) in &inc.new_pool_names {
+            map.pool_name.insert(*id, name.clone());
+        }
+
+        for id in &inc.old_pools {
+            map.pools.remove(id);
+            map.pool_name.remove(id);
+        }
+
+        for (osd, weight) in &inc.new_weight {
+            let osd = map.osd_index(*osd)?;
+            map.osd_weight[osd] = *weight;
+
+            if *weight != 0 {
+                map.osd_state[osd].0 |= OsdState::EXISTS;
+                map.osd_state[osd].0 &= !(OsdState::AUTOOUT | OsdState::NEW);
+                map.osd_xinfo[osd].old_weight = 0;
+            }
+        }
+
+        for (osd, affinity) in &inc.new_primary_affinity {
+            let osd = map.osd_index(*osd)?;
+
+            if map.osd_primary_affinity.is_empty() {
+                map.osd_primary_affinity = vec![DEFAULT_PRIMARY_AFFINITY; map.osd_state.len()];
+            }
+
+            map.osd_primary_affinity[osd] = *affinity;
+        }
+
+        for profile in &inc.old_erasure_code_profiles {
+            map.erasure_code_profiles.remove(profile);
+        }
+
+        for (name, profile) in &inc.new_erasure_code_profiles {
+            map.erasure_code_profiles
+                .insert(name.clone(), profile.clone());
+        }
+
+        for (osd, state) in &inc.new_state {
+            let osd = map.osd_index(*osd)?;
+            let state = if *state == 0 { OsdState::UP } else { *state };
+            let current = map.osd_state[osd];
+
+            if current.contains(OsdState::UP) && state & OsdState::UP != 0 {
+                map.osd_info[osd].down_at = map.epoch;
+                map.osd_xinfo[osd].down_stamp = map.modified.clone();
+            }
+
+            if current.contains(OsdState::EXISTS) && state & OsdState::EXISTS != 0 {
+                // The OSD was destroyed.
+                map.osd_uuid[osd] = Uuid([0; 16]);
+                map.osd_info[osd] = OsdInfo::default();
+                map.osd_xinfo[osd] = OsdXInfo::default();
+
+                if let Some(affinity) = map.osd_primary_affinity.get_mut(osd) {
+                    *affinity = DEFAULT_PRIMARY_AFFINITY;
+                }
+
+                map.client_addrs[osd].clear();
+                map.cluster_addrs[osd].clear();
+                map.hb_front_addrs[osd].clear();
+                map.hb_back_addrs[osd].clear();
+                map.osd_state[osd] = OsdState(0);
+            } else {
+                map.osd_state[osd].0 ^= state;
+            }
+        }
+
+        for (osd, addrs) in &inc.new_up_client {
+            let id = *osd;
+            let osd = map.osd_index(id)?;
+
+            map.osd_state[osd].0 |= OsdState::EXISTS | OsdState::UP;
+            map.client_addrs[osd] = addrs.clone();
+            map.hb_back_addrs[osd] = inc.new_hb_back_up.get(&id).cloned().unwrap_or_default();
+            map.hb_front_addrs[osd] = inc.new_hb_front_up.get(&id).cloned().unwrap_or_default();
+            map.osd_info[osd].up_from = map.epoch;
+        }
+
+        for (osd, addrs) in &inc.new_up_cluster {
+            let osd = map.osd_index(*osd)?;
+            map.cluster_addrs[osd] = addrs.clone();
+        }
+
+        for (osd, up_thru) in &inc.new_up_thru {
+            let osd = map.osd_index(*osd)?;
+            map.osd_info[osd].up_thru = *up_thru;
+        }
+
+        for (osd, (begin, end)) in &inc.new_last_clean_interval {
+            let osd = map.osd_index(*osd)?;
+            map.osd_info[osd].last_clean_begin = *begin;
+            map.osd_info[osd].last_clean_end = *end;
+        }
+
+        for (osd, lost_at) in &inc.new_lost {
+            let osd = map.osd_index(*osd)?;
+            map.osd_info[osd].lost_at = *lost_at;
+        }
+
+        for (osd, xinfo) in &inc.new_xinfo {
+            let osd = map.osd_index(*osd)?;
+            map.osd_xinfo[osd] = xinfo.clone();
+        }
+
+        for (osd, uuid) in &inc.new_uuid {
+            let osd = map.osd_index(*osd)?;
+            map.osd_uuid[osd] = *uuid;
+        }
+
+        for (pg, osds) in &inc.new_pg_temp {
+            if osds.is_empty() {
+                map.pg_temp.remove(pg);
+            } else {
+                map.pg_temp.insert(*pg, osds.clone());
+            }
+        }
+
+        for (pg, osd) in &inc.new_primary_temp {
+            if *osd == -1 {
+                map.primary_temp.remove(pg);
+            } else {
+                map.primary_temp.insert(*pg, *osd);
+            }
+        }
+
+        map.pg_upmap.extend(inc.new_pg_upmap.clone());
+        map.pg_upmap.retain(|pg, _| !inc.old_pg_upmap.contains(pg));
+
+        map.pg_upmap_items.extend(inc.new_pg_upmap_items.clone());
+        map.pg_upmap_items
+            .retain(|pg, _| !inc.old_pg_upmap_items.contains(pg));
+
+        map.pg_upmap_primaries
+            .extend(inc.new_pg_upmap_primary.clone());
+        map.pg_upmap_primaries
+            .retain(|pg, _| !inc.old_pg_upmap_primary.contains(pg));
+
+        map.blocklist.extend(inc.new_blocklist.clone());
+        for address in &inc.old_blocklist {
+            map.blocklist.remove(address);
+        }
+
+        if inc.cluster_snapshot.is_empty() {
+            map.cluster_snapshot.clear();
+            map.cluster_snapshot_epoch = Epoch(0);
+        } else {
+            map.cluster_snapshot = inc.cluster_snapshot.clone();
+            map.cluster_snapshot_epoch = inc.epoch;
+        }
+
+        if inc.new_last_up_change != Timestamp::default() {
+            map.last_up_change = inc.new_last_up_change.clone();
+        }
+
+        if inc.new_last_in_change != Timestamp::default() {
+            map.last_in_change = inc.new_last_in_change.clone();
+        }
+
+        if !inc.crush.is_empty() {
+            map.crush = CrushMap::decode(&mut inc.crush.as_slice())?;
+            map.crush_version += 1;
+        }
+
+        Ok(map)
+    }
+
+    /// The index of `osd` in the per-OSD lists of this map.
+    fn osd_index(&self, osd: i32) -> Result<usize, ApplyIncrementalError> {
+        match usize::try_from(osd) {
+            Ok(index) if osd < self.max_osd => Ok(index),
+            _ => Err(ApplyIncrementalError::InvalidOsd(osd)),
+        }
+    }
+
+    /// Set `max_osd`, and resize all per-OSD lists to match.
+    fn set_max_osd(&mut self, max_osd: i32) {
+        let len = usize::try_from(max_osd).unwrap_or(0);
+
+        self.max_osd = max_osd;
+        self.osd_state.resize(len, OsdState(0));
+        self.osd_weight.resize(len, 0);
+        self.osd_info.resize(len, OsdInfo::default());
+        self.osd_xinfo.resize(len, OsdXInfo::default());
+        self.client_addrs.resize(len, Vec::new());
+        self.cluster_addrs.resize(len, Vec::new());
+        self.hb_back_addrs.resize(len, Vec::new());
+        self.hb_front_addrs.resize(len, Vec::new());
+        self.osd_uuid.resize(len, Uuid([0; 16]));
+
+        if !self.osd_primary_affinity.is_empty() {
+            self.osd_primary_affinity
+                .resize(len, DEFAULT_PRIMARY_AFFINITY);
+        }
+    }
+}
+
+#[cfg(test)]
+fn new_crush() -> CrushMap {
+    let mut crush = crate::crush::crush_map();
+    crush.tunables.choose_total_tries = 100;
+    crush
+}
+
+#[cfg(test)]
+fn incremental() -> OsdMapIncremental {
+    use ceph_foundation::{Encode, Encoder};
+
+    use super::{address, pool::pool};
+
+    let addrs = |port| AddrVec::from(&vec![address(port)]);
+    let pg = PgId { pool: 1, seed: 7 };
+
+    let mut encoded = Vec::new();
+
+    {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(&mut encoded, 8, 7);
+
+        {
+            let client = &mut ceph_foundation::write_versions_and_data!(buffer, 9, 1);
+            Uuid([1; 16]).encode(client);
+            Epoch(21).encode(client);
+            Timestamp::new(21, 0).encode(client);
+            2i64.encode(client); // New pool max
+            (-1i32).encode(client); // New flags
+            [0u8; 0].as_slice().encode(client); // Full map
+            new_crush().to_vec().as_slice().encode(client);
+            3i32.encode(client); // New max OSD
+            HashMap::from([(PoolId(2), pool())]).encode(client);
+            HashMap::from([(PoolId(2), "data".to_string())]).encode(client);
+            HashSet::<PoolId>::new().encode(client);
+            HashMap::from([(1i32, addrs(6900))]).encode(client);
+            HashMap::from([(0i32, 0u32)]).encode(client); // Mark `osd.0` down
+            HashMap::from([(1i32, OsdMap::WEIGHT_IN)]).encode(client);
+            // `new_pg_temp` removes the entry
+            (1u32, pg).encode(client);
+            [0i32; 0].as_slice().encode(client);
+            HashMap::from([(pg, -1i32)]).encode(client);
+            HashMap::<i32, u32>::new().encode(client);
+            HashMap::<String, HashMap<String, String>>::new().encode(client);
+            Vec::<String>::new().as_slice().encode(client);
+            HashMap::<PgId, i32>::new().encode(client); // `new_pg_upmap`, empty
+            HashSet::from([pg]).encode(client);
+            HashMap::<PgId, i32>::new().encode(client); // `new_pg_upmap_items`, empty
+            HashSet::from([pg]).encode(client);
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            Timestamp::new(21, 0).encode(client);
+            Timestamp::default().encode(client);
+            HashMap::<PgId, i32>::new().encode(client);
+            HashSet::<PgId>::new().encode(client);
+        }
+
+        {
+            let osd = &mut ceph_foundation::write_versions_and_data!(buffer, 9, 1);
+            HashMap::from([(1i32, addrs(6901))]).encode(osd);
+            HashMap::from([(1i32, Epoch(21))]).encode(osd);
+            HashMap::<i32, (Epoch, Epoch)>::new().encode(osd);
+            HashMap::<i32, Epoch>::new().encode(osd);
+            HashMap::from([(address(2), Timestamp::new(6, 0))]).encode(osd);
+            [address(1)].as_slice().encode(osd);
+            HashMap::from([(1i32, addrs(6902))]).encode(osd);
+            String::new().encode(osd);
+            HashMap::from([(2i32, Uuid([4; 16]))]).encode(osd);
+            HashMap::<i32, OsdXInfo>::new().encode(osd);
+            HashMap::from([(1i32, addrs(6903))]).encode(osd);
+            // Features and other data that we ignore.
+            [0u8; 8].encode(osd);
+        }
+
+        // CRCs
+        [0u8; 8].encode(buffer);
+    }
+
+    OsdMapIncremental::decode(&mut encoded.as_slice()).unwrap()
+}
+
+#[cfg(test)]
+fn osd_map() -> OsdMap {
+    OsdMap::decode(&mut super::encoded_osd_map().as_slice()).unwrap()
+}
+
+#[test]
+fn apply() {
+    use super::address;
+
+    let map = osd_map().apply_incremental(&incremental()).unwrap();
+
+    assert_eq!(map.epoch, Epoch(21));
+    assert_eq!(map.modified, Timestamp::new(21, 0));
+    assert_eq!(map.pool_max, PoolMax(2));
+    assert_eq!(map.pools[&PoolId(2)].last_change, Epoch(21));
+    assert_eq!(map.pool_name[&PoolId(2)], "data");
+    assert_eq!(map.crush, new_crush());
+    assert_eq!(map.crush_version, 4);
+
+    assert_eq!(map.max_osd, 3);
+    assert!(!map.is_up(0) && map.is_in(0));
+    assert_eq!(map.osd_info[0].down_at, Epoch(21));
+    assert!(map.is_up(1) && map.is_in(1));
+    assert_eq!(map.osd_info[1].up_from, Epoch(21));
+    assert_eq!(map.osd_info[1].up_thru, Epoch(21));
+    assert_eq!(map.client_addrs[1], [address(6900)]);
+    assert_eq!(map.hb_back_addrs[1], [address(6901)]);
+    assert_eq!(map.cluster_addrs[1], [address(6902)]);
+    assert_eq!(map.hb_front_addrs[1], [address(6903)]);
+    assert!(!map.exists(2));
+    assert_eq!(map.osd_uuid[2], Uuid([4; 16]));
+
+    assert!(map.pg_temp.is_empty());
+    assert!(map.primary_temp.is_empty());
+    assert!(map.pg_upmap.is_empty());
+    assert!(map.pg_upmap_items.is_empty());
+
+    assert!(!map.blocklist.contains_key(&address(1)));
+    assert_eq!(map.blocklist[&address(2)], Timestamp::new(6, 0));
+    assert_eq!(map.last_up_change, Timestamp::new(21, 0));
+    assert_eq!(map.last_in_change, Timestamp::new(4, 0));
+}
+
+#[test]
+fn apply_checks_sequence() {
+    let map = osd_map();
+
+    let mut inc = incremental();
+    inc.fsid = Uuid([2; 16]);
+    assert!(matches!(
+        map.apply_incremental(&inc),
+        Err(ApplyIncrementalError::FsidMismatch { .. })
+    ));
+
+    let mut inc = incremental();
+    inc.epoch = Epoch(22);
+    assert!(matches!(
+        map.apply_incremental(&inc),
+        Err(ApplyIncrementalError::UnexpectedEpoch {
+            expected: Epoch(21),
+            got: Epoch(22)
+        })
+    ));
+
+    let mut inc = incremental();
+    inc.new_weight.insert(3, 0);
+    assert!(matches!(
+        map.apply_incremental(&inc),
+        Err(ApplyIncrementalError::InvalidOsd(3))
+    ));
+}
+
+#[test]
+fn apply_full_map() {
+    let mut map = osd_map();
+    map.epoch = Epoch(19);
+    map.pools.clear();
+
+    let mut inc = incremental();
+    inc.epoch = Epoch(20);
+    inc.fullmap = super::encoded_osd_map();
+
+    let map = map.apply_incremental(&inc).unwrap();
+    assert_eq!(map.epoch, Epoch(20));
+    assert_eq!(map.pools.len(), 1);
+}
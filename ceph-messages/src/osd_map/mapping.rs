@@ -0,0 +1,393 @@
+//! Mapping objects to placement groups and OSDs.
+
+use crate::{
+    ObjectLocator,
+    crush::{self, ITEM_NONE},
+};
+
+use super::{OsdMap, PgId, Pool, PoolFlags, PoolId, PoolType};
+
+/// The default (and maximum) primary affinity of an OSD.
+const MAX_PRIMARY_AFFINITY: u32 = 0x10000;
+
+/// Reduce `x` to a value below `b`, such that the mapping is stable
+/// when `b` grows (`ceph_stable_mod`).
+///
+/// `bmask` must be the smallest `2^n - 1` that is at least `b - 1`.
+pub fn stable_mod(x: u32, b: u32, bmask: u32) -> u32 {
+    if x & bmask < b {
+        x & bmask
+    } else {
+        x & (bmask >> 1)
+    }
+}
+
+/// The smallest `2^n - 1` that is at least `num - 1`.
+fn mask(num: u32) -> u32 {
+    let bits = u32::BITS - num.saturating_sub(1).leading_zeros();
+    ((1u64 << bits) - 1) as u32
+}
+
+impl Pool {
+    /// Object names are hashed with [`crush::str_hash_linux`].
+    pub const HASH_LINUX: u8 = 1;
+    /// Object names are hashed with [`crush::str_hash_rjenkins`].
+    pub const HASH_RJENKINS: u8 = 2;
+
+    /// Whether OSDs may be shifted to other positions in a mapping,
+    /// which is only the case for replicated pools.
+    pub fn can_shift_osds(&self) -> bool {
+        self.ty == PoolType::Replicated
+    }
+
+    /// Hash the name of an object in `namespace` to its placement seed.
+    pub fn hash_key(&self, key: &str, namespace: &str) -> u32 {
+        let hash: fn(&[u8]) -> u32 = match self.object_hash {
+            Self::HASH_LINUX => crush::str_hash_linux,
+            Self::HASH_RJENKINS => crush::str_hash_rjenkins,
+            _ => return u32::MAX,
+        };
+
+        if namespace.is_empty() {
+            hash(key.as_bytes())
+        } else {
+            let mut data = Vec::with_capacity(namespace.len() + 1 + key.len());
+            data.extend_from_slice(namespace.as_bytes());
+            data.push(0x1f);
+            data.extend_from_slice(key.as_bytes());
+            hash(&data)
+        }
+    }
+
+    /// Map a raw placement seed to an actual placement group of this pool.
+    pub fn raw_pg_to_pg(&self, pg: PgId) -> PgId {
+        PgId {
+            pool: pg.pool,
+            seed: stable_mod(pg.seed, self.pg_num, mask(self.pg_num)),
+        }
+    }
+
+    /// The input to CRUSH for `pg`.
+    pub fn raw_pg_to_pps(&self, pg: PgId) -> u32 {
+        let seed = stable_mod(pg.seed, self.pgp_num, mask(self.pgp_num));
+
+        if self.flags.contains(PoolFlags::HASHPSPOOL) {
+            crush::hash32_2(seed, pg.pool as u32)
+        } else {
+            seed.wrapping_add(pg.pool as u32)
+        }
+    }
+}
+
+impl OsdMap {
+    /// Map the object called `name` in `pool` to its placement group,
+    /// its up set, its acting set and its acting primary.
+    ///
+    /// Returns `None` if the pool does not exist. OSD sets of
+    /// erasure coded pools contain [`ITEM_NONE`] for positions that
+    /// could not be mapped, and the primary is `-1` if there is none.
+    pub fn map_object(&self, pool: PoolId, name: &str) -> Option<(PgId, Vec<i32>, Vec<i32>, i32)> {
+        let raw = self.object_locator_to_pg(name, &ObjectLocator::new(pool.0))?;
+        let pg = self.pools[&pool].raw_pg_to_pg(raw);
+        let (up, _, acting, primary) = self.pg_to_up_acting_osds(pg)?;
+
+        Some((pg, up, acting, primary))
+    }
+
+    /// Map the object called `name` at `locator` to its raw placement
+    /// group, whose seed is the full hash of the object.
+    ///
+    /// The key of `locator` is hashed instead of `name` if it is set, and
+    /// its hash is used as is if it is not negative. Returns `None` if the
+    /// pool does not exist.
+    pub fn object_locator_to_pg(&self, name: &str, locator: &ObjectLocator) -> Option<PgId> {
+        let pool = self.pools.get(&PoolId(locator.pool))?;
+
+        let seed = if locator.hash >= 0 {
+            locator.hash as u32
+        } else {
+            let key = if locator.key.is_empty() {
+                name
+            } else {
+                &locator.key
+            };
+
+            pool.hash_key(key, &locator.namespace)
+        };
+
+        Some(PgId {
+            pool: locator.pool as u64,
+            seed,
+        })
+    }
+
+    /// The up set, up primary, acting set and acting primary of `pg`.
+    ///
+    /// Returns `None` if the pool of `pg` does not exist.
+    pub fn pg_to_up_acting_osds(&self, pg: PgId) -> Option<(Vec<i32>, i32, Vec<i32>, i32)> {
+        let pool = self.pools.get(&PoolId(pg.pool as i64))?;
+        Some(self.pg_to_up_acting(pool, pg))
+    }
+
+    /// Compute the up set, up primary, acting set and acting primary of `pg`.
+    fn pg_to_up_acting(&self, pool: &Pool, pg: PgId) -> (Vec<i32>, i32, Vec<i32>, i32) {
+        let (mut acting, mut acting_primary) = self.temp_osds(pool, pg);
+
+        let pps = pool.raw_pg_to_pps(pg);
+        let mut raw = self.pg_to_raw_osds(pool, pg, pps);
+        self.apply_upmap(pg, &mut raw);
+
+        let mut up = self.raw_to_up_osds(pool, &raw);
+        let mut up_primary = up.iter().copied().find(|o| *o != ITEM_NONE).unwrap_or(-1);
+        self.apply_primary_affinity(pool, pps, &mut up, &mut up_primary);
+
+        if acting.is_empty() {
+            acting = up.clone();
+
+            if acting_primary == -1 {
+                acting_primary = up_primary;
+            }
+        }
+
+        (up, up_primary, acting, acting_primary)
+    }
+
+    fn pg_to_raw_osds(&self, pool: &Pool, pg: PgId, pps: u32) -> Vec<i32> {
+        let choose_args = self.crush.choose_args_for(pg.pool as i64);
+        let mut osds = self.crush.do_rule(
+            pool.crush_rule as u32,
+            pps,
+            pool.size as usize,
+            &self.osd_weight,
+            choose_args,
+        );
+
+        // Remove OSDs that do not exist.
+        if pool.can_shift_osds() {
+            osds.retain(|osd| self.exists(*osd));
+        } else {
+            osds.iter_mut()
+                .filter(|osd| !self.exists(**osd))
+                .for_each(|osd| *osd = ITEM_NONE);
+        }
+
+        osds
+    }
+
+    /// Whether `osd` is a valid OSD that is marked out.
+    fn is_marked_out(&self, osd: i32) -> bool {
+        osd != ITEM_NONE
+            && osd >= 0
+            && osd < self.max_osd
+            && self.osd_weight.get(osd as usize) == Some(&0)
+    }
+
+    /// Apply the explicit mappings of `pg_upmap`, `pg_upmap_items`
+    /// and `pg_upmap_primaries`.
+    fn apply_upmap(&self, pg: PgId, raw: &mut Vec<i32>) {
+        if let Some(upmap) = self.pg_upmap.get(&pg) {
+            // Ignore the explicit mapping if any of its targets are out.
+            if upmap.iter().any(|osd| self.is_marked_out(*osd)) {
+                return;
+            }
+
+            *raw = upmap.clone();
+        }
+
+        // This does not allow a bidirectional swap: `[(1, 2), (2, 1)]`
+        // applied to `[0, 1, 2]` gives `[0, 2, 1]`.
+        for (from, to) in self.pg_upmap_items.get(&pg).into_iter().flatten() {
+            // The replacement should not already be present.
+            if raw.contains(to) || self.is_marked_out(*to) {
+                continue;
+            }
+
+            if let Some(osd) = raw.iter_mut().find(|osd| *osd == from) {
+                *osd = *to;
+            }
+        }
+
+        if let Some(primary) = self.pg_upmap_primaries.get(&pg)
+            && *primary != ITEM_NONE
+            && *primary >= 0
+            && *primary < self.max_osd
+            && !self.is_marked_out(*primary)
+            && let Some(pos) = raw.iter().skip(1).position(|osd| osd == primary)
+        {
+            raw.swap(0, pos + 1);
+        }
+    }
+
+    /// Remove (or replace with [`ITEM_NONE`]) the OSDs that are down.
+    fn raw_to_up_osds(&self, pool: &Pool, raw: &[i32]) -> Vec<i32> {
+        if pool.can_shift_osds() {
+            raw.iter().copied().filter(|osd| self.is_up(*osd)).collect()
+        } else {
+            raw.iter()
+                .map(|osd| if self.is_up(*osd) { *osd } else { ITEM_NONE })
+                .collect()
+        }
+    }
+
+    /// Pick a primary according to the primary affinity of the OSDs.
+    fn apply_primary_affinity(&self, pool: &Pool, pps: u32, osds: &mut [i32], primary: &mut i32) {
+        let affinity = |osd: i32| {
+            self.osd_primary_affinity
+                .get(osd as usize)
+                .copied()
+                .unwrap_or(MAX_PRIMARY_AFFINITY)
+        };
+
+        let non_default = osds
+            .iter()
+            .any(|osd| *osd != ITEM_NONE && affinity(*osd) != MAX_PRIMARY_AFFINITY);
+
+        if !non_default {
+            return;
+        }
+
+        // Feed both the seed and the OSD into the hash, so that a
+        // proportional fraction of the PGs of an OSD are rejected.
+        let mut pos = None;
+        for (i, osd) in osds.iter().enumerate() {
+            if *osd == ITEM_NONE {
+                continue;
+            }
+
+            let a = affinity(*osd);
+            if a < MAX_PRIMARY_AFFINITY && (crush::hash32_2(pps, *osd as u32) >> 16) >= a {
+                // Rejected, but remember it in case no other OSD is chosen.
+                pos.get_or_insert(i);
+            } else {
+                pos = Some(i);
+                break;
+            }
+        }
+
+        let Some(pos) = pos else {
+            return;
+        };
+
+        *primary = osds[pos];
+
+        // Move the new primary to the front.
+        if pool.can_shift_osds() && pos > 0 {
+            osds[..=pos].rotate_right(1);
+        }
+    }
+
+    /// Get the `pg_temp` and `primary_temp` overrides for `pg`.
+    fn temp_osds(&self, pool: &Pool, pg: PgId) -> (Vec<i32>, i32) {
+        let mut temp = Vec::new();
+
+        for osd in self.pg_temp.get(&pg).into_iter().flatten() {
+            if self.is_up(*osd) {
+                temp.push(*osd);
+            } else if !pool.can_shift_osds() {
+                temp.push(ITEM_NONE);
+            }
+        }
+
+        let primary = match self.primary_temp.get(&pg) {
+            Some(primary) => *primary,
+            None => temp.iter().copied().find(|o| *o != ITEM_NONE).unwrap_or(-1),
+        };
+
+        (temp, primary)
+    }
+}
+
+#[cfg(test)]
+fn osd_map() -> OsdMap {
+    use ceph_foundation::Decode;
+
+    OsdMap::decode(&mut super::encoded_osd_map().as_slice()).unwrap()
+}
+
+#[test]
+fn stable_mod_mask() {
+    assert_eq!(mask(1), 0);
+    assert_eq!(mask(12), 15);
+    assert_eq!(mask(16), 15);
+    assert_eq!(mask(17), 31);
+
+    // Seeds above `b` map to the lower half.
+    assert_eq!(stable_mod(11, 12, 15), 11);
+    assert_eq!(stable_mod(13, 12, 15), 5);
+    assert_eq!(stable_mod(29, 12, 15), 5);
+}
+
+#[test]
+fn pg_overrides() {
+    let map = osd_map();
+    let pool = &map.pools[&PoolId(1)];
+    let pg = PgId { pool: 1, seed: 7 };
+
+    // `osd.1` is down and out, so its upmaps are ignored and
+    // it is removed from the `pg_temp`. The `primary_temp` is
+    // applied as is.
+    assert_eq!(map.pg_to_up_acting(pool, pg), (vec![0], 0, vec![0], 1));
+
+    let mut map = map;
+    map.osd_state[1].0 |= super::OsdState::UP;
+    map.osd_weight[1] = OsdMap::WEIGHT_IN;
+    map.primary_temp.clear();
+
+    // The upmap now applies, while the `pg_temp` still overrides
+    // the acting set.
+    let pool = &map.pools[&PoolId(1)];
+    assert_eq!(
+        map.pg_to_up_acting(pool, pg),
+        (vec![0, 1], 0, vec![1, 0], 1)
+    );
+
+    map.pg_temp.clear();
+    map.pg_upmap_items.insert(pg, vec![(1, 0)]);
+    map.pg_upmap_primaries.insert(pg, 1);
+
+    // `osd.0` is already present, so the item is not applied,
+    // but the primary is swapped.
+    let pool = &map.pools[&PoolId(1)];
+    assert_eq!(
+        map.pg_to_up_acting(pool, pg),
+        (vec![1, 0], 1, vec![1, 0], 1)
+    );
+}
+
+#[test]
+fn map_object() {
+    let mut map = osd_map();
+    map.pg_temp.clear();
+    map.primary_temp.clear();
+
+    let (pg, up, acting, primary) = map.map_object(PoolId(1), "object").unwrap();
+    assert_eq!(pg.pool, 1);
+    assert!(pg.seed < 32);
+    assert_eq!(up, [0]);
+    assert_eq!(acting, [0]);
+    assert_eq!(primary, 0);
+
+    let pool = &map.pools[&PoolId(1)];
+    assert_eq!(
+        pool.raw_pg_to_pg(PgId {
+            pool: 1,
+            seed: pool.hash_key("object", "")
+        }),
+        pg
+    );
+    assert_ne!(pool.hash_key("object", "ns"), pool.hash_key("object", ""));
+
+    assert!(map.map_object(PoolId(2), "object").is_none());
+
+    // The locator key is hashed instead of the name.
+    let mut locator = ObjectLocator::new(1);
+    locator.key = "object".to_string();
+    let raw = map.object_locator_to_pg("other", &locator).unwrap();
+    assert_eq!(pool.raw_pg_to_pg(raw), pg);
+
+    locator.hash = 7;
+    assert_eq!(
+        map.object_locator_to_pg("other", &locator),
+        Some(PgId { pool: 1, seed: 7 })
+    );
+}
@@ -0,0 +1,455 @@
+mod incremental;
+mod mapping;
+mod osd;
+mod pool;
+
+use std::collections::HashMap;
+
+use ceph_foundation::{
+    Decode, DecodeError, Encode, Encoder, Timestamp, Uuid,
+    entity::{AddrVec, EntityAddress},
+    write_decode_encode,
+};
+
+use crate::{DecodeMessage, Epoch, crush::CrushMap};
+
+pub use incremental::{ApplyIncrementalError, OsdMapIncremental};
+pub use mapping::stable_mod;
+pub use osd::{OsdInfo, OsdState, OsdXInfo};
+pub use pool::{EVersion, PgMergeMeta, Pool, PoolFlags, PoolSnapInfo, PoolType};
+
+#[derive(Debug, Clone)]
+pub struct MessageOsdMap {
+    pub fsid: Uuid,
+    pub incremental_maps: HashMap<Epoch, ByteArrayEncoded<OsdMapIncremental>>,
+    pub maps: HashMap<Epoch, ByteArrayEncoded<OsdMap>>,
+    pub cluster_osdmap_trim_lower_bound: Epoch,
+    pub newest_map: Epoch,
+}
+
+impl DecodeMessage<'_> for MessageOsdMap {
+    fn decode_message(segments: &[&'_ [u8]]) -> Result<Self, crate::DecodeMessageError> {
+        if segments.len() > 1 {
+            return Err(crate::DecodeMessageError::TooManySegments {
+                have: segments.len(),
+                want: 1,
+            });
+        } else if segments.is_empty() {
+            return Err(crate::DecodeMessageError::NotEnoughSegments { have: 0, need: 1 });
+        }
+
+        let buffer = &mut &*segments[0];
+        let fsid = Uuid::decode(buffer)?;
+        let incremental_maps = Decode::decode(buffer)?;
+        let maps = Decode::decode(buffer)?;
+        let cluster_osdmap_trim_lower_bound = Epoch::decode(buffer)?;
+        let newest_map = Epoch::decode(buffer)?;
+
+        Ok(Self {
+            fsid,
+            incremental_maps,
+            maps,
+            cluster_osdmap_trim_lower_bound,
+            newest_map,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct PoolId(pub i64);
+
+impl<'a> Decode<'a> for PoolId {
+    fn decode(buffer: &mut &'a [u8]) -> Result<Self, ceph_foundation::DecodeError> {
+        Ok(Self(i64::decode(buffer)?))
+    }
+}
+
+impl Encode for PoolId {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        self.0.encode(buffer);
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct PoolMax(pub i32);
+
+impl<'a> Decode<'a> for PoolMax {
+    fn decode(buffer: &mut &'a [u8]) -> Result<Self, ceph_foundation::DecodeError> {
+        Ok(Self(i32::decode(buffer)?))
+    }
+}
+
+/// The ID of a placement group (`pg_t`).
+#[derive(Debug, Copy, Clone, Default, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct PgId {
+    pub pool: u64,
+    pub seed: u32,
+}
+
+write_decode_encode!(PgId = const version 1 as u8 | pool | seed | const -1 as i32);
+
+/// A versioned structure that is kept in its encoded form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opaque {
+    pub version: u8,
+    pub compat: u8,
+    pub data: Vec<u8>,
+}
+
+impl Decode<'_> for Opaque {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let [version, compat] = Decode::decode(buffer)?;
+        let data = Decode::decode(buffer)?;
+
+        Ok(Self {
+            version,
+            compat,
+            data,
+        })
+    }
+}
+
+impl Encode for Opaque {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        buffer.extend_from_slice(&[self.version, self.compat]);
+        self.data.encode(buffer);
+    }
+}
+
+/// A map of the OSDs and pools of a cluster (`OSDMap`).
+///
+/// Only the data that is relevant to clients and monitoring is
+/// decoded: the OSD-only data after `hb_front_addrs` is ignored.
+#[derive(Debug, Clone)]
+pub struct OsdMap {
+    pub fsid: Uuid,
+    pub epoch: Epoch,
+    pub created: Timestamp,
+    pub modified: Timestamp,
+    pub pools: HashMap<PoolId, Pool>,
+    pub pool_name: HashMap<PoolId, String>,
+    pub pool_max: PoolMax,
+    pub flags: u32,
+    pub max_osd: i32,
+    pub osd_state: Vec<OsdState>,
+    /// The weights of the OSDs, where `0x10000` is fully in
+    /// and `0` is out.
+    pub osd_weight: Vec<u32>,
+    pub client_addrs: Vec<Vec<EntityAddress>>,
+    pub pg_temp: HashMap<PgId, Vec<i32>>,
+    pub primary_temp: HashMap<PgId, i32>,
+    pub osd_primary_affinity: Vec<u32>,
+    pub crush: CrushMap,
+    pub erasure_code_profiles: HashMap<String, HashMap<String, String>>,
+    pub pg_upmap: HashMap<PgId, Vec<i32>>,
+    pub pg_upmap_items: HashMap<PgId, Vec<(i32, i32)>>,
+    pub crush_version: i32,
+    pub new_removed_snaps: HashMap<i64, HashMap<u64, u64>>,
+    pub new_purged_snaps: HashMap<i64, HashMap<u64, u64>>,
+    pub last_up_change: Timestamp,
+    pub last_in_change: Timestamp,
+    pub pg_upmap_primaries: HashMap<PgId, i32>,
+    pub hb_back_addrs: Vec<Vec<EntityAddress>>,
+    pub osd_info: Vec<OsdInfo>,
+    pub blocklist: HashMap<EntityAddress, Timestamp>,
+    pub cluster_addrs: Vec<Vec<EntityAddress>>,
+    pub cluster_snapshot_epoch: Epoch,
+    pub cluster_snapshot: String,
+    pub osd_uuid: Vec<Uuid>,
+    pub osd_xinfo: Vec<OsdXInfo>,
+    pub hb_front_addrs: Vec<Vec<EntityAddress>>,
+}
+
+impl OsdMap {
+    /// The weight of an OSD that is fully in.
+    pub const WEIGHT_IN: u32 = 0x10000;
+
+    fn state(&self, osd: i32) -> Option<OsdState> {
+        let osd = usize::try_from(osd).ok()?;
+        self.osd_state.get(osd).copied()
+    }
+
+    /// Whether `osd` exists.
+    pub fn exists(&self, osd: i32) -> bool {
+        osd < self.max_osd
+            && self
+                .state(osd)
+                .is_some_and(|s| s.contains(OsdState::EXISTS))
+    }
+
+    /// Whether `osd` exists and is up.
+    pub fn is_up(&self, osd: i32) -> bool {
+        self.exists(osd) && self.state(osd).is_some_and(|s| s.contains(OsdState::UP))
+    }
+
+    /// Whether `osd` exists and is in.
+    pub fn is_in(&self, osd: i32) -> bool {
+        self.exists(osd)
+            && usize::try_from(osd)
+                .ok()
+                .and_then(|osd| self.osd_weight.get(osd))
+                .is_some_and(|weight| *weight != 0)
+    }
+
+    /// The ID of the pool called `name`, if it exists.
+    pub fn lookup_pool(&self, name: &str) -> Option<PoolId> {
+        self.pool_name
+            .iter()
+            .find(|(_, pool_name)| *pool_name == name)
+            .map(|(id, _)| *id)
+    }
+}
+
+/// Decode a list of `entity_addrvec_t`s.
+fn decode_addrs(buffer: &mut &[u8]) -> Result<Vec<Vec<EntityAddress>>, DecodeError> {
+    Vec::<AddrVec>::decode(buffer)?
+        .into_iter()
+        .map(Vec::try_from)
+        .collect()
+}
+
+impl<'a> Decode<'a> for OsdMap {
+    fn decode(buffer: &mut &'a [u8]) -> Result<Self, ceph_foundation::DecodeError> {
+        let (_, mut buffer) = ceph_foundation::get_versions_and_data!(OsdMap: buffer, 8);
+        let buffer = &mut buffer;
+
+        // Client-usable data
+        let (version, mut client) = ceph_foundation::get_versions_and_data!(OsdMap: buffer, 10);
+        let client = &mut client;
+
+        if version < 7 {
+            return Err(DecodeError::UnexpectedVersion {
+                ty: "OsdMap.client",
+                got: version,
+                expected: 7..=10,
+            });
+        }
+
+        let fsid = Uuid::decode(client)?;
+        let epoch = Epoch::decode(client)?;
+        let created = Timestamp::decode(client)?;
+        let modified = Timestamp::decode(client)?;
+        let pools = Decode::decode(client)?;
+        let pool_name = HashMap::<PoolId, String>::decode(client)?;
+        let pool_max = PoolMax::decode(client)?;
+        let flags = Decode::decode(client)?;
+        let max_osd = Decode::decode(client)?;
+        let osd_state = Decode::decode(client)?;
+        let osd_weight = Decode::decode(client)?;
+        let client_addrs = decode_addrs(client)?;
+        let pg_temp = Decode::decode(client)?;
+        let primary_temp = Decode::decode(client)?;
+        let osd_primary_affinity = Decode::decode(client)?;
+        let ByteArrayEncoded(crush) = Decode::decode(client)?;
+        let erasure_code_profiles = Decode::decode(client)?;
+        let pg_upmap = Decode::decode(client)?;
+        let pg_upmap_items = Decode::decode(client)?;
+        let crush_version = Decode::decode(client)?;
+        let new_removed_snaps = Decode::decode(client)?;
+        let new_purged_snaps = Decode::decode(client)?;
+
+        let (last_up_change, last_in_change) = if version >= 9 {
+            (Decode::decode(client)?, Decode::decode(client)?)
+        } else {
+            Default::default()
+        };
+
+        let pg_upmap_primaries = Decode::decode_if(version >= 10, client)?.unwrap_or_default();
+
+        // OSD-only data
+        let (version, mut osd) = ceph_foundation::get_versions_and_data!(OsdMap: buffer, 12);
+        let osd = &mut osd;
+
+        if version < 7 {
+            return Err(DecodeError::UnexpectedVersion {
+                ty: "OsdMap.osd",
+                got: version,
+                expected: 7..=12,
+            });
+        }
+
+        let hb_back_addrs = decode_addrs(osd)?;
+        let osd_info = Decode::decode(osd)?;
+        let blocklist = Decode::decode(osd)?;
+        let cluster_addrs = decode_addrs(osd)?;
+        let cluster_snapshot_epoch = Decode::decode(osd)?;
+        let cluster_snapshot = Decode::decode(osd)?;
+        let osd_uuid = Decode::decode(osd)?;
+        let osd_xinfo = Decode::decode(osd)?;
+        let hb_front_addrs = decode_addrs(osd)?;
+
+        Ok(Self {
+            fsid,
+            epoch,
+            created,
+            modified,
+            pools,
+            pool_name,
+            pool_max,
+            flags,
+            max_osd,
+            osd_state,
+            osd_weight,
+            client_addrs,
+            pg_temp,
+            primary_temp,
+            osd_primary_affinity,
+            crush,
+            erasure_code_profiles,
+            pg_upmap,
+            pg_upmap_items,
+            crush_version,
+            new_removed_snaps,
+            new_purged_snaps,
+            last_up_change,
+            last_in_change,
+            pg_upmap_primaries,
+            hb_back_addrs,
+            osd_info,
+            blocklist,
+            cluster_addrs,
+            cluster_snapshot_epoch,
+            cluster_snapshot,
+            osd_uuid,
+            osd_xinfo,
+            hb_front_addrs,
+        })
+    }
+}
+
+/// Wrapper meant for types that ceph library can express as an array of bytes
+///
+/// ceph/src/messages/MOSDMap.h:34 is an example
+#[derive(Debug, Clone)]
+pub struct ByteArrayEncoded<T>(pub T);
+
+impl<'a, T: Decode<'a>> Decode<'a> for ByteArrayEncoded<T> {
+    fn decode(buffer: &mut &'a [u8]) -> Result<Self, ceph_foundation::DecodeError> {
+        // consume length
+        let buffer = &mut <&[u8]>::decode(buffer)?;
+        Ok(Self(T::decode(buffer)?))
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn address(port: u16) -> EntityAddress {
+    EntityAddress {
+        ty: ceph_foundation::entity::EntityAddressType::Msgr2,
+        nonce: 1,
+        address: Some(([10, 0, 0, 1], port).into()),
+    }
+}
+
+#[cfg(test)]
+fn info() -> OsdInfo {
+    OsdInfo {
+        last_clean_begin: Epoch(1),
+        last_clean_end: Epoch(2),
+        up_from: Epoch(3),
+        up_thru: Epoch(4),
+        down_at: Epoch(5),
+        lost_at: Epoch(0),
+    }
+}
+
+/// An encoded map at epoch 20 with a single pool and two OSDs,
+/// of which `osd.0` is up and in, and `osd.1` is down and out.
+#[cfg(test)]
+pub(crate) fn encoded_osd_map() -> Vec<u8> {
+    let addrs = |port| [AddrVec::from(&vec![address(port)]), AddrVec::from(&vec![])];
+    let pg = PgId { pool: 1, seed: 7 };
+
+    let mut encoded = Vec::new();
+
+    {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(&mut encoded, 8, 7);
+
+        {
+            let client = &mut ceph_foundation::write_versions_and_data!(buffer, 10, 1);
+            Uuid([1; 16]).encode(client);
+            Epoch(20).encode(client);
+            Timestamp::new(1, 0).encode(client);
+            Timestamp::new(2, 0).encode(client);
+            HashMap::from([(PoolId(1), pool::pool())]).encode(client);
+            HashMap::from([(PoolId(1), "rbd".to_string())]).encode(client);
+            1i32.encode(client); // Pool max
+            0u32.encode(client); // Flags
+            2i32.encode(client); // Max OSD
+            [OsdState::EXISTS | OsdState::UP, OsdState::EXISTS]
+                .as_slice()
+                .encode(client);
+            [OsdMap::WEIGHT_IN, 0].as_slice().encode(client);
+            addrs(6800).as_slice().encode(client);
+            // `pg_temp`, with a single entry
+            (1u32, pg).encode(client);
+            [1i32, 0].as_slice().encode(client);
+            HashMap::from([(pg, 1)]).encode(client);
+            Vec::<u32>::new().encode(client);
+            crate::crush::crush_map().to_vec().as_slice().encode(client);
+            HashMap::<String, HashMap<String, String>>::new().encode(client);
+            // `pg_upmap` and `pg_upmap_items`, with a single entry
+            (1u32, pg).encode(client);
+            [0i32, 1].as_slice().encode(client);
+            (1u32, pg).encode(client);
+            [(0i32, 1i32)].as_slice().encode(client);
+            3i32.encode(client); // Crush version
+            HashMap::<i64, HashMap<u64, u64>>::new().encode(client);
+            HashMap::from([(1i64, HashMap::from([(4u64, 1u64)]))]).encode(client);
+            Timestamp::new(3, 0).encode(client);
+            Timestamp::new(4, 0).encode(client);
+            HashMap::<PgId, i32>::new().encode(client);
+        }
+
+        {
+            let osd = &mut ceph_foundation::write_versions_and_data!(buffer, 9, 1);
+            addrs(6801).as_slice().encode(osd);
+            [info(), info()].as_slice().encode(osd);
+            HashMap::from([(address(1), Timestamp::new(5, 0))]).encode(osd);
+            addrs(6802).as_slice().encode(osd);
+            Epoch(0).encode(osd);
+            String::new().encode(osd);
+            [Uuid([2; 16]), Uuid([3; 16])].as_slice().encode(osd);
+            Vec::<OsdXInfo>::new().encode(osd);
+            addrs(6803).as_slice().encode(osd);
+            // Ratios and other data that we ignore.
+            [0u8; 12].encode(osd);
+        }
+
+        // CRC
+        0u32.encode(buffer);
+    }
+
+    encoded
+}
+
+#[test]
+fn osd_map() {
+    let pg = PgId { pool: 1, seed: 7 };
+    let map = OsdMap::decode(&mut encoded_osd_map().as_slice()).unwrap();
+
+    assert_eq!(map.epoch, Epoch(20));
+    assert_eq!(map.pools[&PoolId(1)], pool::pool());
+    assert_eq!(map.pool_name[&PoolId(1)], "rbd");
+    assert_eq!(map.lookup_pool("rbd"), Some(PoolId(1)));
+    assert_eq!(map.lookup_pool("data"), None);
+    assert_eq!(map.max_osd, 2);
+    assert_eq!(map.client_addrs, [vec![address(6800)], vec![]]);
+    assert_eq!(map.pg_temp[&pg], [1, 0]);
+    assert_eq!(map.primary_temp[&pg], 1);
+    assert_eq!(map.crush, crate::crush::crush_map());
+    assert_eq!(map.pg_upmap[&pg], [0, 1]);
+    assert_eq!(map.pg_upmap_items[&pg], [(0, 1)]);
+    assert_eq!(map.crush_version, 3);
+    assert_eq!(map.new_purged_snaps[&1][&4], 1);
+    assert_eq!(map.last_in_change, Timestamp::new(4, 0));
+    assert_eq!(map.hb_back_addrs[0], [address(6801)]);
+    assert_eq!(map.osd_info, [info(), info()]);
+    assert_eq!(map.blocklist[&address(1)], Timestamp::new(5, 0));
+    assert_eq!(map.cluster_addrs[0], [address(6802)]);
+    assert_eq!(map.osd_uuid.len(), 2);
+    assert_eq!(map.hb_front_addrs[0], [address(6803)]);
+
+    assert!(map.is_up(0) && map.is_in(0));
+    assert!(map.exists(1) && !map.is_up(1) && !map.is_in(1));
+    assert!(!map.exists(2) && !map.exists(-1));
+}
@@ -0,0 +1,150 @@
+use ceph_foundation::{Decode, DecodeError, Encode, Encoder, Timestamp, write_decode_encode};
+
+use crate::Epoch;
+
+/// The state of an OSD in an [`OsdMap`](super::OsdMap).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OsdState(pub u32);
+
+impl OsdState {
+    /// The OSD exists.
+    pub const EXISTS: u32 = 1 << 0;
+    /// The OSD is up.
+    pub const UP: u32 = 1 << 1;
+    /// The OSD was marked out automatically.
+    pub const AUTOOUT: u32 = 1 << 2;
+    /// The OSD was newly created.
+    pub const NEW: u32 = 1 << 3;
+    /// The OSD is full.
+    pub const FULL: u32 = 1 << 4;
+    /// The OSD is nearly full.
+    pub const NEARFULL: u32 = 1 << 5;
+    /// The OSD is too full to backfill.
+    pub const BACKFILLFULL: u32 = 1 << 6;
+    /// The OSD was destroyed.
+    pub const DESTROYED: u32 = 1 << 7;
+
+    /// Whether all bits of `state` are set.
+    pub fn contains(&self, state: u32) -> bool {
+        self.0 & state == state
+    }
+}
+
+impl Decode<'_> for OsdState {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self(u32::decode(buffer)?))
+    }
+}
+
+impl Encode for OsdState {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        self.0.encode(buffer);
+    }
+}
+
+/// Information about the up and down history of an OSD (`osd_info_t`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OsdInfo {
+    pub last_clean_begin: Epoch,
+    pub last_clean_end: Epoch,
+    pub up_from: Epoch,
+    pub up_thru: Epoch,
+    pub down_at: Epoch,
+    pub lost_at: Epoch,
+}
+
+write_decode_encode!(
+    OsdInfo = const version 1 as u8
+        | last_clean_begin
+        | last_clean_end
+        | up_from
+        | up_thru
+        | down_at
+        | lost_at
+);
+
+/// Extended information about an OSD (`osd_xinfo_t`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OsdXInfo {
+    pub down_stamp: Timestamp,
+    /// The probability that the OSD is laggy, where `u32::MAX` is 1.
+    pub laggy_probability: u32,
+    pub laggy_interval: u32,
+    pub features: u64,
+    pub old_weight: u32,
+    pub last_purged_snaps_scrub: Timestamp,
+    pub dead_epoch: Epoch,
+}
+
+impl Decode<'_> for OsdXInfo {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (version, mut data) = ceph_foundation::get_versions_and_data!(OsdXInfo: buffer, 4);
+        let data = &mut data;
+
+        let down_stamp = Decode::decode(data)?;
+        let laggy_probability = Decode::decode(data)?;
+        let laggy_interval = Decode::decode(data)?;
+        let features = Decode::decode_if(version >= 2, data)?.unwrap_or_default();
+        let old_weight = Decode::decode_if(version >= 3, data)?.unwrap_or_default();
+
+        let (last_purged_snaps_scrub, dead_epoch) = if version >= 4 {
+            (Decode::decode(data)?, Decode::decode(data)?)
+        } else {
+            Default::default()
+        };
+
+        Ok(Self {
+            down_stamp,
+            laggy_probability,
+            laggy_interval,
+            features,
+            old_weight,
+            last_purged_snaps_scrub,
+            dead_epoch,
+        })
+    }
+}
+
+impl Encode for OsdXInfo {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 4, 1);
+        self.down_stamp.encode(buffer);
+        self.laggy_probability.encode(buffer);
+        self.laggy_interval.encode(buffer);
+        self.features.encode(buffer);
+        self.old_weight.encode(buffer);
+        self.last_purged_snaps_scrub.encode(buffer);
+        self.dead_epoch.encode(buffer);
+    }
+}
+
+#[test]
+fn xinfo_v3() {
+    #[rustfmt::skip]
+    let data = [
+        3, 1, 28, 0, 0, 0,
+        1, 0, 0, 0, 2, 0, 0, 0, // Down stamp
+        255, 255, 255, 255, // Laggy probability
+        30, 0, 0, 0, // Laggy interval
+        7, 0, 0, 0, 0, 0, 0, 0, // Features
+        0, 0, 1, 0, // Old weight
+    ];
+
+    let xinfo = OsdXInfo::decode(&mut data.as_slice()).unwrap();
+
+    let expected = OsdXInfo {
+        down_stamp: Timestamp::new(1, 2),
+        laggy_probability: u32::MAX,
+        laggy_interval: 30,
+        features: 7,
+        old_weight: 0x10000,
+        last_purged_snaps_scrub: Timestamp::default(),
+        dead_epoch: Epoch(0),
+    };
+
+    assert_eq!(xinfo, expected);
+    assert_eq!(
+        OsdXInfo::decode(&mut xinfo.to_vec().as_slice()).unwrap(),
+        expected
+    );
+}
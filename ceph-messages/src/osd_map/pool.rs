@@ -0,0 +1,589 @@
+use std::collections::{HashMap, HashSet};
+
+use ceph_foundation::{Decode, DecodeError, Encode, Encoder, Timestamp, write_decode_encode};
+
+use crate::Epoch;
+
+use super::{Opaque, PgId};
+
+/// The type of a [`Pool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum PoolType {
+    /// Objects are replicated to `size` OSDs.
+    Replicated = 1,
+    /// Objects are erasure coded over `size` OSDs.
+    Erasure = 3,
+}
+
+impl From<&PoolType> for [u8; 1] {
+    fn from(value: &PoolType) -> Self {
+        [*value as u8]
+    }
+}
+
+impl TryFrom<[u8; 1]> for PoolType {
+    type Error = DecodeError;
+
+    fn try_from([value]: [u8; 1]) -> Result<Self, Self::Error> {
+        let res = match value {
+            1 => Self::Replicated,
+            3 => Self::Erasure,
+            _ => return Err(DecodeError::unknown_value("PoolType", value)),
+        };
+
+        Ok(res)
+    }
+}
+
+/// The flags of a [`Pool`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PoolFlags(pub u64);
+
+impl PoolFlags {
+    /// Placement seeds are hashed with the pool ID.
+    pub const HASHPSPOOL: u64 = 1 << 0;
+    /// The pool is full.
+    pub const FULL: u64 = 1 << 1;
+    /// Overwrites are allowed on this erasure coded pool.
+    pub const EC_OVERWRITES: u64 = 1 << 2;
+    /// The pool has reached its quota.
+    pub const FULL_QUOTA: u64 = 1 << 10;
+    /// The pool uses self-managed snapshots.
+    pub const SELFMANAGED_SNAPS: u64 = 1 << 13;
+    /// The pool uses pool snapshots.
+    pub const POOL_SNAPS: u64 = 1 << 14;
+
+    /// Whether all bits of `flag` are set.
+    pub fn contains(&self, flag: u64) -> bool {
+        self.0 & flag == flag
+    }
+}
+
+impl Decode<'_> for PoolFlags {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self(u64::decode(buffer)?))
+    }
+}
+
+impl Encode for PoolFlags {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        self.0.encode(buffer);
+    }
+}
+
+/// A snapshot of a [`Pool`] (`pool_snap_info_t`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolSnapInfo {
+    pub snap_id: u64,
+    pub stamp: Timestamp,
+    pub name: String,
+}
+
+impl Decode<'_> for PoolSnapInfo {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (_, mut data) = ceph_foundation::get_versions_and_data!(PoolSnapInfo: buffer, 2);
+        let data = &mut data;
+
+        Ok(Self {
+            snap_id: Decode::decode(data)?,
+            stamp: Decode::decode(data)?,
+            name: Decode::decode(data)?,
+        })
+    }
+}
+
+impl Encode for PoolSnapInfo {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 2, 2);
+        self.snap_id.encode(buffer);
+        self.stamp.encode(buffer);
+        self.name.encode(buffer);
+    }
+}
+
+/// A version of an object or PG (`eversion_t`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EVersion {
+    pub version: u64,
+    pub epoch: u32,
+}
+
+write_decode_encode!(EVersion = version | epoch);
+
+/// Information about the last merge of two PGs in a
+/// pool (`pg_merge_meta_t`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgMergeMeta {
+    pub source_pgid: PgId,
+    pub ready_epoch: Epoch,
+    pub last_epoch_started: Epoch,
+    pub last_epoch_clean: Epoch,
+    pub source_version: EVersion,
+    pub target_version: EVersion,
+}
+
+impl Decode<'_> for PgMergeMeta {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (_, mut data) = ceph_foundation::get_versions_and_data!(PgMergeMeta: buffer, 1);
+        let data = &mut data;
+
+        Ok(Self {
+            source_pgid: Decode::decode(data)?,
+            ready_epoch: Decode::decode(data)?,
+            last_epoch_started: Decode::decode(data)?,
+            last_epoch_clean: Decode::decode(data)?,
+            source_version: Decode::decode(data)?,
+            target_version: Decode::decode(data)?,
+        })
+    }
+}
+
+impl Encode for PgMergeMeta {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 1, 1);
+        self.source_pgid.encode(buffer);
+        self.ready_epoch.encode(buffer);
+        self.last_epoch_started.encode(buffer);
+        self.last_epoch_clean.encode(buffer);
+        self.source_version.encode(buffer);
+        self.target_version.encode(buffer);
+    }
+}
+
+/// A pool (`pg_pool_t`).
+///
+/// Pools with encoding versions 27 and up (as sent by Nautilus and
+/// later) can be decoded. Fields that were added in later versions have
+/// their default values if they are missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pool {
+    pub ty: PoolType,
+    pub size: u8,
+    pub min_size: u8,
+    pub crush_rule: u8,
+    pub object_hash: u8,
+    pub pg_num: u32,
+    pub pgp_num: u32,
+    pub last_change: Epoch,
+    pub snap_seq: u64,
+    pub snap_epoch: Epoch,
+    pub snaps: HashMap<u64, PoolSnapInfo>,
+    /// Removed snapshots, as a map from start to length.
+    pub removed_snaps: HashMap<u64, u64>,
+    pub auid: u64,
+    pub flags: PoolFlags,
+    pub quota_max_bytes: u64,
+    pub quota_max_objects: u64,
+    pub tiers: HashSet<u64>,
+    pub tier_of: i64,
+    pub cache_mode: u8,
+    pub read_tier: i64,
+    pub write_tier: i64,
+    pub properties: HashMap<String, String>,
+    /// The encoded `HitSet::Params` of the pool.
+    pub hit_set_params: Opaque,
+    pub hit_set_period: u32,
+    pub hit_set_count: u32,
+    pub stripe_width: u32,
+    pub target_max_bytes: u64,
+    pub target_max_objects: u64,
+    pub cache_target_dirty_ratio_micro: u32,
+    pub cache_target_full_ratio_micro: u32,
+    pub cache_min_flush_age: u32,
+    pub cache_min_evict_age: u32,
+    pub erasure_code_profile: String,
+    pub last_force_op_resend_preluminous: Epoch,
+    pub min_read_recency_for_promote: u32,
+    pub expected_num_objects: u64,
+    pub cache_target_dirty_high_ratio_micro: u32,
+    pub min_write_recency_for_promote: u32,
+    pub use_gmt_hitset: bool,
+    pub fast_read: bool,
+    pub hit_set_grade_decay_rate: u32,
+    pub hit_set_search_last_n: u32,
+    /// The encoded `pool_opts_t` of the pool.
+    pub opts: Opaque,
+    pub last_force_op_resend_prenautilus: Epoch,
+    pub application_metadata: HashMap<String, HashMap<String, String>>,
+    pub create_time: Timestamp,
+    pub pg_num_target: u32,
+    pub pgp_num_target: u32,
+    pub pg_num_pending: u32,
+    pub last_force_op_resend: Epoch,
+    pub pg_autoscale_mode: u8,
+    pub last_pg_merge_meta: PgMergeMeta,
+    pub peering_crush_bucket_count: u32,
+    pub peering_crush_bucket_target: u32,
+    pub peering_crush_bucket_barrier: u32,
+    pub peering_crush_mandatory_member: i32,
+}
+
+impl Pool {
+    /// The oldest encoding version that we can decode.
+    const MIN_VERSION: u8 = 27;
+
+    /// Whether this is a stretch pool.
+    pub fn is_stretch_pool(&self) -> bool {
+        self.peering_crush_bucket_count != 0
+    }
+}
+
+impl Decode<'_> for Pool {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (version, mut data) = ceph_foundation::get_versions_and_data!(Pool: buffer, 30);
+        let data = &mut data;
+
+        if version < Self::MIN_VERSION {
+            return Err(DecodeError::UnexpectedVersion {
+                ty: "Pool",
+                got: version,
+                expected: Self::MIN_VERSION..=30,
+            });
+        }
+
+        let ty = PoolType::try_from(<[u8; 1]>::decode(data)?)?;
+        let [size] = Decode::decode(data)?;
+        let [crush_rule] = Decode::decode(data)?;
+        let [object_hash] = Decode::decode(data)?;
+        let pg_num = u32::decode(data)?;
+        let pgp_num = u32::decode(data)?;
+        let _lpg_num = u32::decode(data)?;
+        let _lpgp_num = u32::decode(data)?;
+        let last_change = Decode::decode(data)?;
+        let snap_seq = Decode::decode(data)?;
+        let snap_epoch = Decode::decode(data)?;
+        let snaps = Decode::decode(data)?;
+        let removed_snaps = Decode::decode(data)?;
+        let auid = Decode::decode(data)?;
+        let flags = Decode::decode(data)?;
+        let _crash_replay_interval = u32::decode(data)?;
+        let [min_size] = Decode::decode(data)?;
+        let quota_max_bytes = Decode::decode(data)?;
+        let quota_max_objects = Decode::decode(data)?;
+        let tiers = Decode::decode(data)?;
+        let tier_of = Decode::decode(data)?;
+        let [cache_mode] = Decode::decode(data)?;
+        let read_tier = Decode::decode(data)?;
+        let write_tier = Decode::decode(data)?;
+        let properties = Decode::decode(data)?;
+        let hit_set_params = Decode::decode(data)?;
+        let hit_set_period = Decode::decode(data)?;
+        let hit_set_count = Decode::decode(data)?;
+        let stripe_width = Decode::decode(data)?;
+        let target_max_bytes = Decode::decode(data)?;
+        let target_max_objects = Decode::decode(data)?;
+        let cache_target_dirty_ratio_micro = Decode::decode(data)?;
+        let cache_target_full_ratio_micro = Decode::decode(data)?;
+        let cache_min_flush_age = Decode::decode(data)?;
+        let cache_min_evict_age = Decode::decode(data)?;
+        let erasure_code_profile = Decode::decode(data)?;
+        let last_force_op_resend_preluminous = Decode::decode(data)?;
+        let min_read_recency_for_promote = Decode::decode(data)?;
+        let expected_num_objects = Decode::decode(data)?;
+        let cache_target_dirty_high_ratio_micro = Decode::decode(data)?;
+        let min_write_recency_for_promote = Decode::decode(data)?;
+        let use_gmt_hitset = Decode::decode(data)?;
+        let fast_read = Decode::decode(data)?;
+        let hit_set_grade_decay_rate = Decode::decode(data)?;
+        let hit_set_search_last_n = Decode::decode(data)?;
+        let opts = Decode::decode(data)?;
+        let last_force_op_resend_prenautilus = Decode::decode(data)?;
+        let application_metadata = Decode::decode(data)?;
+        let create_time = Decode::decode(data)?;
+
+        let (pg_num_target, pgp_num_target, pg_num_pending, last_force_op_resend) = if version >= 28
+        {
+            let pg_num_target = u32::decode(data)?;
+            let pgp_num_target = u32::decode(data)?;
+            let pg_num_pending = u32::decode(data)?;
+            let _pg_num_dec_last_epoch_started = Epoch::decode(data)?;
+            let _pg_num_dec_last_epoch_clean = Epoch::decode(data)?;
+            let last_force_op_resend = Epoch::decode(data)?;
+
+            (
+                pg_num_target,
+                pgp_num_target,
+                pg_num_pending,
+                last_force_op_resend,
+            )
+        } else {
+            (pg_num, pgp_num, pg_num, last_force_op_resend_prenautilus)
+        };
+
+        let [pg_autoscale_mode] = Decode::decode_if(version >= 28, data)?.unwrap_or_default();
+        let last_pg_merge_meta = Decode::decode_if(version >= 29, data)?.unwrap_or_default();
+
+        let (
+            peering_crush_bucket_count,
+            peering_crush_bucket_target,
+            peering_crush_bucket_barrier,
+            peering_crush_mandatory_member,
+        ) = if version >= 30 {
+            (
+                Decode::decode(data)?,
+                Decode::decode(data)?,
+                Decode::decode(data)?,
+                Decode::decode(data)?,
+            )
+        } else {
+            // `CRUSH_ITEM_NONE`
+            (0, 0, 0, 0x7fffffff)
+        };
+
+        Ok(Self {
+            ty,
+            size,
+            min_size,
+            crush_rule,
+            object_hash,
+            pg_num,
+            pgp_num,
+            last_change,
+            snap_seq,
+            snap_epoch,
+            snaps,
+            removed_snaps,
+            auid,
+            flags,
+            quota_max_bytes,
+            quota_max_objects,
+            tiers,
+            tier_of,
+            cache_mode,
+            read_tier,
+            write_tier,
+            properties,
+            hit_set_params,
+            hit_set_period,
+            hit_set_count,
+            stripe_width,
+            target_max_bytes,
+            target_max_objects,
+            cache_target_dirty_ratio_micro,
+            cache_target_full_ratio_micro,
+            cache_min_flush_age,
+            cache_min_evict_age,
+            erasure_code_profile,
+            last_force_op_resend_preluminous,
+            min_read_recency_for_promote,
+            expected_num_objects,
+            cache_target_dirty_high_ratio_micro,
+            min_write_recency_for_promote,
+            use_gmt_hitset,
+            fast_read,
+            hit_set_grade_decay_rate,
+            hit_set_search_last_n,
+            opts,
+            last_force_op_resend_prenautilus,
+            application_metadata,
+            create_time,
+            pg_num_target,
+            pgp_num_target,
+            pg_num_pending,
+            last_force_op_resend,
+            pg_autoscale_mode,
+            last_pg_merge_meta,
+            peering_crush_bucket_count,
+            peering_crush_bucket_target,
+            peering_crush_bucket_barrier,
+            peering_crush_mandatory_member,
+        })
+    }
+}
+
+impl Encode for Pool {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        // Like ceph, only use version 30 for stretch pools.
+        let version = if self.is_stretch_pool() { 30 } else { 29 };
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, version, 5);
+
+        <[u8; 1]>::from(&self.ty).encode(buffer);
+        buffer.push(self.size);
+        buffer.push(self.crush_rule);
+        buffer.push(self.object_hash);
+        self.pg_num.encode(buffer);
+        self.pgp_num.encode(buffer);
+        // lpg_num and lpgp_num
+        0u32.encode(buffer);
+        0u32.encode(buffer);
+        self.last_change.encode(buffer);
+        self.snap_seq.encode(buffer);
+        self.snap_epoch.encode(buffer);
+        self.snaps.encode(buffer);
+        self.removed_snaps.encode(buffer);
+        self.auid.encode(buffer);
+        self.flags.encode(buffer);
+        // crash_replay_interval
+        0u32.encode(buffer);
+        buffer.push(self.min_size);
+        self.quota_max_bytes.encode(buffer);
+        self.quota_max_objects.encode(buffer);
+        self.tiers.encode(buffer);
+        self.tier_of.encode(buffer);
+        buffer.push(self.cache_mode);
+        self.read_tier.encode(buffer);
+        self.write_tier.encode(buffer);
+        self.properties.encode(buffer);
+        self.hit_set_params.encode(buffer);
+        self.hit_set_period.encode(buffer);
+        self.hit_set_count.encode(buffer);
+        self.stripe_width.encode(buffer);
+        self.target_max_bytes.encode(buffer);
+        self.target_max_objects.encode(buffer);
+        self.cache_target_dirty_ratio_micro.encode(buffer);
+        self.cache_target_full_ratio_micro.encode(buffer);
+        self.cache_min_flush_age.encode(buffer);
+        self.cache_min_evict_age.encode(buffer);
+        self.erasure_code_profile.encode(buffer);
+        self.last_force_op_resend_preluminous.encode(buffer);
+        self.min_read_recency_for_promote.encode(buffer);
+        self.expected_num_objects.encode(buffer);
+        self.cache_target_dirty_high_ratio_micro.encode(buffer);
+        self.min_write_recency_for_promote.encode(buffer);
+        self.use_gmt_hitset.encode(buffer);
+        self.fast_read.encode(buffer);
+        self.hit_set_grade_decay_rate.encode(buffer);
+        self.hit_set_search_last_n.encode(buffer);
+        self.opts.encode(buffer);
+        self.last_force_op_resend_prenautilus.encode(buffer);
+        self.application_metadata.encode(buffer);
+        self.create_time.encode(buffer);
+        self.pg_num_target.encode(buffer);
+        self.pgp_num_target.encode(buffer);
+        self.pg_num_pending.encode(buffer);
+        // pg_num_dec_last_epoch_started and pg_num_dec_last_epoch_clean
+        0u32.encode(buffer);
+        0u32.encode(buffer);
+        self.last_force_op_resend.encode(buffer);
+        buffer.push(self.pg_autoscale_mode);
+        self.last_pg_merge_meta.encode(buffer);
+
+        if version >= 30 {
+            self.peering_crush_bucket_count.encode(buffer);
+            self.peering_crush_bucket_target.encode(buffer);
+            self.peering_crush_bucket_barrier.encode(buffer);
+            self.peering_crush_mandatory_member.encode(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn pool() -> Pool {
+    Pool {
+        ty: PoolType::Replicated,
+        size: 3,
+        min_size: 2,
+        crush_rule: 0,
+        object_hash: 2,
+        pg_num: 32,
+        pgp_num: 32,
+        last_change: Epoch(17),
+        snap_seq: 3,
+        snap_epoch: Epoch(16),
+        snaps: [(
+            3,
+            PoolSnapInfo {
+                snap_id: 3,
+                stamp: Timestamp::new(1000, 2),
+                name: "snap".to_string(),
+            },
+        )]
+        .into(),
+        removed_snaps: [(1, 2)].into(),
+        auid: 0,
+        flags: PoolFlags(PoolFlags::HASHPSPOOL | PoolFlags::POOL_SNAPS),
+        quota_max_bytes: 1 << 30,
+        quota_max_objects: 0,
+        tiers: HashSet::new(),
+        tier_of: -1,
+        cache_mode: 0,
+        read_tier: -1,
+        write_tier: -1,
+        properties: HashMap::new(),
+        hit_set_params: Opaque {
+            version: 1,
+            compat: 1,
+            data: vec![0],
+        },
+        hit_set_period: 0,
+        hit_set_count: 0,
+        stripe_width: 0,
+        target_max_bytes: 0,
+        target_max_objects: 0,
+        cache_target_dirty_ratio_micro: 400_000,
+        cache_target_full_ratio_micro: 800_000,
+        cache_min_flush_age: 0,
+        cache_min_evict_age: 0,
+        erasure_code_profile: String::new(),
+        last_force_op_resend_preluminous: Epoch(0),
+        min_read_recency_for_promote: 0,
+        expected_num_objects: 0,
+        cache_target_dirty_high_ratio_micro: 600_000,
+        min_write_recency_for_promote: 0,
+        use_gmt_hitset: true,
+        fast_read: false,
+        hit_set_grade_decay_rate: 0,
+        hit_set_search_last_n: 0,
+        opts: Opaque {
+            version: 2,
+            compat: 1,
+            data: vec![0; 4],
+        },
+        last_force_op_resend_prenautilus: Epoch(0),
+        application_metadata: [("rbd".to_string(), HashMap::new())].into(),
+        create_time: Timestamp::new(1000, 0),
+        pg_num_target: 32,
+        pgp_num_target: 32,
+        pg_num_pending: 32,
+        last_force_op_resend: Epoch(0),
+        pg_autoscale_mode: 1,
+        last_pg_merge_meta: PgMergeMeta::default(),
+        peering_crush_bucket_count: 0,
+        peering_crush_bucket_target: 0,
+        peering_crush_bucket_barrier: 0,
+        peering_crush_mandatory_member: 0x7fffffff,
+    }
+}
+
+#[test]
+fn pool_roundtrip() {
+    let mut pool = pool();
+
+    let encoded = pool.to_vec();
+    assert_eq!(&encoded[..2], [29, 5]);
+    assert_eq!(Pool::decode(&mut encoded.as_slice()).unwrap(), pool);
+
+    pool.peering_crush_bucket_count = 2;
+    pool.peering_crush_mandatory_member = 5;
+
+    let encoded = pool.to_vec();
+    assert_eq!(&encoded[..2], [30, 5]);
+    assert_eq!(Pool::decode(&mut encoded.as_slice()).unwrap(), pool);
+}
+
+#[test]
+fn pool_too_old() {
+    let mut encoded = pool().to_vec();
+    encoded[0] = 26;
+
+    assert!(matches!(
+        Pool::decode(&mut encoded.as_slice()),
+        Err(DecodeError::UnexpectedVersion { got: 26, .. })
+    ));
+}
+
+#[test]
+fn pool_newer_version() {
+    // Fields that we do not know about yet are ignored.
+    let mut pool = pool();
+    pool.peering_crush_bucket_count = 2;
+
+    let mut encoded = pool.to_vec();
+    encoded[0] = 31;
+    encoded.extend_from_slice(&[1, 2, 3, 4]);
+    let len = u32::from_le_bytes(encoded[2..6].try_into().unwrap()) + 4;
+    encoded[2..6].copy_from_slice(&len.to_le_bytes());
+
+    assert_eq!(Pool::decode(&mut encoded.as_slice()).unwrap(), pool);
+}
@@ -0,0 +1,971 @@
+use std::collections::{HashMap, HashSet};
+
+use ceph_foundation::{
+    Decode, DecodeError, Encode, Encoder, Timestamp, WireString, entity::EntityType,
+};
+
+use crate::{
+    DecodeMessage, DecodeMessageError, EVersion, EncodeMessage, Epoch, PgId,
+    mon_command::{front_and_data, push_front_and_data},
+};
+
+/// The size of the union of op-specific arguments in `ceph_osd_op`.
+const ARGS_LEN: usize = 28;
+
+/// An empty `blkin_trace_info`.
+const EMPTY_TRACE: [u8; 24] = [0; 24];
+
+/// The flags of a [`MessageOsdOp`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OsdOpFlags(pub u32);
+
+impl OsdOpFlags {
+    /// Reply when the op has been applied.
+    pub const ACK: u32 = 1 << 0;
+    /// Reply when the op has been committed to NVRAM.
+    pub const ONNVRAM: u32 = 1 << 1;
+    /// Reply when the op has been committed to disk.
+    pub const ONDISK: u32 = 1 << 2;
+    /// The op is being resent.
+    pub const RETRY: u32 = 1 << 3;
+    /// The op reads data.
+    pub const READ: u32 = 1 << 4;
+    /// The op writes data.
+    pub const WRITE: u32 = 1 << 5;
+    /// The op may be served by any replica.
+    pub const BALANCE_READS: u32 = 1 << 8;
+    /// The op is a PG op.
+    pub const PGOP: u32 = 1 << 10;
+    /// The op may be served by a nearby replica.
+    pub const LOCALIZE_READS: u32 = 1 << 13;
+    /// The op is ordered with respect to other reads and writes.
+    pub const RWORDERED: u32 = 1 << 14;
+    /// The op should be attempted even if the cluster is full.
+    pub const FULL_TRY: u32 = 1 << 23;
+    /// The op should be applied even if the cluster is full.
+    pub const FULL_FORCE: u32 = 1 << 24;
+    /// Return the result and output of every op, even on failure.
+    pub const RETURNVEC: u32 = 1 << 26;
+
+    /// Whether all bits of `flag` are set.
+    pub fn contains(&self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+}
+
+impl Decode<'_> for OsdOpFlags {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self(u32::decode(buffer)?))
+    }
+}
+
+impl Encode for OsdOpFlags {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        self.0.encode(buffer);
+    }
+}
+
+/// The location of an object (`object_locator_t`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLocator {
+    pub pool: i64,
+    /// A key that is hashed instead of the object name, if not empty.
+    pub key: String,
+    pub namespace: String,
+    /// An explicit hash that is used instead of the object name,
+    /// or `-1`.
+    pub hash: i64,
+}
+
+impl ObjectLocator {
+    /// A locator for objects in the default namespace of `pool`.
+    pub fn new(pool: i64) -> Self {
+        Self {
+            pool,
+            key: String::new(),
+            namespace: String::new(),
+            hash: -1,
+        }
+    }
+}
+
+impl Decode<'_> for ObjectLocator {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (version, mut data) = ceph_foundation::get_versions_and_data!(ObjectLocator: buffer, 6);
+        let data = &mut data;
+
+        let pool = Decode::decode(data)?;
+        let _preferred = i32::decode(data)?;
+        let key = Decode::decode(data)?;
+        let namespace = Decode::decode_if(version >= 5, data)?.unwrap_or_default();
+        let hash = Decode::decode_if(version >= 6, data)?.unwrap_or(-1);
+
+        Ok(Self {
+            pool,
+            key,
+            namespace,
+            hash,
+        })
+    }
+}
+
+impl Encode for ObjectLocator {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        // Older code can decode this, unless it has to interpret the hash.
+        let compat = if self.hash != -1 { 6 } else { 3 };
+
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 6, compat);
+        self.pool.encode(buffer);
+        // There is no preferred OSD.
+        (-1i32).encode(buffer);
+        self.key.encode(buffer);
+        self.namespace.encode(buffer);
+        self.hash.encode(buffer);
+    }
+}
+
+/// A placement group and the shard of an erasure coded pool (`spg_t`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SpgId {
+    pub pgid: PgId,
+    /// The shard, or `-1` for replicated pools.
+    pub shard: i8,
+}
+
+impl SpgId {
+    /// The shard of PGs of replicated pools.
+    pub const NO_SHARD: i8 = -1;
+}
+
+impl Decode<'_> for SpgId {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (_, mut data) = ceph_foundation::get_versions_and_data!(SpgId: buffer, 1);
+        let data = &mut data;
+
+        Ok(Self {
+            pgid: Decode::decode(data)?,
+            shard: Decode::decode(data)?,
+        })
+    }
+}
+
+impl Encode for SpgId {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 1, 1);
+        self.pgid.encode(buffer);
+        self.shard.encode(buffer);
+    }
+}
+
+/// A unique ID of a request (`osd_reqid_t`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsdReqId {
+    /// The type of the entity that sent the request.
+    pub entity_type: EntityType,
+    /// The number of the entity that sent the request, which
+    /// is the global ID for clients.
+    pub entity_num: i64,
+    pub tid: u64,
+    /// The incarnation of the client.
+    pub inc: i32,
+}
+
+impl Decode<'_> for OsdReqId {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (_, mut data) = ceph_foundation::get_versions_and_data!(OsdReqId: buffer, 2);
+        let data = &mut data;
+
+        let [entity_type] = Decode::decode(data)?;
+
+        Ok(Self {
+            entity_type: EntityType::try_from(entity_type)?,
+            entity_num: Decode::decode(data)?,
+            tid: Decode::decode(data)?,
+            inc: Decode::decode(data)?,
+        })
+    }
+}
+
+impl Encode for OsdReqId {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 2, 2);
+        buffer.push(u8::from(self.entity_type));
+        self.entity_num.encode(buffer);
+        self.tid.encode(buffer);
+        self.inc.encode(buffer);
+    }
+}
+
+/// The op-specific arguments of an [`OsdOp`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsdOpArgs {
+    /// Arguments of data and omap ops.
+    Extent {
+        offset: u64,
+        length: u64,
+        truncate_size: u64,
+        truncate_seq: u32,
+    },
+    /// Arguments of extended attribute ops.
+    Xattr {
+        name_len: u32,
+        value_len: u32,
+        cmp_op: u8,
+        cmp_mode: u8,
+    },
+    /// Arguments of [`OsdOp::CALL`].
+    Call {
+        class_len: u8,
+        method_len: u8,
+        argc: u8,
+        indata_len: u32,
+    },
+    /// Arguments of other ops.
+    Raw([u8; ARGS_LEN]),
+}
+
+impl OsdOpArgs {
+    fn extent(offset: u64, length: u64) -> Self {
+        Self::Extent {
+            offset,
+            length,
+            truncate_size: 0,
+            truncate_seq: 0,
+        }
+    }
+
+    fn decode(op: u16, buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let raw: [u8; ARGS_LEN] = Decode::decode(buffer)?;
+        let args = &mut raw.as_slice();
+
+        let res = match op & OsdOp::TYPE_MASK {
+            OsdOp::TYPE_DATA => Self::Extent {
+                offset: Decode::decode(args)?,
+                length: Decode::decode(args)?,
+                truncate_size: Decode::decode(args)?,
+                truncate_seq: Decode::decode(args)?,
+            },
+            OsdOp::TYPE_ATTR => {
+                let name_len = Decode::decode(args)?;
+                let value_len = Decode::decode(args)?;
+                let [cmp_op, cmp_mode] = Decode::decode(args)?;
+
+                Self::Xattr {
+                    name_len,
+                    value_len,
+                    cmp_op,
+                    cmp_mode,
+                }
+            }
+            _ if op == OsdOp::CALL => {
+                let [class_len, method_len, argc] = Decode::decode(args)?;
+
+                Self::Call {
+                    class_len,
+                    method_len,
+                    argc,
+                    indata_len: Decode::decode(args)?,
+                }
+            }
+            _ => Self::Raw(raw),
+        };
+
+        Ok(res)
+    }
+}
+
+impl Encode for OsdOpArgs {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let mut raw = Vec::with_capacity(ARGS_LEN);
+
+        match self {
+            OsdOpArgs::Extent {
+                offset,
+                length,
+                truncate_size,
+                truncate_seq,
+            } => {
+                offset.encode(&mut raw);
+                length.encode(&mut raw);
+                truncate_size.encode(&mut raw);
+                truncate_seq.encode(&mut raw);
+            }
+            OsdOpArgs::Xattr {
+                name_len,
+                value_len,
+                cmp_op,
+                cmp_mode,
+            } => {
+                name_len.encode(&mut raw);
+                value_len.encode(&mut raw);
+                raw.extend_from_slice(&[*cmp_op, *cmp_mode]);
+            }
+            OsdOpArgs::Call {
+                class_len,
+                method_len,
+                argc,
+                indata_len,
+            } => {
+                raw.extend_from_slice(&[*class_len, *method_len, *argc]);
+                indata_len.encode(&mut raw);
+            }
+            OsdOpArgs::Raw(data) => raw.extend_from_slice(data),
+        }
+
+        raw.resize(ARGS_LEN, 0);
+        buffer.extend_from_slice(&raw);
+    }
+}
+
+/// A single operation on an object (`OSDOp`).
+///
+/// A [`MessageOsdOp`] carries a vector of these, which are applied
+/// to the object atomically.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsdOp {
+    pub op: u16,
+    pub flags: u32,
+    pub args: OsdOpArgs,
+    /// The input data of the op in requests, and its output
+    /// data in replies.
+    pub data: Vec<u8>,
+    /// The result of the op, only present in replies.
+    pub rval: i32,
+}
+
+impl OsdOp {
+    const MODE_RD: u16 = 0x1000;
+    const MODE_WR: u16 = 0x2000;
+    const TYPE_MASK: u16 = 0x0f00;
+    const TYPE_DATA: u16 = 0x0200;
+    const TYPE_ATTR: u16 = 0x0300;
+    const TYPE_EXEC: u16 = 0x0400;
+
+    pub const READ: u16 = Self::MODE_RD | Self::TYPE_DATA | 1;
+    pub const STAT: u16 = Self::MODE_RD | Self::TYPE_DATA | 2;
+    pub const OMAP_GET_KEYS: u16 = Self::MODE_RD | Self::TYPE_DATA | 17;
+    pub const OMAP_GET_VALS: u16 = Self::MODE_RD | Self::TYPE_DATA | 18;
+    pub const OMAP_GET_HEADER: u16 = Self::MODE_RD | Self::TYPE_DATA | 19;
+    pub const OMAP_GET_VALS_BY_KEYS: u16 = Self::MODE_RD | Self::TYPE_DATA | 20;
+    pub const WRITE: u16 = Self::MODE_WR | Self::TYPE_DATA | 1;
+    pub const WRITE_FULL: u16 = Self::MODE_WR | Self::TYPE_DATA | 2;
+    pub const TRUNCATE: u16 = Self::MODE_WR | Self::TYPE_DATA | 3;
+    pub const ZERO: u16 = Self::MODE_WR | Self::TYPE_DATA | 4;
+    pub const DELETE: u16 = Self::MODE_WR | Self::TYPE_DATA | 5;
+    pub const APPEND: u16 = Self::MODE_WR | Self::TYPE_DATA | 6;
+    pub const CREATE: u16 = Self::MODE_WR | Self::TYPE_DATA | 13;
+    pub const OMAP_SET_VALS: u16 = Self::MODE_WR | Self::TYPE_DATA | 21;
+    pub const OMAP_SET_HEADER: u16 = Self::MODE_WR | Self::TYPE_DATA | 22;
+    pub const OMAP_CLEAR: u16 = Self::MODE_WR | Self::TYPE_DATA | 23;
+    pub const OMAP_RM_KEYS: u16 = Self::MODE_WR | Self::TYPE_DATA | 24;
+    pub const GET_XATTR: u16 = Self::MODE_RD | Self::TYPE_ATTR | 1;
+    pub const GET_XATTRS: u16 = Self::MODE_RD | Self::TYPE_ATTR | 2;
+    pub const SET_XATTR: u16 = Self::MODE_WR | Self::TYPE_ATTR | 1;
+    pub const RM_XATTR: u16 = Self::MODE_WR | Self::TYPE_ATTR | 3;
+    pub const CALL: u16 = Self::MODE_RD | Self::TYPE_EXEC | 1;
+
+    /// Fail if the object already exists ([`OsdOp::CREATE`]).
+    pub const FLAG_EXCL: u32 = 1 << 0;
+    /// Ignore the failure of this op.
+    pub const FLAG_FAILOK: u32 = 1 << 1;
+
+    fn new(op: u16, args: OsdOpArgs, data: Vec<u8>) -> Self {
+        Self {
+            op,
+            flags: 0,
+            args,
+            data,
+            rval: 0,
+        }
+    }
+
+    /// An op whose input is `data`, which is described by its extent.
+    fn with_input(op: u16, data: Vec<u8>) -> Self {
+        Self::new(op, OsdOpArgs::extent(0, data.len() as u64), data)
+    }
+
+    /// Whether this op reads data.
+    pub fn is_read(&self) -> bool {
+        self.op & Self::MODE_RD != 0
+    }
+
+    /// Whether this op modifies the object.
+    pub fn is_write(&self) -> bool {
+        self.op & Self::MODE_WR != 0
+    }
+
+    /// Read `length` bytes at `offset`, where a `length` of zero
+    /// reads the whole object.
+    pub fn read(offset: u64, length: u64) -> Self {
+        Self::new(Self::READ, OsdOpArgs::extent(offset, length), Vec::new())
+    }
+
+    /// Write `data` at `offset`.
+    pub fn write(offset: u64, data: Vec<u8>) -> Self {
+        Self::new(
+            Self::WRITE,
+            OsdOpArgs::extent(offset, data.len() as u64),
+            data,
+        )
+    }
+
+    /// Replace the contents of the object with `data`.
+    pub fn write_full(data: Vec<u8>) -> Self {
+        Self::with_input(Self::WRITE_FULL, data)
+    }
+
+    /// Append `data` to the object.
+    pub fn append(data: Vec<u8>) -> Self {
+        Self::with_input(Self::APPEND, data)
+    }
+
+    /// Truncate (or extend) the object to `size` bytes.
+    pub fn truncate(size: u64) -> Self {
+        Self::new(Self::TRUNCATE, OsdOpArgs::extent(size, 0), Vec::new())
+    }
+
+    /// Get the size and modification time of the object, which
+    /// can be decoded with [`OsdOp::stat_output`].
+    pub fn stat() -> Self {
+        Self::new(Self::STAT, OsdOpArgs::extent(0, 0), Vec::new())
+    }
+
+    /// Delete the object.
+    pub fn delete() -> Self {
+        Self::new(Self::DELETE, OsdOpArgs::extent(0, 0), Vec::new())
+    }
+
+    /// Create the object, failing if it exists and `exclusive` is set.
+    pub fn create(exclusive: bool) -> Self {
+        let mut op = Self::new(Self::CREATE, OsdOpArgs::extent(0, 0), Vec::new());
+        if exclusive {
+            op.flags |= Self::FLAG_EXCL;
+        }
+        op
+    }
+
+    fn xattr(op: u16, name: &str, value: &[u8]) -> Self {
+        let args = OsdOpArgs::Xattr {
+            name_len: name.len() as u32,
+            value_len: value.len() as u32,
+            cmp_op: 0,
+            cmp_mode: 0,
+        };
+
+        let mut data = Vec::with_capacity(name.len() + value.len());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(value);
+
+        Self::new(op, args, data)
+    }
+
+    /// Get the value of the extended attribute `name`.
+    pub fn get_xattr(name: &str) -> Self {
+        Self::xattr(Self::GET_XATTR, name, &[])
+    }
+
+    /// Set the extended attribute `name` to `value`.
+    pub fn set_xattr(name: &str, value: &[u8]) -> Self {
+        Self::xattr(Self::SET_XATTR, name, value)
+    }
+
+    /// Remove the extended attribute `name`.
+    pub fn rm_xattr(name: &str) -> Self {
+        Self::xattr(Self::RM_XATTR, name, &[])
+    }
+
+    /// Get at most `max` omap keys after `start_after`, which
+    /// can be decoded with [`OsdOp::omap_keys_output`].
+    pub fn omap_get_keys(start_after: &str, max: u64) -> Self {
+        let mut data = Vec::new();
+        WireString::from(start_after).encode(&mut data);
+        max.encode(&mut data);
+
+        Self::with_input(Self::OMAP_GET_KEYS, data)
+    }
+
+    /// Get at most `max` omap entries after `start_after` whose keys
+    /// start with `prefix`, which can be decoded with
+    /// [`OsdOp::omap_vals_output`].
+    pub fn omap_get_vals(start_after: &str, prefix: &str, max: u64) -> Self {
+        let mut data = Vec::new();
+        WireString::from(start_after).encode(&mut data);
+        max.encode(&mut data);
+        WireString::from(prefix).encode(&mut data);
+
+        Self::with_input(Self::OMAP_GET_VALS, data)
+    }
+
+    /// Get the omap entries with `keys`, which can be decoded
+    /// with [`OsdOp::omap_vals_output`].
+    pub fn omap_get_vals_by_keys(keys: &HashSet<String>) -> Self {
+        Self::with_input(Self::OMAP_GET_VALS_BY_KEYS, keys.to_vec())
+    }
+
+    /// Set the omap entries in `values`.
+    pub fn omap_set(values: &HashMap<String, Vec<u8>>) -> Self {
+        let mut data = Vec::new();
+        (values.len() as u32).encode(&mut data);
+
+        for (key, value) in values {
+            key.encode(&mut data);
+            value.as_slice().encode(&mut data);
+        }
+
+        Self::with_input(Self::OMAP_SET_VALS, data)
+    }
+
+    /// Remove the omap entries with `keys`.
+    pub fn omap_rm_keys(keys: &HashSet<String>) -> Self {
+        Self::with_input(Self::OMAP_RM_KEYS, keys.to_vec())
+    }
+
+    /// Call `method` of object class `class` with `input`.
+    pub fn call(class: &str, method: &str, input: &[u8]) -> Self {
+        let args = OsdOpArgs::Call {
+            class_len: u8::try_from(class.len()).expect("Class name too long"),
+            method_len: u8::try_from(method.len()).expect("Method name too long"),
+            argc: 0,
+            indata_len: input.len() as u32,
+        };
+
+        let mut data = Vec::with_capacity(class.len() + method.len() + input.len());
+        data.extend_from_slice(class.as_bytes());
+        data.extend_from_slice(method.as_bytes());
+        data.extend_from_slice(input);
+
+        Self::new(Self::CALL, args, data)
+    }
+
+    /// Decode the size and modification time in the output
+    /// of [`OsdOp::stat`].
+    pub fn stat_output(&self) -> Result<(u64, Timestamp), DecodeError> {
+        let data = &mut self.data.as_slice();
+        Ok((Decode::decode(data)?, Decode::decode(data)?))
+    }
+
+    /// Decode the keys in the output of [`OsdOp::omap_get_keys`], and
+    /// whether there are more keys.
+    pub fn omap_keys_output(&self) -> Result<(HashSet<String>, bool), DecodeError> {
+        let data = &mut self.data.as_slice();
+        let keys = Decode::decode(data)?;
+        let more = Decode::decode_if(!data.is_empty(), data)?.unwrap_or(false);
+        Ok((keys, more))
+    }
+
+    /// Decode the entries in the output of [`OsdOp::omap_get_vals`] or
+    /// [`OsdOp::omap_get_vals_by_keys`], and whether there are more
+    /// entries.
+    pub fn omap_vals_output(&self) -> Result<(HashMap<String, Vec<u8>>, bool), DecodeError> {
+        let data = &mut self.data.as_slice();
+        let values = Decode::decode(data)?;
+        let more = Decode::decode_if(!data.is_empty(), data)?.unwrap_or(false);
+        Ok((values, more))
+    }
+
+    /// Decode the `ceph_osd_op` of an op, without its data.
+    fn decode_header(buffer: &mut &[u8]) -> Result<(Self, u32), DecodeError> {
+        let op = u16::decode(buffer)?;
+        let flags = Decode::decode(buffer)?;
+        let args = OsdOpArgs::decode(op, buffer)?;
+        let payload_len = Decode::decode(buffer)?;
+
+        Ok((
+            Self::new(op, args, Vec::new()).with_flags(flags),
+            payload_len,
+        ))
+    }
+
+    fn with_flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Encode the `ceph_osd_op` of this op, without its data.
+    fn encode_header(&self, buffer: &mut impl Encoder) {
+        self.op.encode(buffer);
+        self.flags.encode(buffer);
+        self.args.encode(buffer);
+        (self.data.len() as u32).encode(buffer);
+    }
+}
+
+/// Distribute `data` over `ops`, according to their payload lengths.
+fn split_data(ops: &mut [(OsdOp, u32)], mut data: &[u8]) -> Result<(), DecodeError> {
+    for (op, len) in ops {
+        let Some((payload, rest)) = data.split_at_checked(*len as usize) else {
+            return Err(DecodeError::NotEnoughData {
+                field: Some("op data"),
+                have: data.len(),
+                need: *len as usize,
+            });
+        };
+
+        op.data = payload.to_vec();
+        data = rest;
+    }
+
+    Ok(())
+}
+
+fn merge_data(ops: &[OsdOp]) -> Vec<u8> {
+    ops.iter().flat_map(|op| op.data.iter().copied()).collect()
+}
+
+/// A request to perform ops on an object (`MOSDOp`).
+///
+/// Only the encoding used by Nautilus and later (version 8) is
+/// supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageOsdOp {
+    pub pgid: SpgId,
+    /// The full hash of the object name.
+    pub hash: u32,
+    pub epoch: Epoch,
+    pub flags: OsdOpFlags,
+    pub reqid: OsdReqId,
+    pub client_inc: i32,
+    pub mtime: Timestamp,
+    pub locator: ObjectLocator,
+    pub oid: String,
+    pub ops: Vec<OsdOp>,
+    /// The snapshot to read from.
+    pub snap_id: u64,
+    /// The snapshot context of writes.
+    pub snap_seq: u64,
+    pub snaps: Vec<u64>,
+    pub retry_attempt: i32,
+    pub features: u64,
+}
+
+impl MessageOsdOp {
+    /// The message encoding version.
+    pub const VERSION: u16 = 8;
+    /// The oldest encoding version that can decode this message.
+    pub const COMPAT_VERSION: u16 = 3;
+    /// The snapshot ID of the head (current version) of an object.
+    pub const SNAP_HEAD: u64 = u64::MAX - 1;
+}
+
+impl DecodeMessage<'_> for MessageOsdOp {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        let (mut front, data) = front_and_data(segments)?;
+        let front = &mut front;
+
+        let pgid = Decode::decode(front)?;
+        let hash = Decode::decode(front)?;
+        let epoch = Decode::decode(front)?;
+        let flags = Decode::decode(front)?;
+        let reqid = Decode::decode(front)?;
+        let _trace: [u8; 24] = Decode::decode(front)?;
+        let client_inc = Decode::decode(front)?;
+        let mtime = Decode::decode(front)?;
+        let locator = Decode::decode(front)?;
+        let oid = Decode::decode(front)?;
+
+        let num_ops = u16::decode(front)?;
+        let mut ops = (0..num_ops)
+            .map(|_| OsdOp::decode_header(front))
+            .collect::<Result<Vec<_>, _>>()?;
+        split_data(&mut ops, data)?;
+
+        Ok(Self {
+            pgid,
+            hash,
+            epoch,
+            flags,
+            reqid,
+            client_inc,
+            mtime,
+            locator,
+            oid,
+            ops: ops.into_iter().map(|(op, _)| op).collect(),
+            snap_id: Decode::decode(front)?,
+            snap_seq: Decode::decode(front)?,
+            snaps: Decode::decode(front)?,
+            retry_attempt: Decode::decode(front)?,
+            features: Decode::decode(front)?,
+        })
+    }
+}
+
+impl EncodeMessage for MessageOsdOp {
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>) {
+        let mut front = Vec::new();
+        self.pgid.encode(&mut front);
+        self.hash.encode(&mut front);
+        self.epoch.encode(&mut front);
+        self.flags.encode(&mut front);
+        self.reqid.encode(&mut front);
+        EMPTY_TRACE.encode(&mut front);
+        self.client_inc.encode(&mut front);
+        self.mtime.encode(&mut front);
+        self.locator.encode(&mut front);
+        self.oid.encode(&mut front);
+
+        (u16::try_from(self.ops.len()).expect("Too many ops")).encode(&mut front);
+        self.ops.iter().for_each(|op| op.encode_header(&mut front));
+
+        self.snap_id.encode(&mut front);
+        self.snap_seq.encode(&mut front);
+        self.snaps.encode(&mut front);
+        self.retry_attempt.encode(&mut front);
+        self.features.encode(&mut front);
+
+        push_front_and_data(output_segments, front, &merge_data(&self.ops));
+    }
+}
+
+/// An instruction to resend an op to another object (`request_redirect_t`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestRedirect {
+    pub locator: ObjectLocator,
+    /// The object to send the op to, or the same object if empty.
+    pub object: String,
+}
+
+impl Decode<'_> for RequestRedirect {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (_, mut data) = ceph_foundation::get_versions_and_data!(RequestRedirect: buffer, 1);
+        let data = &mut data;
+
+        Ok(Self {
+            locator: Decode::decode(data)?,
+            object: Decode::decode(data)?,
+        })
+    }
+}
+
+impl Encode for RequestRedirect {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        let buffer = &mut ceph_foundation::write_versions_and_data!(buffer, 1, 1);
+        self.locator.encode(buffer);
+        self.object.encode(buffer);
+        // The legacy `osd_instructions`.
+        0u32.encode(buffer);
+    }
+}
+
+/// The reply to a [`MessageOsdOp`] (`MOSDOpReply`).
+///
+/// The output and result of each op are in [`MessageOsdOpReply::ops`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageOsdOpReply {
+    pub oid: String,
+    pub pgid: PgId,
+    /// The [`OsdOpFlags`] of the request.
+    pub flags: u64,
+    /// The result of the request: zero or a negative errno.
+    pub result: i32,
+    pub bad_replay_version: EVersion,
+    pub epoch: Epoch,
+    pub ops: Vec<OsdOp>,
+    pub retry_attempt: i32,
+    pub replay_version: EVersion,
+    pub user_version: u64,
+    /// Where to resend the request to, if it was redirected.
+    pub redirect: Option<RequestRedirect>,
+}
+
+impl MessageOsdOpReply {
+    /// The message encoding version.
+    pub const VERSION: u16 = 8;
+    /// The oldest encoding version that can decode this message.
+    pub const COMPAT_VERSION: u16 = 2;
+}
+
+impl DecodeMessage<'_> for MessageOsdOpReply {
+    fn decode_message(segments: &[&[u8]]) -> Result<Self, DecodeMessageError> {
+        let (mut front, data) = front_and_data(segments)?;
+        let front = &mut front;
+
+        let oid = Decode::decode(front)?;
+        let pgid = Decode::decode(front)?;
+        let flags = Decode::decode(front)?;
+        let result = Decode::decode(front)?;
+        let bad_replay_version = Decode::decode(front)?;
+        let epoch = Decode::decode(front)?;
+
+        let num_ops = u32::decode(front)?;
+        let mut ops = (0..num_ops)
+            .map(|_| OsdOp::decode_header(front))
+            .collect::<Result<Vec<_>, _>>()?;
+        split_data(&mut ops, data)?;
+
+        let retry_attempt = Decode::decode(front)?;
+
+        for (op, _) in &mut ops {
+            op.rval = Decode::decode(front)?;
+        }
+
+        let replay_version = Decode::decode(front)?;
+        let user_version = Decode::decode(front)?;
+        let do_redirect = bool::decode(front)?;
+        let redirect = if do_redirect {
+            Some(Decode::decode(front)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            oid,
+            pgid,
+            flags,
+            result,
+            bad_replay_version,
+            epoch,
+            ops: ops.into_iter().map(|(op, _)| op).collect(),
+            retry_attempt,
+            replay_version,
+            user_version,
+            redirect,
+        })
+    }
+}
+
+impl EncodeMessage for MessageOsdOpReply {
+    fn encode_message(&self, output_segments: &mut Vec<Vec<u8>>) {
+        let mut front = Vec::new();
+        self.oid.encode(&mut front);
+        self.pgid.encode(&mut front);
+        self.flags.encode(&mut front);
+        self.result.encode(&mut front);
+        self.bad_replay_version.encode(&mut front);
+        self.epoch.encode(&mut front);
+
+        (self.ops.len() as u32).encode(&mut front);
+        self.ops.iter().for_each(|op| op.encode_header(&mut front));
+
+        self.retry_attempt.encode(&mut front);
+        self.ops.iter().for_each(|op| op.rval.encode(&mut front));
+
+        self.replay_version.encode(&mut front);
+        self.user_version.encode(&mut front);
+        self.redirect.is_some().encode(&mut front);
+        if let Some(redirect) = &self.redirect {
+            redirect.encode(&mut front);
+        }
+        EMPTY_TRACE.encode(&mut front);
+
+        push_front_and_data(output_segments, front, &merge_data(&self.ops));
+    }
+}
+
+#[cfg(test)]
+fn roundtrip<T: for<'a> DecodeMessage<'a> + EncodeMessage>(message: &T) -> T {
+    let mut segments = Vec::new();
+    message.encode_message(&mut segments);
+
+    let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+    T::decode_message(&segments).unwrap()
+}
+
+#[test]
+fn osd_op() {
+    let message = MessageOsdOp {
+        pgid: SpgId {
+            pgid: PgId { pool: 1, seed: 7 },
+            shard: SpgId::NO_SHARD,
+        },
+        hash: 0x1234_5677,
+        epoch: Epoch(20),
+        flags: OsdOpFlags(OsdOpFlags::WRITE | OsdOpFlags::ONDISK),
+        reqid: OsdReqId {
+            entity_type: EntityType::Client,
+            entity_num: 4100,
+            tid: 3,
+            inc: 0,
+        },
+        client_inc: 0,
+        mtime: Timestamp::new(100, 5),
+        locator: ObjectLocator::new(1),
+        oid: "object".to_string(),
+        ops: vec![
+            OsdOp::create(true),
+            OsdOp::write(2, b"data".to_vec()),
+            OsdOp::set_xattr("name", b"value"),
+            OsdOp::omap_set(&HashMap::from([("key".to_string(), b"value".to_vec())])),
+            OsdOp::call("lock", "lock", b"input"),
+        ],
+        snap_id: MessageOsdOp::SNAP_HEAD,
+        snap_seq: 0,
+        snaps: Vec::new(),
+        retry_attempt: 0,
+        features: u64::MAX,
+    };
+
+    let mut segments = Vec::new();
+    message.encode_message(&mut segments);
+    assert_eq!(segments.len(), 3);
+    let omap = b"\x01\0\0\0\x03\0\0\0key\x05\0\0\0value";
+    assert_eq!(
+        segments[2],
+        [b"data".as_slice(), b"namevalue", omap, b"locklockinput"].concat()
+    );
+
+    let decoded = roundtrip(&message);
+    assert_eq!(decoded, message);
+    assert_eq!(
+        decoded.ops[4].args,
+        OsdOpArgs::Call {
+            class_len: 4,
+            method_len: 4,
+            argc: 0,
+            indata_len: 5
+        }
+    );
+}
+
+#[test]
+fn osd_op_reply() {
+    let mut stat = OsdOp::stat();
+    stat.data = (42u64, Timestamp::new(1, 2)).to_vec();
+
+    let mut omap = OsdOp::omap_get_vals("", "", 10);
+    omap.data = (
+        HashMap::from([("key".to_string(), "value".to_string())]),
+        true,
+    )
+        .to_vec();
+
+    let mut xattr = OsdOp::get_xattr("missing");
+    xattr.rval = -61;
+
+    let reply = MessageOsdOpReply {
+        oid: "object".to_string(),
+        pgid: PgId { pool: 1, seed: 7 },
+        flags: (OsdOpFlags::READ | OsdOpFlags::ONDISK) as u64,
+        result: 0,
+        bad_replay_version: EVersion::default(),
+        epoch: Epoch(20),
+        ops: vec![stat, omap, xattr],
+        retry_attempt: 0,
+        replay_version: EVersion {
+            version: 5,
+            epoch: 20,
+        },
+        user_version: 5,
+        redirect: None,
+    };
+
+    let decoded = roundtrip(&reply);
+    assert_eq!(decoded, reply);
+    assert_eq!(
+        decoded.ops[0].stat_output().unwrap(),
+        (42, Timestamp::new(1, 2))
+    );
+
+    let (values, more) = decoded.ops[1].omap_vals_output().unwrap();
+    assert_eq!(values["key"], b"value");
+    assert!(more);
+    assert_eq!(decoded.ops[2].rval, -61);
+
+    let redirected = MessageOsdOpReply {
+        redirect: Some(RequestRedirect {
+            locator: ObjectLocator::new(2),
+            object: String::new(),
+        }),
+        ..reply
+    };
+    assert_eq!(roundtrip(&redirected), redirected);
+}
@@ -0,0 +1,355 @@
+use ceph_foundation::{
+    Decode, DecodeError, Encode, Encoder,
+    crypto::{Key, decode_decrypt_enc_bl, encode_encrypt, encode_encrypt_enc_bl},
+    entity::EntityType,
+};
+
+use crate::{CephXServiceTicketInfo, CephXTicketBlob};
+
+/// The proof that a client holds the session key of a
+/// ticket, sent as part of an authorizer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CephXAuthorize {
+    /// A random value, which the service returns incremented
+    /// by one in its [`CephXAuthorizeReply`].
+    pub nonce: u64,
+    /// Whether this authorize responds to a challenge.
+    pub have_challenge: bool,
+    /// The challenge of the service, incremented by one.
+    pub server_challenge_plus_one: u64,
+}
+
+ceph_foundation::write_decode_encode!(CephXAuthorize = const version 2 as u8 | nonce | have_challenge | server_challenge_plus_one);
+
+/// The reply of a service to an authorizer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CephXAuthorizeReply {
+    /// The nonce of the [`CephXAuthorize`], incremented by one.
+    pub nonce_plus_one: u64,
+    /// The secret used to secure the connection, if secure
+    /// mode was negotiated.
+    pub connection_secret: Vec<u8>,
+}
+
+/// Like Ceph, the reply is encoded as version 1 (without
+/// connection secret) if the connection secret is empty.
+impl Encode for CephXAuthorizeReply {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        if self.connection_secret.is_empty() {
+            buffer.push(1);
+            self.nonce_plus_one.encode(buffer);
+        } else {
+            buffer.push(2);
+            self.nonce_plus_one.encode(buffer);
+            self.connection_secret.encode(buffer);
+        }
+    }
+}
+
+impl Decode<'_> for CephXAuthorizeReply {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let [version] = <[u8; 1]>::decode(buffer)?;
+
+        if !(1..=2).contains(&version) {
+            return Err(DecodeError::UnexpectedVersion {
+                ty: "CephXAuthorizeReply",
+                got: version,
+                expected: 1..=2,
+            });
+        }
+
+        let nonce_plus_one = u64::decode(buffer)?;
+        let connection_secret = if version >= 2 {
+            Vec::decode(buffer)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            nonce_plus_one,
+            connection_secret,
+        })
+    }
+}
+
+/// A challenge sent by a service in reply to an authorizer, which
+/// the client must include in its [`CephXAuthorize`].
+///
+/// This prevents replaying an authorizer that was sent earlier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CephXAuthorizeChallenge {
+    pub server_challenge: u64,
+}
+
+ceph_foundation::write_decode_encode!(CephXAuthorizeChallenge = const version 1 as u8 | server_challenge);
+
+/// The unencrypted part of an authorizer.
+pub(crate) struct AuthorizerHeader {
+    global_id: u64,
+    service: EntityType,
+    ticket: CephXTicketBlob,
+}
+
+ceph_foundation::write_decode_encode!(AuthorizerHeader = const version 1 as u8 | global_id | service as u32 | ticket);
+
+/// A CephX authorizer, used to authenticate to a service (i.e. any
+/// daemon other than the monitors) using a ticket for that service.
+///
+/// It consists of the ticket blob, which only the service can decrypt,
+/// and a [`CephXAuthorize`] encrypted with the session key of the ticket.
+#[derive(Debug, Clone)]
+pub struct CephXAuthorizer {
+    session_key: Key,
+    nonce: u64,
+    /// The encoded [`AuthorizerHeader`].
+    base: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl CephXAuthorizer {
+    /// Build an authorizer for `service` as `global_id`, using `ticket`
+    /// and its `session_key`.
+    pub fn build(
+        global_id: u64,
+        service: EntityType,
+        ticket: &CephXTicketBlob,
+        session_key: &Key,
+        nonce: u64,
+    ) -> Self {
+        let header = AuthorizerHeader {
+            global_id,
+            service,
+            ticket: ticket.clone(),
+        };
+
+        let mut authorizer = Self {
+            session_key: session_key.clone(),
+            nonce,
+            base: header.to_vec(),
+            payload: Vec::new(),
+        };
+
+        authorizer.encode_authorize(None);
+        authorizer
+    }
+
+    /// Encode the payload, responding to `server_challenge` (if any).
+    fn encode_authorize(&mut self, server_challenge: Option<u64>) {
+        let authorize = CephXAuthorize {
+            nonce: self.nonce,
+            have_challenge: server_challenge.is_some(),
+            server_challenge_plus_one: server_challenge.map_or(0, |c| c.wrapping_add(1)),
+        };
+
+        self.payload = self.base.clone();
+        self.payload
+            .extend_from_slice(&encode_encrypt(&authorize, &self.session_key));
+    }
+
+    /// Respond to the encrypted [`CephXAuthorizeChallenge`] in `challenge`,
+    /// after which the updated [`CephXAuthorizer::payload`] should be
+    /// sent to the service again.
+    pub fn add_challenge(&mut self, challenge: &[u8]) -> Result<(), DecodeError> {
+        let server_challenge = if challenge.is_empty() {
+            None
+        } else {
+            let mut challenge = challenge.to_vec();
+            let challenge: CephXAuthorizeChallenge =
+                decode_decrypt_enc_bl(&mut challenge, &self.session_key)?;
+            Some(challenge.server_challenge)
+        };
+
+        self.encode_authorize(server_challenge);
+        Ok(())
+    }
+
+    /// The encoded authorizer, which is sent as the payload
+    /// of an `AuthRequest`.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// The session key of the ticket used by this authorizer.
+    pub fn session_key(&self) -> &Key {
+        &self.session_key
+    }
+
+    /// Verify the `reply` of the service, returning the
+    /// connection secret that it contains.
+    pub fn verify_reply(&self, reply: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut reply = reply.to_vec();
+        let encrypted = ceph_foundation::decode_full_mut_slice(&mut reply)?;
+        let reply: CephXAuthorizeReply = decode_decrypt_enc_bl(encrypted, &self.session_key)?;
+
+        if reply.nonce_plus_one != self.nonce.wrapping_add(1) {
+            return Err(DecodeError::Custom(
+                "Authorizer reply has incorrect nonce".to_string(),
+            ));
+        }
+
+        Ok(reply.connection_secret)
+    }
+}
+
+/// An authorizer as received by a service, after decrypting
+/// it using [`verify_authorizer`].
+#[derive(Debug)]
+pub struct VerifiedAuthorizer {
+    /// The global ID of the client.
+    pub global_id: u64,
+    /// The service that the ticket was issued for.
+    pub service: EntityType,
+    /// The decrypted ticket.
+    pub ticket_info: CephXServiceTicketInfo,
+    pub authorize: CephXAuthorize,
+}
+
+impl VerifiedAuthorizer {
+    /// Encrypt a [`CephXAuthorizeChallenge`] containing `server_challenge`,
+    /// which is sent to the client instead of a reply if this authorizer
+    /// does not respond to a challenge yet.
+    pub fn challenge(&self, server_challenge: u64) -> Vec<u8> {
+        let challenge = CephXAuthorizeChallenge { server_challenge };
+        encode_encrypt_enc_bl(&challenge, &self.ticket_info.session_key)
+    }
+
+    /// Whether this authorizer responds to `server_challenge`.
+    pub fn responds_to(&self, server_challenge: u64) -> bool {
+        self.authorize.have_challenge
+            && self.authorize.server_challenge_plus_one == server_challenge.wrapping_add(1)
+    }
+
+    /// Encode and encrypt a reply to this authorizer, which
+    /// contains `connection_secret` (if it is not empty).
+    pub fn reply(&self, connection_secret: &[u8]) -> Vec<u8> {
+        let reply = CephXAuthorizeReply {
+            nonce_plus_one: self.authorize.nonce.wrapping_add(1),
+            connection_secret: connection_secret.to_vec(),
+        };
+
+        encode_encrypt(&reply, &self.ticket_info.session_key)
+    }
+}
+
+/// Decode the authorizer in `payload`, and decrypt its ticket
+/// using `service_key`.
+///
+/// This is the service side of [`CephXAuthorizer`].
+pub fn verify_authorizer(
+    mut payload: &[u8],
+    service_key: &Key,
+) -> Result<VerifiedAuthorizer, DecodeError> {
+    let header = AuthorizerHeader::decode(&mut payload)?;
+
+    let mut blob = header.ticket.blob;
+    let blob = ceph_foundation::decode_full_mut_slice(&mut blob)?;
+    let ticket_info: CephXServiceTicketInfo = decode_decrypt_enc_bl(blob, service_key)?;
+
+    let mut authorize = Vec::<u8>::decode(&mut payload)?;
+    let authorize = decode_decrypt_enc_bl(&mut authorize, &ticket_info.session_key)?;
+
+    Ok(VerifiedAuthorizer {
+        global_id: header.global_id,
+        service: header.service,
+        ticket_info,
+        authorize,
+    })
+}
+
+#[cfg(test)]
+fn test_key() -> Key {
+    Key::new(ceph_foundation::Timestamp::new(0, 0), [7; 16])
+}
+
+#[test]
+fn authorize_reply_versions() {
+    let v1 = CephXAuthorizeReply {
+        nonce_plus_one: 0x0102030405060708,
+        connection_secret: Vec::new(),
+    };
+
+    let encoded = v1.to_vec();
+    assert_eq!(encoded, [1, 8, 7, 6, 5, 4, 3, 2, 1]);
+    assert_eq!(CephXAuthorizeReply::decode(&mut &encoded[..]).unwrap(), v1);
+
+    let v2 = CephXAuthorizeReply {
+        nonce_plus_one: 0x0102030405060708,
+        connection_secret: vec![0xaa; 4],
+    };
+
+    let encoded = v2.to_vec();
+    assert_eq!(
+        encoded,
+        [
+            2, 8, 7, 6, 5, 4, 3, 2, 1, 4, 0, 0, 0, 0xaa, 0xaa, 0xaa, 0xaa
+        ]
+    );
+    assert_eq!(CephXAuthorizeReply::decode(&mut &encoded[..]).unwrap(), v2);
+
+    assert!(CephXAuthorizeReply::decode(&mut &[3, 0, 0, 0, 0, 0, 0, 0, 0][..]).is_err());
+}
+
+#[test]
+fn verify_v1_reply() {
+    let key = test_key();
+    let authorizer =
+        CephXAuthorizer::build(1, EntityType::Osd, &CephXTicketBlob::default(), &key, 41);
+
+    // A reply without connection secret, as sent by a service in CRC mode.
+    let reply = CephXAuthorizeReply {
+        nonce_plus_one: 42,
+        connection_secret: Vec::new(),
+    };
+
+    let encrypted = encode_encrypt(&reply, &key);
+    assert_eq!(
+        authorizer.verify_reply(&encrypted).unwrap(),
+        Vec::<u8>::new()
+    );
+
+    let reply = CephXAuthorizeReply {
+        nonce_plus_one: 42,
+        connection_secret: vec![1; 40],
+    };
+
+    let encrypted = encode_encrypt(&reply, &key);
+    assert_eq!(authorizer.verify_reply(&encrypted).unwrap(), vec![1; 40]);
+
+    let reply = CephXAuthorizeReply {
+        nonce_plus_one: 41,
+        connection_secret: Vec::new(),
+    };
+    assert!(
+        authorizer
+            .verify_reply(&encode_encrypt(&reply, &key))
+            .is_err()
+    );
+}
+
+#[test]
+fn authorize_and_challenge() {
+    let authorize = CephXAuthorize {
+        nonce: 1,
+        have_challenge: true,
+        server_challenge_plus_one: 3,
+    };
+
+    let encoded = authorize.to_vec();
+    assert_eq!(encoded[0], 2);
+    assert_eq!(encoded.len(), 1 + 8 + 1 + 8);
+    assert_eq!(
+        CephXAuthorize::decode(&mut &encoded[..]).unwrap(),
+        authorize
+    );
+
+    let challenge = CephXAuthorizeChallenge {
+        server_challenge: 2,
+    };
+
+    let encoded = challenge.to_vec();
+    assert_eq!(encoded, [1, 2, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(
+        CephXAuthorizeChallenge::decode(&mut &encoded[..]).unwrap(),
+        challenge
+    );
+}
@@ -1,13 +1,20 @@
 //! CephX messages.
 
+mod authorizer;
+mod principal;
 mod ticket;
 
+pub use authorizer::{
+    CephXAuthorize, CephXAuthorizeChallenge, CephXAuthorizeReply, CephXAuthorizer,
+    VerifiedAuthorizer, verify_authorizer,
+};
+pub use principal::{CephXServiceTicketRequest, GetPrincipalSessionKey, PrincipalSessionKeyReply};
 use std::collections::HashSet;
 pub use ticket::{Ticket, TicketsAndConnectionSecret};
 
 use ceph_foundation::{
     Decode, DecodeError, Encode, Encoder, Timestamp,
-    crypto::{Key, decode_decrypt_enc_bl, encode_encrypt},
+    crypto::{Key, decode_decrypt_enc_bl, encode_encrypt, encode_encrypt_enc_bl},
     entity::{EntityName, EntityType},
 };
 
@@ -24,7 +31,7 @@ pub struct CephXTicketBlob {
 ceph_foundation::write_decode_encode!(CephXTicketBlob = const version 1 as u8 | secret_id | blob);
 
 /// A CephX service ticket.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CephXServiceTicket {
     /// The session key used for this ticket.
     pub session_key: Key,
@@ -129,6 +136,30 @@ impl CephXMessage {
     pub fn payload(&self) -> &[u8] {
         &self.payload
     }
+
+    /// Decode a request message, as sent by a client.
+    ///
+    /// Unlike replies, requests do not carry a status.
+    pub fn decode_request(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let ty = CephXMessageType::try_from(u16::decode(buffer)?)?;
+
+        Ok(Self {
+            ty,
+            payload: core::mem::take(buffer).to_vec(),
+        })
+    }
+
+    /// Encode this message as a successful reply, as sent by
+    /// a server.
+    pub fn encode_reply(&self, buffer: &mut impl Encoder) {
+        let header = CephXResponseHeader {
+            ty: self.ty,
+            status: 0,
+        };
+
+        header.encode(buffer);
+        buffer.extend_from_slice(&self.payload);
+    }
 }
 
 impl Decode<'_> for CephXMessage {
@@ -149,7 +180,7 @@ impl Decode<'_> for CephXMessage {
 }
 
 /// A CephX authentication key.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CephXAuthenticateKey(u64);
 
 impl From<&CephXAuthenticateKey> for u64 {
@@ -235,7 +266,7 @@ ceph_foundation::write_decode_encode!(EntitySet = value);
 
 impl From<&HashSet<EntityType>> for EntitySet {
     fn from(value: &HashSet<EntityType>) -> Self {
-        let value = value.iter().fold(0u32, |a, v| a | a | u32::from(v));
+        let value = value.iter().fold(0u32, |a, v| a | u32::from(v));
         Self { value }
     }
 }
@@ -251,6 +282,10 @@ impl TryFrom<EntitySet> for HashSet<EntityType> {
             let masked = value.value & mask;
             mask <<= 1;
 
+            if masked == 0 {
+                continue;
+            }
+
             let ty = EntityType::try_from(masked)?;
             set.insert(ty);
         }
@@ -378,26 +413,13 @@ impl AuthServiceTicketReply {
             decode_decrypt_enc_bl(encrypted_session_ticket, master_key)?;
 
         let encrypted = self.connection_secret.as_mut_slice();
-        let mut encrypted = ceph_foundation::decode_full_mut_slice(encrypted)?;
+        let encrypted = ceph_foundation::decode_full_mut_slice(encrypted)?;
         let auth_service_secret: &[u8] =
-            decode_decrypt_enc_bl(&mut encrypted, &auth_service_ticket.session_key)?;
-
-        let mut out_tickets = Vec::new();
-
-        for mut info in self.extra_service_tickets.tickets {
-            let session_ticket: CephXServiceTicket = decode_decrypt_enc_bl(
-                &mut info.encrypted_session_ticket,
-                &auth_service_ticket.session_key,
-            )?;
-
-            let refresh_ticket = info.refresh_ticket.clone();
+            decode_decrypt_enc_bl(encrypted, &auth_service_ticket.session_key)?;
 
-            out_tickets.push(Ticket {
-                ty: info.ty,
-                session_ticket,
-                refresh_ticket,
-            })
-        }
+        let mut out_tickets = self
+            .extra_service_tickets
+            .decrypt(&auth_service_ticket.session_key)?;
 
         out_tickets.push(Ticket {
             session_ticket: auth_service_ticket,
@@ -412,6 +434,36 @@ impl AuthServiceTicketReply {
     }
 }
 
+impl AuthServiceTicketReply {
+    /// Create a new reply containing the `auth` ticket, encrypted
+    /// using `master_key`, and the `extra` tickets and `connection_secret`,
+    /// both encrypted using the session key of the `auth` ticket.
+    ///
+    /// This is the inverse of [`AuthServiceTicketReply::decrypt`].
+    pub fn encrypt(
+        master_key: &Key,
+        auth: &Ticket,
+        extra: &[Ticket],
+        connection_secret: &[u8],
+    ) -> Self {
+        let session_key = &auth.session_ticket.session_key;
+
+        let auth_info = AuthServiceTicketInfo {
+            ty: EntityType::Auth,
+            encrypted_session_ticket: encode_encrypt_enc_bl(&auth.session_ticket, master_key),
+            refresh_ticket: auth.refresh_ticket.clone(),
+        };
+
+        Self {
+            service_ticket_reply: ServiceTicketReply {
+                tickets: vec![auth_info],
+            },
+            connection_secret: encode_encrypt(connection_secret, session_key),
+            extra_service_tickets: ServiceTicketReply::encrypt(extra, session_key),
+        }
+    }
+}
+
 ceph_foundation::write_decode_encode!(
     AuthServiceTicketReply =
         service_ticket_reply | connection_secret | extra_service_tickets as Vec<u8>
@@ -424,6 +476,43 @@ struct ServiceTicketReply {
 
 ceph_foundation::write_decode_encode!(ServiceTicketReply = const version 1 as u8 | tickets);
 
+impl ServiceTicketReply {
+    /// Encrypt the session tickets of `tickets` using `session_key`.
+    fn encrypt(tickets: &[Ticket], session_key: &Key) -> Self {
+        let tickets = tickets
+            .iter()
+            .map(|ticket| AuthServiceTicketInfo {
+                ty: ticket.ty,
+                encrypted_session_ticket: encode_encrypt_enc_bl(
+                    &ticket.session_ticket,
+                    session_key,
+                ),
+                refresh_ticket: ticket.refresh_ticket.clone(),
+            })
+            .collect();
+
+        Self { tickets }
+    }
+
+    /// Decrypt the session tickets in this reply using `session_key`.
+    fn decrypt(self, session_key: &Key) -> Result<Vec<Ticket>, DecodeError> {
+        let mut out_tickets = Vec::with_capacity(self.tickets.len());
+
+        for mut info in self.tickets {
+            let session_ticket: CephXServiceTicket =
+                decode_decrypt_enc_bl(&mut info.encrypted_session_ticket, session_key)?;
+
+            out_tickets.push(Ticket {
+                ty: info.ty,
+                session_ticket,
+                refresh_ticket: info.refresh_ticket,
+            })
+        }
+
+        Ok(out_tickets)
+    }
+}
+
 impl From<&ServiceTicketReply> for Vec<u8> {
     fn from(value: &ServiceTicketReply) -> Self {
         value.to_vec()
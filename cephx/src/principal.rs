@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use ceph_foundation::{Decode, DecodeError, Encode, Encoder, crypto::Key, entity::EntityType};
+
+use crate::{CephXAuthorizer, EntitySet, ServiceTicketReply, Ticket, authorizer::AuthorizerHeader};
+
+/// A request for tickets for the services in `keys`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CephXServiceTicketRequest {
+    pub keys: HashSet<EntityType>,
+}
+
+ceph_foundation::write_decode_encode!(CephXServiceTicketRequest = const version 1 as u8 | keys as EntitySet);
+
+/// A `GetPrincipalSessionKey` request, with which a client that has
+/// a ticket for the auth service requests (new) tickets for other
+/// services from the monitors.
+///
+/// The request is authenticated using an authorizer for the auth
+/// service, and the tickets in the [`PrincipalSessionKeyReply`] are
+/// encrypted using the session key of the auth ticket.
+#[derive(Debug, Clone)]
+pub struct GetPrincipalSessionKey {
+    /// The encoded authorizer.
+    authorizer: Vec<u8>,
+    pub request: CephXServiceTicketRequest,
+}
+
+impl GetPrincipalSessionKey {
+    /// Create a new request for tickets for the services in `keys`,
+    /// authenticated using `authorizer`.
+    pub fn new(authorizer: &CephXAuthorizer, keys: HashSet<EntityType>) -> Self {
+        Self {
+            authorizer: authorizer.payload().to_vec(),
+            request: CephXServiceTicketRequest { keys },
+        }
+    }
+
+    /// The encoded authorizer, which can be verified
+    /// using [`verify_authorizer`](crate::verify_authorizer).
+    pub fn authorizer(&self) -> &[u8] {
+        &self.authorizer
+    }
+}
+
+impl Encode for GetPrincipalSessionKey {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        buffer.extend_from_slice(&self.authorizer);
+        self.request.encode(buffer);
+    }
+}
+
+impl Decode<'_> for GetPrincipalSessionKey {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        let start = *buffer;
+
+        // The authorizer is not length-prefixed, so it is
+        // decoded to find out where it ends.
+        AuthorizerHeader::decode(buffer)?;
+        Vec::<u8>::decode(buffer)?;
+
+        let authorizer = start[..start.len() - buffer.len()].to_vec();
+        let request = CephXServiceTicketRequest::decode(buffer)?;
+
+        Ok(Self {
+            authorizer,
+            request,
+        })
+    }
+}
+
+/// The reply to a [`GetPrincipalSessionKey`] request.
+#[derive(Debug)]
+pub struct PrincipalSessionKeyReply {
+    service_ticket_reply: ServiceTicketReply,
+}
+
+impl PrincipalSessionKeyReply {
+    /// Create a new reply containing `tickets`, encrypted using
+    /// `auth_session_key`: the session key of the auth ticket of
+    /// the client.
+    pub fn encrypt(auth_session_key: &Key, tickets: &[Ticket]) -> Self {
+        Self {
+            service_ticket_reply: ServiceTicketReply::encrypt(tickets, auth_session_key),
+        }
+    }
+
+    /// Decrypt the tickets in this reply using `auth_session_key`.
+    ///
+    /// This is the inverse of [`PrincipalSessionKeyReply::encrypt`].
+    pub fn decrypt(self, auth_session_key: &Key) -> Result<Vec<Ticket>, DecodeError> {
+        self.service_ticket_reply.decrypt(auth_session_key)
+    }
+}
+
+ceph_foundation::write_decode_encode!(PrincipalSessionKeyReply = service_ticket_reply);
@@ -1,6 +1,6 @@
 use ceph_foundation::entity::EntityType;
 
-use crate::{CephXServiceTicket, MaybeEncryptedCephXTicketBlob};
+use crate::{CephXServiceTicket, CephXTicketBlob, MaybeEncryptedCephXTicketBlob};
 
 #[derive(Debug)]
 pub struct TicketsAndConnectionSecret {
@@ -8,9 +8,20 @@ pub struct TicketsAndConnectionSecret {
     pub connection_secret: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ticket {
     pub ty: EntityType,
     pub session_ticket: CephXServiceTicket,
     pub refresh_ticket: MaybeEncryptedCephXTicketBlob,
 }
+
+impl Ticket {
+    /// The ticket blob that is presented to the service, if
+    /// it is not encrypted.
+    pub fn blob(&self) -> Option<&CephXTicketBlob> {
+        match &self.refresh_ticket {
+            MaybeEncryptedCephXTicketBlob::Unencrypted(blob) => Some(blob),
+            MaybeEncryptedCephXTicketBlob::Encrypted(_) => None,
+        }
+    }
+}
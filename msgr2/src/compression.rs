@@ -0,0 +1,373 @@
+//! On-wire compression of frames.
+//!
+//! Which algorithms are available depends on the enabled cargo features
+//! (`snappy`, `zlib`, `zstd` and `lz4`). The compressed representation of
+//! each algorithm matches that of the corresponding Ceph `Compressor`.
+
+use ceph_foundation::{Decode, DecodeError, Encode, Encoder};
+
+use crate::Frame;
+
+/// The default minimum size of a frame before it is compressed.
+///
+/// This is the default value of `ms_osd_compression_min_size`.
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// A compression algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressionMethod {
+    /// Snappy compression.
+    Snappy = 1,
+    /// Zlib compression.
+    Zlib = 2,
+    /// Zstandard compression.
+    Zstd = 3,
+    /// LZ4 compression.
+    Lz4 = 4,
+}
+
+impl CompressionMethod {
+    /// All compression methods supported by this build, in
+    /// order of preference.
+    pub const SUPPORTED: &'static [CompressionMethod] = &[
+        #[cfg(feature = "lz4")]
+        CompressionMethod::Lz4,
+        #[cfg(feature = "snappy")]
+        CompressionMethod::Snappy,
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd,
+        #[cfg(feature = "zlib")]
+        CompressionMethod::Zlib,
+    ];
+
+    /// Whether this build supports compressing and decompressing
+    /// data with this method.
+    pub fn is_supported(&self) -> bool {
+        Self::SUPPORTED.contains(self)
+    }
+
+    /// Compress `data`.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        // Avoid unused variable warnings if no methods are enabled.
+        let _ = data;
+
+        match self {
+            #[cfg(feature = "snappy")]
+            CompressionMethod::Snappy => snap::raw::Encoder::new()
+                .compress_vec(data)
+                .map_err(|e| CompressionError::Failed(e.to_string())),
+            #[cfg(feature = "zlib")]
+            CompressionMethod::Zlib => {
+                use std::io::Write;
+
+                // Ceph prepends a byte indicating the compressor variant: 0 for zlib.
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(vec![0u8], flate2::Compression::new(5));
+
+                encoder
+                    .write_all(data)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|e| CompressionError::Failed(e.to_string()))
+            }
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd => {
+                let compressed = zstd::bulk::compress(data, 1)
+                    .map_err(|e| CompressionError::Failed(e.to_string()))?;
+
+                // Prefixed with the decompressed length.
+                let mut output = Vec::with_capacity(4 + compressed.len());
+                (data.len() as u32).encode(&mut output);
+                output.extend_from_slice(&compressed);
+                Ok(output)
+            }
+            #[cfg(feature = "lz4")]
+            CompressionMethod::Lz4 => {
+                let compressed = lz4_flex::block::compress(data);
+
+                // The amount of chunks, their decompressed and compressed lengths,
+                // and the concatenated compressed chunks. We always compress
+                // a single chunk.
+                let mut output = Vec::with_capacity(12 + compressed.len());
+                1u32.encode(&mut output);
+                (data.len() as u32).encode(&mut output);
+                (compressed.len() as u32).encode(&mut output);
+                output.extend_from_slice(&compressed);
+                Ok(output)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(CompressionError::Unsupported(*self)),
+        }
+    }
+
+    /// Decompress `data`.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        // Avoid unused variable warnings if no methods are enabled.
+        let _ = data;
+
+        match self {
+            #[cfg(feature = "snappy")]
+            CompressionMethod::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(data)
+                .map_err(|e| CompressionError::Failed(e.to_string())),
+            #[cfg(feature = "zlib")]
+            CompressionMethod::Zlib => {
+                use std::io::Read;
+
+                let Some((_variant, data)) = data.split_first() else {
+                    return Err(CompressionError::Failed("Missing zlib variant".into()));
+                };
+
+                let mut output = Vec::new();
+                flate2::read::DeflateDecoder::new(data)
+                    .read_to_end(&mut output)
+                    .map_err(|e| CompressionError::Failed(e.to_string()))?;
+
+                Ok(output)
+            }
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd => {
+                let mut data = data;
+                let len = u32::decode(&mut data)?;
+
+                zstd::bulk::decompress(data, len as usize)
+                    .map_err(|e| CompressionError::Failed(e.to_string()))
+            }
+            #[cfg(feature = "lz4")]
+            CompressionMethod::Lz4 => {
+                let mut data = data;
+                let chunks = u32::decode(&mut data)?;
+
+                let mut lengths = Vec::with_capacity(chunks.min(64) as usize);
+                for _ in 0..chunks {
+                    let decompressed_len = u32::decode(&mut data)? as usize;
+                    let compressed_len = u32::decode(&mut data)? as usize;
+                    lengths.push((decompressed_len, compressed_len));
+                }
+
+                // Chunks are compressed as a stream, so each chunk may refer
+                // to the data of the chunks before it.
+                let mut output = Vec::new();
+                for (decompressed_len, compressed_len) in lengths {
+                    let Some((chunk, rest)) = data.split_at_checked(compressed_len) else {
+                        return Err(DecodeError::NotEnoughData {
+                            field: Some("lz4_chunk"),
+                            have: data.len(),
+                            need: compressed_len,
+                        }
+                        .into());
+                    };
+
+                    let dict = &output[output.len().saturating_sub(64 * 1024)..];
+                    let decompressed =
+                        lz4_flex::block::decompress_with_dict(chunk, decompressed_len, dict)
+                            .map_err(|e| CompressionError::Failed(e.to_string()))?;
+
+                    output.extend_from_slice(&decompressed);
+                    data = rest;
+                }
+
+                Ok(output)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(CompressionError::Unsupported(*self)),
+        }
+    }
+}
+
+impl From<CompressionMethod> for u32 {
+    fn from(value: CompressionMethod) -> Self {
+        value as u32
+    }
+}
+
+impl TryFrom<u32> for CompressionMethod {
+    type Error = DecodeError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let res = match value {
+            1 => Self::Snappy,
+            2 => Self::Zlib,
+            3 => Self::Zstd,
+            4 => Self::Lz4,
+            _ => return Err(DecodeError::unknown_value("CompressionMethod", value)),
+        };
+
+        Ok(res)
+    }
+}
+
+impl Encode for CompressionMethod {
+    fn encode(&self, buffer: &mut impl Encoder) {
+        u32::from(*self).encode(buffer);
+    }
+}
+
+impl Decode<'_> for CompressionMethod {
+    fn decode(buffer: &mut &[u8]) -> Result<Self, DecodeError> {
+        Self::try_from(u32::decode(buffer)?)
+    }
+}
+
+/// An error that occurred while compressing or decompressing
+/// data.
+#[derive(Debug, Clone)]
+pub enum CompressionError {
+    /// The compression method is not supported by this build.
+    Unsupported(CompressionMethod),
+    /// A frame was marked as compressed, but no compression
+    /// was negotiated.
+    NotNegotiated,
+    /// The compressed data could not be decoded.
+    Decode(DecodeError),
+    /// Compression or decompression failed.
+    Failed(String),
+}
+
+impl From<DecodeError> for CompressionError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+/// The compression state of a connection.
+#[derive(Debug, Clone, Default)]
+pub struct FrameCompression {
+    method: Option<CompressionMethod>,
+    min_size: usize,
+}
+
+impl FrameCompression {
+    /// No compression.
+    pub const fn new() -> Self {
+        Self {
+            method: None,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Compress frames of at least `min_size` bytes using `method`.
+    pub fn set_method(&mut self, method: CompressionMethod, min_size: usize) {
+        self.method = Some(method);
+        self.min_size = min_size;
+    }
+
+    /// The negotiated compression method, if any.
+    pub fn method(&self) -> Option<CompressionMethod> {
+        self.method
+    }
+
+    /// Compress the segments of `frame` into `buffers`, and return the
+    /// compressed frame.
+    ///
+    /// Returns `None` if no compression was negotiated, if the frame is smaller
+    /// than the minimum size, or if compression failed. In that case, `frame`
+    /// should be sent as-is.
+    pub fn compress<'a>(&self, frame: &Frame, buffers: &'a mut Vec<Vec<u8>>) -> Option<Frame<'a>> {
+        let method = self.method?;
+
+        let total_len: usize = frame.segments().iter().map(|v| v.len()).sum();
+        if total_len < self.min_size {
+            return None;
+        }
+
+        buffers.clear();
+        for segment in frame.segments() {
+            if segment.is_empty() {
+                buffers.push(Vec::new());
+            } else {
+                buffers.push(method.compress(segment).ok()?);
+            }
+        }
+
+        let segments: Vec<&[u8]> = buffers.iter().map(Vec::as_slice).collect();
+        let mut frame = Frame::new(frame.tag(), &segments).ok()?;
+        frame.set_compressed(true);
+        Some(frame)
+    }
+
+    /// Decompress the segments of `frame` into `buffers`, and return
+    /// the decompressed frame.
+    ///
+    /// Returns `frame` as-is if it is not compressed.
+    pub fn decompress<'a>(
+        &self,
+        frame: &Frame<'a>,
+        buffers: &'a mut Vec<Vec<u8>>,
+    ) -> Result<Frame<'a>, CompressionError> {
+        if !frame.is_compressed() {
+            return Ok(frame.clone());
+        }
+
+        let method = self.method.ok_or(CompressionError::NotNegotiated)?;
+
+        buffers.clear();
+        for segment in frame.segments() {
+            if segment.is_empty() {
+                buffers.push(Vec::new());
+            } else {
+                buffers.push(method.decompress(segment)?);
+            }
+        }
+
+        let segments: Vec<&[u8]> = buffers.iter().map(Vec::as_slice).collect();
+        Frame::new(frame.tag(), &segments).map_err(CompressionError::Failed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..4096u32).map(|v| (v % 251) as u8).collect();
+
+        for method in CompressionMethod::SUPPORTED {
+            let compressed = method.compress(&data).unwrap();
+            assert!(compressed.len() < data.len(), "{method:?}");
+
+            let decompressed = method.decompress(&compressed).unwrap();
+            assert_eq!(decompressed, data, "{method:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(any(
+        feature = "lz4",
+        feature = "snappy",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    fn frame_round_trip() {
+        let header = [1u8; 8];
+        let front = [2u8; 2048];
+        let frame = Frame::new(crate::Tag::Message, &[&header, &front]).unwrap();
+
+        for method in CompressionMethod::SUPPORTED {
+            let mut compression = FrameCompression::new();
+            compression.set_method(*method, 16);
+
+            let mut buffers = Vec::new();
+            let compressed = compression.compress(&frame, &mut buffers).unwrap();
+            assert!(compressed.is_compressed(), "{method:?}");
+            assert!(compressed.segments()[1].len() < front.len(), "{method:?}");
+
+            let mut decompressed_buffers = Vec::new();
+            let decompressed = compression
+                .decompress(&compressed, &mut decompressed_buffers)
+                .unwrap();
+            assert!(!decompressed.is_compressed(), "{method:?}");
+            assert_eq!(decompressed.segments(), frame.segments(), "{method:?}");
+        }
+    }
+
+    #[test]
+    fn small_frames_are_not_compressed() {
+        let mut compression = FrameCompression::new();
+        compression.set_method(CompressionMethod::Snappy, 1024);
+
+        let data = [0u8; 1023];
+        let frame = Frame::new(crate::Tag::Message, &[&data]).unwrap();
+        assert!(compression.compress(&frame, &mut Vec::new()).is_none());
+    }
+}
@@ -73,6 +73,23 @@ impl FrameEncryption {
         }
     }
 
+    /// Decrypt the start of the next incoming ciphertext, without authenticating
+    /// it or consuming a nonce.
+    ///
+    /// In `msgr2.0` secure mode, an entire frame is encrypted as a single message,
+    /// so the preamble must be inspected before the tag of the frame is available.
+    /// The frame must still be authenticated using [`FrameEncryption::decrypt`].
+    pub fn peek(&self, buffer: &mut [u8]) -> Result<(), DecryptError> {
+        match &self.inner {
+            EncryptionInner::None => Ok(()),
+            EncryptionInner::CryptoKey { key, rx_nonce, .. } => {
+                let nonce = rx_nonce.peek().ok_or(DecryptError)?;
+                key.peek_gcm(&nonce, buffer);
+                Ok(())
+            }
+        }
+    }
+
     pub fn encrypt(&mut self, buffer: &mut [u8]) -> Result<[u8; 16], EncryptError> {
         match &mut self.inner {
             EncryptionInner::None => Ok([0u8; _]),
@@ -135,24 +152,39 @@ impl Nonce {
         }
     }
 
+    fn peek(&self) -> Option<[u8; 12]> {
+        if self.current == self.start && self.used_start {
+            None
+        } else {
+            Some(self.current)
+        }
+    }
+
     fn next(&mut self, rev: Revision) -> Option<[u8; 12]> {
-        let next = self.current;
-        if next == self.start {
-            if self.used_start {
-                return None;
-            }
+        let next = self.peek()?;
 
+        if next == self.start {
             self.used_start = true;
         }
 
         match rev {
-            Revision::Rev0 => todo!(),
+            Revision::Rev0 => self.inc_rev0(),
             Revision::Rev1 => self.inc_rev1(),
         }
 
         Some(next)
     }
 
+    /// `msgr2.0` treats the nonce as a 32-bit counter followed by
+    /// a 64-bit fixed field, and increments the counter.
+    fn inc_rev0(&mut self) {
+        let current = self.current.first_chunk_mut().unwrap();
+        let next = u32::from_le_bytes(*current).wrapping_add(1);
+        *current = next.to_le_bytes();
+    }
+
+    /// `msgr2.1` treats the nonce as a 32-bit fixed field followed
+    /// by a 64-bit counter, and increments the counter.
     fn inc_rev1(&mut self) {
         let current = self.current.last_chunk_mut().unwrap();
         let next = u64::from_le_bytes(*current).wrapping_add(1);
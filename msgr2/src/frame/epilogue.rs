@@ -11,6 +11,7 @@ pub struct Epilogue<'a> {
 impl<'a> Epilogue<'a> {
     pub const SERIALIZED_SIZE_V2_0_CRC: usize = 17;
     pub const SERIALIZED_SIZE_V2_1_CRC: usize = 13;
+    pub const SERIALIZED_SIZE_V2_0_SECURE: usize = 16;
     pub const SERIALIZED_SIZE_V2_1_SECURE: usize = 16;
 
     pub fn write(&self, output: &mut Vec<u8>) {
@@ -45,7 +46,7 @@ impl<'a> Epilogue<'a> {
         match format {
             FrameFormat::Rev0Crc => self.late_flags & 0x1 == 0x0,
             FrameFormat::Rev1Crc => self.late_flags & 0xF == 0xE,
-            FrameFormat::Rev0Secure => todo!(),
+            FrameFormat::Rev0Secure => self.late_flags & 0x1 == 0x0,
             FrameFormat::Rev1Secure => self.late_flags & 0xF == 0xE,
         }
     }
@@ -13,6 +13,7 @@ use crate::{frame::preamble::SegmentDetail, wire::TxFrame};
 
 use ceph_foundation::DecodeError;
 
+pub const REV0_SECURE_PAD_SIZE: NonZeroUsize = NonZeroUsize::new(16).unwrap();
 pub const REV1_SECURE_PAD_SIZE: NonZeroUsize = NonZeroUsize::new(16).unwrap();
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -36,7 +37,7 @@ impl FrameFormat {
     pub const fn segment_pad_size(&self) -> NonZeroUsize {
         match self {
             FrameFormat::Rev0Crc | FrameFormat::Rev1Crc => NonZeroUsize::new(1).unwrap(),
-            FrameFormat::Rev0Secure => todo!(),
+            FrameFormat::Rev0Secure => REV0_SECURE_PAD_SIZE,
             FrameFormat::Rev1Secure => REV1_SECURE_PAD_SIZE,
         }
     }
@@ -75,6 +76,7 @@ const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&ALGO);
 #[derive(Debug, Clone)]
 pub struct Frame<'a> {
     tag: Tag,
+    compressed: bool,
     valid_segments: NonZeroU8,
     segments: [&'a [u8]; 4],
 }
@@ -100,7 +102,9 @@ impl<'a> Frame<'a> {
             return Err("Invalid amount of segments".to_string());
         }
 
-        if segments.last().map(|v| v.is_empty()).unwrap_or(false) {
+        // A single, empty segment is allowed for frames without
+        // any content.
+        if segments.len() > 1 && segments.last().map(|v| v.is_empty()).unwrap_or(false) {
             return Err("Last segment in list was empty".to_string());
         }
 
@@ -111,6 +115,7 @@ impl<'a> Frame<'a> {
 
         Ok(Self {
             tag,
+            compressed: false,
             valid_segments,
             segments: segments_out,
         })
@@ -125,9 +130,15 @@ impl<'a> Frame<'a> {
             };
         }
 
+        let flags = if self.compressed {
+            Preamble::FLAG_COMPRESSED
+        } else {
+            0
+        };
+
         Preamble {
             format,
-            flags: 0,
+            flags,
             tag: self.tag,
             segment_count: self.valid_segments,
             segment_details,
@@ -136,7 +147,7 @@ impl<'a> Frame<'a> {
     }
 
     /// > **Note**: this function should, generally, not be used. Use [`Frame::send`]
-    /// instead.
+    /// > instead.
     ///
     /// Write the unencrypted binary representation of this [`Frame`] to `output`,
     /// with preamble, frame data, optional epilogue and optional padding.
@@ -157,7 +168,9 @@ impl<'a> Frame<'a> {
             let required_padding = full_pad_len - segment.len();
             output.extend(core::iter::repeat_n(0, required_padding));
 
-            if format == FrameFormat::Rev1Crc && idx == 0 && !segment.is_empty() {
+            // The CRC of the first segment is always present, even if
+            // the segment is empty.
+            if format == FrameFormat::Rev1Crc && idx == 0 {
                 output.extend_from_slice(&crc.to_le_bytes());
             }
         }
@@ -224,6 +237,7 @@ impl<'a> Frame<'a> {
 
         Ok(Self {
             tag: preamble.tag,
+            compressed: preamble.is_compressed(),
             valid_segments: preamble.segment_count,
             segments,
         })
@@ -242,14 +256,19 @@ impl<'a> Frame<'a> {
             FrameFormat::Rev1Crc => {
                 if preamble.need_epilogue_rev2_1() {
                     let epilogue = Epilogue {
-                        late_flags: 0,
+                        late_flags: 0xE,
                         crcs: &crcs[1..],
                     };
 
                     epilogue.write(output);
                 }
             }
-            FrameFormat::Rev0Secure => todo!(),
+            FrameFormat::Rev0Secure => {
+                // `msgr2.0` always transmits an epilogue, which consists
+                // of the late flags and padding up to a full cipher block.
+                output.push(0);
+                output.extend_from_slice(&[0u8; 15]);
+            }
             FrameFormat::Rev1Secure => {
                 if preamble.need_epilogue_rev2_1() {
                     output.push(0xEu8);
@@ -287,7 +306,20 @@ impl<'a> Frame<'a> {
                     true
                 }
             }
-            FrameFormat::Rev0Secure => todo!(),
+            FrameFormat::Rev0Secure => {
+                if trailer.len() != Epilogue::SERIALIZED_SIZE_V2_0_SECURE {
+                    return Err(DecodeError::Custom(format!(
+                        "Expected {} bytes of epilogue data, got {}",
+                        Epilogue::SERIALIZED_SIZE_V2_0_SECURE,
+                        trailer.len()
+                    )));
+                }
+
+                // The padding is not verified by peers, so we do not
+                // verify it either.
+                let epilogue = Epilogue::decode(&trailer[..1], &mut [])?;
+                epilogue.is_completed(preamble.format)
+            }
             FrameFormat::Rev1Secure => {
                 if preamble.need_epilogue_rev2_1() {
                     if trailer.len() != 16 {
@@ -352,6 +384,16 @@ impl<'a> Frame<'a> {
         self.tag
     }
 
+    /// Whether the segments of this frame are compressed.
+    pub const fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Set whether the segments of this frame are compressed.
+    pub const fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+    }
+
     pub fn segments(&self) -> &[&[u8]] {
         &self.segments[..self.valid_segments.get() as usize]
     }
@@ -94,6 +94,10 @@ pub struct Preamble {
 impl Preamble {
     pub const SERIALIZED_SIZE: usize = 32;
 
+    /// The flag indicating that the segments of the frame
+    /// are compressed.
+    pub const FLAG_COMPRESSED: u8 = 1 << 0;
+
     pub fn write(&self, output: &mut Vec<u8>) {
         output.reserve(Self::SERIALIZED_SIZE);
 
@@ -168,6 +172,10 @@ impl Preamble {
         })
     }
 
+    pub fn is_compressed(&self) -> bool {
+        self.flags & Self::FLAG_COMPRESSED == Self::FLAG_COMPRESSED
+    }
+
     pub fn need_epilogue_rev2_1(&self) -> bool {
         self.segments().iter().skip(1).any(|v| v.len() > 0)
     }
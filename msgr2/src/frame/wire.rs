@@ -2,8 +2,13 @@ use std::io::Read;
 
 use ceph_foundation::crypto::AES_GCM_SIG_SIZE;
 
-use crate::frame::{
-    Epilogue, FrameFormat, Preamble, REV1_SECURE_PAD_SIZE, encryption::FrameEncryption,
+use crate::{
+    Frame,
+    compression::{CompressionError, FrameCompression},
+    frame::{
+        Epilogue, FrameFormat, Preamble, REV0_SECURE_PAD_SIZE, REV1_SECURE_PAD_SIZE,
+        encryption::FrameEncryption,
+    },
 };
 
 const REV1_SECURE_INLINE_SIZE: usize = 48;
@@ -28,7 +33,17 @@ fn wire_segments<'a>(preamble: &Preamble) -> impl Iterator<Item = usize> + 'a +
             let data_and_epilogue = total_data_len + epilogue_len;
             [Some(data_and_epilogue), None]
         }
-        FrameFormat::Rev0Secure => todo!(),
+        FrameFormat::Rev0Secure => {
+            // All segments are padded, and the epilogue is always present.
+            let total_data_len: usize = preamble
+                .segments()
+                .iter()
+                .map(|v| v.len().next_multiple_of(REV0_SECURE_PAD_SIZE.get()))
+                .sum();
+
+            let data_and_epilogue = total_data_len + Epilogue::SERIALIZED_SIZE_V2_0_SECURE;
+            [Some(data_and_epilogue), None]
+        }
         FrameFormat::Rev1Secure => {
             let mut segments = preamble.segments().iter();
             let seg1_len = segments.next().unwrap().len();
@@ -58,7 +73,7 @@ fn start_bytes(format: FrameFormat) -> usize {
     match format {
         FrameFormat::Rev0Crc => crate::frame::Preamble::SERIALIZED_SIZE,
         FrameFormat::Rev1Crc => crate::frame::Preamble::SERIALIZED_SIZE,
-        FrameFormat::Rev0Secure => todo!(),
+        FrameFormat::Rev0Secure => crate::frame::Preamble::SERIALIZED_SIZE,
         FrameFormat::Rev1Secure => Preamble::SERIALIZED_SIZE + REV1_SECURE_INLINE_SIZE,
     }
 }
@@ -126,23 +141,38 @@ impl<'buf, 'enc> RxFrame<'buf, Unstarted<'enc>> {
             frame_data,
         } = self;
 
-        // Decrypt incoming data frame
-        let tag_len = encryption
-            .decrypt(frame_data)
-            .map_err(|_| RxError::DecryptionFailed)?;
-
-        // Truncate tag
-        let new_len = frame_data.len().checked_sub(tag_len).unwrap();
-        frame_data.truncate(new_len);
+        let preamble_data = if format == FrameFormat::Rev0Secure {
+            // The preamble is encrypted as part of a message that spans the
+            // entire frame, so we can only authenticate it once the rest of
+            // the frame has been received. Peek at it so that we know how much
+            // data to expect, and keep the ciphertext around until then.
+            let mut preamble_data = *frame_data.first_chunk().expect("self.preamble_len() >= 32");
+
+            encryption
+                .peek(&mut preamble_data)
+                .map_err(|_| RxError::DecryptionFailed)?;
+
+            preamble_data
+        } else {
+            // Decrypt incoming data frame
+            let tag_len = encryption
+                .decrypt(frame_data)
+                .map_err(|_| RxError::DecryptionFailed)?;
+
+            // Truncate tag
+            let new_len = frame_data.len().checked_sub(tag_len).unwrap();
+            frame_data.truncate(new_len);
+
+            *frame_data.first_chunk().expect("self.preamble_len() >= 32")
+        };
 
-        // Get and parse preamble
-        let preamble_data = *frame_data.first_chunk().expect("self.preamble_len() >= 32");
+        // Parse preamble
         let preamble = Preamble::parse(&preamble_data, format).map_err(RxError::DecodePreamble)?;
 
         match format {
             crate::frame::FrameFormat::Rev0Crc => frame_data.truncate(0),
             crate::frame::FrameFormat::Rev1Crc => frame_data.truncate(0),
-            crate::frame::FrameFormat::Rev0Secure => todo!(),
+            crate::frame::FrameFormat::Rev0Secure => {}
             crate::frame::FrameFormat::Rev1Secure => {
                 frame_data.copy_within(Preamble::SERIALIZED_SIZE.., 0);
                 let non_trailer_data = preamble.segments()[0].len().min(REV1_SECURE_INLINE_SIZE);
@@ -187,16 +217,23 @@ impl<'buf, 'enc> RxFrame<'buf, Unstarted<'enc>> {
         }
     }
 
+    /// The amount of bytes that must be read before the
+    /// preamble can be parsed.
+    fn start_rx_bytes(&self) -> usize {
+        match self.format {
+            FrameFormat::Rev0Crc | FrameFormat::Rev1Crc | FrameFormat::Rev0Secure => {
+                start_bytes(self.format)
+            }
+            FrameFormat::Rev1Secure => start_bytes(self.format) + AES_GCM_SIG_SIZE,
+        }
+    }
+
     pub fn read_preamble(
         self,
         read: impl std::io::Read,
     ) -> Result<RxFrame<'buf, ReadPreamble<'enc>>, RxError> {
         // Read pre data
-        let start_rx_bytes = match self.format {
-            FrameFormat::Rev0Crc | FrameFormat::Rev1Crc => start_bytes(self.format),
-            FrameFormat::Rev0Secure => todo!(),
-            FrameFormat::Rev1Secure => start_bytes(self.format) + AES_GCM_SIG_SIZE,
-        };
+        let start_rx_bytes = self.start_rx_bytes();
 
         let mut take = read.take(start_rx_bytes as u64);
         let rx_bytes = take.read_to_end(self.frame_data)?;
@@ -207,42 +244,85 @@ impl<'buf, 'enc> RxFrame<'buf, Unstarted<'enc>> {
 
         self.handle_pre_data()
     }
+
+    /// The asynchronous counterpart of [`RxFrame::read_preamble`].
+    #[cfg(feature = "tokio")]
+    pub async fn read_preamble_async(
+        self,
+        read: impl tokio::io::AsyncRead + Unpin,
+    ) -> Result<RxFrame<'buf, ReadPreamble<'enc>>, RxError> {
+        use tokio::io::AsyncReadExt;
+
+        let start_rx_bytes = self.start_rx_bytes();
+
+        let mut take = read.take(start_rx_bytes as u64);
+        let rx_bytes = take.read_to_end(self.frame_data).await?;
+
+        if rx_bytes != start_rx_bytes {
+            return Err(RxError::PreambleTruncated);
+        }
+
+        self.handle_pre_data()
+    }
 }
 
 impl<'buf> RxFrame<'buf, ReadPreamble<'_>> {
     fn decrypt_block(&mut self, new_data_start: usize) -> Result<(), RxError> {
         let Self {
-            state, frame_data, ..
+            state,
+            frame_data,
+            format,
         } = self;
 
+        // In `msgr2.0` secure mode, the block is the remainder of a message
+        // that starts with the (still encrypted) preamble.
+        let block_start = if *format == FrameFormat::Rev0Secure {
+            0
+        } else {
+            new_data_start
+        };
+
         let tag_len = state
             .encryption
-            .decrypt(&mut frame_data[new_data_start..])
+            .decrypt(&mut frame_data[block_start..])
             .map_err(|_| RxError::DecryptionFailed)?;
 
         let new_len = frame_data.len().checked_sub(tag_len).unwrap();
         frame_data.truncate(new_len);
 
+        if *format == FrameFormat::Rev0Secure {
+            // The preamble has now been authenticated, and must match
+            // what we used to determine the layout of the frame.
+            if frame_data[..Preamble::SERIALIZED_SIZE] != state.preamble_data {
+                return Err(RxError::DecryptionFailed);
+            }
+
+            frame_data.drain(..Preamble::SERIALIZED_SIZE);
+        }
+
         // No need to insert artificial padding here:
 
         Ok(())
     }
 
+    /// The amount of bytes on the wire for each of the remaining
+    /// blocks of the frame.
+    fn wire_blocks(&self) -> Vec<usize> {
+        wire_segments(&self.state.preamble)
+            .map(|block| match self.format {
+                FrameFormat::Rev0Crc | FrameFormat::Rev1Crc => block,
+                FrameFormat::Rev0Secure | FrameFormat::Rev1Secure => block + AES_GCM_SIG_SIZE,
+            })
+            .collect()
+    }
+
     pub fn read_rest(
         mut self,
         mut read: impl std::io::Read,
     ) -> Result<RxFrame<'buf, Completed>, RxError> {
-        let additional_blocks = wire_segments(&self.state.preamble);
-
-        for block in additional_blocks {
+        for block in self.wire_blocks() {
             let new_data_start = self.frame_data.len();
 
-            let block = match self.format {
-                FrameFormat::Rev0Crc | FrameFormat::Rev1Crc => block,
-                FrameFormat::Rev0Secure => todo!(),
-                FrameFormat::Rev1Secure => block + AES_GCM_SIG_SIZE,
-            };
-
             let mut take = (&mut read).take(block as u64);
             let rx_bytes = take.read_to_end(self.frame_data)?;
 
@@ -253,14 +333,42 @@ impl<'buf> RxFrame<'buf, ReadPreamble<'_>> {
             self.decrypt_block(new_data_start)?;
         }
 
-        Ok(RxFrame {
+        Ok(self.complete())
+    }
+
+    /// The asynchronous counterpart of [`RxFrame::read_rest`].
+    #[cfg(feature = "tokio")]
+    pub async fn read_rest_async(
+        mut self,
+        mut read: impl tokio::io::AsyncRead + Unpin,
+    ) -> Result<RxFrame<'buf, Completed>, RxError> {
+        use tokio::io::AsyncReadExt;
+
+        for block in self.wire_blocks() {
+            let new_data_start = self.frame_data.len();
+
+            let mut take = (&mut read).take(block as u64);
+            let rx_bytes = take.read_to_end(self.frame_data).await?;
+
+            if rx_bytes != block {
+                return Err(RxError::FrameDataTruncated);
+            }
+
+            self.decrypt_block(new_data_start)?;
+        }
+
+        Ok(self.complete())
+    }
+
+    fn complete(self) -> RxFrame<'buf, Completed> {
+        RxFrame {
             state: Completed {
                 preamble_data: self.state.preamble_data,
                 preamble: self.state.preamble,
             },
             format: self.format,
             frame_data: self.frame_data,
-        })
+        }
     }
 }
 
@@ -273,11 +381,146 @@ impl<'a> RxFrame<'a, Completed> {
         &self.state.preamble
     }
 
+    /// Decompress the segments of this frame, if they are compressed.
+    pub fn decompress(&mut self, compression: &FrameCompression) -> Result<(), CompressionError> {
+        if !self.state.preamble.is_compressed() {
+            return Ok(());
+        }
+
+        let mut buffers = Vec::new();
+        let mut output = Vec::new();
+
+        {
+            let frame = Frame::decode(&self.state.preamble, self.frame_data)?;
+            let frame = compression.decompress(&frame, &mut buffers)?;
+            frame.write(self.format, &mut output);
+        }
+
+        let preamble_data = *output
+            .first_chunk()
+            .expect("Frame::write always writes a preamble");
+
+        self.state.preamble =
+            Preamble::parse(&preamble_data, self.format).map_err(CompressionError::Failed)?;
+
+        output.drain(..Preamble::SERIALIZED_SIZE);
+        *self.frame_data = output;
+
+        Ok(())
+    }
+
     pub fn into_preamble_and_data(self) -> (Preamble, &'a [u8]) {
         (self.state.preamble, self.frame_data)
     }
 }
 
+/// A frame whose preamble was decoded by a [`FrameDecoder`], but
+/// whose remaining data has not been received yet.
+#[derive(Debug)]
+struct PartialFrame {
+    format: FrameFormat,
+    preamble: Preamble,
+    preamble_data: [u8; Preamble::SERIALIZED_SIZE],
+    frame_data: Vec<u8>,
+    /// The amount of bytes on the wire that are still
+    /// required to complete the frame.
+    remaining: usize,
+}
+
+/// A push-based frame decoder, for use with non-blocking I/O.
+///
+/// Received data is fed to the decoder with [`FrameDecoder::push`] in chunks
+/// of arbitrary size, after which completed frames can be obtained by calling
+/// [`FrameDecoder::next_frame`] until it returns `None`.
+///
+/// Frames are decoded one at a time, since the format and encryption of a
+/// connection may change after receiving a frame (e.g. after an
+/// [`AuthDone`](crate::frames::AuthDone)).
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    /// Received data that was not consumed yet.
+    pending: Vec<u8>,
+    partial: Option<PartialFrame>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed received `data` to the decoder.
+    pub fn push(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+    }
+
+    /// The amount of received bytes that were not consumed by
+    /// a frame yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Decode the next frame using `format` and `encryption`, storing
+    /// its data in `buffer`.
+    ///
+    /// Returns `None` if more data is required to complete the frame. If
+    /// the preamble of the frame was already decoded, the `format` that
+    /// was used at that point is used for the rest of the frame.
+    pub fn next_frame<'buf>(
+        &mut self,
+        format: FrameFormat,
+        encryption: &mut FrameEncryption,
+        buffer: &'buf mut Vec<u8>,
+    ) -> Result<Option<RxFrame<'buf, Completed>>, RxError> {
+        let frame = if let Some(partial) = self.partial.take() {
+            if self.pending.len() < partial.remaining {
+                self.partial = Some(partial);
+                return Ok(None);
+            }
+
+            *buffer = partial.frame_data;
+
+            RxFrame {
+                state: ReadPreamble {
+                    encryption,
+                    preamble: partial.preamble,
+                    preamble_data: partial.preamble_data,
+                },
+                format: partial.format,
+                frame_data: buffer,
+            }
+        } else {
+            let frame = RxFrame::new(format, encryption, buffer);
+            let start_rx_bytes = frame.start_rx_bytes();
+
+            if self.pending.len() < start_rx_bytes {
+                return Ok(None);
+            }
+
+            let frame = frame.read_preamble(&self.pending[..start_rx_bytes]);
+            self.pending.drain(..start_rx_bytes);
+            frame?
+        };
+
+        let remaining = frame.wire_blocks().iter().sum();
+
+        if self.pending.len() < remaining {
+            self.partial = Some(PartialFrame {
+                format: frame.format,
+                preamble: frame.state.preamble,
+                preamble_data: frame.state.preamble_data,
+                frame_data: core::mem::take(frame.frame_data),
+                remaining,
+            });
+
+            return Ok(None);
+        }
+
+        let frame = frame.read_rest(&self.pending[..remaining]);
+        self.pending.drain(..remaining);
+        frame.map(Some)
+    }
+}
+
 #[derive(Debug)]
 pub struct TxFrame<'enc_buf> {
     pub(crate) preamble: Preamble,
@@ -294,7 +537,19 @@ impl TxFrame<'_> {
                 output.write_all(self.frame_data)?;
                 Ok(self.frame_data.len())
             }
-            FrameFormat::Rev0Secure => todo!(),
+            FrameFormat::Rev0Secure => {
+                // The preamble, segments and epilogue are all encrypted as
+                // a single message.
+                let tag = self
+                    .enc
+                    .encrypt(self.frame_data)
+                    .map_err(|_| TxError::EncryptionFailed)?;
+
+                output.write_all(self.frame_data)?;
+                output.write_all(tag.as_slice())?;
+
+                Ok(self.frame_data.len() + tag.len())
+            }
             FrameFormat::Rev1Secure => {
                 const LEN: usize = Preamble::SERIALIZED_SIZE + REV1_SECURE_INLINE_SIZE;
                 let mut preamble = [0u8; LEN];
@@ -346,4 +601,22 @@ impl TxFrame<'_> {
             }
         }
     }
+
+    /// The asynchronous counterpart of [`TxFrame::write`].
+    #[cfg(feature = "tokio")]
+    pub async fn write_async(
+        self,
+        mut output: impl tokio::io::AsyncWrite + Unpin,
+    ) -> Result<usize, TxError> {
+        use tokio::io::AsyncWriteExt;
+
+        // Encryption happens in-place while writing, so the
+        // encrypted frame is collected before sending it.
+        let mut data = Vec::new();
+        let len = self.write(&mut data)?;
+
+        output.write_all(&data).await?;
+
+        Ok(len)
+    }
 }
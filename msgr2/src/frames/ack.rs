@@ -0,0 +1,9 @@
+/// An acknowledgement of received messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ack {
+    /// The sequence number of the last message that
+    /// was received.
+    pub seq: u64,
+}
+
+ceph_foundation::write_decode_encode!(Ack = seq);
@@ -34,6 +34,36 @@ impl AuthRequest {
             auth_payload: auth_method.to_vec(),
         }
     }
+
+    /// Create a new authentication request for `method` with an already
+    /// encoded `auth_payload`, such as a CephX authorizer.
+    pub fn with_payload(
+        method: AuthMethod,
+        preferred_modes: Vec<ConMode>,
+        auth_payload: Vec<u8>,
+    ) -> Self {
+        Self {
+            method,
+            preferred_modes,
+            auth_payload,
+        }
+    }
+
+    /// The requested authentication method.
+    pub fn method(&self) -> AuthMethod {
+        self.method
+    }
+
+    /// The prioritized list of preferred connection modes.
+    pub fn preferred_modes(&self) -> &[ConMode] {
+        &self.preferred_modes
+    }
+
+    /// The encoded authentication payload, whose type depends
+    /// on [`AuthRequest::method`].
+    pub fn auth_payload(&self) -> &[u8] {
+        &self.auth_payload
+    }
 }
 
 ceph_foundation::write_decode_encode!(AuthRequest = method | preferred_modes | auth_payload);
@@ -0,0 +1,56 @@
+/// A request to compress the frames exchanged on
+/// this connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionRequest {
+    /// Whether compression is requested at all.
+    pub is_compress: bool,
+    /// The compression methods supported by the requester, in
+    /// order of preference.
+    ///
+    /// These are kept as raw values, as the peer may support
+    /// methods that we do not know about.
+    pub preferred_methods: Vec<u32>,
+}
+
+ceph_foundation::write_decode_encode!(CompressionRequest = is_compress | preferred_methods);
+
+/// The response to a [`CompressionRequest`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionDone {
+    /// Whether frames should be compressed.
+    pub is_compress: bool,
+    /// The selected compression method.
+    pub method: u32,
+}
+
+ceph_foundation::write_decode_encode!(CompressionDone = is_compress | method);
+
+#[test]
+fn empty_request() {
+    use ceph_foundation::Encode;
+
+    let request = CompressionRequest {
+        is_compress: false,
+        preferred_methods: Vec::new(),
+    };
+
+    let mut buffer = Vec::new();
+    request.encode(&mut buffer);
+    assert_eq!(buffer, [0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn decode_done() {
+    use ceph_foundation::Decode;
+
+    let data = [1, 3, 0, 0, 0];
+    let done = CompressionDone::decode(&mut &data[..]).unwrap();
+
+    assert_eq!(
+        done,
+        CompressionDone {
+            is_compress: true,
+            method: 3
+        }
+    );
+}
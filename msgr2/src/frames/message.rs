@@ -54,7 +54,7 @@ impl<'a> Message<'a> {
     pub fn pop_data_segment(&mut self) -> Option<&'a [u8]> {
         if let Some(len) = self.len.checked_sub(1) {
             self.len = len;
-            Some(&self.inner[self.len])
+            Some(self.inner[self.len])
         } else {
             None
         }
@@ -65,15 +65,15 @@ impl<'a> Message<'a> {
     }
 
     pub fn front(&self) -> Option<&[u8]> {
-        self.inner.get(0).map(|v| *v)
+        self.inner.first().copied()
     }
 
     pub fn middle(&self) -> Option<&[u8]> {
-        self.inner.get(1).map(|v| *v)
+        self.inner.get(1).copied()
     }
 
     pub fn back(&self) -> Option<&'a [u8]> {
-        self.inner.get(2).map(|v| *v)
+        self.inner.get(2).copied()
     }
 
     pub fn data_segments(&self) -> &[&'a [u8]] {
@@ -1,25 +1,33 @@
 //! Messages that can be exchanged over a `msgr2` connection.
 
+mod ack;
 mod auth;
 mod banner;
 mod client_ident;
+mod compression;
 mod hello;
 mod ident_missing_features;
 mod keepalive;
 mod message;
 mod server_ident;
+mod session;
 
+pub use ack::Ack;
 pub use auth::{
     AuthBadMethod, AuthDone, AuthMethod, AuthMethodCephX, AuthMethodNone, AuthReplyMore,
     AuthRequest, AuthRequestMore, AuthRequestPayload, AuthSignature, ConMode,
 };
 pub use banner::Banner;
 pub use client_ident::ClientIdent;
+pub use compression::{CompressionDone, CompressionRequest};
 pub use hello::Hello;
 pub use ident_missing_features::IdentMissingFeatures;
 pub use keepalive::{Keepalive, KeepaliveAck};
 pub use message::Message;
 pub use server_ident::ServerIdent;
+pub use session::{
+    SessionReconnect, SessionReconnectOk, SessionReset, SessionRetry, SessionRetryGlobal, Wait,
+};
 
 use ceph_foundation::{Decode, DecodeError, Encode, Encoder};
 
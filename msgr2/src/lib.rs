@@ -3,6 +3,7 @@
 //! [0]: https://docs.ceph.com/en/quincy/dev/msgr2/
 //! [1]: https://ceph.com/en/
 
+pub mod compression;
 mod frame;
 pub mod frames;
 
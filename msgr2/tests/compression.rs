@@ -0,0 +1,91 @@
+use ceph_foundation::{Timestamp, crypto::Key};
+use msgr2::{
+    Frame, FrameEncryption, FrameFormat, Tag,
+    compression::{CompressionMethod, FrameCompression},
+    wire::RxFrame,
+};
+
+/// Create a transmitting and a receiving encryption for `format`
+/// that match each other.
+fn encryption(format: FrameFormat) -> (FrameEncryption, FrameEncryption) {
+    let mut tx = FrameEncryption::new();
+    let mut rx = FrameEncryption::new();
+
+    if !format.has_crc() {
+        let key = Key::new(Timestamp::default(), [0x42; 16]);
+        let (a, b) = ([1; 12], [2; 12]);
+        tx.set_secret_data(format.revision(), key.clone(), b, a);
+        rx.set_secret_data(format.revision(), key, a, b);
+    }
+
+    (tx, rx)
+}
+
+fn round_trip(format: FrameFormat, method: CompressionMethod) {
+    let mut compression = FrameCompression::new();
+    compression.set_method(method, 64);
+
+    let header = [0xAB; 41];
+    let front: Vec<u8> = (0..4096u32).map(|v| (v % 13) as u8).collect();
+    let data = [0x11; 512];
+
+    let frame = Frame::new(Tag::Message, &[&header, &front, &[], &data]).unwrap();
+
+    let mut buffers = Vec::new();
+    let compressed = compression.compress(&frame, &mut buffers).unwrap();
+
+    let (mut tx_enc, mut rx_enc) = encryption(format);
+    let mut buffer = Vec::new();
+    let mut wire = Vec::new();
+    compressed
+        .send(format, &mut tx_enc, &mut buffer)
+        .write(&mut wire)
+        .unwrap();
+
+    assert!(wire.len() < header.len() + front.len() + data.len());
+
+    let mut rx_buffer = Vec::new();
+    let mut rx_wire = wire.as_slice();
+    let rx = RxFrame::new(format, &mut rx_enc, &mut rx_buffer);
+    let rx = rx.read_preamble(&mut rx_wire).unwrap();
+    let mut rx = rx.read_rest(&mut rx_wire).unwrap();
+    assert!(rx.preamble().is_compressed());
+
+    rx.decompress(&compression).unwrap();
+    assert!(!rx.preamble().is_compressed());
+
+    let (preamble, data) = rx.into_preamble_and_data();
+    let received = Frame::decode(&preamble, data).unwrap();
+
+    assert_eq!(received.tag(), Tag::Message);
+    assert!(!received.is_compressed());
+    assert_eq!(received.segments(), frame.segments());
+}
+
+#[test]
+fn compressed_frames() {
+    for method in CompressionMethod::SUPPORTED {
+        round_trip(FrameFormat::Rev0Crc, *method);
+        round_trip(FrameFormat::Rev1Crc, *method);
+        round_trip(FrameFormat::Rev0Secure, *method);
+        round_trip(FrameFormat::Rev1Secure, *method);
+    }
+}
+
+#[test]
+fn not_negotiated() {
+    let mut frame = Frame::new(Tag::Message, &[&[1, 2, 3, 4]]).unwrap();
+    frame.set_compressed(true);
+
+    let mut wire = Vec::new();
+    frame.write(FrameFormat::Rev1Crc, &mut wire);
+
+    let mut enc = FrameEncryption::new();
+    let mut buffer = Vec::new();
+    let mut rx_wire = wire.as_slice();
+    let rx = RxFrame::new(FrameFormat::Rev1Crc, &mut enc, &mut buffer);
+    let rx = rx.read_preamble(&mut rx_wire).unwrap();
+    let mut rx = rx.read_rest(&mut rx_wire).unwrap();
+
+    assert!(rx.decompress(&FrameCompression::new()).is_err());
+}
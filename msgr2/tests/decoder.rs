@@ -0,0 +1,126 @@
+use ceph_foundation::{Timestamp, crypto::Key};
+use msgr2::{
+    Frame, FrameEncryption, FrameFormat, Tag,
+    wire::{FrameDecoder, RxFrame},
+};
+
+const FORMATS: [FrameFormat; 4] = [
+    FrameFormat::Rev0Crc,
+    FrameFormat::Rev1Crc,
+    FrameFormat::Rev0Secure,
+    FrameFormat::Rev1Secure,
+];
+
+const SESSION_KEY: [u8; 16] = [7; 16];
+const C_TX_NONCE: [u8; 12] = [1; 12];
+const S_TX_NONCE: [u8; 12] = [2; 12];
+
+/// Create the transmitting encryption of the client and the
+/// receiving encryption of the server.
+fn pair(format: FrameFormat) -> (FrameEncryption, FrameEncryption) {
+    let mut client = FrameEncryption::new();
+    let mut server = FrameEncryption::new();
+
+    if !format.has_crc() {
+        let key = || Key::new(Timestamp::default(), SESSION_KEY);
+        client.set_secret_data(format.revision(), key(), S_TX_NONCE, C_TX_NONCE);
+        server.set_secret_data(format.revision(), key(), C_TX_NONCE, S_TX_NONCE);
+    }
+
+    (client, server)
+}
+
+/// Segments that cover the different layouts of frames, including
+/// `msgr2.1` secure frames with and without data beyond the inline data.
+fn segments() -> Vec<(Tag, Vec<Vec<u8>>)> {
+    let data = |len: usize| (0..len).map(|v| v as u8).collect::<Vec<u8>>();
+
+    vec![
+        (Tag::Keepalive2, vec![data(12)]),
+        (Tag::AuthSignature, vec![data(32)]),
+        (Tag::Hello, vec![data(48)]),
+        (Tag::Hello, vec![data(49)]),
+        (Tag::Message, vec![data(41), data(300)]),
+        (Tag::Message, vec![data(100), vec![], vec![], data(3)]),
+        (Tag::Wait, vec![vec![]]),
+    ]
+}
+
+fn wire(format: FrameFormat, encryption: &mut FrameEncryption) -> Vec<u8> {
+    let mut wire = Vec::new();
+
+    for (tag, segments) in segments() {
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+        let frame = Frame::new(tag, &segments).unwrap();
+
+        let mut buffer = Vec::new();
+        frame
+            .send(format, encryption, &mut buffer)
+            .write(&mut wire)
+            .unwrap();
+    }
+
+    wire
+}
+
+fn assert_frame(frame: RxFrame<'_, msgr2::wire::Completed>, expected: &(Tag, Vec<Vec<u8>>)) {
+    let (preamble, data) = frame.into_preamble_and_data();
+    let frame = Frame::decode(&preamble, data).unwrap();
+
+    assert_eq!(frame.tag(), expected.0);
+    assert_eq!(frame.segments(), expected.1);
+}
+
+#[test]
+fn arbitrary_chunks() {
+    for format in FORMATS {
+        for chunk_size in [1, 7, 16, 64, 1000, usize::MAX] {
+            let (mut tx, mut rx) = pair(format);
+            let wire = wire(format, &mut tx);
+
+            let mut decoder = FrameDecoder::new();
+            let expected = segments();
+            let mut received = 0;
+
+            for chunk in wire.chunks(chunk_size.min(wire.len())) {
+                decoder.push(chunk);
+
+                let mut buffer = Vec::new();
+                while let Some(frame) = decoder.next_frame(format, &mut rx, &mut buffer).unwrap() {
+                    assert_frame(frame, &expected[received]);
+                    received += 1;
+                }
+            }
+
+            assert_eq!(received, expected.len(), "{format:?}, {chunk_size}");
+            assert_eq!(decoder.pending(), 0);
+        }
+    }
+}
+
+#[test]
+fn incomplete_frame() {
+    for format in FORMATS {
+        let (mut tx, mut rx) = pair(format);
+        let wire = wire(format, &mut tx);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&wire[..wire.len() - 1]);
+
+        let mut received = 0;
+        let mut buffer = Vec::new();
+        while decoder
+            .next_frame(format, &mut rx, &mut buffer)
+            .unwrap()
+            .is_some()
+        {
+            received += 1;
+        }
+
+        assert_eq!(received, segments().len() - 1, "{format:?}");
+
+        decoder.push(&wire[wire.len() - 1..]);
+        let frame = decoder.next_frame(format, &mut rx, &mut buffer).unwrap();
+        assert_frame(frame.unwrap(), segments().last().unwrap());
+    }
+}
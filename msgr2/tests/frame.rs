@@ -0,0 +1,81 @@
+use msgr2::{Frame, FrameEncryption, FrameFormat, Tag, wire::RxFrame};
+
+/// Decode the single frame in `wire`, returning its tag and segments, or
+/// `None` if the frame could not be decoded.
+fn decode(format: FrameFormat, mut wire: &[u8]) -> Option<(Tag, Vec<Vec<u8>>)> {
+    let mut buffer = Vec::new();
+    let mut encryption = FrameEncryption::new();
+    let rx = RxFrame::new(format, &mut encryption, &mut buffer);
+    let rx = rx.read_preamble(&mut wire).unwrap();
+    let rx = rx.read_rest(&mut wire).unwrap();
+
+    let (preamble, data) = rx.into_preamble_and_data();
+    let frame = Frame::decode(&preamble, data).ok()?;
+    let segments = frame.segments().iter().map(|s| s.to_vec()).collect();
+
+    Some((frame.tag(), segments))
+}
+
+#[test]
+fn first_segment_crc() {
+    // In `msgr2.1` CRC mode, the CRC of the first segment directly follows
+    // it, even if it is empty. Ceph's CRC of an empty segment is the seed.
+    let frame = Frame::new(Tag::Message, &[&[], &[1, 2, 3]]).unwrap();
+
+    let mut wire = Vec::new();
+    frame.write(FrameFormat::Rev1Crc, &mut wire);
+
+    // The preamble, the CRC, the second segment and the epilogue.
+    assert_eq!(wire.len(), 32 + 4 + 3 + 13);
+    assert_eq!(wire[32..36], [0xff; 4]);
+    assert_eq!(wire[36..39], [1, 2, 3]);
+
+    assert_eq!(
+        decode(FrameFormat::Rev1Crc, &wire),
+        Some((Tag::Message, vec![vec![], vec![1, 2, 3]]))
+    );
+}
+
+#[test]
+fn single_empty_segment() {
+    // Frames without content, such as `Wait`, consist of a single empty
+    // segment. Otherwise, the last segment may not be empty.
+    assert!(Frame::new(Tag::Wait, &[&[]]).is_ok());
+    assert!(Frame::new(Tag::Message, &[&[1], &[]]).is_err());
+    assert!(Frame::new(Tag::Wait, &[]).is_err());
+
+    for format in [FrameFormat::Rev0Crc, FrameFormat::Rev1Crc] {
+        let frame = Frame::new(Tag::Wait, &[&[]]).unwrap();
+
+        let mut wire = Vec::new();
+        frame.write(format, &mut wire);
+
+        assert_eq!(decode(format, &wire), Some((Tag::Wait, vec![vec![]])));
+    }
+}
+
+#[test]
+fn rev1_crc_epilogue() {
+    // In `msgr2.1`, a completed frame is marked by `FRAME_LATE_STATUS_COMPLETE`
+    // in the low nibble of the late flags, rather than by the absence of
+    // `FRAME_LATE_FLAG_ABORTED`.
+    let frame = Frame::new(Tag::Message, &[&[1], &[2, 3]]).unwrap();
+
+    let mut wire = Vec::new();
+    frame.write(FrameFormat::Rev1Crc, &mut wire);
+
+    // The preamble, the first segment and its CRC, the second segment and the epilogue.
+    assert_eq!(wire.len(), 32 + 1 + 4 + 2 + 13);
+    let late_flags = wire.len() - 13;
+    assert_eq!(wire[late_flags], 0xE);
+
+    assert_eq!(
+        decode(FrameFormat::Rev1Crc, &wire),
+        Some((Tag::Message, vec![vec![1], vec![2, 3]]))
+    );
+
+    // A `msgr2.0` style epilogue does not mark the frame as completed.
+    wire[late_flags] = 0;
+
+    assert_eq!(decode(FrameFormat::Rev1Crc, &wire), None);
+}
@@ -87,7 +87,7 @@ fn lspools_exchange() {
         assert_eq!(c_hello.segments().len(), 1);
         assert_eq!(c_hello.segments()[0].len(), 36);
 
-        let c_hello = Hello::decode(&mut c_hello.segments()[0].as_ref()).unwrap();
+        let c_hello = Hello::decode(&mut &*c_hello.segments()[0]).unwrap();
 
         let expected_c_hello = Hello {
             entity_type: EntityType::Client,
@@ -107,7 +107,7 @@ fn lspools_exchange() {
         assert_eq!(s_hello.segments().len(), 1);
         assert_eq!(s_hello.segments()[0].len(), 36);
 
-        let s_hello = Hello::decode(&mut s_hello.segments()[0].as_ref()).unwrap();
+        let s_hello = Hello::decode(&mut &*s_hello.segments()[0]).unwrap();
 
         let expected_s_hello = Hello {
             entity_type: EntityType::Mon,
@@ -127,7 +127,7 @@ fn lspools_exchange() {
         assert_eq!(auth_req.segments().len(), 1);
         assert_eq!(auth_req.segments()[0].len(), 42);
 
-        let auth_req = AuthRequest::decode(&mut auth_req.segments()[0].as_ref()).unwrap();
+        let auth_req = AuthRequest::decode(&mut &*auth_req.segments()[0]).unwrap();
 
         let expected_auth_req = AuthRequest::new(
             AuthMethodCephX {
@@ -149,8 +149,7 @@ fn lspools_exchange() {
         assert_eq!(auth_reply_more.segments().len(), 1);
         assert_eq!(auth_reply_more.segments()[0].len(), 13);
 
-        let auth_reply_more =
-            AuthReplyMore::decode(&mut auth_reply_more.segments()[0].as_ref()).unwrap();
+        let auth_reply_more = AuthReplyMore::decode(&mut &*auth_reply_more.segments()[0]).unwrap();
 
         let payload = vec![1, 56, 244, 156, 125, 244, 205, 166, 69];
         let expected_auth_reply_more = AuthReplyMore { payload };
@@ -164,8 +163,7 @@ fn lspools_exchange() {
         assert_eq!(auth_req_more.segments().len(), 1);
         assert_eq!(auth_req_more.segments()[0].len(), 40);
 
-        let auth_req_more =
-            AuthRequestMore::decode(&mut auth_req_more.segments()[0].as_ref()).unwrap();
+        let auth_req_more = AuthRequestMore::decode(&mut &*auth_req_more.segments()[0]).unwrap();
 
         #[rustfmt::skip]
         let payload = vec![0, 1, 3, 53, 182, 1, 161, 71, 174, 99, 173, 141, 205, 172, 6, 25, 209, 38, 243, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0];
@@ -180,7 +178,7 @@ fn lspools_exchange() {
         assert_eq!(auth_done.segments().len(), 1);
         assert_eq!(auth_done.segments()[0].len(), 290);
 
-        let auth_done = AuthDone::decode(&mut auth_done.segments()[0].as_ref()).unwrap();
+        let auth_done = AuthDone::decode(&mut &*auth_done.segments()[0]).unwrap();
 
         let auth_payload = vec![
             0, 1, 0, 0, 0, 0, 1, 1, 0, 0, 0, 32, 0, 0, 0, 1, 48, 0, 0, 0, 242, 204, 2, 44, 40, 93,
@@ -221,7 +219,7 @@ fn lspools_exchange() {
         assert_eq!(client_sig.segments().len(), 1);
         assert_eq!(client_sig.segments()[0].len(), 32);
 
-        let sig = AuthSignature::decode(&mut client_sig.segments()[0].as_ref()).unwrap();
+        let sig = AuthSignature::decode(&mut &*client_sig.segments()[0]).unwrap();
 
         #[rustfmt::skip]
         let sha256_hmac = [91, 44, 8, 74, 105, 138, 26, 102, 10, 44, 135, 36, 126, 144, 70, 105, 58, 203, 209, 225, 187, 204, 8, 46, 156, 7, 62, 190, 86, 202, 251, 121];
@@ -235,7 +233,7 @@ fn lspools_exchange() {
         assert_eq!(server_sig.segments().len(), 1);
         assert_eq!(server_sig.segments()[0].len(), 32);
 
-        let sig = AuthSignature::decode(&mut server_sig.segments()[0].as_ref()).unwrap();
+        let sig = AuthSignature::decode(&mut &*server_sig.segments()[0]).unwrap();
         #[rustfmt::skip]
         let sha256_hmac = [13, 120, 211, 51, 110, 74, 95, 42, 17, 183, 92, 127, 51, 104, 80, 90, 20, 14, 107, 221, 206, 153, 116, 132, 94, 44, 81, 126, 162, 65, 166, 75];
         let expected_sig = AuthSignature { sha256_hmac };
@@ -262,7 +260,7 @@ fn lspools_exchange() {
         assert_eq!(client_ident.segments().len(), 1);
         assert_eq!(client_ident.segments()[0].len(), 123);
 
-        let _ident = ClientIdent::decode(&mut client_ident.segments()[0].as_ref()).unwrap();
+        let _ident = ClientIdent::decode(&mut &*client_ident.segments()[0]).unwrap();
     }
 
     {
@@ -271,7 +269,7 @@ fn lspools_exchange() {
         assert_eq!(server_ident.segments().len(), 1);
         assert_eq!(server_ident.segments()[0].len(), 123);
 
-        let _ident = ServerIdent::decode(&mut server_ident.segments()[0].as_ref()).unwrap();
+        let _ident = ServerIdent::decode(&mut &*server_ident.segments()[0]).unwrap();
     }
 
     {
@@ -0,0 +1,155 @@
+use ceph_foundation::{Timestamp, crypto::Key};
+use msgr2::{
+    Frame, FrameEncryption, FrameFormat, Tag,
+    wire::{RxError, RxFrame},
+};
+
+const FMT: FrameFormat = FrameFormat::Rev0Secure;
+
+#[rustfmt::skip]
+const SESSION_KEY: [u8; 16] = [108, 28, 132, 21, 133, 70, 253, 148, 37, 227, 91, 179, 135, 65, 186, 18];
+const C_TX_NONCE: [u8; 12] = [125, 228, 109, 239, 216, 72, 244, 252, 52, 110, 241, 163];
+const S_TX_NONCE: [u8; 12] = [81, 64, 21, 176, 136, 112, 18, 215, 80, 248, 20, 146];
+
+fn session_key() -> Key {
+    Key::new(Timestamp::default(), SESSION_KEY)
+}
+
+/// Create the transmitting encryption of the client and the
+/// receiving encryption of the server.
+fn pair() -> (FrameEncryption, FrameEncryption) {
+    let mut client = FrameEncryption::new();
+    client.set_secret_data(FMT.revision(), session_key(), S_TX_NONCE, C_TX_NONCE);
+
+    let mut server = FrameEncryption::new();
+    server.set_secret_data(FMT.revision(), session_key(), C_TX_NONCE, S_TX_NONCE);
+
+    (client, server)
+}
+
+fn transmit(frame: &Frame, enc: &mut FrameEncryption) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut wire = Vec::new();
+    let written = frame.send(FMT, enc, &mut buffer).write(&mut wire).unwrap();
+    assert_eq!(written, wire.len());
+    wire
+}
+
+fn receive<'a>(
+    enc: &mut FrameEncryption,
+    buffer: &'a mut Vec<u8>,
+    mut wire: &[u8],
+) -> Result<Frame<'a>, RxError> {
+    let frame = RxFrame::new(FMT, enc, buffer);
+    let frame = frame.read_preamble(&mut wire)?;
+    let frame = frame.read_rest(&mut wire)?;
+    assert!(wire.is_empty(), "Frame did not consume all data");
+
+    let (preamble, data) = frame.into_preamble_and_data();
+    Ok(Frame::decode(&preamble, data).unwrap())
+}
+
+/// Frames sent by this crate must match the same frames produced
+/// independently of it from Ceph's rev0 frame layout: one AES-GCM
+/// message per frame, covering the preamble, the segments padded to 16
+/// bytes and a zeroed epilogue block.
+#[test]
+fn frame_layout() {
+    let (mut c_tx_enc, mut s_rx_enc) = pair();
+    let mut expected: &[u8] = include_bytes!("../../test-data/rev0secure_frames.bin");
+
+    let header = [0xAB; 41];
+    let front: Vec<u8> = (0..=255).collect();
+    let signature = [0x5A; 32];
+    let compression = [0u8; 5];
+
+    let frames: [(Tag, &[&[u8]]); 4] = [
+        (Tag::AuthSignature, &[&signature]),
+        (Tag::CompressionRequest, &[&compression]),
+        (Tag::Message, &[&header, &front]),
+        (Tag::Message, &[&header, &[], &[], &front[..3]]),
+    ];
+
+    for (tag, segments) in frames {
+        let frame = Frame::new(tag, segments).unwrap();
+        let wire = transmit(&frame, &mut c_tx_enc);
+
+        let (reference, rest) = expected.split_at(wire.len());
+        assert_eq!(wire, reference);
+        expected = rest;
+
+        let mut buffer = Vec::new();
+        let received = receive(&mut s_rx_enc, &mut buffer, &wire).unwrap();
+        assert_eq!(received.tag(), tag);
+        assert_eq!(received.segments(), segments);
+    }
+
+    assert!(expected.is_empty());
+}
+
+#[test]
+fn tampered_epilogue() {
+    let (mut c_tx_enc, mut s_rx_enc) = pair();
+
+    let frame = Frame::new(Tag::Keepalive2, &[&[1, 2, 3, 4, 5, 6, 7, 8]]).unwrap();
+    let mut wire = transmit(&frame, &mut c_tx_enc);
+
+    // Flip a bit in the epilogue, which is only covered by the
+    // tag at the end of the frame.
+    let epilogue_start = wire.len() - 32;
+    wire[epilogue_start] ^= 1;
+
+    let mut buffer = Vec::new();
+    let result = receive(&mut s_rx_enc, &mut buffer, &wire);
+    assert!(matches!(result, Err(RxError::DecryptionFailed)));
+}
+
+#[test]
+fn truncated_frame() {
+    let (mut c_tx_enc, mut s_rx_enc) = pair();
+
+    let frame = Frame::new(Tag::Keepalive2, &[&[1, 2, 3, 4, 5, 6, 7, 8]]).unwrap();
+    let wire = transmit(&frame, &mut c_tx_enc);
+
+    let mut buffer = Vec::new();
+    let result = receive(&mut s_rx_enc, &mut buffer, &wire[..wire.len() - 1]);
+    assert!(matches!(result, Err(RxError::FrameDataTruncated)));
+}
+
+/// A stream of frames sent by a `msgr2.0` client in secure mode,
+/// produced independently of this crate from Ceph's rev0 frame layout:
+/// segments aligned to 8 (the data segment to 4096), a zeroed epilogue
+/// and a nonce counter that starts at `C_TX_NONCE`.
+#[test]
+fn reference_stream() {
+    let (_, mut s_rx_enc) = pair();
+    let mut wire: &[u8] = include_bytes!("../../test-data/rev0secure_stream.bin");
+
+    let header: Vec<u8> = (0..41).collect();
+    let front: Vec<u8> = (0..=255).chain(0..=255).collect();
+    let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    let keepalive = [0, 241, 83, 101, 21, 205, 91, 7];
+
+    let expected: [(Tag, &[&[u8]]); 6] = [
+        (Tag::AuthSignature, &[&[0x5A; 32]]),
+        (Tag::Keepalive2, &[&keepalive]),
+        (Tag::Wait, &[&[]]),
+        (Tag::Message, &[&header, &front]),
+        (Tag::Message, &[&[0xAB; 41], b"front", &[], &data]),
+        (Tag::Ack, &[&2u64.to_le_bytes()]),
+    ];
+
+    for (tag, segments) in expected {
+        let mut buffer = Vec::new();
+        let frame = RxFrame::new(FMT, &mut s_rx_enc, &mut buffer);
+        let frame = frame.read_preamble(&mut wire).unwrap();
+        let frame = frame.read_rest(&mut wire).unwrap();
+
+        let (preamble, data) = frame.into_preamble_and_data();
+        let frame = Frame::decode(&preamble, data).unwrap();
+        assert_eq!(frame.tag(), tag);
+        assert_eq!(frame.segments(), segments);
+    }
+
+    assert!(wire.is_empty());
+}